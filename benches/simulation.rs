@@ -0,0 +1,158 @@
+//! Benchmarks of the simulation core, in epochs per second, for representative configurations: the
+//! baseline, node operators as individual agents, and several locked assets with prices of their
+//! own. Ensembles and sweeps are measured in epochs per second too, so that the throughput budget
+//! of a Monte Carlo run (or a sweep) can be read straight off the report. The cells of a sweep
+//! are also simulated on one thread both as independent runs and as a batch, to compare the two.
+//!
+//! Run them with `cargo bench`, and compare against a saved baseline with
+//! `cargo bench -- --save-baseline main` and `cargo bench -- --baseline main`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use eco::batch::Batch;
+use eco::config::Config;
+use eco::sweep::Axis;
+use eco::{monte_carlo, sweep, Simulation};
+
+/// The number of epochs that every run is simulated for.
+const EPOCHS: usize = 365;
+
+/// The seed of every run, so that every iteration simulates the same path.
+const SEED: u64 = 42;
+
+/// Node operators as a population of agents, registering and deregistering darknodes.
+const AGENTS: &str = r#"
+[external.bonding]
+model = "operators"
+bond = 100000.0
+operators = 50
+max_nodes = 10
+target_roi = [0.03, 0.15]
+cost = [500.0, 5000.0]
+entry = 0.01
+exit = 0.01
+
+[external.mint_volume]
+model = "lognormal"
+mean = 4000000.0
+volatility = 0.3
+drift = 0.0
+"#;
+
+/// Two locked assets with their own volumes and correlated prices.
+const MULTI_ASSET: &str = r#"
+[external]
+correlation = [[1.0], [0.8, 1.0]]
+
+[[external.assets]]
+name = "BTC"
+mint_volume = { model = "constant", volume = 3000000.0 }
+burn_volume = { model = "constant", volume = 1500000.0 }
+price = { model = "gbm", initial = 30000.0, drift = 0.0, volatility = 0.04 }
+
+[[external.assets]]
+name = "ZEC"
+mint_volume = { model = "lognormal", mean = 1000000.0, volatility = 0.1, drift = 0.0 }
+burn_volume = { model = "constant", volume = 500000.0 }
+price = { model = "gbm", initial = 100.0, drift = 0.0, volatility = 0.06 }
+"#;
+
+/// The configurations that are benchmarked, by name.
+fn configs() -> Vec<(&'static str, Config)> {
+    let parse = |toml| Config::from_toml(toml).expect("valid benchmark configuration");
+    vec![
+        ("baseline", Config::default()),
+        ("agents", parse(AGENTS)),
+        ("multi-asset", parse(MULTI_ASSET)),
+    ]
+}
+
+fn epochs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("epochs");
+    group.throughput(Throughput::Elements(EPOCHS as u64));
+    for (name, config) in configs() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &config, |b, config| {
+            b.iter(|| {
+                let mut sim = Simulation::from_config(config).with_seed(SEED);
+                sim.run(EPOCHS)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn ensembles(c: &mut Criterion) {
+    const RUNS: usize = 32;
+    let mut group = c.benchmark_group("monte_carlo");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((RUNS * EPOCHS) as u64));
+    for (name, mut config) in configs() {
+        config.steps = EPOCHS;
+        group.bench_with_input(BenchmarkId::from_parameter(name), &config, |b, config| {
+            b.iter(|| monte_carlo::run(config, RUNS, SEED).expect("ensemble keeps the invariants"))
+        });
+    }
+    group.finish();
+}
+
+/// The axes of the benchmarked sweep, on the baseline configuration.
+fn axes() -> Vec<Axis> {
+    [
+        "internal.mint_fee=0.001:0.008:0.001",
+        "internal.rebate_share=0,0.5,1",
+    ]
+    .iter()
+    .map(|axis| axis.parse().expect("valid axis"))
+    .collect()
+}
+
+fn sweeps(c: &mut Criterion) {
+    let axes = axes();
+    let cells = sweep::grid(&axes).len();
+    let config = Config {
+        steps: EPOCHS,
+        ..Config::default()
+    };
+    let mut group = c.benchmark_group("sweep");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((cells * EPOCHS) as u64));
+    group.bench_function("baseline", |b| {
+        b.iter(|| sweep::run(&config, &axes, SEED).expect("sweep keeps the invariants"))
+    });
+    group.finish();
+}
+
+fn batches(c: &mut Criterion) {
+    let axes = axes();
+    let configs: Vec<Config> = sweep::grid(&axes)
+        .into_iter()
+        .map(|params| {
+            let mut config = Config::default();
+            for (axis, value) in axes.iter().zip(params) {
+                config.set(&axis.path, value).expect("valid parameter");
+            }
+            config
+        })
+        .collect();
+    let mut group = c.benchmark_group("batch");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((configs.len() * EPOCHS) as u64));
+    group.bench_function("independent", |b| {
+        b.iter(|| {
+            for config in &configs {
+                let mut sim = Simulation::from_config(config).with_seed(SEED);
+                sim.try_run(EPOCHS).expect("sweep keeps the invariants");
+            }
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            let mut batch = Batch::new(&configs, SEED).expect("baseline cells are supported");
+            batch.run(EPOCHS);
+            batch.outcomes()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, epochs, ensembles, sweeps, batches);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+//! Records the git commit that the crate is built from as `ECON_SIM_COMMIT`, so that the metadata
+//! of every run can say exactly which code produced it. A commit given in the environment (when
+//! building from a source archive, say) takes precedence, and builds outside a git checkout have
+//! no commit at all.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=ECON_SIM_COMMIT");
+    if std::env::var_os("ECON_SIM_COMMIT").is_some() {
+        return;
+    }
+    let Some(commit) = git(&["rev-parse", "HEAD"]) else {
+        return;
+    };
+    println!("cargo:rustc-env=ECON_SIM_COMMIT={}", commit);
+
+    // The commit changes whenever HEAD moves, or the branch that it points to does. Paths that do
+    // not exist would rerun the script on every build, so they are left out.
+    let git_dir = git(&["rev-parse", "--absolute-git-dir"]).map(PathBuf::from);
+    let common_dir = git(&["rev-parse", "--git-common-dir"]).map(PathBuf::from);
+    let paths = [
+        git_dir.map(|dir| dir.join("HEAD")),
+        common_dir.as_ref().map(|dir| dir.join("refs")),
+        common_dir.map(|dir| dir.join("packed-refs")),
+    ];
+    for path in paths.iter().flatten().filter(|path| path.exists()) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+}
+
+/// The output of a git command, or nothing if git is not installed or the command fails.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string())
+}
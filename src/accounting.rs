@@ -0,0 +1,368 @@
+//! For accounting for the balances that RenVM keeps itself: the fees that node operators have
+//! claimed (and have yet to claim), the rebates that have been collected, and the rebate pool. By
+//! default they are floating point numbers like everything else, which is fast but accumulates
+//! rounding error over long runs. With the `decimal` feature, they can be kept as fixed-point
+//! decimals instead, rounded to a number of decimal places like the integer arithmetic on chain.
+//! Values that come from the models (volumes, fees, and rebates) are taken to the same number of
+//! places, and every amount that is charged or paid is rounded down (as integer division rounds
+//! down on chain). TVL and TVB follow market prices rather than balances, so they stay floating
+//! point either way.
+
+use crate::config::AccountingConfig;
+use crate::error::Result;
+use crate::state::State;
+use crate::{Pct, Usd};
+
+/// Ledger adds up the fees that are charged during an epoch, and settles them (along with the
+/// rebate) into the balances of the state at the end of it. Stages that see what one another do
+/// can pool the rebate and claim fees as they happen instead.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ledger {
+    /// Every amount is a floating point number, and the balances are the ones in the state.
+    Float {
+        /// The fees charged on minting so far this epoch.
+        minted: Usd,
+        /// The fees charged on burning so far this epoch.
+        burned: Usd,
+    },
+    /// Every amount is a fixed-point decimal.
+    #[cfg(feature = "decimal")]
+    Decimal(decimal::Ledger),
+}
+
+impl Ledger {
+    /// Create a ledger that accounts as the configuration says.
+    pub fn new(config: &AccountingConfig) -> Self {
+        match *config {
+            AccountingConfig::Float => Ledger::Float {
+                minted: Usd::ZERO,
+                burned: Usd::ZERO,
+            },
+            #[cfg(feature = "decimal")]
+            AccountingConfig::Decimal { decimals } => {
+                Ledger::Decimal(decimal::Ledger::new(decimals))
+            }
+        }
+    }
+
+    /// Charge a fee on value that is minted this epoch.
+    pub fn charge_mint(&mut self, volume: Usd, fee: Pct) {
+        match self {
+            Ledger::Float { minted, .. } => *minted += volume * fee,
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.charge_mint(volume, fee),
+        }
+    }
+
+    /// Charge a fee on value that is burned this epoch.
+    pub fn charge_burn(&mut self, volume: Usd, fee: Pct) {
+        match self {
+            Ledger::Float { burned, .. } => *burned += volume * fee,
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.charge_burn(volume, fee),
+        }
+    }
+
+    /// Collect value that is not a fee (like a slashed bond) along with the fees charged on
+    /// minting this epoch, so that it is settled in the same way.
+    pub fn collect(&mut self, value: Usd) {
+        self.charge_mint(value, Pct::ALL);
+    }
+
+    /// Take value that was charged this epoch back out (along with the fees charged on minting), so
+    /// that it is never settled, for value that is not charged after all or that goes somewhere
+    /// other than the balances that the ledger keeps (like the treasury).
+    pub fn withdraw(&mut self, value: Usd) {
+        self.collect(-value);
+    }
+
+    /// The fees charged on minting so far this epoch.
+    pub fn minted(&self) -> Usd {
+        match self {
+            Ledger::Float { minted, .. } => *minted,
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.minted(),
+        }
+    }
+
+    /// The fees charged on burning so far this epoch.
+    pub fn burned(&self) -> Usd {
+        match self {
+            Ledger::Float { burned, .. } => *burned,
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.burned(),
+        }
+    }
+
+    /// Move `r_collected` of the fees charged so far (along with those charged on minting) into the
+    /// rebate pool, which then pays the `rebate` on `burn_volume`, for as long as it can, ahead of
+    /// settling the rest.
+    pub fn pool(&mut self, state: &mut State, r_collected: Usd, burn_volume: Usd, rebate: Pct) {
+        match self {
+            Ledger::Float { .. } => {
+                let r_paid = burn_volume * rebate;
+                state.tvr += r_collected;
+                state.r_pool = (state.r_pool + r_collected - r_paid).max(Usd::ZERO);
+                self.withdraw(r_collected);
+            }
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.pool(state, r_collected, burn_volume, rebate),
+        }
+    }
+
+    /// Node operators claim `claim_rate` of the fees that they have not claimed yet, ahead of
+    /// settling the epoch.
+    pub fn claim(&mut self, state: &mut State, claim_rate: Pct) {
+        match self {
+            Ledger::Float { .. } => {
+                let claim = state.f_unclaimed * claim_rate;
+                state.f_unclaimed -= claim;
+                state.f_claimed += claim;
+            }
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => ledger.claim(state, claim_rate),
+        }
+    }
+
+    /// Settle the epoch into the balances of the state, and start charging the next one from
+    /// nothing. `r_collected` of the fees go to the rebate pool and the rest to node operators, who
+    /// then claim `claim_rate` of whatever they have not claimed yet. The rebate pool pays the
+    /// `rebate` on everything that was burned, for as long as it can.
+    pub fn settle(
+        &mut self,
+        state: &mut State,
+        r_collected: Usd,
+        burn_volume: Usd,
+        rebate: Pct,
+        claim_rate: Pct,
+    ) {
+        match self {
+            Ledger::Float { minted, burned } => {
+                let f_collected = *minted + *burned - r_collected;
+                let r_paid = burn_volume * rebate;
+                let claim = state.f_unclaimed * claim_rate;
+                state.tvr += r_collected;
+                state.f_unclaimed += f_collected - claim;
+                state.f_claimed += claim;
+                state.r_pool = (state.r_pool + r_collected - r_paid).max(Usd::ZERO);
+                *minted = Usd::ZERO;
+                *burned = Usd::ZERO;
+            }
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => {
+                ledger.settle(state, r_collected, burn_volume, rebate, claim_rate)
+            }
+        }
+    }
+
+    /// The balances that the ledger keeps, if they are not the ones in the state, so that they can
+    /// be saved in a checkpoint.
+    pub fn checkpoint(&self) -> Option<serde_json::Value> {
+        match self {
+            Ledger::Float { .. } => None,
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => serde_json::to_value(ledger).ok(),
+        }
+    }
+
+    /// Replace the balances that the ledger keeps with ones that were returned by `checkpoint`.
+    #[cfg_attr(not(feature = "decimal"), allow(unused_variables))]
+    pub fn restore(&mut self, balances: serde_json::Value) -> Result<()> {
+        match self {
+            Ledger::Float { .. } => Ok(()),
+            #[cfg(feature = "decimal")]
+            Ledger::Decimal(ledger) => {
+                *ledger = serde_json::from_value(balances)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "decimal")]
+mod decimal {
+    use crate::state::State;
+    use crate::{Pct, Usd};
+    use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+    use rust_decimal::{Decimal, RoundingStrategy};
+    use serde::{Deserialize, Serialize};
+
+    /// The most decimal places that a decimal can have.
+    const MAX_DECIMALS: u32 = 28;
+
+    /// Ledger keeps the balances as decimals, and writes the nearest floating point numbers into
+    /// the state. Amounts that cannot be represented (because they are not finite, or too large)
+    /// are missing, and make every balance that they flow into NaN, so that the invariants still
+    /// catch them.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Ledger {
+        decimals: u32,
+        minted: Option<Decimal>,
+        burned: Option<Decimal>,
+        balances: Option<Balances>,
+    }
+
+    /// The balances of RenVM, as they are at the end of an epoch.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    struct Balances {
+        tvr: Decimal,
+        f_unclaimed: Decimal,
+        f_claimed: Decimal,
+        r_pool: Decimal,
+    }
+
+    impl Balances {
+        /// The balances in the state, taken to the given number of decimal places.
+        fn from_state(state: &State, decimals: u32) -> Option<Self> {
+            Some(Self {
+                tvr: amount(state.tvr, decimals)?,
+                f_unclaimed: amount(state.f_unclaimed, decimals)?,
+                f_claimed: amount(state.f_claimed, decimals)?,
+                r_pool: amount(state.r_pool, decimals)?,
+            })
+        }
+
+        /// The balances as floating point numbers, in the same order as they appear in the state.
+        fn values(&self) -> [Usd; 4] {
+            [self.tvr, self.f_unclaimed, self.f_claimed, self.r_pool]
+                .map(|balance| Usd(balance.to_f64().unwrap_or(f64::NAN)))
+        }
+    }
+
+    /// Take a value to the given number of decimal places, rounding down.
+    fn amount(value: Usd, decimals: u32) -> Option<Decimal> {
+        Decimal::from_f64(value.0).map(|d| round_down(d, decimals))
+    }
+
+    /// Apply a percentage to an amount, rounding down.
+    fn charge(amount: Decimal, pct: Pct, decimals: u32) -> Option<Decimal> {
+        let pct = Decimal::from_f64(pct.0)?;
+        amount.checked_mul(pct).map(|d| round_down(d, decimals))
+    }
+
+    fn round_down(d: Decimal, decimals: u32) -> Decimal {
+        d.round_dp_with_strategy(decimals, RoundingStrategy::ToZero)
+    }
+
+    fn to_usd(amount: Option<Decimal>) -> Usd {
+        Usd(amount.and_then(|d| d.to_f64()).unwrap_or(f64::NAN))
+    }
+
+    impl Ledger {
+        pub fn new(decimals: u32) -> Self {
+            Self {
+                decimals: decimals.min(MAX_DECIMALS),
+                minted: Some(Decimal::ZERO),
+                burned: Some(Decimal::ZERO),
+                balances: None,
+            }
+        }
+
+        pub fn charge_mint(&mut self, volume: Usd, fee: Pct) {
+            let fee = self.fee(volume, fee);
+            self.minted = self.minted.zip(fee).and_then(|(a, b)| a.checked_add(b));
+        }
+
+        pub fn charge_burn(&mut self, volume: Usd, fee: Pct) {
+            let fee = self.fee(volume, fee);
+            self.burned = self.burned.zip(fee).and_then(|(a, b)| a.checked_add(b));
+        }
+
+        pub fn minted(&self) -> Usd {
+            to_usd(self.minted)
+        }
+
+        pub fn burned(&self) -> Usd {
+            to_usd(self.burned)
+        }
+
+        pub fn pool(&mut self, state: &mut State, r_collected: Usd, burn_volume: Usd, rebate: Pct) {
+            let decimals = self.decimals;
+            let r_collected = amount(r_collected, decimals);
+            let r_paid = amount(burn_volume, decimals).and_then(|v| charge(v, rebate, decimals));
+            self.minted = self
+                .minted
+                .zip(r_collected)
+                .and_then(|(a, b)| a.checked_sub(b));
+            self.update(state, |balances| {
+                let (r_collected, r_paid) = (r_collected?, r_paid?);
+                let r_pool = balances.r_pool.checked_add(r_collected)?;
+                Some(Balances {
+                    tvr: balances.tvr.checked_add(r_collected)?,
+                    r_pool: r_pool.checked_sub(r_paid)?.max(Decimal::ZERO),
+                    ..balances
+                })
+            });
+        }
+
+        pub fn claim(&mut self, state: &mut State, claim_rate: Pct) {
+            let decimals = self.decimals;
+            self.update(state, |balances| {
+                let claim = charge(balances.f_unclaimed, claim_rate, decimals)?;
+                Some(Balances {
+                    f_unclaimed: balances.f_unclaimed.checked_sub(claim)?,
+                    f_claimed: balances.f_claimed.checked_add(claim)?,
+                    ..balances
+                })
+            });
+        }
+
+        pub fn settle(
+            &mut self,
+            state: &mut State,
+            r_collected: Usd,
+            burn_volume: Usd,
+            rebate: Pct,
+            claim_rate: Pct,
+        ) {
+            let (decimals, minted, burned) = (self.decimals, self.minted, self.burned);
+            self.update(state, |balances| {
+                let r_collected = amount(r_collected, decimals)?;
+                let f_collected = minted?.checked_add(burned?)?;
+                let f_collected = f_collected.checked_sub(r_collected)?;
+                let r_paid = charge(amount(burn_volume, decimals)?, rebate, decimals)?;
+                let claim = charge(balances.f_unclaimed, claim_rate, decimals)?;
+                let r_pool = balances.r_pool.checked_add(r_collected)?;
+                Some(Balances {
+                    tvr: balances.tvr.checked_add(r_collected)?,
+                    f_unclaimed: balances
+                        .f_unclaimed
+                        .checked_add(f_collected)?
+                        .checked_sub(claim)?,
+                    f_claimed: balances.f_claimed.checked_add(claim)?,
+                    r_pool: r_pool.checked_sub(r_paid)?.max(Decimal::ZERO),
+                })
+            });
+            self.minted = Some(Decimal::ZERO);
+            self.burned = Some(Decimal::ZERO);
+        }
+
+        /// Change the balances, and write them into the state.
+        fn update(&mut self, state: &mut State, f: impl FnOnce(Balances) -> Option<Balances>) {
+            // The balances are only taken from the state when the state is not the one that the
+            // ledger last wrote (at the start of a run, or when its history has been replaced).
+            let balances = self
+                .balances
+                .filter(|balances| {
+                    balances.values()
+                        == [state.tvr, state.f_unclaimed, state.f_claimed, state.r_pool]
+                })
+                .or_else(|| Balances::from_state(state, self.decimals));
+            let updated = balances.and_then(f);
+            let [tvr, f_unclaimed, f_claimed, r_pool] = match &updated {
+                Some(balances) => balances.values(),
+                None => [Usd(f64::NAN); 4],
+            };
+            state.tvr = tvr;
+            state.f_unclaimed = f_unclaimed;
+            state.f_claimed = f_claimed;
+            state.r_pool = r_pool;
+            self.balances = updated;
+        }
+
+        /// The fee on a volume, where the volume is first taken to the ledger's decimal places.
+        fn fee(&self, volume: Usd, fee: Pct) -> Option<Decimal> {
+            charge(amount(volume, self.decimals)?, fee, self.decimals)
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! For weighing what it would cost to attack RenVM against what an attack could steal. An attacker
+//! who controls a share of the bond (a third, by default) controls the signatures on what is
+//! locked, so while the REN for that share of the bond costs less than the value that it secures,
+//! stealing is profitable. Buying that much REN moves its price, so the cost is estimated with a
+//! model of the depth of the market (see `MarketDepthConfig`) rather than at the current price.
+//! A sharded RenVM is only as strong as its weakest shard, which is the one that is assessed.
+
+use crate::config::{AttackConfig, EpochConfig, MarketDepthConfig};
+use crate::error::Result;
+use crate::state::State;
+use crate::Usd;
+use std::io::Write;
+
+/// Assessment is the most profitable attack on one state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Assessment {
+    /// What it costs to buy the REN for the share of the bond.
+    pub cost: Usd,
+    /// The value that the bond secures, which could be stolen.
+    pub value: Usd,
+    /// The shard that is attacked, if RenVM is sharded.
+    pub shard: Option<usize>,
+}
+
+impl Assessment {
+    /// Assess the cheapest attack (relative to what it steals) on the state.
+    pub fn new(state: &State, config: &AttackConfig) -> Self {
+        let price = state.ren_price.0;
+        // The REN that backs a value bonded in USD, at the REN per USD of the whole bond.
+        let ren = |tvb: Usd| {
+            if state.tvb > Usd::ZERO {
+                config.share * state.tvb_ren.0 * (tvb / state.tvb)
+            } else {
+                0.0
+            }
+        };
+        let assess = |tvb: Usd, tvl: Usd, shard| Self {
+            cost: Usd(cost(ren(tvb), price, &config.depth)),
+            value: tvl,
+            shard,
+        };
+        if state.shards.is_empty() {
+            return assess(state.tvb, state.tvl, None);
+        }
+        state
+            .shards
+            .iter()
+            .enumerate()
+            .map(|(i, shard)| assess(shard.tvb, shard.tvl, Some(i)))
+            .max_by(|a, b| a.margin().0.total_cmp(&b.margin().0))
+            .expect("sharded states have shards")
+    }
+
+    /// What the attacker would gain (the value stolen less the cost), which is negative while the
+    /// attack does not pay.
+    pub fn margin(&self) -> Usd {
+        self.value - self.cost
+    }
+
+    /// Whether the attack pays for itself.
+    pub fn is_profitable(&self) -> bool {
+        self.value > self.cost
+    }
+}
+
+/// The cost (in USD) of buying the given amount of REN at the given price, with the price impact of
+/// the market depth. Buying more than a constant-product pool holds is impossible, so it costs an
+/// infinite amount.
+pub fn cost(ren: f64, price: f64, depth: &MarketDepthConfig) -> f64 {
+    if ren <= 0.0 {
+        return 0.0;
+    }
+    match *depth {
+        MarketDepthConfig::Infinite => ren * price,
+        // The marginal price is p * (1 + p * x / depth) after buying x REN, which integrates to
+        // p * q + p^2 * q^2 / (2 * depth).
+        MarketDepthConfig::Linear { depth } => {
+            ren * price + (ren * price).powi(2) / (2.0 * depth.0)
+        }
+        MarketDepthConfig::ConstantProduct { liquidity } => {
+            let pool = liquidity.0 / price;
+            if ren >= pool {
+                f64::INFINITY
+            } else {
+                liquidity.0 * ren / (pool - ren)
+            }
+        }
+    }
+}
+
+/// Summary tallies the assessments of every epoch of a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// The number of epochs that were assessed.
+    pub epochs: usize,
+    /// The number of epochs in which an attack was profitable.
+    pub profitable: usize,
+    /// The first epoch in which an attack was profitable.
+    pub first: Option<usize>,
+    /// The epoch with the largest margin, and its assessment.
+    pub best: Option<(usize, Assessment)>,
+}
+
+impl Summary {
+    /// Tally the assessment of an epoch.
+    pub fn add(&mut self, epoch: usize, assessment: Assessment) {
+        self.epochs += 1;
+        if assessment.is_profitable() {
+            self.profitable += 1;
+            self.first.get_or_insert(epoch);
+        }
+        let better = self
+            .best
+            .is_none_or(|(_, best)| assessment.margin() > best.margin());
+        if better {
+            self.best = Some((epoch, assessment));
+        }
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.first, self.best) {
+            (Some(first), Some((epoch, best))) => write!(
+                f,
+                "attack: profitable in {} of {} epochs (first at epoch {}), gaining at most {:.2} \
+                 at epoch {}",
+                self.profitable,
+                self.epochs,
+                first,
+                best.margin(),
+                epoch
+            ),
+            (None, Some((epoch, best))) => write!(
+                f,
+                "attack: never profitable in {} epochs, closest at epoch {} (costing {:.2} for \
+                 {:.2})",
+                self.epochs, epoch, best.cost, best.value
+            ),
+            _ => write!(f, "attack: nothing assessed"),
+        }
+    }
+}
+
+/// Log writes the assessment of every epoch as CSV.
+pub struct Log<W: Write> {
+    writer: ::csv::Writer<W>,
+    epoch: EpochConfig,
+}
+
+impl<W: Write> Log<W> {
+    /// Create a log that writes to the given writer, with dates if the epochs have a start date.
+    pub fn new(writer: W, epoch: &EpochConfig) -> Result<Self> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        let mut header = vec!["epoch"];
+        if epoch.start.is_some() {
+            header.push("date");
+        }
+        header.extend(["cost", "value", "margin", "shard", "profitable"]);
+        writer.write_record(header)?;
+        Ok(Self {
+            writer,
+            epoch: *epoch,
+        })
+    }
+
+    /// Write the assessment of an epoch.
+    pub fn write(&mut self, epoch: usize, assessment: &Assessment) -> Result<()> {
+        let mut record = vec![epoch.to_string()];
+        if let Some(date) = self.epoch.date(epoch) {
+            record.push(date.to_string());
+        }
+        record.extend([
+            assessment.cost.0.to_string(),
+            assessment.value.0.to_string(),
+            assessment.margin().0.to_string(),
+            assessment
+                .shard
+                .map_or(String::new(), |shard| shard.to_string()),
+            assessment.is_profitable().to_string(),
+        ]);
+        self.writer.write_record(record)?;
+        Ok(())
+    }
+
+    /// Flush everything that has been written so far.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! For modelling an adversary that works towards an attack rather than buying its way in all at
+//! once (see `attack` for the one-off cost of an attack). From its first epoch, the attacker buys
+//! REN at every epoch boundary and bonds it alongside node operators, so that an attack needs less
+//! REN bought at once (and moves the price of REN less) the longer it waits. At every boundary, it
+//! weighs what it could steal against the REN that it still has to buy (priced as in `attack`) and
+//! the bond that it would lose to slashing, and attacks as soon as that pays. The attack steals
+//! everything that is locked and slashes the whole bond of the attacker, after which the run goes
+//! on with what is left, so that what follows a breach can be studied too.
+
+use crate::attack;
+use crate::config::{AttackConfig, AttackerConfig};
+use crate::state::State;
+use crate::{Ren, Usd};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Attacker is an adversary that accumulates bonds according to an `AttackerConfig`, and attacks
+/// when it pays according to an `AttackConfig`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attacker {
+    config: AttackerConfig,
+    attack: AttackConfig,
+    /// The REN that the attacker has bonded so far.
+    ren: Ren,
+    breach: Option<Breach>,
+}
+
+/// Breach is the attack that the attacker made.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Breach {
+    /// The epoch in which the attack was made.
+    pub epoch: usize,
+    /// What the REN that the attacker bought for the attack cost (on top of what it had bonded).
+    pub cost: Usd,
+    /// The value that was locked, and stolen.
+    pub stolen: Usd,
+    /// The value of the bond of the attacker, which was slashed.
+    pub slashed: Usd,
+}
+
+impl Attacker {
+    /// Create an attacker using the given parameters.
+    pub fn new(config: AttackerConfig, attack: AttackConfig) -> Self {
+        Self {
+            config,
+            attack,
+            ren: Ren::ZERO,
+            breach: None,
+        }
+    }
+
+    /// The REN that the attacker has bonded.
+    pub fn ren(&self) -> Ren {
+        self.ren
+    }
+
+    /// The attack that the attacker made, if it has made one.
+    pub fn breach(&self) -> Option<&Breach> {
+        self.breach.as_ref()
+    }
+
+    /// Bond more REN at an epoch boundary, returning the TVB with the bond of the attacker added to
+    /// the `tvb` of node operators. Between boundaries, the same REN stays bonded (and is already
+    /// part of the TVB). Nothing more is bonded once the attacker has attacked.
+    pub fn bond(&mut self, epoch: usize, boundary: bool, tvb: Usd, ren_price: Usd) -> Usd {
+        if !boundary {
+            return tvb;
+        }
+        if self.breach.is_none() && epoch >= self.config.start && ren_price > Usd::ZERO {
+            self.ren += Ren(self.config.budget / ren_price);
+        }
+        tvb + self.ren * ren_price
+    }
+
+    /// Attack at an epoch boundary if it pays, stealing what is locked in the state and slashing
+    /// the bond of the attacker out of its TVB. The state must already hold the bond of the
+    /// attacker. Returns the attack, if one was made.
+    pub fn attack(&mut self, epoch: usize, boundary: bool, state: &mut State) -> Option<Breach> {
+        if !boundary || self.breach.is_some() || epoch < self.config.start {
+            return None;
+        }
+        let price = state.ren_price;
+        let honest = (state.tvb_ren - self.ren).max(Ren::ZERO);
+        // The attacker needs `share` of everything that is bonded, including what it buys.
+        let share = self.attack.share.clamp(0.0, 1.0);
+        let needed = if share < 1.0 {
+            (honest * (share / (1.0 - share)) - self.ren).max(Ren::ZERO)
+        } else {
+            return None;
+        };
+        let cost = Usd(attack::cost(needed.0, price.0, &self.attack.depth));
+        let slashed = self.ren * price;
+        if state.tvl <= cost + slashed {
+            return None;
+        }
+        let breach = Breach {
+            epoch,
+            cost,
+            stolen: state.tvl,
+            slashed,
+        };
+        tracing::debug!(
+            stolen = breach.stolen.0,
+            slashed = breach.slashed.0,
+            cost = breach.cost.0,
+            "attacked"
+        );
+        state.tvb = (state.tvb - slashed).max(Usd::ZERO);
+        state.tvb_ren = honest;
+        state.tvl = Usd::ZERO;
+        for asset in &mut state.assets {
+            asset.tvl = Usd::ZERO;
+            asset.amount = 0.0;
+        }
+        self.ren = Ren::ZERO;
+        self.breach = Some(breach);
+        self.breach
+    }
+}
+
+impl fmt::Display for Breach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "attacked at epoch {}: stole {:.2}, buying {:.2} of REN and losing a bond of {:.2}",
+            self.epoch, self.stolen, self.cost, self.slashed
+        )
+    }
+}
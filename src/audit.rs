@@ -0,0 +1,79 @@
+//! For explaining surprising trajectories without adding prints. Every epoch, the internal model
+//! records which branch each of its curves took, why, and the inputs that the choice was made on
+//! (like "the rebate increased because TVL-TVB is not below its average over the rebate window"),
+//! and the audit log writes them out as JSON Lines, one decision per line, tagged with its epoch.
+//! The first line is a header record, `{"metadata": ...}`, with the `Metadata` of the run.
+
+use crate::error::Result;
+use crate::output::Metadata;
+use serde_json::{Map, Value};
+use std::io::{BufWriter, Write};
+
+/// Decision is the branch that one curve took in one epoch, and what it was decided on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decision {
+    /// The curve that decided (`mint_fee`, `burn_fee`, or `rebate`).
+    pub curve: &'static str,
+    /// The asset that the curve decided for, if it has its own fees.
+    pub asset: Option<String>,
+    /// The branch that the curve took (e.g. `increase` or `decrease`, for the adaptive rebate).
+    pub branch: &'static str,
+    /// Why the curve took the branch, in words.
+    pub reason: &'static str,
+    /// The value that the curve returned.
+    pub value: f64,
+    /// The inputs that the branch was chosen on, by name.
+    pub inputs: Vec<(&'static str, f64)>,
+}
+
+impl Decision {
+    /// The decision as a JSON object, tagged with the epoch that it was made in.
+    pub fn to_json(&self, epoch: usize) -> Value {
+        let mut object = Map::new();
+        object.insert("epoch".to_string(), epoch.into());
+        object.insert("curve".to_string(), self.curve.into());
+        if let Some(asset) = &self.asset {
+            object.insert("asset".to_string(), asset.as_str().into());
+        }
+        object.insert("branch".to_string(), self.branch.into());
+        object.insert("reason".to_string(), self.reason.into());
+        object.insert("value".to_string(), self.value.into());
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::from(*value)))
+            .collect();
+        object.insert("inputs".to_string(), Value::Object(inputs));
+        Value::Object(object)
+    }
+}
+
+/// Log writes the decisions of every epoch as JSON Lines.
+pub struct Log<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> Log<W> {
+    /// Create a log that writes to the given writer, beginning with the metadata of the run.
+    pub fn new(writer: W, metadata: &Metadata) -> Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer(&mut writer, &serde_json::json!({ "metadata": metadata }))?;
+        writeln!(writer)?;
+        Ok(Self { writer })
+    }
+
+    /// Write the decisions that were made in the given epoch.
+    pub fn write(&mut self, epoch: usize, decisions: &[Decision]) -> Result<()> {
+        for decision in decisions {
+            serde_json::to_writer(&mut self.writer, &decision.to_json(epoch))?;
+            writeln!(self.writer)?;
+        }
+        Ok(())
+    }
+
+    /// Flush everything that has been written so far.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! For replaying what actually happened to RenVM through the internal models, so that proposed fee
+//! and rebate curves can be evaluated against real history instead of synthetic volumes. Historical
+//! data is read from a CSV file with one row per epoch and the columns:
+//!
+//! - `mint_volume` and `burn_volume`: the value (in USD) minted and burned in the epoch,
+//! - `tvl` (optional): the value locked at the end of the epoch,
+//! - `tvb` (optional): the value bonded at the end of the epoch.
+//!
+//! Any other columns (like dates) are ignored. When TVL is given, locked value follows it exactly
+//! (the difference from net minting is treated as revaluation). When TVB is given it is used as is,
+//! otherwise bonding is simulated by the basic external model.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::external::{self, ExternalModel};
+use crate::history::History;
+use crate::internal;
+use crate::registry;
+#[cfg(feature = "script")]
+use crate::script::Script;
+use crate::simulation::Simulation;
+use crate::{SimRng, Usd};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Record is what actually happened in one epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// The value minted in the epoch.
+    pub mint_volume: Usd,
+    /// The value burned in the epoch.
+    pub burn_volume: Usd,
+    /// The value locked at the end of the epoch, if it is known.
+    pub tvl: Option<Usd>,
+    /// The value bonded at the end of the epoch, if it is known.
+    pub tvb: Option<Usd>,
+}
+
+/// Load the historical records from a CSV file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Record>> {
+    let path = path.as_ref();
+    let records = ::csv::Reader::from_path(path)?
+        .deserialize()
+        .collect::<std::result::Result<Vec<Record>, _>>()?;
+    if records.is_empty() {
+        return Err(Error::Config(format!(
+            "{} does not contain any epochs",
+            path.display()
+        )));
+    }
+    Ok(records)
+}
+
+/// Historical is an external model that replays recorded volumes (and, when they are known, TVL and
+/// TVB). Everything that is not recorded falls back to the basic external model. After the last
+/// record, nothing is minted or burned.
+#[derive(Clone, Debug)]
+pub struct Historical {
+    records: Vec<Record>,
+    basic: external::Basic,
+}
+
+impl Historical {
+    /// Create a historical model from the records, using the basic external model (with the
+    /// parameters from the configuration) for everything that is not recorded.
+    pub fn new(records: Vec<Record>, config: &Config) -> Self {
+        Self {
+            records,
+            basic: external::Basic::new(config.external.clone(), &config.epoch)
+                .with_slashing_risk(config.internal.slashing.risk(&config.epoch))
+                .with_prior_income(config.initial.fee_income),
+        }
+    }
+
+    /// The record for the epoch that is about to be simulated.
+    fn record(&self, history: &History) -> Option<&Record> {
+        self.records.get(history.len() - 1)
+    }
+}
+
+impl ExternalModel for Historical {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        // The basic model is always consulted, so that whatever state it keeps stays up to date.
+        let simulated = self.basic.total_value_bonded(history, rng);
+        self.record(history)
+            .and_then(|record| record.tvb)
+            .unwrap_or(simulated)
+    }
+
+    fn mint_volume(&mut self, history: &History, _rng: &mut SimRng) -> Usd {
+        self.record(history)
+            .map_or(Usd::ZERO, |record| record.mint_volume)
+    }
+
+    fn burn_volume(&mut self, history: &History, _rng: &mut SimRng) -> Usd {
+        // Recorded burns already include whatever arbitrage actually happened.
+        self.record(history)
+            .map_or(Usd::ZERO, |record| record.burn_volume)
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        self.basic.ren_price(history, rng)
+    }
+
+    fn darknodes(&mut self, history: &History, rng: &mut SimRng) -> u64 {
+        self.basic.darknodes(history, rng)
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, net: Usd, _rng: &mut SimRng) -> Usd {
+        let tvl = history.latest().tvl;
+        match self.record(history).and_then(|record| record.tvl) {
+            Some(recorded) => recorded - tvl - net,
+            None => Usd::ZERO,
+        }
+    }
+}
+
+/// Create a simulation that replays the records through the basic internal model, using the
+/// parameters from the configuration. Run it for `records.len()` epochs to replay all of history.
+/// A script only replaces functions of the internal model, since the external ones are history, and
+/// likewise only the built-in models that the configuration selects by name are selected.
+pub fn simulation(config: &Config, records: Vec<Record>) -> Simulation {
+    let config = &*registry::resolve(config);
+    let external = Historical::new(records, config);
+    let internal = internal::Basic::new(config.internal.clone(), &config.epoch);
+    #[cfg(feature = "script")]
+    if let Some(script) = Script::from_config(config) {
+        return Simulation::from_models(config, external, script.internal(internal))
+            .with_governance(config);
+    }
+    Simulation::from_models(config, external, internal).with_governance(config)
+}
@@ -0,0 +1,528 @@
+//! For simulating many runs of the same structure at once, which is what large sweeps are made of.
+//! A batch advances every run (a lane) in lockstep, one epoch at a time, over columns that hold
+//! each field of the state for every lane side by side, rather than over a history of states per
+//! run. The models are called directly rather than through the model traits, nothing is traced,
+//! and the only history that is kept is the columns that the windows of the models look back over.
+//!
+//! Only configurations whose mechanics a batch implements can be simulated this way (see
+//! `supports`): the aggregate external model without assets, shocks, regimes, replays,
+//! transactions, gas, a competitor, a peg, demand for REN that responds to income, or an attacker,
+//! with fixed claims and the basic arbitrage, and the basic internal model with constant fees and
+//! shares and without a controller, a mint cap, transaction fees, a buy-and-burn, a capped rebate
+//! pool, slashing, a subsidy, shards, governance, a script, or models selected by name, accounted
+//! in floating point with one epoch between boundaries, the default order of the stages of an
+//! epoch, an unbounded history, and no stop conditions. Every lane then follows exactly the same
+//! path (bit for bit) as a `Simulation` of the same configuration and seed, so a sweep can use a
+//! batch whenever every cell is supported, and fall back to independent runs otherwise.
+
+use crate::config::{
+    AccountingConfig, ArbitrageConfig, ClaimCurve, Config, ModelsConfig, RebateCurve,
+};
+use crate::curve::Curve;
+use crate::error::Result;
+use crate::external::bonding::Bonding;
+use crate::external::price::Price;
+use crate::external::volume::{Demand, Volume};
+use crate::history::Aggregates;
+use crate::invariants::{Invariants, Violation};
+use crate::metrics::Outcome;
+use crate::shards::Shards;
+use crate::slashing::Slashing;
+use crate::state::State;
+use crate::subsidy::Subsidy;
+use crate::{Pct, Ren, SimRng, Usd};
+use rand::SeedableRng;
+
+/// Whether a batch can simulate the configuration exactly as a `Simulation` would.
+pub fn supports(config: &Config) -> bool {
+    let (external, internal) = (&config.external, &config.internal);
+    let constant = |curve: &Curve| matches!(curve, Curve::Constant(_));
+    config.epoch.boundary() == 1
+        && config.governance.is_empty()
+        && config.stop.is_empty()
+        && config.pipeline.is_default()
+        && config.history.window_days.is_none()
+        && !config.health.abort
+        && config.script.path.is_none()
+        && config.script.source.is_none()
+        && config.models == ModelsConfig::default()
+        && matches!(config.accounting, AccountingConfig::Float)
+        && external.assets.is_empty()
+        && external.shocks.is_empty()
+        && external.regimes.is_empty()
+        && !external.replay.is_enabled()
+        && external.transactions.is_none()
+        && external.gas.is_none()
+        && external.competitor.is_none()
+        && external.peg.is_none()
+        && external.ren_demand.is_none()
+        && matches!(external.claim_curve, ClaimCurve::Fixed)
+        && matches!(external.arbitrage, ArbitrageConfig::Basic)
+        && external.attacker.is_none()
+        && constant(&internal.mint_fee)
+        && constant(&internal.burn_fee)
+        && constant(&internal.rebate_share)
+        && constant(&internal.treasury_share)
+        && internal.buyback_share == Curve::Constant(Pct::ZERO)
+        && internal.rebate_pool.max.is_none()
+        && internal.fee_controller.is_none()
+        && internal.mint_cap.is_none()
+        && internal.transaction_fee.is_none()
+        && Slashing::new(internal.slashing, external.operating_cost.bond).is_none()
+        && Subsidy::new(internal.subsidy, &config.epoch).is_none()
+        && Shards::new(&internal.shards).is_none()
+}
+
+/// The value of a constant curve (every curve of a supported configuration is constant).
+fn constant(curve: &Curve) -> Pct {
+    match curve {
+        Curve::Constant(value) => *value,
+        Curve::Expression(_) => unreachable!("batches only support constant curves"),
+    }
+}
+
+/// Lane is everything about one run that is not in the state: its models, its parameters, and its
+/// random number generator.
+struct Lane {
+    rng: SimRng,
+    mint: Volume,
+    burn: Volume,
+    mint_demand: Demand,
+    burn_demand: Demand,
+    ren_price: Price,
+    bonding: Bonding,
+    prior_income: Usd,
+    roi_window: usize,
+    claim_rate: Pct,
+    arbitrage_rebate: Pct,
+    arbitrage_volume: Usd,
+    mint_fee: Pct,
+    burn_fee: Pct,
+    rebate_curve: RebateCurve,
+    rebate_step: Pct,
+    rebate_window: usize,
+    rebate_decay: f64,
+    rebate_share: Pct,
+    treasury_share: Pct,
+    epochs_per_year: f64,
+    bond: Ren,
+    invariants: Invariants,
+    aggregates: Aggregates,
+    /// The first invariant that the lane broke, after which it stops.
+    violation: Option<Violation>,
+}
+
+impl Lane {
+    /// The lane of a supported configuration, mirroring how `Simulation::from_config` sets up the
+    /// basic models.
+    fn new(config: &Config, seed: u64) -> Self {
+        let (external, internal, epoch) = (&config.external, &config.internal, &config.epoch);
+        let mut bonding = Bonding::new(external.bonding.clone(), external.target_roi)
+            .with_operating_cost(
+                external.operating_cost.per_year(epoch),
+                external.operating_cost.bond,
+            );
+        if external.lifecycle {
+            bonding = bonding.with_lifecycle();
+        }
+        let rebate_decay = match internal.rebate_curve {
+            RebateCurve::Decay { half_life_days, .. } if half_life_days > 0.0 => {
+                0.5f64.powf(epoch.days / half_life_days)
+            }
+            _ => 0.0,
+        };
+        Self {
+            rng: SimRng::seed_from_u64(seed),
+            mint: Volume::new(external.mint_volume),
+            burn: Volume::new(external.burn_volume),
+            mint_demand: Demand::new(external.mint_demand),
+            burn_demand: Demand::new(external.burn_demand),
+            ren_price: Price::new(external.ren_price),
+            bonding: bonding.with_risk_premium(internal.slashing.risk(epoch)),
+            prior_income: config.initial.fee_income,
+            roi_window: epoch.epochs(external.roi_window_days),
+            claim_rate: external.claim_rate,
+            arbitrage_rebate: external.arbitrage_rebate,
+            arbitrage_volume: external.arbitrage_volume,
+            mint_fee: constant(&internal.mint_fee),
+            burn_fee: constant(&internal.burn_fee),
+            rebate_curve: internal.rebate_curve.clone(),
+            rebate_step: internal.rebate_step,
+            rebate_window: epoch.epochs(internal.rebate_window_days),
+            rebate_decay,
+            rebate_share: constant(&internal.rebate_share),
+            treasury_share: constant(&internal.treasury_share),
+            epochs_per_year: epoch.per_year(),
+            bond: external.bonding.bond(external.operating_cost.bond),
+            invariants: Invariants::new(&config.invariants),
+            aggregates: Aggregates::new(
+                &config.initial.state(),
+                config.warmup,
+                config.security.required_ratio,
+            ),
+            violation: None,
+        }
+    }
+}
+
+/// Columns hold every field of the latest state, one element per lane.
+#[derive(Default)]
+struct Columns {
+    tvb: Vec<Usd>,
+    tvb_ren: Vec<Ren>,
+    ren_price: Vec<Usd>,
+    darknodes: Vec<u64>,
+    tvl: Vec<Usd>,
+    tvr: Vec<Usd>,
+    security_ratio: Vec<f64>,
+    mf: Vec<Pct>,
+    bf: Vec<Pct>,
+    r: Vec<Pct>,
+    f_unclaimed: Vec<Usd>,
+    f_claimed: Vec<Usd>,
+    r_pool: Vec<Usd>,
+    treasury: Vec<Usd>,
+    buyback: Vec<Usd>,
+    ren_burned: Vec<Ren>,
+    ren_emitted: Vec<Ren>,
+    mint_volume: Vec<Usd>,
+    burn_volume: Vec<Usd>,
+    implied_darknodes: Vec<f64>,
+    fee_apy: Vec<f64>,
+    net_flow: Vec<Usd>,
+    runway: Vec<f64>,
+}
+
+impl Columns {
+    /// Add a lane that begins from the given state.
+    fn push(&mut self, state: &State) {
+        self.tvb.push(state.tvb);
+        self.tvb_ren.push(state.tvb_ren);
+        self.ren_price.push(state.ren_price);
+        self.darknodes.push(state.darknodes);
+        self.tvl.push(state.tvl);
+        self.tvr.push(state.tvr);
+        self.security_ratio.push(state.security_ratio);
+        self.mf.push(state.mf);
+        self.bf.push(state.bf);
+        self.r.push(state.r);
+        self.f_unclaimed.push(state.f_unclaimed);
+        self.f_claimed.push(state.f_claimed);
+        self.r_pool.push(state.r_pool);
+        self.treasury.push(state.treasury);
+        self.buyback.push(state.buyback);
+        self.ren_burned.push(state.ren_burned);
+        self.ren_emitted.push(state.ren_emitted);
+        self.mint_volume.push(state.mint_volume);
+        self.burn_volume.push(state.burn_volume);
+        self.implied_darknodes.push(state.implied_darknodes);
+        self.fee_apy.push(state.fee_apy);
+        self.net_flow.push(state.net_flow);
+        self.runway.push(state.runway);
+    }
+
+    /// Write a state into the given lane.
+    fn set(&mut self, i: usize, state: &State) {
+        self.tvb[i] = state.tvb;
+        self.tvb_ren[i] = state.tvb_ren;
+        self.ren_price[i] = state.ren_price;
+        self.darknodes[i] = state.darknodes;
+        self.tvl[i] = state.tvl;
+        self.tvr[i] = state.tvr;
+        self.security_ratio[i] = state.security_ratio;
+        self.mf[i] = state.mf;
+        self.bf[i] = state.bf;
+        self.r[i] = state.r;
+        self.f_unclaimed[i] = state.f_unclaimed;
+        self.f_claimed[i] = state.f_claimed;
+        self.r_pool[i] = state.r_pool;
+        self.treasury[i] = state.treasury;
+        self.buyback[i] = state.buyback;
+        self.ren_burned[i] = state.ren_burned;
+        self.ren_emitted[i] = state.ren_emitted;
+        self.mint_volume[i] = state.mint_volume;
+        self.burn_volume[i] = state.burn_volume;
+        self.implied_darknodes[i] = state.implied_darknodes;
+        self.fee_apy[i] = state.fee_apy;
+        self.net_flow[i] = state.net_flow;
+        self.runway[i] = state.runway;
+    }
+
+    /// The latest state of the given lane.
+    fn get(&self, i: usize) -> State {
+        State {
+            tvb: self.tvb[i],
+            tvb_ren: self.tvb_ren[i],
+            ren_price: self.ren_price[i],
+            darknodes: self.darknodes[i],
+            tvl: self.tvl[i],
+            tvr: self.tvr[i],
+            security_ratio: self.security_ratio[i],
+            mf: self.mf[i],
+            bf: self.bf[i],
+            r: self.r[i],
+            f_unclaimed: self.f_unclaimed[i],
+            f_claimed: self.f_claimed[i],
+            r_pool: self.r_pool[i],
+            treasury: self.treasury[i],
+            buyback: self.buyback[i],
+            ren_burned: self.ren_burned[i],
+            ren_emitted: self.ren_emitted[i],
+            mint_volume: self.mint_volume[i],
+            burn_volume: self.burn_volume[i],
+            implied_darknodes: self.implied_darknodes[i],
+            fee_apy: self.fee_apy[i],
+            net_flow: self.net_flow[i],
+            runway: self.runway[i],
+            ..State::default()
+        }
+    }
+}
+
+/// Batch simulates a set of supported configurations in lockstep, every one of them with the same
+/// seed (as the cells of a sweep are).
+pub struct Batch {
+    lanes: Vec<Lane>,
+    columns: Columns,
+    /// The fees claimed by every lane at the end of every epoch so far, epoch by epoch, for the
+    /// ROI window of node operators.
+    claimed: Vec<Usd>,
+    /// How far TVL exceeded TVB in every lane at the end of every epoch so far, epoch by epoch, for
+    /// the window of the adaptive rebate.
+    gaps: Vec<Usd>,
+    /// The number of states in the history of every lane, including the initial state.
+    len: usize,
+}
+
+impl Batch {
+    /// Create a batch with one lane for every configuration, each beginning from its initial
+    /// state, or `None` if a batch cannot simulate any of them.
+    pub fn new(configs: &[Config], seed: u64) -> Option<Self> {
+        if !configs.iter().all(supports) {
+            return None;
+        }
+        let mut batch = Self {
+            lanes: configs
+                .iter()
+                .map(|config| Lane::new(config, seed))
+                .collect(),
+            columns: Columns::default(),
+            claimed: vec![],
+            gaps: vec![],
+            len: 1,
+        };
+        for config in configs {
+            let state = config.initial.state();
+            batch.claimed.push(state.f_claimed);
+            batch.gaps.push(state.tvl - state.tvb);
+            batch.columns.push(&state);
+        }
+        Some(batch)
+    }
+
+    /// The number of lanes.
+    pub fn lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// The number of epochs that have been simulated so far.
+    pub fn epoch(&self) -> usize {
+        self.len - 1
+    }
+
+    /// Advance every lane that has not broken an invariant by one epoch.
+    pub fn step(&mut self) {
+        let n = self.lanes.len();
+        let len = self.len;
+        for i in 0..n {
+            if self.lanes[i].violation.is_some() {
+                // Its columns are left as they were, so that the rest of the lanes keep theirs.
+                self.claimed.push(self.columns.f_claimed[i]);
+                self.gaps.push(self.columns.tvl[i] - self.columns.tvb[i]);
+                continue;
+            }
+            let previous = self.columns.get(i);
+            let lane = &mut self.lanes[i];
+            let state = advance(lane, &previous, len, n, i, &self.claimed, &self.gaps);
+            self.claimed.push(state.f_claimed);
+            self.gaps.push(state.tvl - state.tvb);
+            lane.aggregates.add(len, &state);
+            lane.violation = lane.invariants.rules().iter().find_map(|rule| {
+                rule.check(&state).map(|message| {
+                    tracing::warn!(rule = rule.name(), epoch = len, %message, "invariant violated");
+                    Violation {
+                        rule: *rule,
+                        epoch: len,
+                        message,
+                        previous: Some(previous.clone()),
+                        state: state.clone(),
+                    }
+                })
+            });
+            self.columns.set(i, &state);
+        }
+        self.len += 1;
+    }
+
+    /// Advance every lane by `n` epochs, stopping lanes at the first invariant that they break.
+    pub fn run(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step();
+        }
+    }
+
+    /// The outcome of every lane, in the same order as the configurations, or the first invariant
+    /// that it broke.
+    pub fn outcomes(self) -> Vec<Result<Outcome>> {
+        self.lanes
+            .into_iter()
+            .map(|lane| match lane.violation {
+                Some(violation) => Err(violation.into()),
+                None => Ok(Outcome::from_aggregates(&lane.aggregates)),
+            })
+            .collect()
+    }
+}
+
+/// Simulate the next epoch of one lane from its previous state, where the history holds `len`
+/// states (so the new state is the one at epoch `len`), and `claimed` and `gaps` hold the columns
+/// of every earlier epoch for all `n` lanes. This is `Simulation::step` with everything that a
+/// supported configuration leaves out taken out, and the calls into the basic models inlined, in
+/// the same order and with the same arithmetic.
+fn advance(
+    lane: &mut Lane,
+    previous: &State,
+    len: usize,
+    n: usize,
+    i: usize,
+    claimed: &[Usd],
+    gaps: &[Usd],
+) -> State {
+    let r = rebate(lane, previous, len, n, i, gaps);
+    let rng = &mut lane.rng;
+    let mut state = previous.clone();
+    let fee = |fee: Pct| (len > 1).then_some(fee);
+
+    // Mint and burn volumes, with whatever arbitrageurs route through RenVM for the rebate.
+    let mv = lane.mint.sample(len - 1, rng) * lane.mint_demand.scale(fee(previous.mf));
+    let arbitrage = if previous.r >= lane.arbitrage_rebate {
+        (previous.r_pool / previous.r)
+            .min(lane.arbitrage_volume * (previous.r / lane.arbitrage_rebate))
+    } else {
+        Usd::ZERO
+    };
+    let bv = lane.burn.sample(len - 1, rng) * lane.burn_demand.scale(fee(previous.bf)) + arbitrage;
+    let bv = bv.min((previous.tvl + mv).max(Usd::ZERO));
+
+    // Fees, and the rebate.
+    let mf = lane.mint_fee;
+    let bf = if previous.tvl < previous.tvb {
+        lane.burn_fee
+    } else {
+        Pct::ZERO
+    };
+    let mut minted = Usd::ZERO + mv * mf;
+    let burned = Usd::ZERO + bv * bf;
+    let f_collected = minted + burned;
+    let t_collected = f_collected * lane.treasury_share;
+    if t_collected != Usd::ZERO {
+        minted += -t_collected * Pct::ALL;
+        state.treasury += t_collected;
+    }
+    let b_collected = f_collected * Pct::ZERO;
+    if b_collected != Usd::ZERO {
+        minted += -b_collected * Pct::ALL;
+        state.buyback += b_collected;
+    }
+    let r_collected = (f_collected - t_collected - b_collected) * lane.rebate_share;
+
+    // Bonds, at the new price of REN, for what node operators claimed over their window.
+    state.ren_price = lane.ren_price.next(rng);
+    if b_collected != Usd::ZERO && state.ren_price > Usd::ZERO {
+        state.ren_burned += Ren(b_collected / state.ren_price);
+    }
+    let window = lane.roi_window;
+    let simulated = (len - 1).min(window);
+    let per_annum = ((1..len)
+        .rev()
+        .take(window)
+        .map(|epoch| claimed[epoch * n + i] - claimed[(epoch - 1) * n + i])
+        .sum::<Usd>()
+        + lane.prior_income * (window - simulated) as f64)
+        / window as f64
+        * lane.epochs_per_year;
+    if len == 1 {
+        let price = lane.ren_price.current();
+        lane.bonding.start(previous.tvb, previous.darknodes, price);
+    }
+    let price = lane.ren_price.current();
+    state.tvb = lane.bonding.bond(per_annum, price, Pct::ZERO, rng);
+    state.darknodes = lane.bonding.darknodes();
+    state.tvb_ren = if state.ren_price > Usd::ZERO {
+        Ren(state.tvb / state.ren_price)
+    } else {
+        Ren::ZERO
+    };
+    // Without shocks or regimes, nothing revalues TVL (but it is still added, as it is by the
+    // basic model, so that the sum rounds the same way).
+    let revaluation = Usd::ZERO;
+    state.tvl = (state.tvl + (mv - bv + revaluation)).max(Usd::ZERO);
+    state.security_ratio = if state.tvl > Usd::ZERO {
+        state.tvb / state.tvl
+    } else {
+        0.0
+    };
+    state.mf = mf;
+    state.bf = bf;
+    state.r = r;
+
+    // Settle the fees into the balances, as the floating point ledger does.
+    let f_unpaid = minted + burned - r_collected;
+    let r_paid = bv * r;
+    let claim = state.f_unclaimed * lane.claim_rate;
+    state.tvr += r_collected;
+    state.f_unclaimed += f_unpaid - claim;
+    state.f_claimed += claim;
+    state.r_pool = (state.r_pool + r_collected - r_paid).max(Usd::ZERO);
+    state.mint_volume = mv;
+    state.burn_volume = bv;
+    state.derive(previous, lane.epochs_per_year, lane.bond);
+    state
+}
+
+/// The rebate of one lane for the next epoch, as the basic internal model decides it.
+fn rebate(lane: &Lane, previous: &State, len: usize, n: usize, i: usize, gaps: &[Usd]) -> Pct {
+    if previous.tvb >= previous.tvl {
+        return Pct::ZERO;
+    }
+    let gap = (previous.tvl - previous.tvb) / previous.tvl;
+    match &lane.rebate_curve {
+        RebateCurve::Adaptive => {
+            let window = lane.rebate_window;
+            let average = (0..len)
+                .rev()
+                .take(window)
+                .map(|epoch| gaps[epoch * n + i])
+                .sum::<Usd>()
+                / window as f64;
+            if previous.tvl - previous.tvb < average {
+                (previous.r - lane.rebate_step).max(Pct::ZERO)
+            } else {
+                previous.r + lane.rebate_step
+            }
+        }
+        RebateCurve::Linear { slope, max } => Pct((slope * gap).clamp(0.0, max.0.max(0.0))),
+        RebateCurve::Sigmoid {
+            max,
+            midpoint,
+            steepness,
+        } => *max / (1.0 + (-steepness * (gap - midpoint)).exp()),
+        RebateCurve::Step { steps } => steps
+            .iter()
+            .filter(|step| step.gap <= gap)
+            .max_by(|a, b| a.gap.total_cmp(&b.gap))
+            .map_or(Pct::ZERO, |step| step.rebate),
+        RebateCurve::Decay { initial, .. } if previous.r == Pct::ZERO => *initial,
+        RebateCurve::Decay { .. } => previous.r * lane.rebate_decay,
+    }
+}
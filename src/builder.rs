@@ -0,0 +1,399 @@
+//! For quick experiments that change one model function without defining a model. A
+//! `SimulationBuilder` begins from a configuration (the default one, unless it is given another),
+//! and any of the main functions of its models can be replaced by a closure of the history:
+//!
+//! ```
+//! # use eco::{Pct, Simulation, Usd};
+//! let mut sim = Simulation::builder()
+//!     .seed(1)
+//!     .mint_volume(|history| Usd(4_000_000.0) * (1.0 + 0.01 * history.len() as f64))
+//!     .rebate_curve(|_| Pct::ZERO)
+//!     .build();
+//! sim.run(10);
+//! ```
+//!
+//! Every other function is left to the models of the configuration (including its script and the
+//! models that it selects by name, when it has them), and the replacements stay in effect when
+//! governance changes replace the internal model. The closures are not given the random number
+//! generator, so anything random belongs in a model (or a registered model, see `eco::registry`).
+//! Replacing the mint or burn volume replaces its transactions with one transaction of the
+//! whole volume, and replacing a fee replaces the fee of every asset too, but the volumes of
+//! individual assets are always left to the model.
+
+use crate::audit::Decision;
+use crate::config::Config;
+use crate::error::Result;
+use crate::external::ExternalModel;
+use crate::history::History;
+use crate::internal::InternalModel;
+use crate::registry::{self, Constructed, Slot};
+#[cfg(feature = "script")]
+use crate::script::Script;
+use crate::simulation::{self, Simulation};
+use crate::state::ControllerState;
+use crate::{Pct, SimRng, Usd};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A closure that replaces a model function. Closures are shared, so that the internal models that
+/// governance puts in effect use the same ones.
+type Closure<T> = Rc<RefCell<dyn FnMut(&History, &mut SimRng) -> T>>;
+
+/// The closure that replaces a model function, if it is replaced.
+type Replacement<T> = Option<Closure<T>>;
+
+/// SimulationBuilder builds a simulation whose model functions can be replaced by closures (see
+/// `Simulation::builder`).
+pub struct SimulationBuilder {
+    config: Config,
+    replacements: Replacements,
+}
+
+/// The closures that replace model functions, where they are given.
+#[derive(Clone, Default)]
+struct Replacements {
+    total_value_bonded: Replacement<Usd>,
+    mint_volume: Replacement<Usd>,
+    burn_volume: Replacement<Usd>,
+    ren_price: Replacement<Usd>,
+    claim_curve: Replacement<Pct>,
+    mint_fee_curve: Replacement<Pct>,
+    burn_fee_curve: Replacement<Pct>,
+    rebate_curve: Replacement<Pct>,
+}
+
+impl SimulationBuilder {
+    /// Create a builder that begins from the default configuration.
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            replacements: Replacements::default(),
+        }
+    }
+
+    /// Begin from the given configuration instead.
+    pub fn config(mut self, config: &Config) -> Self {
+        self.config = config.clone();
+        self
+    }
+
+    /// Seed the random number generator of the simulation.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// Replace `ExternalModel::total_value_bonded`. The model is still consulted, so that whatever
+    /// it keeps about bonding stays up to date.
+    pub fn total_value_bonded(mut self, f: impl FnMut(&History) -> Usd + 'static) -> Self {
+        self.replacements.total_value_bonded = Some(of_history(f));
+        self
+    }
+
+    /// Replace `ExternalModel::mint_volume`.
+    pub fn mint_volume(mut self, f: impl FnMut(&History) -> Usd + 'static) -> Self {
+        self.replacements.mint_volume = Some(of_history(f));
+        self
+    }
+
+    /// Replace `ExternalModel::burn_volume`.
+    pub fn burn_volume(mut self, f: impl FnMut(&History) -> Usd + 'static) -> Self {
+        self.replacements.burn_volume = Some(of_history(f));
+        self
+    }
+
+    /// Replace `ExternalModel::ren_price`. The model is still consulted, so that a model that bonds
+    /// in REN keeps pricing it.
+    pub fn ren_price(mut self, f: impl FnMut(&History) -> Usd + 'static) -> Self {
+        self.replacements.ren_price = Some(of_history(f));
+        self
+    }
+
+    /// Replace `ExternalModel::claim_curve`.
+    pub fn claim_curve(mut self, f: impl FnMut(&History) -> Pct + 'static) -> Self {
+        self.replacements.claim_curve = Some(of_history(f));
+        self
+    }
+
+    /// Replace `InternalModel::mint_fee_curve` (and the minting fee of every asset).
+    pub fn mint_fee_curve(mut self, f: impl FnMut(&History) -> Pct + 'static) -> Self {
+        self.replacements.mint_fee_curve = Some(of_history(f));
+        self
+    }
+
+    /// Replace `InternalModel::burn_fee_curve` (and the burning fee of every asset).
+    pub fn burn_fee_curve(mut self, f: impl FnMut(&History) -> Pct + 'static) -> Self {
+        self.replacements.burn_fee_curve = Some(of_history(f));
+        self
+    }
+
+    /// Replace `InternalModel::rebate_curve`.
+    pub fn rebate_curve(mut self, f: impl FnMut(&History) -> Pct + 'static) -> Self {
+        self.replacements.rebate_curve = Some(of_history(f));
+        self
+    }
+
+    /// Build the simulation, like `Simulation::from_config` with the replaced functions. Closures
+    /// take precedence over the registered models that the configuration selects.
+    pub fn build(self) -> Simulation {
+        let config = &*registry::resolve(&self.config);
+        let mut replacements = self.replacements;
+        for (slot, model) in registry::construct(config) {
+            replacements.insert(slot, model);
+        }
+        let (external, internal) = simulation::basic_models(config);
+        #[cfg(feature = "script")]
+        let script = Script::from_config(config);
+        #[cfg(feature = "script")]
+        let sim = match &script {
+            Some(script) => Simulation::from_models(
+                config,
+                replacements.wrap(script.external(external)),
+                replacements.wrap(script.internal(internal)),
+            ),
+            None => Simulation::from_models(
+                config,
+                replacements.wrap(external),
+                replacements.wrap(internal),
+            ),
+        };
+        #[cfg(not(feature = "script"))]
+        let sim = Simulation::from_models(
+            config,
+            replacements.wrap(external),
+            replacements.wrap(internal),
+        );
+        simulation::governance(config)
+            .into_iter()
+            .fold(sim, |sim, (epoch, model)| {
+                #[cfg(feature = "script")]
+                if let Some(script) = &script {
+                    return sim
+                        .with_internal_from(epoch, replacements.wrap(script.internal(model)));
+                }
+                sim.with_internal_from(epoch, replacements.wrap(model))
+            })
+    }
+}
+
+impl Default for SimulationBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Replacements {
+    /// Replace the function of a slot with a registered model, unless a closure already replaces
+    /// it.
+    fn insert(&mut self, slot: Slot, model: Constructed) {
+        match model {
+            Constructed::Usd(f) => {
+                let replacement = match slot {
+                    Slot::Tvb => &mut self.total_value_bonded,
+                    Slot::MintVolume => &mut self.mint_volume,
+                    Slot::BurnVolume => &mut self.burn_volume,
+                    Slot::RenPrice => &mut self.ren_price,
+                    _ => return,
+                };
+                replacement.get_or_insert_with(|| closure(f));
+            }
+            Constructed::Pct(f) => {
+                let replacement = match slot {
+                    Slot::Claim => &mut self.claim_curve,
+                    Slot::MintFee => &mut self.mint_fee_curve,
+                    Slot::BurnFee => &mut self.burn_fee_curve,
+                    Slot::Rebate => &mut self.rebate_curve,
+                    _ => return,
+                };
+                replacement.get_or_insert_with(|| closure(f));
+            }
+        }
+    }
+
+    /// Replace the functions of the given model.
+    fn wrap<M>(&self, inner: M) -> Replaced<M> {
+        Replaced {
+            replacements: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// Replaced is a model whose functions are replaced by closures, where they are given.
+struct Replaced<M> {
+    replacements: Replacements,
+    inner: M,
+}
+
+/// Share a model function.
+fn closure<T>(f: impl FnMut(&History, &mut SimRng) -> T + 'static) -> Closure<T> {
+    Rc::new(RefCell::new(f))
+}
+
+/// Share a closure of the history as a model function.
+fn of_history<T>(mut f: impl FnMut(&History) -> T + 'static) -> Closure<T> {
+    closure(move |history: &History, _: &mut SimRng| f(history))
+}
+
+/// Call the closure that replaces a function.
+fn call<T>(replacement: &Closure<T>, history: &History, rng: &mut SimRng) -> T {
+    (replacement.borrow_mut())(history, rng)
+}
+
+impl<E: ExternalModel> ExternalModel for Replaced<E> {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let bonded = self.inner.total_value_bonded(history, rng);
+        match &self.replacements.total_value_bonded {
+            Some(f) => call(f, history, rng),
+            None => bonded,
+        }
+    }
+
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        match &self.replacements.mint_volume {
+            Some(f) => call(f, history, rng),
+            None => self.inner.mint_volume(history, rng),
+        }
+    }
+
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        match &self.replacements.burn_volume {
+            Some(f) => call(f, history, rng),
+            None => self.inner.burn_volume(history, rng),
+        }
+    }
+
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        match &self.replacements.mint_volume {
+            Some(f) => vec![call(f, history, rng)],
+            None => self.inner.mint_transactions(history, rng),
+        }
+    }
+
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        match &self.replacements.burn_volume {
+            Some(f) => vec![call(f, history, rng)],
+            None => self.inner.burn_transactions(history, rng),
+        }
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let price = self.inner.ren_price(history, rng);
+        match &self.replacements.ren_price {
+            Some(f) => call(f, history, rng),
+            None => price,
+        }
+    }
+
+    fn darknodes(&mut self, history: &History, rng: &mut SimRng) -> u64 {
+        self.inner.darknodes(history, rng)
+    }
+
+    fn assets(&self) -> Vec<String> {
+        self.inner.assets()
+    }
+
+    fn asset_mint_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.inner.asset_mint_volume(history, asset, rng)
+    }
+
+    fn asset_burn_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.inner.asset_burn_volume(history, asset, rng)
+    }
+
+    fn asset_price(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.inner.asset_price(history, asset, rng)
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, net: Usd, rng: &mut SimRng) -> Usd {
+        self.inner.tvl_revaluation(history, net, rng)
+    }
+
+    fn claim_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        match &self.replacements.claim_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.claim_curve(history, rng),
+        }
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        self.inner.checkpoint()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore(state)
+    }
+}
+
+impl<I: InternalModel> InternalModel for Replaced<I> {
+    fn mint_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        match &self.replacements.mint_fee_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.mint_fee_curve(history, rng),
+        }
+    }
+
+    fn burn_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        match &self.replacements.burn_fee_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.burn_fee_curve(history, rng),
+        }
+    }
+
+    fn rebate_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        match &self.replacements.rebate_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.rebate_curve(history, rng),
+        }
+    }
+
+    fn rebate_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        self.inner.rebate_collected(history, f, rng)
+    }
+
+    fn treasury_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        self.inner.treasury_collected(history, f, rng)
+    }
+
+    fn buyback_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        self.inner.buyback_collected(history, f, rng)
+    }
+
+    fn mint_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        self.inner.mint_transaction_fee(size, fee)
+    }
+
+    fn burn_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        self.inner.burn_transaction_fee(size, fee)
+    }
+
+    fn asset_mint_fee_curve(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Pct {
+        match &self.replacements.mint_fee_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.asset_mint_fee_curve(history, asset, rng),
+        }
+    }
+
+    fn asset_burn_fee_curve(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Pct {
+        match &self.replacements.burn_fee_curve {
+            Some(f) => call(f, history, rng),
+            None => self.inner.asset_burn_fee_curve(history, asset, rng),
+        }
+    }
+
+    fn controller(&self) -> Option<ControllerState> {
+        self.inner.controller()
+    }
+
+    fn decisions(&mut self) -> Vec<Decision> {
+        self.inner.decisions()
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        self.inner.checkpoint()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore(state)
+    }
+}
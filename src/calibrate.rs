@@ -0,0 +1,251 @@
+//! For fitting the free parameters of the external models to historical data (in the same CSV
+//! format as backtests), so that forward simulations start from behaviour that has actually been
+//! observed. Calibration happens in two parts:
+//!
+//! - Mint and burn volumes are fitted directly, as lognormal volumes whose mean, volatility, and
+//!   drift minimise the squared error of the log of the recorded volumes. Recorded burns already
+//!   include arbitrage, so the fitted burn volume overstates the burns that happen without it.
+//! - Behavioural parameters (by default, the target ROI of node operators and the claim rate) are
+//!   fitted by replaying the recorded volumes and TVL and minimising the relative error between
+//!   the simulated and recorded TVB. This needs the `tvb` column, and is skipped without it.
+
+use crate::backtest::{self, Record};
+use crate::config::{Config, VolumeConfig};
+use crate::error::{Error, Result};
+use crate::optim;
+use crate::Usd;
+use std::io::Write;
+
+/// The behavioural parameters that are fitted when no others are given.
+pub const DEFAULT_PARAMETERS: [&str; 2] = ["external.target_roi", "external.claim_rate"];
+
+/// Fitted is the calibrated value of one behavioural parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fitted {
+    /// The dotted path of the parameter in the configuration.
+    pub path: String,
+    /// The value before calibration.
+    pub initial: f64,
+    /// The calibrated value.
+    pub value: f64,
+}
+
+/// Calibration is the calibrated configuration, and how well it fits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    /// The configuration with every calibrated parameter replaced.
+    pub config: Config,
+    /// The fitted mint volume, if there were enough epochs with positive volume to fit it.
+    pub mint_volume: Option<VolumeConfig>,
+    /// The fitted burn volume, if there were enough epochs with positive volume to fit it.
+    pub burn_volume: Option<VolumeConfig>,
+    /// The fitted behavioural parameters (empty when there is no recorded TVB).
+    pub parameters: Vec<Fitted>,
+    /// The root mean squared relative error of the simulated TVB before calibration.
+    pub initial_error: Option<f64>,
+    /// The root mean squared relative error of the simulated TVB after calibration.
+    pub error: Option<f64>,
+    /// The number of simulations that were run.
+    pub evaluations: usize,
+}
+
+impl Calibration {
+    /// Write a human readable description of the calibration.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        for (name, volume) in [
+            ("mint_volume", self.mint_volume),
+            ("burn_volume", self.burn_volume),
+        ] {
+            match volume {
+                Some(VolumeConfig::Lognormal {
+                    mean,
+                    volatility,
+                    drift,
+                }) => writeln!(
+                    writer,
+                    "{:<24} mean={:.2} volatility={:.4} drift={:.6}",
+                    name, mean, volatility, drift
+                )?,
+                _ => writeln!(writer, "{:<24} not enough data to fit", name)?,
+            }
+        }
+        for fitted in &self.parameters {
+            writeln!(
+                writer,
+                "{:<24} {} -> {}",
+                fitted.path, fitted.initial, fitted.value
+            )?;
+        }
+        match (self.initial_error, self.error) {
+            (Some(initial), Some(error)) => writeln!(
+                writer,
+                "tvb error (rms relative) {:.4} -> {:.4} after {} runs",
+                initial, error, self.evaluations
+            )?,
+            _ => writeln!(writer, "no recorded tvb, behavioural parameters not fitted")?,
+        }
+        Ok(())
+    }
+}
+
+/// Fit a lognormal volume to the recorded volumes, where the first record is epoch zero. Epochs
+/// with no volume are left out, and at least three must remain.
+pub fn fit_volume(volumes: &[Usd]) -> Option<VolumeConfig> {
+    let points: Vec<(f64, f64)> = volumes
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v > Usd::ZERO)
+        .map(|(t, v)| (t as f64, v.0.ln()))
+        .collect();
+    if points.len() < 3 {
+        return None;
+    }
+
+    // Least squares on the log of the volume, which is linear in the epoch: the intercept is the
+    // log of the median, the slope is the log growth, and the residuals are the volatility.
+    let n = points.len() as f64;
+    let (mean_t, mean_y) = points
+        .iter()
+        .fold((0.0, 0.0), |(t, y), p| (t + p.0 / n, y + p.1 / n));
+    let var_t: f64 = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum();
+    let slope = if var_t > 0.0 {
+        points
+            .iter()
+            .map(|p| (p.0 - mean_t) * (p.1 - mean_y))
+            .sum::<f64>()
+            / var_t
+    } else {
+        0.0
+    };
+    let intercept = mean_y - slope * mean_t;
+    let residuals: f64 = points
+        .iter()
+        .map(|p| (p.1 - intercept - slope * p.0).powi(2))
+        .sum();
+    let volatility = (residuals / (n - 2.0)).sqrt();
+
+    // The volume model subtracts half of the variance so that its mean is the configured mean.
+    Some(VolumeConfig::Lognormal {
+        mean: Usd((intercept + 0.5 * volatility * volatility).exp()),
+        volatility,
+        drift: slope.exp() - 1.0,
+    })
+}
+
+/// The root mean squared relative error between the TVB simulated by backtesting the configuration
+/// (with bonding simulated rather than recorded) and the recorded TVB, or `None` when no TVB is
+/// recorded. Runs that violate an invariant have an infinite error.
+pub fn tvb_error(config: &Config, records: &[Record]) -> Option<f64> {
+    let recorded: Vec<(usize, Usd)> = records
+        .iter()
+        .enumerate()
+        .filter_map(|(i, record)| {
+            record
+                .tvb
+                .filter(|tvb| *tvb > Usd::ZERO)
+                .map(|tvb| (i, tvb))
+        })
+        .collect();
+    if recorded.is_empty() {
+        return None;
+    }
+    let simulated: Vec<Record> = records
+        .iter()
+        .map(|record| Record {
+            tvb: None,
+            ..*record
+        })
+        .collect();
+    // Every simulated epoch is compared with its record, so every state is kept.
+    let mut sim = backtest::simulation(config, simulated).with_history_window(None);
+    if sim.try_run(records.len()).is_err() {
+        return Some(f64::INFINITY);
+    }
+    // The first state in the history is the initial state, before the first record.
+    let history = sim.history();
+    let sum: f64 = recorded
+        .iter()
+        .map(|&(i, tvb)| ((history[i + 1].tvb - tvb) / tvb).powi(2))
+        .sum();
+    Some((sum / recorded.len() as f64).sqrt())
+}
+
+/// Calibrate the configuration to the records, fitting the volumes and then the given behavioural
+/// parameters (by their dotted paths). Every simulation uses the same seed, so that the error only
+/// changes with the parameters.
+pub fn run(
+    config: &Config,
+    records: &[Record],
+    parameters: &[String],
+    seed: u64,
+) -> Result<Calibration> {
+    let mut config = config.clone();
+    let mint_volume = fit_volume(&records.iter().map(|r| r.mint_volume).collect::<Vec<_>>());
+    let burn_volume = fit_volume(&records.iter().map(|r| r.burn_volume).collect::<Vec<_>>());
+    if let Some(volume) = mint_volume {
+        config.external.mint_volume = volume;
+    }
+    if let Some(volume) = burn_volume {
+        config.external.burn_volume = volume;
+    }
+
+    let initial: Vec<f64> = parameters
+        .iter()
+        .map(|path| {
+            let value = config.get(path)?;
+            if config.is_integer(path)? || value <= 0.0 {
+                return Err(Error::Config(format!(
+                    "parameter `{}` must be positive and continuous to be calibrated",
+                    path
+                )));
+            }
+            Ok(value)
+        })
+        .collect::<Result<_>>()?;
+
+    let saved_seed = config.seed;
+    config.seed = Some(seed);
+    let initial_error = tvb_error(&config, records);
+    let mut calibration = Calibration {
+        config: config.clone(),
+        mint_volume,
+        burn_volume,
+        parameters: vec![],
+        initial_error,
+        error: initial_error,
+        evaluations: usize::from(initial_error.is_some()),
+    };
+    if initial_error.is_some() && !parameters.is_empty() {
+        // Parameters are searched in log space, which keeps them positive and makes the initial
+        // simplex (±10%) independent of their scale.
+        let x0: Vec<f64> = initial.iter().map(|v| v.ln()).collect();
+        let objective = |x: &[f64]| {
+            let mut candidate = config.clone();
+            for (path, x) in parameters.iter().zip(x) {
+                if candidate.set(path, x.exp()).is_err() {
+                    return f64::INFINITY;
+                }
+            }
+            tvb_error(&candidate, records).unwrap_or(f64::INFINITY)
+        };
+        let minimum = optim::nelder_mead(objective, &x0, 0.1, 1e-6, 500);
+        for (path, x) in parameters.iter().zip(&minimum.x) {
+            config.set(path, x.exp())?;
+        }
+        calibration.parameters = parameters
+            .iter()
+            .zip(initial)
+            .zip(&minimum.x)
+            .map(|((path, initial), x)| Fitted {
+                path: path.clone(),
+                initial,
+                value: x.exp(),
+            })
+            .collect();
+        calibration.error = Some(minimum.value);
+        calibration.evaluations += minimum.evaluations;
+    }
+    config.seed = saved_seed;
+    calibration.config = config;
+    Ok(calibration)
+}
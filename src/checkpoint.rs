@@ -0,0 +1,87 @@
+//! For saving a simulation part of the way through, so that a long run can be resumed later, or
+//! restarted from an earlier epoch with different parameters to ask "what if?". A checkpoint holds
+//! the configuration and seed of the run, the full history, the exact state of the random number
+//! generator, and whatever internal state the models keep between epochs.
+
+use crate::attacker::Attacker;
+use crate::config::Config;
+use crate::error::Result;
+use crate::history::Aggregates;
+use crate::mint_cap::MintCap;
+use crate::state::State;
+use crate::stop::Conditions;
+use crate::SimRng;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Checkpoint is a snapshot of a simulation, written to disk as JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The version of this crate that wrote the checkpoint.
+    pub version: String,
+    /// The configuration that the run was produced with.
+    pub config: Config,
+    /// The seed of the random number generator.
+    pub seed: u64,
+    /// The random number generator, as it was after the last simulated epoch.
+    pub rng: SimRng,
+    /// The internal state of the external model, if it keeps any.
+    pub external: Option<serde_json::Value>,
+    /// The internal state of the internal model, if it keeps any.
+    pub internal: Option<serde_json::Value>,
+    /// The balances that the ledger keeps, if they are not the ones in the history (which is only
+    /// when they are decimals).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<serde_json::Value>,
+    /// The attacker, with the REN that it has bonded so far and the attack that it made, if there
+    /// is one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attacker: Option<Attacker>,
+    /// The mint cap, with the mints that it has rejected and that are waiting to be tried again, if
+    /// minting is capped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_cap: Option<MintCap>,
+    /// The stop conditions, with how long each has held and the one that stopped the run, if there
+    /// are any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Conditions>,
+    /// The history of states, beginning with the initial state (or with the state at `first_epoch`,
+    /// if the history was bounded).
+    pub history: Vec<State>,
+    /// The epoch of the first state in the history, which is only non-zero if older states were
+    /// dropped.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub first_epoch: usize,
+    /// The aggregates of the run, which are only needed if older states were dropped (otherwise
+    /// they can be accumulated from the history).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregates: Option<Aggregates>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Checkpoint {
+    /// The number of epochs that had been simulated when the checkpoint was made.
+    pub fn epoch(&self) -> usize {
+        (self.first_epoch + self.history.len()).saturating_sub(1)
+    }
+
+    /// Load a checkpoint from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let checkpoint: Self = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+        let epoch = checkpoint.epoch();
+        tracing::debug!(path = %path.as_ref().display(), epoch, "checkpoint loaded");
+        Ok(checkpoint)
+    }
+
+    /// Save the checkpoint to a JSON file, replacing it if it already exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(&path)?), self)?;
+        tracing::debug!(path = %path.as_ref().display(), epoch = self.epoch(), "checkpoint saved");
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! For comparing two runs side by side (two configurations, or two result files), so that the
+//! effect of a change can be seen at a glance: where the key series diverge, and by how much. Runs
+//! are aligned by epoch, and only the epochs that both runs have are compared.
+
+use crate::error::Result;
+use crate::state::State;
+use std::io::Write;
+
+/// A named series, and how to read its value from a state.
+pub type Series = (&'static str, fn(&State) -> f64);
+
+/// The key series that are compared.
+pub const SERIES: [Series; 6] = [
+    ("tvl", |state| state.tvl.0),
+    ("tvb", |state| state.tvb.0),
+    ("security_ratio", |state| state.security_ratio),
+    ("f_claimed", |state| state.f_claimed.0),
+    ("r_pool", |state| state.r_pool.0),
+    ("r", |state| state.r.0),
+];
+
+/// Divergence describes how far one series diverged between the two runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The name of the series.
+    pub name: &'static str,
+    /// The value in the first run at the last compared epoch.
+    pub a: f64,
+    /// The value in the second run at the last compared epoch.
+    pub b: f64,
+    /// The largest absolute difference (second run less first run), by magnitude.
+    pub max_diff: f64,
+    /// The epoch of the largest absolute difference.
+    pub max_diff_epoch: usize,
+    /// The first epoch at which the runs differed by more than the tolerance (relative to the
+    /// larger of the two values), if they ever did.
+    pub diverged: Option<usize>,
+}
+
+/// Comparison holds two runs aligned by epoch, and how far every key series diverged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    /// The epochs that both runs have, in order.
+    pub epochs: Vec<usize>,
+    /// The states of the first run at those epochs.
+    pub a: Vec<State>,
+    /// The states of the second run at those epochs.
+    pub b: Vec<State>,
+    /// How far every series in `SERIES` diverged, in the same order.
+    pub divergences: Vec<Divergence>,
+}
+
+impl Comparison {
+    /// Compare two runs, given as states with their epochs (in increasing order of epoch). The
+    /// runs have diverged once a series differs by more than `tolerance` of the larger value.
+    pub fn new(a: &[(usize, State)], b: &[(usize, State)], tolerance: f64) -> Self {
+        // Both runs are sorted by epoch, so the common epochs can be found by merging them.
+        let (mut epochs, mut aligned_a, mut aligned_b) = (vec![], vec![], vec![]);
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            match a[i].0.cmp(&b[j].0) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    epochs.push(a[i].0);
+                    aligned_a.push(a[i].1.clone());
+                    aligned_b.push(b[j].1.clone());
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        let divergences = SERIES
+            .iter()
+            .map(|&(name, value)| {
+                let diffs = aligned_a
+                    .iter()
+                    .zip(&aligned_b)
+                    .map(|(a, b)| (value(a), value(b)));
+                let mut divergence = Divergence {
+                    name,
+                    a: aligned_a.last().map_or(0.0, value),
+                    b: aligned_b.last().map_or(0.0, value),
+                    max_diff: 0.0,
+                    max_diff_epoch: epochs.first().copied().unwrap_or(0),
+                    diverged: None,
+                };
+                for (&epoch, (a, b)) in epochs.iter().zip(diffs) {
+                    let diff = b - a;
+                    if diff.abs() > divergence.max_diff.abs() {
+                        divergence.max_diff = diff;
+                        divergence.max_diff_epoch = epoch;
+                    }
+                    if divergence.diverged.is_none()
+                        && diff.abs() > tolerance * a.abs().max(b.abs())
+                    {
+                        divergence.diverged = Some(epoch);
+                    }
+                }
+                divergence
+            })
+            .collect();
+
+        Self {
+            epochs,
+            a: aligned_a,
+            b: aligned_b,
+            divergences,
+        }
+    }
+
+    /// Write the divergence of every series as a table, marking the series that diverged.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(
+            writer,
+            "  {:<16} {:>18} {:>18} {:>18} {:>9} {:>26} {:>8}",
+            "series", "a", "b", "b - a", "b / a", "largest b - a (epoch)", "diverged"
+        )?;
+        for d in &self.divergences {
+            let ratio = if d.a != 0.0 {
+                format!("{:.4}", d.b / d.a)
+            } else {
+                "-".to_string()
+            };
+            let diverged = match d.diverged {
+                Some(epoch) => epoch.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(
+                writer,
+                "{} {:<16} {:>18.4} {:>18.4} {:>+18.4} {:>9} {:>26} {:>8}",
+                if d.diverged.is_some() { "*" } else { " " },
+                d.name,
+                d.a,
+                d.b,
+                d.b - d.a,
+                ratio,
+                format!("{:+.4} ({})", d.max_diff, d.max_diff_epoch),
+                diverged,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write both runs and their difference as CSV, with one row per epoch and series.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        writer.write_record(["epoch", "series", "a", "b", "diff"])?;
+        for ((epoch, a), b) in self.epochs.iter().zip(&self.a).zip(&self.b) {
+            for (name, value) in &SERIES {
+                let (a, b) = (value(a), value(b));
+                writer.write_record([
+                    epoch.to_string(),
+                    name.to_string(),
+                    a.to_string(),
+                    b.to_string(),
+                    (b - a).to_string(),
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,2016 @@
+//! For loading the tunable parameters of the simulation from a file. Every parameter has a default
+//! that matches the original hard-coded value, so a configuration file only needs to contain the
+//! values that you actually want to change. Both TOML and YAML files are supported; the format is
+//! chosen by the file extension (`.yaml` and `.yml` are YAML, everything else is TOML).
+
+use crate::curve::{Curve, Expression};
+use crate::error::{Error, Result};
+use crate::registry::Slot;
+use crate::state::State;
+use crate::{Pct, Ren, Usd};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Config holds every tunable parameter of a simulation run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The number of epochs to simulate.
+    pub steps: usize,
+    /// The number of warm-up epochs at the start of the run (counted in `steps`), whose states are
+    /// left out of the outputs and summary statistics so that the transient from the initial state
+    /// does not pollute them.
+    pub warmup: usize,
+    /// The seed of the random number generator. A random seed is chosen (and recorded in the
+    /// output) when there is none.
+    pub seed: Option<u64>,
+    /// How long an epoch lasts, and when the first one begins.
+    pub epoch: EpochConfig,
+    /// The order in which the stages of every epoch are simulated. It is left out while it is the
+    /// default (so that configurations hash as they did before).
+    #[serde(skip_serializing_if = "PipelineConfig::is_default")]
+    pub pipeline: PipelineConfig,
+    /// The state that the run begins from.
+    pub initial: InitialConfig,
+    /// Parameters for the external models.
+    pub external: ExternalConfig,
+    /// Parameters for the internal models.
+    pub internal: InternalConfig,
+    /// How the security of RenVM is judged.
+    pub security: SecurityConfig,
+    /// How the outcome of a run is classified.
+    pub health: HealthConfig,
+    /// How the cost of an attack on the bond is estimated.
+    pub attack: AttackConfig,
+    /// The invariants that are checked every epoch.
+    pub invariants: InvariantsConfig,
+    /// How a steady state is detected, and whether the run stops when it is reached.
+    pub steady_state: SteadyStateConfig,
+    /// Changes to the internal parameters that governance makes during the run.
+    pub governance: Vec<GovernanceConfig>,
+    /// Conditions that stop the run early once they have held for long enough. There are none by
+    /// default, and then they are left out (so that configurations hash as they did before).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<StopConfig>,
+    /// How much of the history is kept while the run goes on.
+    pub history: HistoryConfig,
+    /// How RenVM accounts for its own balances.
+    pub accounting: AccountingConfig,
+    /// A script that replaces model functions.
+    pub script: ScriptConfig,
+    /// The models that are selected by name.
+    pub models: ModelsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            steps: 180,
+            warmup: 0,
+            seed: None,
+            epoch: EpochConfig::default(),
+            pipeline: PipelineConfig::default(),
+            initial: InitialConfig::default(),
+            external: ExternalConfig::default(),
+            internal: InternalConfig::default(),
+            security: SecurityConfig::default(),
+            health: HealthConfig::default(),
+            attack: AttackConfig::default(),
+            invariants: InvariantsConfig::default(),
+            steady_state: SteadyStateConfig::default(),
+            governance: vec![],
+            stop: vec![],
+            history: HistoryConfig::default(),
+            accounting: AccountingConfig::default(),
+            script: ScriptConfig::default(),
+            models: ModelsConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load a configuration from a TOML or YAML file, along with the script and the volumes to
+    /// replay that it names (relative to the directory of the file).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let mut config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&contents)?,
+            _ => Self::from_toml(&contents)?,
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.read_script(dir)?;
+        config.read_replay(dir)?;
+        Ok(config)
+    }
+
+    /// Load a configuration from a TOML or YAML file, where parameters that are not in the file
+    /// keep their values from `base` (instead of their defaults). A table that selects a `model`
+    /// replaces the corresponding table in `base` entirely, since the parameters of one model make
+    /// no sense for another. A script (or volumes to replay) is only read if the file names one
+    /// (otherwise that of `base` is kept as it is).
+    pub fn load_onto<P: AsRef<Path>>(path: P, base: &Config) -> Result<Self> {
+        fn merge(base: &mut serde_json::Value, overrides: serde_json::Value) {
+            match (base, overrides) {
+                (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+                    for (key, value) in overrides {
+                        match base.get_mut(&key) {
+                            Some(field) if !(value.is_object() && value.get("model").is_some()) => {
+                                merge(field, value)
+                            }
+                            _ => {
+                                base.insert(key, value);
+                            }
+                        }
+                    }
+                }
+                (base, overrides) => *base = overrides,
+            }
+        }
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let overrides: serde_json::Value = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)?,
+            _ => toml::from_str(&contents)?,
+        };
+        let script = overrides.pointer("/script/path").is_some();
+        let replay = overrides.pointer("/external/replay/path").is_some();
+        let mut value = serde_json::to_value(base)?;
+        merge(&mut value, overrides);
+        let mut config: Self = serde_json::from_value(value)
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        if script {
+            config.read_script(dir)?;
+        }
+        if replay {
+            config.read_replay(dir)?;
+        }
+        Ok(config)
+    }
+
+    /// Read the script that the configuration names (relative to the given directory) into its
+    /// source, if it names one.
+    pub fn read_script(&mut self, dir: &Path) -> Result<()> {
+        if let Some(path) = &self.script.path {
+            let path = dir.join(path);
+            let source = fs::read_to_string(&path).map_err(|err| {
+                Error::Config(format!("cannot read script {}: {}", path.display(), err))
+            })?;
+            self.script.source = Some(source);
+        }
+        Ok(())
+    }
+
+    /// Read the volumes to replay that the configuration names (relative to the given directory),
+    /// if it names any.
+    pub fn read_replay(&mut self, dir: &Path) -> Result<()> {
+        if let Some(path) = &self.external.replay.path {
+            let path = dir.join(path);
+            let records = crate::backtest::load(&path).map_err(|err| {
+                Error::Config(format!(
+                    "cannot read volumes from {}: {}",
+                    path.display(),
+                    err
+                ))
+            })?;
+            let replay = &mut self.external.replay;
+            replay.mint_volume = records.iter().map(|record| record.mint_volume).collect();
+            replay.burn_volume = records.iter().map(|record| record.burn_volume).collect();
+        }
+        Ok(())
+    }
+
+    /// Save the configuration to a TOML or YAML file (chosen by the extension, as for `load`).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::to_string(self)?,
+            _ => self.to_toml()?,
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Format the configuration as a TOML document.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|err| Error::Config(err.to_string()))
+    }
+
+    /// Parse a configuration from a TOML document.
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Parse a configuration from a YAML document.
+    pub fn from_yaml(contents: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+
+    /// The dotted paths of every numeric model parameter in the configuration, in the order that
+    /// they are declared. The run settings (`steps`, `warmup`, `seed`, `epoch`, `steady_state`,
+    /// `history`, and `accounting`), the security requirement, how runs are classified, how attacks
+    /// are costed, and the script are not model parameters and are not included.
+    pub fn parameters(&self) -> Vec<String> {
+        fn collect(prefix: &str, value: &serde_json::Value, paths: &mut Vec<String>) {
+            match value {
+                serde_json::Value::Number(_) => paths.push(prefix.to_string()),
+                serde_json::Value::Object(fields) => {
+                    for (key, value) in fields {
+                        let path = if prefix.is_empty() {
+                            key.clone()
+                        } else {
+                            format!("{}.{}", prefix, key)
+                        };
+                        collect(&path, value, paths);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut paths = vec![];
+        if let Ok(value) = serde_json::to_value(self) {
+            collect("", &value, &mut paths);
+        }
+        paths.retain(|path| {
+            path != "steps"
+                && path != "warmup"
+                && path != "seed"
+                && !path.starts_with("epoch.")
+                && !path.starts_with("security.")
+                && !path.starts_with("health.")
+                && !path.starts_with("attack.")
+                && !path.starts_with("steady_state.")
+                && !path.starts_with("history.")
+                && !path.starts_with("accounting.")
+                && !path.starts_with("script.")
+        });
+        paths
+    }
+
+    /// Get a numeric parameter by its dotted path (e.g. `internal.mint_fee`).
+    pub fn get(&self, path: &str) -> Result<f64> {
+        let value = serde_json::to_value(self)?;
+        path.split('.')
+            .try_fold(&value, |value, key| value.get(key))
+            .and_then(|value| value.as_f64())
+            .ok_or_else(|| Error::Config(format!("no numeric parameter `{}`", path)))
+    }
+
+    /// Whether a numeric parameter (given by its dotted path) only takes integer values.
+    pub fn is_integer(&self, path: &str) -> Result<bool> {
+        let value = serde_json::to_value(self)?;
+        path.split('.')
+            .try_fold(&value, |value, key| value.get(key))
+            .filter(|value| value.is_number())
+            .map(|value| !value.is_f64())
+            .ok_or_else(|| Error::Config(format!("no numeric parameter `{}`", path)))
+    }
+
+    /// Set a numeric parameter by its dotted path (e.g. `internal.mint_fee`). This allows modes
+    /// like parameter sweeps to vary any parameter without knowing about it in advance.
+    pub fn set(&mut self, path: &str, value: f64) -> Result<()> {
+        let unknown = || Error::Config(format!("no numeric parameter `{}`", path));
+        let mut tree = serde_json::to_value(&*self)?;
+        let field = path
+            .split('.')
+            .try_fold(&mut tree, |tree, key| tree.get_mut(key))
+            .filter(|field| field.is_number())
+            .ok_or_else(unknown)?;
+        // Integer parameters (like window lengths) must stay integers.
+        *field = if field.is_f64() {
+            serde_json::Value::from(value)
+        } else if value >= 0.0 && value.fract() == 0.0 {
+            serde_json::Value::from(value as u64)
+        } else {
+            return Err(Error::Config(format!(
+                "parameter `{}` must be a non-negative integer",
+                path
+            )));
+        };
+        *self = serde_json::from_value(tree)?;
+        Ok(())
+    }
+}
+
+/// Parameters for the length of an epoch, from which every window and annualisation is derived.
+/// Volumes, fees, and other rates are always per epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpochConfig {
+    /// The length of an epoch in days (e.g. 1 for a day, 7 for a week, or 28 for four weeks).
+    pub days: f64,
+    /// The date on which the first epoch begins. When there is one, the outputs label every epoch
+    /// with the date on which it ends.
+    pub start: Option<NaiveDate>,
+    /// The number of days between the epoch boundaries of RenVM, when they are further apart than
+    /// the simulated epochs (e.g. 28, with daily epochs). Fees are claimed, fee levels and rebates
+    /// change, and darknodes register and deregister only in the first simulated epoch after every
+    /// boundary. Without it, every simulated epoch is a boundary.
+    pub boundary_days: Option<f64>,
+}
+
+impl Default for EpochConfig {
+    fn default() -> Self {
+        Self {
+            days: 1.0,
+            start: None,
+            boundary_days: None,
+        }
+    }
+}
+
+impl EpochConfig {
+    /// The number of whole epochs (at least one) that is closest to the given number of days.
+    pub fn epochs(&self, days: f64) -> usize {
+        ((days / self.days).round() as usize).max(1)
+    }
+
+    /// The number of simulated epochs from one epoch boundary to the next.
+    pub fn boundary(&self) -> usize {
+        self.boundary_days.map_or(1, |days| self.epochs(days))
+    }
+
+    /// The number of epochs in a (365 day) year.
+    pub fn per_year(&self) -> f64 {
+        365.0 / self.days
+    }
+
+    /// The date on which the given epoch ends (where epoch zero is the initial state, which ends
+    /// on the start date), if there is a start date.
+    pub fn date(&self, epoch: usize) -> Option<NaiveDate> {
+        let days = (epoch as f64 * self.days).round() as u64;
+        self.start
+            .and_then(|start| start.checked_add_days(Days::new(days)))
+    }
+}
+
+/// The order of the stages of an epoch (see `Simulation::step`), which is implicit in any model of
+/// RenVM and can change its results. By default every stage only sees the states of earlier
+/// epochs, so that the stages happen at once (and their order only decides which random numbers
+/// each of them draws). When they are sequential, every stage sees what the stages before it have
+/// changed (as the latest state, after every earlier epoch), so that, say, bonding can respond to
+/// the value locked or the fees claimed in the same epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// The stages, in the order in which they are simulated.
+    pub order: Order,
+    /// Whether every stage sees what the stages before it have changed in the same epoch.
+    pub sequential: bool,
+}
+
+impl PipelineConfig {
+    /// Whether this is the order of the stages that RenVM has always been simulated in.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A stage of the simulation of an epoch. Once every stage has been simulated, the epoch is closed:
+/// what was collected is settled into the balances, the attacker (if there is one) attacks, and
+/// the security ratio and shards follow from the values that are locked and bonded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// The volumes that are minted and burned (and the prices of locked assets), the fee levels and
+    /// the rebate (which are decided together, since burns only pay a fee while they earn no
+    /// rebate), and the fees that are charged on the volumes.
+    Fees,
+    /// How the fees that were charged are split between the treasury, the buy-and-burn, the rebate
+    /// pool, and node operators, and the rebate that the pool pays on what was burned.
+    Rebate,
+    /// The price of REN, the value that is bonded and the darknodes that bond it, slashing, the
+    /// subsidy, and the bond of the attacker.
+    Bonds,
+    /// The value that is locked, after what is minted and burned and how it is revalued.
+    Locked,
+    /// The unclaimed fees that nodes claim.
+    Claims,
+}
+
+impl Stage {
+    /// Every stage, in the order in which they are simulated by default.
+    pub const ALL: [Stage; 5] = [
+        Stage::Fees,
+        Stage::Rebate,
+        Stage::Bonds,
+        Stage::Locked,
+        Stage::Claims,
+    ];
+
+    /// The name of the stage, as it is written in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Fees => "fees",
+            Stage::Rebate => "rebate",
+            Stage::Bonds => "bonds",
+            Stage::Locked => "locked",
+            Stage::Claims => "claims",
+        }
+    }
+}
+
+/// Order is an order of every stage of an epoch, each once. Fees are charged before the stages
+/// that depend on them (the rebate, and the value that is locked), so orders that do not charge
+/// them first are rejected when the configuration is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Stage>", into = "Vec<Stage>")]
+pub struct Order([Stage; 5]);
+
+impl Order {
+    /// The stages, in order.
+    pub fn stages(&self) -> &[Stage] {
+        &self.0
+    }
+}
+
+impl Default for Order {
+    fn default() -> Self {
+        Self(Stage::ALL)
+    }
+}
+
+impl TryFrom<Vec<Stage>> for Order {
+    type Error = String;
+
+    fn try_from(stages: Vec<Stage>) -> std::result::Result<Self, String> {
+        let position = |stage| stages.iter().position(|s| *s == stage);
+        if let Some(missing) = Stage::ALL.iter().find(|stage| position(**stage).is_none()) {
+            return Err(format!(
+                "the order of stages is missing `{}`",
+                missing.name()
+            ));
+        }
+        let stages: [Stage; 5] = stages
+            .as_slice()
+            .try_into()
+            .map_err(|_| "the order of stages has a stage in it more than once".to_string())?;
+        let fees = position(Stage::Fees);
+        if position(Stage::Rebate) < fees || position(Stage::Locked) < fees {
+            return Err(
+                "`fees` must come before `rebate` and `locked` in the order of stages".to_string(),
+            );
+        }
+        Ok(Self(stages))
+    }
+}
+
+impl From<Order> for Vec<Stage> {
+    fn from(order: Order) -> Self {
+        order.0.to_vec()
+    }
+}
+
+/// The state of RenVM that a run begins from, which is all zeros by default. Fields that are not
+/// listed here (like the TVB in REN, and the security ratio) follow from those that are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InitialConfig {
+    /// The total value bonded (USD).
+    pub tvb: Usd,
+    /// The price of REN (USD).
+    pub ren_price: Usd,
+    /// The number of registered darknodes.
+    pub darknodes: u64,
+    /// The total value locked (USD).
+    pub tvl: Usd,
+    /// The total value collected for rebates (USD).
+    pub tvr: Usd,
+    /// The minting fee.
+    pub mf: Pct,
+    /// The burning fee.
+    pub bf: Pct,
+    /// The rebate.
+    pub r: Pct,
+    /// The fees that have not yet been claimed by nodes (USD).
+    pub f_unclaimed: Usd,
+    /// The fees that have been claimed by nodes (USD).
+    pub f_claimed: Usd,
+    /// The balance of the rebate pool (USD).
+    pub r_pool: Usd,
+    /// The fees that nodes claimed per epoch before the run began (USD). Node operators average
+    /// their income over the ROI window, and this stands in for the epochs of the window that have
+    /// not been simulated yet, so that a run which begins bonded does not begin with zero income.
+    pub fee_income: Usd,
+}
+
+impl InitialConfig {
+    /// The initial state itself.
+    pub fn state(&self) -> State {
+        State {
+            tvb: self.tvb,
+            tvb_ren: if self.ren_price > Usd::ZERO {
+                Ren(self.tvb / self.ren_price)
+            } else {
+                Ren::ZERO
+            },
+            ren_price: self.ren_price,
+            darknodes: self.darknodes,
+            tvl: self.tvl,
+            tvr: self.tvr,
+            security_ratio: if self.tvl > Usd::ZERO {
+                self.tvb / self.tvl
+            } else {
+                0.0
+            },
+            mf: self.mf,
+            bf: self.bf,
+            r: self.r,
+            f_unclaimed: self.f_unclaimed,
+            f_claimed: self.f_claimed,
+            r_pool: self.r_pool,
+            ..State::default()
+        }
+    }
+}
+
+/// Parameters for the behaviour of entities that are external to RenVM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExternalConfig {
+    /// The annual return on bonded value that node operators want to receive.
+    pub target_roi: Pct,
+    /// The number of days over which node operators average their fee income.
+    pub roi_window_days: f64,
+    /// The amount of USD minted every epoch.
+    pub mint_volume: VolumeConfig,
+    /// The amount of USD burned every epoch, before considering the rebate.
+    pub burn_volume: VolumeConfig,
+    /// How the mint volume responds to the minting fee.
+    pub mint_demand: DemandConfig,
+    /// How the burn volume (before considering the rebate) responds to the burning fee.
+    pub burn_demand: DemandConfig,
+    /// The smallest rebate that is high enough to incentivise arbitrage.
+    pub arbitrage_rebate: Pct,
+    /// The additional burn volume that arbitrageurs will route through RenVM for every
+    /// `arbitrage_rebate` of rebate on offer.
+    pub arbitrage_volume: Usd,
+    /// The share of unclaimed fees that node operators claim every epoch, with the fixed claim
+    /// curve.
+    pub claim_rate: Pct,
+    /// When node operators claim the fees that they have earned.
+    pub claim_curve: ClaimCurve,
+    /// How arbitrageurs respond to the rebate.
+    pub arbitrage: ArbitrageConfig,
+    /// An adversary that accumulates bonds and attacks once it pays, if any (see `attack` for how
+    /// the attack is priced).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attacker: Option<AttackerConfig>,
+    /// The price of REN.
+    pub ren_price: PriceConfig,
+    /// The circulating supply of REN, and how its price responds as REN is burned.
+    pub ren_supply: RenSupplyConfig,
+    /// How the value that node operators want to bond turns into value that is bonded.
+    pub bonding: BondingConfig,
+    /// The costs of running a darknode, which node operators take out of their fee income.
+    pub operating_cost: OperatingCostConfig,
+    /// Whether bonding follows the registration lifecycle of darknodes, in which registrations
+    /// and deregistrations only take effect at the next epoch (and deregistered bonds are locked
+    /// for one more epoch), instead of TVB responding immediately.
+    pub lifecycle: bool,
+    /// The assets that are locked in RenVM. When there are none, locked value is modelled as a
+    /// single aggregate using `mint_volume` and `burn_volume`.
+    pub assets: Vec<AssetConfig>,
+    /// The correlation between the everyday moves in the prices of the assets, as a matrix by row
+    /// in the order of `assets` (either triangle will do). When it is empty, the prices move
+    /// independently.
+    pub correlation: Vec<Vec<f64>>,
+    /// One-off shocks that hit at specific epochs.
+    pub shocks: Vec<ShockConfig>,
+    /// The market regimes that the world outside of RenVM switches between, beginning with the
+    /// first. When there are none, volumes and prices follow their models alone.
+    pub regimes: Vec<RegimeConfig>,
+    /// A series of mint and burn volumes that replaces `mint_volume` and `burn_volume`.
+    pub replay: ReplayConfig,
+    /// Individual transactions that replace `mint_volume` and `burn_volume`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<TransactionsConfig>,
+    /// What users pay the network to mint or burn, which puts them off small transactions, if
+    /// anything.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas: Option<GasConfig>,
+    /// A competing bridge that shares the demand for minting and burning with RenVM, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub competitor: Option<CompetitorConfig>,
+    /// A liquidity pool that prices the minted token against the asset, with arbitrageurs that
+    /// mint and burn to hold its peg, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peg: Option<PegConfig>,
+    /// How the demand for REN (and so its price) responds to the income of node operators, if it
+    /// does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ren_demand: Option<RenDemandConfig>,
+}
+
+impl Default for ExternalConfig {
+    fn default() -> Self {
+        Self {
+            target_roi: Pct(0.05),
+            roi_window_days: 7.0,
+            mint_volume: VolumeConfig::Constant {
+                volume: Usd(4_000_000.0),
+            },
+            burn_volume: VolumeConfig::Constant {
+                volume: Usd(2_000_000.0),
+            },
+            mint_demand: DemandConfig::default(),
+            burn_demand: DemandConfig {
+                reference_fee: Pct(0.001),
+                ..DemandConfig::default()
+            },
+            arbitrage_rebate: Pct(0.001),
+            arbitrage_volume: Usd(1_000_000.0),
+            // Claim ~2% of available fees per epoch (~50% per month).
+            claim_rate: Pct(0.024451),
+            claim_curve: ClaimCurve::default(),
+            arbitrage: ArbitrageConfig::Basic,
+            attacker: None,
+            ren_price: PriceConfig::Constant { price: Usd(0.3) },
+            bonding: BondingConfig::Usd,
+            operating_cost: OperatingCostConfig::default(),
+            ren_supply: RenSupplyConfig::default(),
+            lifecycle: false,
+            assets: vec![],
+            correlation: vec![],
+            shocks: vec![],
+            regimes: vec![],
+            replay: ReplayConfig::default(),
+            transactions: None,
+            gas: None,
+            competitor: None,
+            peg: None,
+            ren_demand: None,
+        }
+    }
+}
+
+/// Parameters for sampling the individual transactions that mint and burn value every epoch,
+/// rather than only their totals, so that fees can depend on the size of a transaction. Like the
+/// volumes that they replace, the numbers of transactions respond to fees, regimes, and shocks
+/// (which scale how often they arrive rather than how large they are). Arbitrage is routed as one
+/// more burn. Replays take precedence, and only locked value that is modelled in aggregate is split
+/// into transactions.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionsConfig {
+    /// The transactions that mint value.
+    pub mint: TransactionConfig,
+    /// The transactions that burn value.
+    pub burn: TransactionConfig,
+}
+
+impl Default for TransactionsConfig {
+    fn default() -> Self {
+        Self {
+            mint: TransactionConfig {
+                rate: 400.0,
+                ..TransactionConfig::default()
+            },
+            burn: TransactionConfig {
+                rate: 200.0,
+                ..TransactionConfig::default()
+            },
+        }
+    }
+}
+
+/// Parameters for one kind of transaction, which arrive at random (as a Poisson process) and have
+/// lognormally distributed sizes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionConfig {
+    /// The expected number of transactions every epoch.
+    pub rate: f64,
+    /// The expected size of a transaction (in USD).
+    pub size: Usd,
+    /// The volatility of the logarithm of the size, where zero makes every transaction the same
+    /// size.
+    pub volatility: f64,
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        Self {
+            rate: 100.0,
+            size: Usd(10_000.0),
+            volatility: 1.0,
+        }
+    }
+}
+
+/// A series of mint and burn volumes, one per epoch, that is replayed verbatim instead of sampling
+/// volumes from their models, so that one demand path (historical or made up) can be pushed through
+/// different internal designs. Replayed volumes do not respond to fees, regimes, shocks, or the
+/// rebate, and once the series runs out nothing more is minted or burned. Only locked value that is
+/// modelled in aggregate is replayed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    /// The CSV file of the series, relative to the configuration file that names it, with the
+    /// `mint_volume` and `burn_volume` columns of the data that `backtest` reads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// The value minted in every epoch, which is read from `path` when the configuration is loaded
+    /// (and kept with the configuration, like the source of a script).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mint_volume: Vec<Usd>,
+    /// The value burned in every epoch, which is read along with `mint_volume`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub burn_volume: Vec<Usd>,
+}
+
+impl ReplayConfig {
+    /// Whether volumes are replayed, which they are once a series has been read.
+    pub fn is_enabled(&self) -> bool {
+        !self.mint_volume.is_empty() || !self.burn_volume.is_empty()
+    }
+}
+
+/// Parameters for an asset that is locked in RenVM (e.g. BTC), which is minted and burned
+/// independently of every other asset.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetConfig {
+    /// The name of the asset, which is used to label its output and to look up its fees.
+    pub name: String,
+    /// The amount of USD of this asset minted every epoch.
+    pub mint_volume: VolumeConfig,
+    /// The amount of USD of this asset burned every epoch, before considering the rebate.
+    pub burn_volume: VolumeConfig,
+    /// The price of the asset, which revalues the locked asset every epoch. When there is no price
+    /// model, locked value only changes when the asset is minted or burned.
+    #[serde(default)]
+    pub price: Option<PriceConfig>,
+}
+
+/// Parameters for a mint (or burn) volume model. The `model` field selects which one is used.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum VolumeConfig {
+    /// The same volume every epoch.
+    Constant {
+        /// The volume every epoch.
+        volume: Usd,
+    },
+    /// A lognormally distributed volume around a mean that drifts every epoch.
+    Lognormal {
+        /// The expected volume in the first epoch.
+        mean: Usd,
+        /// The standard deviation of the log of the volume.
+        volatility: f64,
+        /// The growth of the expected volume per epoch (e.g. 0.01 is 1% growth per epoch).
+        drift: f64,
+    },
+}
+
+/// A claim curve, selected by the `model` field, which decides the share of unclaimed fees that
+/// node operators claim every epoch. Claiming is what turns fees into the income that node
+/// operators see, so when they claim shapes how bonding responds to fees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ClaimCurve {
+    /// Node operators claim `claim_rate` of their unclaimed fees every epoch.
+    #[default]
+    Fixed,
+    /// Node operators claim all of their unclaimed fees at once, every `days` (e.g. monthly), and
+    /// nothing in between.
+    Periodic {
+        /// The number of days between claims.
+        days: f64,
+    },
+    /// Node operators claim all of their unclaimed fees once they are worth claiming (for
+    /// example, once they are worth more than the gas it costs to claim them).
+    Threshold {
+        /// The unclaimed fees at which node operators claim them.
+        threshold: Usd,
+    },
+}
+
+/// Parameters for how a volume responds to the fee that is charged on it. Demand is linear around
+/// the reference fee, at which the volume is what the volume model says, and falls by `elasticity`
+/// percent for every percent that the fee is above it (and rises likewise below it), but never
+/// below zero.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemandConfig {
+    /// The elasticity of the volume to the fee at the reference fee, or zero for a volume that
+    /// ignores the fee.
+    pub elasticity: f64,
+    /// The fee at which the volume is what the volume model says.
+    pub reference_fee: Pct,
+}
+
+impl Default for DemandConfig {
+    fn default() -> Self {
+        Self {
+            elasticity: 0.0,
+            reference_fee: Pct(0.003),
+        }
+    }
+}
+
+/// Parameters for a token price model. The `model` field selects which one is used.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum PriceConfig {
+    /// The same price every epoch.
+    Constant {
+        /// The price every epoch.
+        price: Usd,
+    },
+    /// Geometric Brownian motion.
+    Gbm {
+        /// The price before the first epoch.
+        initial: Usd,
+        /// The expected log return per epoch.
+        drift: f64,
+        /// The standard deviation of the log return per epoch.
+        volatility: f64,
+    },
+    /// Geometric Brownian motion with rare jumps (like crashes), and optionally fatter tails in
+    /// the everyday moves.
+    JumpDiffusion {
+        /// The price before the first epoch.
+        initial: Usd,
+        /// The expected log return per epoch, not counting jumps.
+        drift: f64,
+        /// The standard deviation of the log return per epoch, not counting jumps.
+        volatility: f64,
+        /// The expected number of jumps per epoch.
+        #[serde(deserialize_with = "finite")]
+        jump_rate: f64,
+        /// The mean log return of a jump (e.g. -0.5 for jumps that are mostly crashes of ~40%).
+        #[serde(deserialize_with = "finite")]
+        jump_mean: f64,
+        /// The standard deviation of the log return of a jump.
+        #[serde(deserialize_with = "finite")]
+        jump_volatility: f64,
+        /// The degrees of freedom of the Student's t distribution of the everyday moves, where
+        /// fewer means fatter tails (with the same standard deviation). It must be more than two,
+        /// or zero for normally distributed moves (the default).
+        #[serde(default, deserialize_with = "finite")]
+        degrees_of_freedom: f64,
+    },
+}
+
+/// Parameters for an arbitrage model. The `model` field selects which one is used.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ArbitrageConfig {
+    /// Arbitrageurs route `arbitrage_volume` for every `arbitrage_rebate` of rebate on offer, up to
+    /// what the rebate pool can pay for.
+    Basic,
+    /// A single arbitrageur with limited capital, who reacts to the rebate with some delay.
+    Agent {
+        /// The most value (in USD) that the arbitrageur can burn in one epoch.
+        capital: Usd,
+        /// The cost of the arbitrage (exchange fees, slippage, etc.) as a share of the volume.
+        cost: Pct,
+        /// The smallest profit, as a share of the volume, that the arbitrageur will act on.
+        threshold: Pct,
+        /// The number of epochs that it takes the arbitrageur to react to the state of RenVM.
+        latency: usize,
+    },
+}
+
+/// Parameters for an adversary that bonds REN over time, and attacks when stealing what is locked
+/// pays for the rest of the REN that it needs and the bond that it loses.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttackerConfig {
+    /// The value of REN (in USD) that the attacker buys and bonds at every epoch boundary.
+    pub budget: Usd,
+    /// The epoch from which the attacker bonds (and may attack).
+    pub start: usize,
+}
+
+impl Default for AttackerConfig {
+    fn default() -> Self {
+        Self {
+            budget: Usd(1_000_000.0),
+            start: 1,
+        }
+    }
+}
+
+/// Parameters for a bonding model. The `model` field selects which one is used.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum BondingConfig {
+    /// Node operators bond exactly as much USD as they want to.
+    Usd,
+    /// Node operators bond REN in whole darknodes, so the value that is bonded is the number of
+    /// darknodes they can afford at the current price of REN.
+    Darknodes {
+        /// The amount of REN bonded by each darknode.
+        bond: Ren,
+    },
+    /// A population of individual operator agents, each of which registers or deregisters
+    /// darknodes based on its own economics.
+    Operators(OperatorsConfig),
+    /// Cohorts of node operators, each with its own share of the capital and its own target ROI.
+    /// Fee income is shared by everything that is bonded, so cohorts bond from the lowest target
+    /// up, and each bonds as much of its capital as it can while the return still meets its
+    /// target. The top-level `target_roi` is not used by this model.
+    Cohorts {
+        /// The most value (in USD) that node operators would bond between them.
+        capital: Usd,
+        /// The cohorts of node operators.
+        cohorts: Vec<CohortConfig>,
+    },
+}
+
+impl BondingConfig {
+    /// The amount of REN bonded by each darknode, where `bond` is used by the models that do not
+    /// bond their own amount.
+    pub fn bond(&self, bond: Ren) -> Ren {
+        match self {
+            BondingConfig::Darknodes { bond } => *bond,
+            BondingConfig::Operators(operators) => operators.bond,
+            BondingConfig::Usd | BondingConfig::Cohorts { .. } => bond,
+        }
+    }
+}
+
+/// A cohort of node operators that want the same return.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CohortConfig {
+    /// The cohort's share of the capital.
+    pub share: Pct,
+    /// The annual return on bonded value that the cohort wants to receive.
+    pub target_roi: Pct,
+}
+
+/// Parameters for how the price of REN responds to changes in its supply, when fees buy and burn it
+/// or a subsidy emits it. The price model moves the price as it would anyway, and on top of that
+/// the price moves by `(circulating / remaining) ^ elasticity`, where `remaining` is the
+/// circulating supply less what has been burned, plus what has been emitted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenSupplyConfig {
+    /// The circulating supply of REN before anything is burned.
+    pub circulating: Ren,
+    /// How strongly the price responds to the supply, where one keeps the market value of the
+    /// circulating supply the same and zero ignores the supply.
+    pub elasticity: f64,
+}
+
+impl Default for RenSupplyConfig {
+    fn default() -> Self {
+        Self {
+            circulating: Ren(1_000_000_000.0),
+            elasticity: 1.0,
+        }
+    }
+}
+
+/// Parameters for how the price of REN responds to the income of node operators, which makes the
+/// price (and so TVB in USD) reflexive: more income makes REN worth more to bond, which raises the
+/// value that is bonded, and less income does the opposite. On top of the price model, the price
+/// moves by `((income + base) / (before + base)) ^ elasticity` every epoch, where `income` is the
+/// annual income of node operators (the fees they claimed over the ROI window) and `before` is the
+/// income that the price has already responded to. The `base` is the demand for REN that does not
+/// depend on the income, in the same terms.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenDemandConfig {
+    /// How strongly the price responds to the income, where one keeps the price in proportion to
+    /// the income plus the base and zero ignores the income.
+    pub elasticity: f64,
+    /// The annual income (in USD) that the demand for REN apart from node income is worth.
+    pub base: Usd,
+}
+
+impl Default for RenDemandConfig {
+    fn default() -> Self {
+        Self {
+            elasticity: 1.0,
+            base: Usd(1_000_000.0),
+        }
+    }
+}
+
+/// The costs of running a darknode. Node operators want their target ROI on what they earn after
+/// paying them, so a darknode has to earn its costs before it is worth bonding.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OperatingCostConfig {
+    /// The annual cost (in USD) of the infrastructure that runs one darknode.
+    pub infra: Usd,
+    /// The cost (in USD) of the gas for one claim of fees. Every darknode claims once per epoch.
+    pub claim_gas: Usd,
+    /// The amount of REN bonded by each darknode, for the `usd` bonding model (which otherwise
+    /// does not count darknodes). The other models use their own bond.
+    pub bond: Ren,
+}
+
+impl Default for OperatingCostConfig {
+    fn default() -> Self {
+        Self {
+            infra: Usd::ZERO,
+            claim_gas: Usd::ZERO,
+            bond: Ren(100_000.0),
+        }
+    }
+}
+
+impl OperatingCostConfig {
+    /// The annual cost (in USD) of running one darknode, with epochs of the given length.
+    pub fn per_year(&self, epoch: &EpochConfig) -> Usd {
+        self.infra + self.claim_gas * epoch.per_year()
+    }
+}
+
+/// Parameters for a market regime (like a bull, bear, or crab market), which lasts for as long as
+/// the Markov chain of regimes stays in it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegimeConfig {
+    /// The name of the regime, which is used to log when it begins.
+    pub name: String,
+    /// The growth of mint and burn volumes per epoch during the regime (e.g. 0.01 is 1% growth
+    /// per epoch), on top of whatever their models do.
+    #[serde(default)]
+    pub volume_growth: f64,
+    /// The log return per epoch that is added to the price of REN and of every locked asset (or,
+    /// when locked value is modelled in aggregate, to all of it) during the regime.
+    #[serde(default)]
+    pub price_drift: f64,
+    /// The relative probability of being in each regime (in order, including this one) in the
+    /// next epoch. The regime never ends when this is empty.
+    #[serde(default)]
+    pub transitions: Vec<f64>,
+}
+
+/// A one-off shock that hits at a specific epoch (where the first simulated epoch is epoch one). A
+/// negative drop is a rise (so a `drop` of -1 doubles a price, or a volume).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ShockConfig {
+    /// The price of a locked asset drops by `drop` (e.g. 0.5 for a 50% crash), and its price
+    /// model continues from there. The value of the asset that is locked drops with it. Without an
+    /// asset, every priced asset crashes (or, when locked value is modelled in aggregate, all of
+    /// it). Assets without a price model are not affected.
+    PriceCrash {
+        /// The epoch in which the price crashes.
+        epoch: usize,
+        /// The share of the price that is lost.
+        drop: Pct,
+        /// The name of the asset that crashes.
+        #[serde(default)]
+        asset: Option<String>,
+    },
+    /// The price of REN drops by `drop`, and its price model continues from there.
+    RenPriceCrash {
+        /// The epoch in which the price crashes.
+        epoch: usize,
+        /// The share of the price that is lost.
+        drop: Pct,
+    },
+    /// Node operators suddenly withdraw `share` of their bonds. Agent-based operators each
+    /// deregister that share of their darknodes (and only come back one darknode per epoch), while
+    /// the aggregate bonding models bond that much less for the epoch.
+    Unbonding {
+        /// The epoch in which bonds are withdrawn.
+        epoch: usize,
+        /// The share of bonded value that is withdrawn.
+        share: Pct,
+    },
+    /// Minting demand drops by `drop` for `epochs` epochs.
+    DemandCollapse {
+        /// The first epoch of the collapse.
+        epoch: usize,
+        /// How many epochs the collapse lasts.
+        epochs: usize,
+        /// The share of minting volume that is lost.
+        drop: Pct,
+    },
+    /// Burning demand drops by `drop` for `epochs` epochs. Arbitrageurs still burn to collect the
+    /// rebate.
+    BurnCollapse {
+        /// The first epoch of the collapse.
+        epoch: usize,
+        /// How many epochs the collapse lasts.
+        epochs: usize,
+        /// The share of burning volume that is lost.
+        drop: Pct,
+    },
+}
+
+/// Parameters for a population of node operator agents. Each operator draws its own target ROI and
+/// operating cost uniformly from the configured `[min, max]` ranges when the simulation starts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperatorsConfig {
+    /// The amount of REN bonded by each darknode.
+    pub bond: Ren,
+    /// The number of operators.
+    pub operators: usize,
+    /// The most darknodes that each operator will run.
+    pub max_nodes: u64,
+    /// The range of annual returns on bonded value that operators want to receive.
+    pub target_roi: [Pct; 2],
+    /// The range of annual costs (in USD) of operating one darknode.
+    pub cost: [Usd; 2],
+    /// How far expected ROI must exceed an operator's target before it registers a darknode.
+    pub entry: Pct,
+    /// How far ROI must fall below an operator's target before it deregisters a darknode.
+    pub exit: Pct,
+}
+
+/// Parameters for the fee and rebate mechanics of RenVM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InternalConfig {
+    /// The minting fee, which (like the burning fee and the shares of fees) is either a number or
+    /// an expression of the latest state (see `eco::curve`).
+    pub mint_fee: Curve,
+    /// The burning fee, charged only while TVL is below TVB.
+    pub burn_fee: Curve,
+    /// How the rebate is set every epoch.
+    pub rebate_curve: RebateCurve,
+    /// How much the rebate moves by every epoch, with the adaptive rebate curve.
+    pub rebate_step: Pct,
+    /// The number of days over which TVL-TVB is averaged when adjusting the rebate, with the
+    /// adaptive rebate curve.
+    pub rebate_window_days: f64,
+    /// The share of collected fees that is made available as a rebate, after the treasury and the
+    /// buy-and-burn have taken their shares.
+    pub rebate_share: Curve,
+    /// The share of collected fees that goes to the treasury, before rebates and node operators.
+    pub treasury_share: Curve,
+    /// The share of collected fees that buys REN and burns it, before rebates and node operators.
+    pub buyback_share: Curve,
+    /// How large the rebate pool can grow, and what happens to fees beyond it.
+    pub rebate_pool: RebatePoolConfig,
+    /// A controller that moves the fees to keep the security ratio at a target, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_controller: Option<PidConfig>,
+    /// A cap on what can be minted every epoch, tied to TVB, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mint_cap: Option<MintCapConfig>,
+    /// How the fee on a transaction depends on its size, if it does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_fee: Option<TransactionFeeConfig>,
+    /// Fees for individual assets, by name. Assets that are not listed (and fees that are not set)
+    /// use `mint_fee` and `burn_fee`.
+    pub assets: BTreeMap<String, AssetFeeConfig>,
+    /// How darknodes that misbehave are slashed.
+    pub slashing: SlashingConfig,
+    /// The REN that is emitted to node operators on top of their fees.
+    pub subsidy: SubsidyConfig,
+    /// How locked value and bonds are split between shards.
+    pub shards: ShardsConfig,
+}
+
+/// A rebate curve, selected by the `model` field. Every curve pays no rebate while TVB covers TVL,
+/// and the curves other than the adaptive one are functions of the gap between them, `(tvl - tvb)
+/// / tvl`, which grows from zero (when TVB just covers TVL) towards one (when nothing is bonded).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum RebateCurve {
+    /// The rebate moves by `rebate_step` every epoch: down if TVL-TVB is below its average over
+    /// `rebate_window_days`, and up otherwise.
+    #[default]
+    Adaptive,
+    /// The rebate grows in proportion to the gap, up to a maximum.
+    Linear {
+        /// The rebate per unit of gap.
+        slope: f64,
+        /// The highest rebate.
+        max: Pct,
+    },
+    /// The rebate follows a logistic curve of the gap, from zero up to a maximum.
+    Sigmoid {
+        /// The highest rebate, which the curve reaches as the gap grows.
+        max: Pct,
+        /// The gap at which the rebate is half of its maximum.
+        midpoint: f64,
+        /// How steeply the rebate rises around the midpoint.
+        steepness: f64,
+    },
+    /// The rebate jumps to the rebate of every step whose gap has been reached.
+    Step {
+        /// The steps, in any order. The rebate is that of the largest gap that has been reached,
+        /// or zero before the first.
+        steps: Vec<RebateStep>,
+    },
+    /// The rebate begins at `initial` when TVL rises above TVB, and halves every `half_life_days`
+    /// for as long as it stays there.
+    Decay {
+        /// The rebate when TVL first rises above TVB.
+        initial: Pct,
+        /// The number of days in which the rebate halves.
+        half_life_days: f64,
+    },
+}
+
+/// One step of a step rebate curve.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RebateStep {
+    /// The gap from which the step applies.
+    pub gap: f64,
+    /// The rebate of the step.
+    pub rebate: Pct,
+}
+
+/// Parameters for the gas that users pay the network (on top of fees) for every mint or burn. Users
+/// only make a transaction when its gas is at most `tolerance` of its size, so when gas spikes, the
+/// smallest transactions stop. Individual transactions (see `TransactionsConfig`) are each made or
+/// not, and otherwise the volume of an epoch loses the share that transactions too small to pay
+/// for their gas make up, out of lognormally distributed sizes around `size`. Replayed volumes and
+/// arbitrage are not affected.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GasConfig {
+    /// The gas (in USD) of one mint or burn, which follows a price model.
+    pub cost: PriceConfig,
+    /// The largest share of a transaction that users pay in gas.
+    pub tolerance: Pct,
+    /// The expected size of a transaction (in USD), for volumes that are not split into
+    /// transactions.
+    pub size: Usd,
+    /// The volatility of the logarithm of the size, for volumes that are not split into
+    /// transactions.
+    pub volatility: f64,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            cost: PriceConfig::Constant { price: Usd(20.0) },
+            tolerance: Pct(0.01),
+            size: Usd(10_000.0),
+            volatility: 1.0,
+        }
+    }
+}
+
+/// Parameters for a competing bridge with fees of its own. The mint and burn volumes become the
+/// demand of the whole market, of which RenVM keeps `share` while its fees (of the previous epoch)
+/// are the same as those of the competitor. Every basis point that a fee of RenVM is above the fee
+/// of the competitor lowers the log-odds that users choose RenVM by `sensitivity` (and every basis
+/// point below raises them). Arbitrage is only worth routing through RenVM, and replayed volumes
+/// are already what RenVM kept, so neither is shared.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompetitorConfig {
+    /// The fee that the competitor charges for minting.
+    pub mint_fee: Pct,
+    /// The fee that the competitor charges for burning.
+    pub burn_fee: Pct,
+    /// The share of the demand that RenVM keeps when its fees are the same as the competitor's.
+    pub share: Pct,
+    /// How much the log-odds of choosing RenVM change for every basis point of difference in fees.
+    pub sensitivity: f64,
+}
+
+impl Default for CompetitorConfig {
+    fn default() -> Self {
+        Self {
+            mint_fee: Pct(0.003),
+            burn_fee: Pct(0.001),
+            share: Pct(0.5),
+            sensitivity: 0.1,
+        }
+    }
+}
+
+/// Parameters for a Curve-style (StableSwap) pool of the minted token (such as renBTC) and the
+/// asset that it is pegged to, which starts balanced. Every epoch, outside traders sell a normally
+/// distributed amount (with a standard deviation of `flow`) of the token into the pool, or buy it
+/// out, which moves its price off the peg. Arbitrageurs then burn the token while its discount is
+/// more than the burning fee (of the previous epoch), or mint it while its premium is more than the
+/// minting fee, until the price is back to the fee. When assets are modelled individually, the pool
+/// belongs to `asset` (and is ignored without it).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PegConfig {
+    /// The value (in USD) of the pool when it is balanced.
+    pub liquidity: Usd,
+    /// The amplification coefficient of the pool, where more keeps the price closer to the peg for
+    /// longer as the pool moves off balance.
+    pub amplification: f64,
+    /// The standard deviation of the value (in USD) of the token that outside traders sell into the
+    /// pool every epoch.
+    pub flow: Usd,
+    /// The asset whose token the pool holds, when assets are modelled individually.
+    pub asset: String,
+}
+
+impl Default for PegConfig {
+    fn default() -> Self {
+        Self {
+            liquidity: Usd(50_000_000.0),
+            amplification: 100.0,
+            flow: Usd(2_000_000.0),
+            asset: "BTC".to_string(),
+        }
+    }
+}
+
+/// Parameters for charging fees by the size of a transaction, from tables of tiers. A transaction
+/// is charged the fee of the largest tier whose threshold it reaches (or the fee of the epoch,
+/// below every tier), but never less than the `minimum` (or more than the transaction itself).
+/// Nothing is charged on a transaction when the fee of the epoch is zero, which keeps burns free
+/// while there is a rebate. Transactions are the individual transactions with
+/// `external.transactions`, and otherwise the whole volume of the epoch (or of an asset) counts as
+/// one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionFeeConfig {
+    /// The smallest fee (in USD) that is charged on a transaction.
+    pub minimum: Usd,
+    /// The tiers of the sizes of mints, in any order.
+    pub mint_tiers: Vec<FeeTier>,
+    /// The tiers of the sizes of burns, in any order.
+    pub burn_tiers: Vec<FeeTier>,
+}
+
+/// A tier of transaction sizes with its own fee, which is either a `fee` of its own or a `scale` of
+/// the fee of the epoch (so that it follows the fee curve).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTier {
+    /// The size (in USD) from which a transaction is in the tier.
+    pub above: Usd,
+    /// The fee that transactions in the tier are charged, instead of the fee of the epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<Pct>,
+    /// The multiple of the fee of the epoch that transactions in the tier are charged, when the
+    /// tier has no `fee` (one by default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+}
+
+impl TransactionFeeConfig {
+    /// The fee charged on a mint of the given size, as a share of it, when the minting fee of the
+    /// epoch is `fee`.
+    pub fn mint_fee(&self, size: Usd, fee: Pct) -> Pct {
+        self.fee(&self.mint_tiers, size, fee)
+    }
+
+    /// The fee charged on a burn of the given size, as a share of it, when the burning fee of the
+    /// epoch is `fee`.
+    pub fn burn_fee(&self, size: Usd, fee: Pct) -> Pct {
+        self.fee(&self.burn_tiers, size, fee)
+    }
+
+    fn fee(&self, tiers: &[FeeTier], size: Usd, fee: Pct) -> Pct {
+        if fee <= Pct::ZERO {
+            return fee;
+        }
+        let tiered = tiers
+            .iter()
+            .filter(|tier| size >= tier.above)
+            .max_by(|a, b| a.above.0.total_cmp(&b.above.0))
+            .map_or(fee, |tier| {
+                tier.fee.unwrap_or_else(|| fee * tier.scale.unwrap_or(1.0))
+            });
+        if size <= Usd::ZERO {
+            return tiered;
+        }
+        Pct(tiered.0.max(self.minimum / size).min(1.0))
+    }
+}
+
+/// Parameters for capping what is minted every epoch, so that TVL never grows beyond TVB divided by
+/// `ratio` through minting. Mints that would take it further are rejected, and users try them again
+/// in later epochs if `carry_over` is set (or give up otherwise).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MintCapConfig {
+    /// The multiple of TVL that TVB must stay at for minting to be accepted.
+    pub ratio: f64,
+    /// Whether rejected mints are tried again (before any new ones) in the next epoch.
+    pub carry_over: bool,
+}
+
+impl Default for MintCapConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 3.0,
+            carry_over: false,
+        }
+    }
+}
+
+/// Parameters for a PID controller of the fees, which adds the same adjustment to the minting and
+/// burning fees every epoch to steer the security ratio (TVB/TVL) towards a target: higher fees
+/// while too little is bonded (so that bonding pays more), and lower fees while more is bonded
+/// than needed. The error is the target less the security ratio of the previous epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PidConfig {
+    /// The security ratio that the controller aims for.
+    pub target_ratio: f64,
+    /// The adjustment to the fees per unit of error.
+    pub kp: f64,
+    /// The adjustment to the fees per unit of error summed over every epoch so far.
+    pub ki: f64,
+    /// The adjustment to the fees per unit of change in the error since the previous epoch.
+    pub kd: f64,
+    /// The lowest fee that the controller sets.
+    pub min_fee: Pct,
+    /// The highest fee that the controller sets.
+    pub max_fee: Pct,
+}
+
+impl Default for PidConfig {
+    fn default() -> Self {
+        Self {
+            target_ratio: 3.0,
+            kp: 0.001,
+            ki: 0.0,
+            kd: 0.0,
+            min_fee: Pct::ZERO,
+            max_fee: Pct(0.01),
+        }
+    }
+}
+
+/// Parameters for capping the rebate pool. Without a cap, the pool grows for as long as more is
+/// collected for rebates than is paid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RebatePoolConfig {
+    /// The most that the rebate pool can hold, if it is capped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Usd>,
+    /// Where the fees that would take the pool beyond its cap go instead.
+    pub overflow: Overflow,
+}
+
+/// Where the fees that would take the rebate pool beyond its cap go.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    /// The fees go to node operators, like the rest of the fees.
+    #[default]
+    Nodes,
+    /// The fees go to the treasury.
+    Treasury,
+    /// The fees are not collected at all, so users pay less.
+    Stop,
+}
+
+/// Parameters for subsidising node operators with newly emitted REN, which is paid to them like
+/// fees (at the price of REN when it is emitted) and adds to the circulating supply of REN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubsidyConfig {
+    /// The REN emitted per year at the start of the run, or zero for no subsidy.
+    pub per_year: Ren,
+    /// How the emission changes as the run goes on.
+    pub schedule: EmissionSchedule,
+}
+
+/// How the emission of a subsidy changes over time, selected by the `model` field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum EmissionSchedule {
+    /// The same emission throughout.
+    #[default]
+    Constant,
+    /// The emission shrinks smoothly, halving every `half_life_days`.
+    Decay {
+        /// The number of days in which the emission halves.
+        half_life_days: f64,
+    },
+    /// The emission halves at once every `interval_days`, like the block reward of Bitcoin.
+    Halving {
+        /// The number of days between halvings.
+        interval_days: f64,
+    },
+    /// The emission falls in a straight line to zero over `days`, and stays there.
+    Linear {
+        /// The number of days until there is no emission.
+        days: f64,
+    },
+}
+
+/// Parameters for slashing darknodes that misbehave. Node operators want a higher return to make
+/// up for what they expect to lose, so slashing bonds less as well as taking from what is bonded.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlashingConfig {
+    /// The probability that any one darknode is slashed in an epoch.
+    #[serde(deserialize_with = "finite")]
+    pub probability: Pct,
+    /// The share of its bond that a slashed darknode loses.
+    pub fraction: Pct,
+    /// Where the value that is slashed goes.
+    pub destination: SlashDestination,
+}
+
+impl Default for SlashingConfig {
+    fn default() -> Self {
+        Self {
+            probability: Pct::ZERO,
+            fraction: Pct(0.5),
+            destination: SlashDestination::Burn,
+        }
+    }
+}
+
+impl SlashingConfig {
+    /// The share of its bond that a darknode expects to lose to slashing in a year, with epochs of
+    /// the given length, which node operators add to the return that they want.
+    pub fn risk(&self, epoch: &EpochConfig) -> Pct {
+        let loss = (self.probability.0 * self.fraction.0).clamp(0.0, 1.0);
+        Pct(1.0 - (1.0 - loss).powf(epoch.per_year()))
+    }
+}
+
+/// Read a number that must be finite, for parameters of distributions (which reject the `nan` and
+/// `inf` that TOML and YAML can write), so that the configuration is rejected when it is read
+/// rather than the run when it draws from them.
+fn finite<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Copy + Into<f64>,
+{
+    let value = T::deserialize(deserializer)?;
+    if value.into().is_finite() {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "{} is not a finite number",
+            value.into()
+        )))
+    }
+}
+
+/// Where the value that is slashed from darknodes goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlashDestination {
+    /// The value is destroyed.
+    #[default]
+    Burn,
+    /// The value goes to the rebate pool.
+    Rebates,
+    /// The value is shared between node operators, like fees.
+    Nodes,
+}
+
+/// Parameters for splitting locked value and bonds between shards, each of which is only as secure
+/// as the bonds of its own darknodes. Without shards, all of TVB secures all of TVL.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShardsConfig {
+    /// The number of shards, or zero to compare TVB with TVL in aggregate.
+    pub count: usize,
+    /// The relative share of minted value that goes to each shard, or an even share for every
+    /// shard when empty. Burns and revaluations change every shard in proportion to what it holds.
+    pub weights: Vec<f64>,
+    /// How bonds or locked value are moved between shards at the end of every epoch.
+    pub rebalance: RebalanceRule,
+    /// The share of the difference between where bonds (or locked value) are and where the rule
+    /// would have them that is moved every epoch.
+    pub rebalance_rate: Pct,
+}
+
+impl Default for ShardsConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            weights: vec![],
+            rebalance: RebalanceRule::None,
+            rebalance_rate: Pct::ALL,
+        }
+    }
+}
+
+/// How bonds or locked value are moved between shards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RebalanceRule {
+    /// Nothing is moved. Darknodes that join are spread evenly between shards, and darknodes that
+    /// leave are taken from every shard in proportion to its bonds.
+    #[default]
+    None,
+    /// Darknodes are moved so that every shard bonds the same share of TVB as it locks of TVL.
+    Bonds,
+    /// Locked value is moved so that every shard locks the same share of TVL as it bonds of TVB.
+    Value,
+}
+
+/// Fees that override the default fees for one asset.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetFeeConfig {
+    /// The minting fee for the asset.
+    pub mint_fee: Option<Curve>,
+    /// The burning fee for the asset, charged only while TVL is below TVB.
+    pub burn_fee: Option<Curve>,
+}
+
+impl Default for InternalConfig {
+    fn default() -> Self {
+        Self {
+            mint_fee: Curve::Constant(Pct(0.003)),
+            burn_fee: Curve::Constant(Pct(0.001)),
+            rebate_curve: RebateCurve::default(),
+            rebate_step: Pct(0.0001),
+            rebate_window_days: 7.0,
+            rebate_share: Curve::Constant(Pct(0.5)),
+            treasury_share: Curve::Constant(Pct::ZERO),
+            buyback_share: Curve::Constant(Pct::ZERO),
+            rebate_pool: RebatePoolConfig::default(),
+            fee_controller: None,
+            mint_cap: None,
+            transaction_fee: None,
+            assets: BTreeMap::new(),
+            slashing: SlashingConfig::default(),
+            subsidy: SubsidyConfig::default(),
+            shards: ShardsConfig::default(),
+        }
+    }
+}
+
+/// A change to the internal parameters that governance makes at the start of an epoch. Parameters
+/// that are not set keep their values from before the change.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GovernanceConfig {
+    /// The first epoch that is simulated with the changed parameters (where the first simulated
+    /// epoch is epoch one).
+    pub epoch: usize,
+    /// What the change is, for the output.
+    pub description: String,
+    /// The new minting fee.
+    pub mint_fee: Option<Curve>,
+    /// The new burning fee.
+    pub burn_fee: Option<Curve>,
+    /// The new amount by which the rebate moves every epoch.
+    pub rebate_step: Option<Pct>,
+    /// The new number of days over which TVL-TVB is averaged when adjusting the rebate.
+    pub rebate_window_days: Option<f64>,
+    /// The new share of collected fees that is made available as a rebate.
+    pub rebate_share: Option<Curve>,
+    /// The new share of collected fees that goes to the treasury.
+    pub treasury_share: Option<Curve>,
+    /// The new share of collected fees that buys and burns REN.
+    pub buyback_share: Option<Curve>,
+    /// New fees for individual assets, by name. Fees that are set replace the fees for the asset.
+    pub assets: BTreeMap<String, AssetFeeConfig>,
+}
+
+impl GovernanceConfig {
+    /// Apply the change to the internal parameters.
+    pub fn apply(&self, internal: &mut InternalConfig) {
+        fn set<T: Clone>(value: &mut T, new: &Option<T>) {
+            if let Some(new) = new {
+                *value = new.clone();
+            }
+        }
+        set(&mut internal.mint_fee, &self.mint_fee);
+        set(&mut internal.burn_fee, &self.burn_fee);
+        set(&mut internal.rebate_step, &self.rebate_step);
+        set(&mut internal.rebate_window_days, &self.rebate_window_days);
+        set(&mut internal.rebate_share, &self.rebate_share);
+        set(&mut internal.treasury_share, &self.treasury_share);
+        set(&mut internal.buyback_share, &self.buyback_share);
+        for (name, fees) in &self.assets {
+            let asset = internal.assets.entry(name.clone()).or_default();
+            if fees.mint_fee.is_some() {
+                asset.mint_fee = fees.mint_fee.clone();
+            }
+            if fees.burn_fee.is_some() {
+                asset.burn_fee = fees.burn_fee.clone();
+            }
+        }
+    }
+}
+
+/// Parameters for judging the security of RenVM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// The multiple of TVL that must be bonded for RenVM to be secure. An epoch in which the ratio
+    /// of TVB to TVL falls below this (while anything is locked) is a breach.
+    pub required_ratio: f64,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            required_ratio: 3.0,
+        }
+    }
+}
+
+/// A condition that stops the run early (see `stop`), such as `r_pool == 0` for ten epochs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopConfig {
+    /// An expression of the state (like `security_ratio < 1`), which holds when it is not zero.
+    pub when: Expression,
+    /// The number of consecutive epochs (at least one) that the condition must hold for before the
+    /// run stops.
+    #[serde(default = "StopConfig::default_epochs")]
+    pub epochs: usize,
+    /// What the condition means, for the summary.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl StopConfig {
+    /// A condition stops the run as soon as it holds, unless it says otherwise.
+    fn default_epochs() -> usize {
+        1
+    }
+}
+
+/// Parameters for classifying the outcome of a run (see `metrics::Health`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// A run that ends under-collateralised is in a death spiral if its TVB has fallen below this
+    /// share of its peak...
+    pub spiral_tvb: f64,
+    /// ...while its TVL is still at least this share of its peak (so value is still at risk).
+    pub spiral_tvl: f64,
+    /// The fees that nodes earn in an epoch have collapsed when they are below this share of their
+    /// peak (or never, if zero).
+    pub fee_collapse: f64,
+    /// A run diverges when it is in a death spiral, or its fees have collapsed, for this many
+    /// consecutive epochs (or never, if zero). See `divergence::Detector`.
+    pub divergence_epochs: usize,
+    /// Whether a run stops as soon as it diverges, rather than running to the end.
+    pub abort: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            spiral_tvb: 0.1,
+            spiral_tvl: 0.5,
+            fee_collapse: 0.01,
+            divergence_epochs: 30,
+            abort: false,
+        }
+    }
+}
+
+/// Parameters for estimating what it would cost to attack RenVM (see `attack`): the attacker buys
+/// enough REN to control a share of the bond, paying more for every REN as the market thins out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttackConfig {
+    /// The share of the bonded REN that an attacker has to control to steal what is locked (a
+    /// third, for a Byzantine fault tolerant quorum).
+    pub share: f64,
+    /// How the price of REN rises as the attacker buys it.
+    pub depth: MarketDepthConfig,
+}
+
+impl Default for AttackConfig {
+    fn default() -> Self {
+        Self {
+            share: 1.0 / 3.0,
+            depth: MarketDepthConfig::ConstantProduct {
+                liquidity: Usd(10_000_000.0),
+            },
+        }
+    }
+}
+
+/// Parameters for a model of the depth of the market for REN. The `model` field selects which one
+/// is used.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum MarketDepthConfig {
+    /// Any amount of REN can be bought at the current price.
+    Infinite,
+    /// The price rises in proportion to what has been bought, by the whole current price for
+    /// every `depth` (in USD, at the current price) that is bought.
+    Linear {
+        /// The value of REN that doubles the price when it is bought.
+        depth: Usd,
+    },
+    /// The market is a constant-product pool with `liquidity` (in USD) on each side, so the REN
+    /// in the pool can never be bought out.
+    ConstantProduct {
+        /// The value of the USD (and of the REN) in the pool.
+        liquidity: Usd,
+    },
+}
+
+/// Which invariants are checked every epoch. Every invariant is checked by default; a run stops
+/// with an error at the first epoch that violates one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InvariantsConfig {
+    /// The burning fee is zero whenever the rebate is non-zero.
+    pub no_burn_fee_with_rebate: bool,
+    /// Locked value (in aggregate, and for every asset) is never negative.
+    pub non_negative_tvl: bool,
+    /// The rebate pool is never negative.
+    pub non_negative_r_pool: bool,
+    /// Every value in the state is finite (neither NaN nor infinite).
+    pub finite: bool,
+}
+
+impl Default for InvariantsConfig {
+    fn default() -> Self {
+        Self {
+            no_burn_fee_with_rebate: true,
+            non_negative_tvl: true,
+            non_negative_r_pool: true,
+            finite: true,
+        }
+    }
+}
+
+/// Parameters for bounding the history of a run, so that very long runs use a constant amount of
+/// memory. Summary statistics still cover the whole run (and per-epoch outputs are streamed), but
+/// charts need every state, so they cannot be produced from a bounded history.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// The number of days of states that are kept (every state is kept if there is none). It must
+    /// be at least as long as the longest window that the models look back over (like the ROI and
+    /// rebate windows, the latency of arbitrageurs, and the steady-state window).
+    pub window_days: Option<f64>,
+}
+
+impl HistoryConfig {
+    /// The number of epochs of states that are kept, if the history is bounded.
+    pub fn window(&self, epoch: &EpochConfig) -> Option<usize> {
+        self.window_days.map(|days| epoch.epochs(days))
+    }
+}
+
+/// Parameters for how RenVM accounts for the balances that it keeps itself (fees, rebates, and the
+/// rebate pool). The `model` field selects which one is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum AccountingConfig {
+    /// Floating point arithmetic, which is the fastest.
+    #[default]
+    Float,
+    /// Fixed-point decimal arithmetic, like the integer arithmetic on chain, so that balances do
+    /// not drift over long runs. Only available with the `decimal` feature.
+    #[cfg(feature = "decimal")]
+    Decimal {
+        /// The number of decimal places that amounts are kept to (at most 28), like the decimals
+        /// of a token.
+        decimals: u32,
+    },
+}
+
+/// Parameters for a [Rhai](https://rhai.rs) script whose functions replace the matching model
+/// functions (see `eco::script`), so that curve designs can be prototyped without recompiling.
+/// Only available with the `script` feature.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// The script file, relative to the configuration file that names it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// The source of the script, which is read from `path` when the configuration is loaded (and
+    /// kept with the configuration, so that a checkpoint resumes with the same script).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The number of days of history that the script functions are given.
+    pub window_days: f64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            source: None,
+            window_days: 30.0,
+        }
+    }
+}
+
+impl ScriptConfig {
+    /// Check that the script (if there is one) compiles, which needs the `script` feature.
+    #[cfg_attr(not(feature = "script"), allow(unused_variables))]
+    pub fn check(&self, epoch: &EpochConfig) -> Result<()> {
+        #[cfg(feature = "script")]
+        crate::script::Script::new(self, epoch)?;
+        #[cfg(not(feature = "script"))]
+        if self.source.is_some() {
+            return Err(Error::Config(
+                "scripts need the `script` feature".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The names of the models that are selected for functions of the simulation, in place of those
+/// that the rest of the configuration sets up (see `eco::registry`). Functions without a name keep
+/// their configured models.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelsConfig {
+    /// The model of the value that is bonded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tvb: Option<String>,
+    /// The model of the minting volume.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mint_volume: Option<String>,
+    /// The model of the burning volume.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burn_volume: Option<String>,
+    /// The model of the price of REN.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ren_price: Option<String>,
+    /// The model of when fees are claimed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claim: Option<String>,
+    /// The model of the minting fee.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mint_fee: Option<String>,
+    /// The model of the burning fee.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burn_fee: Option<String>,
+    /// The model of the rebate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rebate: Option<String>,
+}
+
+impl ModelsConfig {
+    /// The name of the model that is selected for a slot, if there is one.
+    pub fn get(&self, slot: Slot) -> Option<&str> {
+        self.field(slot).as_deref()
+    }
+
+    /// Select the model of a slot by its name.
+    pub fn set(&mut self, slot: Slot, name: &str) {
+        *self.field_mut(slot) = Some(name.to_string());
+    }
+
+    /// Check that every selected model is either built in or registered.
+    pub fn check(&self) -> Result<()> {
+        crate::registry::check(self)
+    }
+
+    fn field(&self, slot: Slot) -> &Option<String> {
+        match slot {
+            Slot::Tvb => &self.tvb,
+            Slot::MintVolume => &self.mint_volume,
+            Slot::BurnVolume => &self.burn_volume,
+            Slot::RenPrice => &self.ren_price,
+            Slot::Claim => &self.claim,
+            Slot::MintFee => &self.mint_fee,
+            Slot::BurnFee => &self.burn_fee,
+            Slot::Rebate => &self.rebate,
+        }
+    }
+
+    fn field_mut(&mut self, slot: Slot) -> &mut Option<String> {
+        match slot {
+            Slot::Tvb => &mut self.tvb,
+            Slot::MintVolume => &mut self.mint_volume,
+            Slot::BurnVolume => &mut self.burn_volume,
+            Slot::RenPrice => &mut self.ren_price,
+            Slot::Claim => &mut self.claim,
+            Slot::MintFee => &mut self.mint_fee,
+            Slot::BurnFee => &mut self.burn_fee,
+            Slot::Rebate => &mut self.rebate,
+        }
+    }
+}
+
+/// Parameters for detecting that a run has converged to a steady state, which is what many
+/// questions about fee designs are really about.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SteadyStateConfig {
+    /// How much (relative to its latest value) every tracked field may differ over the window.
+    pub tolerance: f64,
+    /// The number of days over which every tracked field must stay within the tolerance.
+    pub window_days: f64,
+    /// The state fields that are tracked (by their names in the output).
+    pub fields: Vec<String>,
+    /// Whether the run stops as soon as a steady state is reached.
+    pub stop: bool,
+}
+
+impl Default for SteadyStateConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.01,
+            window_days: 30.0,
+            fields: ["tvl", "tvb", "r", "f_unclaimed", "r_pool"]
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            stop: false,
+        }
+    }
+}
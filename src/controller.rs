@@ -0,0 +1,80 @@
+//! For setting the fees with a PID controller that steers the security ratio towards a target,
+//! instead of fixing them. Fees pay for bonding, so raising them while too little is bonded draws
+//! more bonds in, and lowering them while more is bonded than needed leaves more with users. The
+//! controller runs once per epoch, on the security ratio of the previous epoch, and its adjustment
+//! is added to every fee of that epoch (including the fees of individual assets).
+
+use crate::config::PidConfig;
+use crate::history::History;
+use crate::state::ControllerState;
+use crate::Pct;
+use serde::{Deserialize, Serialize};
+
+/// Pid adjusts the fees according to a `PidConfig`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pid {
+    config: PidConfig,
+    /// The epoch that the adjustment was last computed for, or zero before the first epoch.
+    epoch: usize,
+    /// The error of the epoch before the latest one, if there was one.
+    previous: Option<f64>,
+    state: ControllerState,
+    adjustment: f64,
+}
+
+impl Pid {
+    /// Create a controller using the given parameters, which has seen no error yet.
+    pub fn new(config: PidConfig) -> Self {
+        Self {
+            config,
+            epoch: 0,
+            previous: None,
+            state: ControllerState::default(),
+            adjustment: 0.0,
+        }
+    }
+
+    /// Update the controller for the next epoch of the history. This can be called any number of
+    /// times in an epoch, but only the first call in an epoch moves the controller.
+    fn update(&mut self, history: &History) {
+        let epoch = history.len();
+        if epoch <= self.epoch {
+            return;
+        }
+        let config = &self.config;
+        let state = history.latest();
+        // Nothing is at risk before anything is locked, so there is no error to correct.
+        let error = if state.tvl.0 > 0.0 {
+            config.target_ratio - state.security_ratio
+        } else {
+            0.0
+        };
+        // The integral term alone never moves the fees by more than the range that they can take,
+        // so that it does not wind up while they are held at a bound.
+        let range = (config.max_fee.0 - config.min_fee.0).max(0.0);
+        let mut integral = self.state.integral + error;
+        if config.ki != 0.0 {
+            let max = range / config.ki.abs();
+            integral = integral.clamp(-max, max);
+        }
+        let derivative = self.previous.map_or(0.0, |previous| error - previous);
+        self.adjustment = config.kp * error + config.ki * integral + config.kd * derivative;
+        self.previous = Some(error);
+        self.state = ControllerState { error, integral };
+        self.epoch = epoch;
+    }
+
+    /// The fee for the next epoch of the history, given the fee that would be charged without the
+    /// controller, kept between the bounds of the controller.
+    pub fn fee(&mut self, history: &History, fee: Pct) -> Pct {
+        self.update(history);
+        let config = &self.config;
+        Pct((fee.0 + self.adjustment)
+            .clamp(config.min_fee.0, config.max_fee.0.max(config.min_fee.0)))
+    }
+
+    /// The diagnostics of the controller for the latest epoch that it was updated for.
+    pub fn state(&self) -> ControllerState {
+        self.state
+    }
+}
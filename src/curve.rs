@@ -0,0 +1,493 @@
+//! For writing the curves of the internal model as expressions in the configuration, which covers
+//! the common case of an algebraic curve without a script. A fee (or share) is either a number, or
+//! a string holding an expression of the latest state that is evaluated every epoch:
+//!
+//! ```toml
+//! [internal]
+//! mint_fee = "clamp(0.001 + 0.002 * (tvl / tvb - 1), 0.0005, 0.01)"
+//! ```
+//!
+//! Expressions are made of numbers, the fields of the state (by their names in the output, like
+//! `tvl`, `tvb`, `r`, and `r_pool`), `epoch` (the epoch that is being simulated), the operators
+//! `+`, `-`, `*`, `/`, `^` (power), and `<`, `<=`, `>`, `>=`, `==`, `!=` (which are one if they
+//! hold and zero otherwise), and the functions `min`, `max`, `clamp(x, low, high)`, `abs`, `sqrt`,
+//! `exp`, `ln`, and `if(condition, then, else)`. Dividing by zero is zero, so that ratios like
+//! `tvl / tvb` are defined before anything is bonded.
+
+use crate::history::History;
+use crate::state::State;
+use crate::Pct;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Curve is a value of the internal model that is either constant or given by an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    Constant(Pct),
+    Expression(Expression),
+}
+
+impl Curve {
+    /// The value of the curve for the next epoch of the history.
+    pub fn value(&self, history: &History) -> Pct {
+        match self {
+            Curve::Constant(value) => *value,
+            Curve::Expression(expression) => Pct(expression.eval(history.latest(), history.len())),
+        }
+    }
+}
+
+impl From<Pct> for Curve {
+    fn from(value: Pct) -> Self {
+        Curve::Constant(value)
+    }
+}
+
+/// Curves are serialized as a number when they are constant, and as the source of the expression
+/// otherwise, so that constant curves remain numeric parameters (for sweeps and the like).
+impl Serialize for Curve {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Curve::Constant(value) => value.serialize(serializer),
+            Curve::Expression(expression) => serializer.serialize_str(&expression.source),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Curve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CurveVisitor;
+
+        impl<'de> Visitor<'de> for CurveVisitor {
+            type Value = Curve;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number or an expression")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Curve, E> {
+                Ok(Curve::Constant(Pct(value)))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Curve, E> {
+                Ok(Curve::Constant(Pct(value as f64)))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Curve, E> {
+                Ok(Curve::Constant(Pct(value as f64)))
+            }
+
+            fn visit_str<E: de::Error>(self, source: &str) -> Result<Curve, E> {
+                source
+                    .parse()
+                    .map(Curve::Expression)
+                    .map_err(|err| E::custom(format!("invalid expression `{}`: {}", source, err)))
+            }
+        }
+
+        deserializer.deserialize_any(CurveVisitor)
+    }
+}
+
+/// Expression is a parsed expression, which keeps its source for serialization.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    /// Evaluate the expression for the epoch, given the latest state.
+    pub fn eval(&self, state: &State, epoch: usize) -> f64 {
+        self.root.eval(&state.values(), epoch as f64)
+    }
+
+    /// The source of the expression.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Expressions are serialized as their source.
+impl Serialize for Expression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        source
+            .parse()
+            .map_err(|err| de::Error::custom(format!("invalid expression `{}`: {}", source, err)))
+    }
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            next: 0,
+            end: source.len(),
+        };
+        let root = parser.expression()?;
+        if let Some((position, _)) = parser.peek() {
+            return Err(ParseError::new(position, "unexpected input"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+}
+
+/// ParseError describes why an expression is invalid, and where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// The byte offset in the source at which the error was found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    If,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "clamp" => Some(Function::Clamp),
+            "abs" => Some(Function::Abs),
+            "sqrt" => Some(Function::Sqrt),
+            "exp" => Some(Function::Exp),
+            "ln" => Some(Function::Ln),
+            "if" => Some(Function::If),
+            _ => None,
+        }
+    }
+
+    /// Whether the function can be called with the given number of arguments.
+    fn takes(self, args: usize) -> bool {
+        match self {
+            Function::Min | Function::Max => args >= 1,
+            Function::Clamp | Function::If => args == 3,
+            Function::Abs | Function::Sqrt | Function::Exp | Function::Ln => args == 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(f64),
+    /// A field of the state, by its index in `State::FIELDS`.
+    Field(usize),
+    Epoch,
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, fields: &[f64], epoch: f64) -> f64 {
+        let eval = |node: &Node| node.eval(fields, epoch);
+        let truth = |holds: bool| if holds { 1.0 } else { 0.0 };
+        match self {
+            Node::Number(value) => *value,
+            Node::Field(i) => fields[*i],
+            Node::Epoch => epoch,
+            Node::Neg(node) => -eval(node),
+            Node::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (eval(lhs), eval(rhs));
+                match op {
+                    Op::Add => lhs + rhs,
+                    Op::Sub => lhs - rhs,
+                    Op::Mul => lhs * rhs,
+                    Op::Div if rhs == 0.0 => 0.0,
+                    Op::Div => lhs / rhs,
+                    Op::Pow => lhs.powf(rhs),
+                    Op::Lt => truth(lhs < rhs),
+                    Op::Le => truth(lhs <= rhs),
+                    Op::Gt => truth(lhs > rhs),
+                    Op::Ge => truth(lhs >= rhs),
+                    Op::Eq => truth(lhs == rhs),
+                    Op::Ne => truth(lhs != rhs),
+                }
+            }
+            Node::Call(function, args) => match function {
+                Function::Min => args.iter().map(eval).fold(f64::INFINITY, f64::min),
+                Function::Max => args.iter().map(eval).fold(f64::NEG_INFINITY, f64::max),
+                Function::Clamp => eval(&args[0]).max(eval(&args[1])).min(eval(&args[2])),
+                Function::Abs => eval(&args[0]).abs(),
+                Function::Sqrt => eval(&args[0]).sqrt(),
+                Function::Exp => eval(&args[0]).exp(),
+                Function::Ln => eval(&args[0]).ln(),
+                Function::If if eval(&args[0]) != 0.0 => eval(&args[1]),
+                Function::If => eval(&args[2]),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(Op),
+    Open,
+    Close,
+    Comma,
+}
+
+/// Split the source into tokens, each with its position.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '0'..='9' | '.' => {
+                let mut end = position + 1;
+                let mut last = c;
+                while let Some(&(i, c)) = chars.peek() {
+                    let exponent = (c == '+' || c == '-') && (last == 'e' || last == 'E');
+                    if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent) {
+                        break;
+                    }
+                    chars.next();
+                    end = i + 1;
+                    last = c;
+                }
+                let number = &source[position..end];
+                Token::Number(number.parse().map_err(|_| {
+                    ParseError::new(position, format!("invalid number `{}`", number))
+                })?)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position + 1;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    chars.next();
+                    end = i + 1;
+                }
+                Token::Name(source[position..end].to_string())
+            }
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Sub),
+            '*' => Token::Op(Op::Mul),
+            '/' => Token::Op(Op::Div),
+            '^' => Token::Op(Op::Pow),
+            '<' | '>' | '=' | '!' => {
+                let op = match (c, next) {
+                    ('<', Some('=')) => Op::Le,
+                    ('>', Some('=')) => Op::Ge,
+                    ('=', Some('=')) => Op::Eq,
+                    ('!', Some('=')) => Op::Ne,
+                    ('<', _) => Op::Lt,
+                    ('>', _) => Op::Gt,
+                    _ => return Err(ParseError::new(position, format!("unexpected `{}`", c))),
+                };
+                if next == Some('=') {
+                    chars.next();
+                }
+                Token::Op(op)
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            c => return Err(ParseError::new(position, format!("unexpected `{}`", c))),
+        };
+        tokens.push((position, token));
+    }
+    Ok(tokens)
+}
+
+/// Parser is a recursive descent parser, where each method parses one level of precedence.
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    next: usize,
+    /// The length of the source, which is where errors at the end of the input are reported.
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens
+            .get(self.next)
+            .map(|(position, token)| (*position, token))
+    }
+
+    /// Consume the next token if it is one of the operators.
+    fn op(&mut self, ops: &[Op]) -> Option<Op> {
+        match self.peek() {
+            Some((_, Token::Op(op))) if ops.contains(op) => {
+                let op = *op;
+                self.next += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// Consume the next token, which must be the given one.
+    fn expect(&mut self, expected: Token, description: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some((_, token)) if *token == expected => {
+                self.next += 1;
+                Ok(())
+            }
+            Some((position, _)) => Err(ParseError::new(
+                position,
+                format!("expected {}", description),
+            )),
+            None => Err(ParseError::new(
+                self.end,
+                format!("expected {}", description),
+            )),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let lhs = self.sum()?;
+        match self.op(&[Op::Lt, Op::Le, Op::Gt, Op::Ge, Op::Eq, Op::Ne]) {
+            Some(op) => Ok(Node::Binary(op, Box::new(lhs), Box::new(self.sum()?))),
+            None => Ok(lhs),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.product()?;
+        while let Some(op) = self.op(&[Op::Add, Op::Sub]) {
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.op(&[Op::Mul, Op::Div]) {
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        if self.op(&[Op::Sub]).is_some() {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// Powers bind tighter than negation on their left (so `-x^2` is `-(x^2)`), and to the right.
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.atom()?;
+        match self.op(&[Op::Pow]) {
+            Some(op) => Ok(Node::Binary(op, Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, ParseError> {
+        let (position, token) = match self.peek() {
+            Some((position, token)) => (position, token.clone()),
+            None => return Err(ParseError::new(self.end, "unexpected end of expression")),
+        };
+        self.next += 1;
+        match token {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::Open => {
+                let node = self.expression()?;
+                self.expect(Token::Close, "`)`")?;
+                Ok(node)
+            }
+            Token::Name(name) if self.peek().map(|(_, token)| token) == Some(&Token::Open) => {
+                let function = Function::from_name(&name).ok_or_else(|| {
+                    ParseError::new(position, format!("unknown function `{}`", name))
+                })?;
+                self.next += 1;
+                let mut args = vec![];
+                if self.peek().map(|(_, token)| token) != Some(&Token::Close) {
+                    args.push(self.expression()?);
+                    while self.peek().map(|(_, token)| token) == Some(&Token::Comma) {
+                        self.next += 1;
+                        args.push(self.expression()?);
+                    }
+                }
+                self.expect(Token::Close, "`,` or `)`")?;
+                if !function.takes(args.len()) {
+                    return Err(ParseError::new(
+                        position,
+                        format!("`{}` cannot take {} arguments", name, args.len()),
+                    ));
+                }
+                Ok(Node::Call(function, args))
+            }
+            Token::Name(name) if name == "epoch" => Ok(Node::Epoch),
+            Token::Name(name) => State::FIELDS
+                .iter()
+                .position(|field| *field == name)
+                .map(Node::Field)
+                .ok_or_else(|| ParseError::new(position, format!("unknown variable `{}`", name))),
+            _ => Err(ParseError::new(
+                position,
+                "expected a number, variable, or `(`",
+            )),
+        }
+    }
+}
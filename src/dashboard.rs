@@ -0,0 +1,278 @@
+//! For watching a simulation as it runs, which is useful for demos and for building intuition about
+//! how a curve behaves. With the `tui` feature, `econ-sim watch` draws the same three charts as
+//! `--plot` (TVL vs TVB, fee income per epoch, and the rebate pool) in the terminal, and updates
+//! them every epoch. The run can be paused, stepped one epoch at a time, and sped up or slowed
+//! down.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::simulation::Simulation;
+use crate::state::State;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+/// The slowest and fastest that a run can go, in epochs per second.
+const SPEEDS: (f64, f64) = (0.25, 1000.0);
+
+/// How often the charts are redrawn while nothing else happens.
+const FRAME: Duration = Duration::from_millis(50);
+
+/// Dashboard is what is shown about a run, and how it is being driven.
+struct Dashboard {
+    sim: Simulation,
+    /// The epoch at which the run ends.
+    end: usize,
+    stop_at_steady_state: bool,
+    speed: f64,
+    paused: bool,
+    /// Why the run ended, if it has.
+    finished: Option<String>,
+}
+
+/// Watch the simulation run for the given number of epochs at the given speed (in epochs per
+/// second), until it is quit. The run stops early at a steady state or at its first divergence if
+/// the configuration says so, and at the first invariant that it breaks.
+pub fn watch(sim: Simulation, config: &Config, steps: usize, speed: f64) -> Result<()> {
+    let mut dashboard = Dashboard {
+        end: sim.epoch() + steps,
+        sim,
+        stop_at_steady_state: config.steady_state.stop,
+        speed: speed.clamp(SPEEDS.0, SPEEDS.1),
+        paused: false,
+        finished: None,
+    };
+    let mut terminal = ratatui::init();
+    let result = dashboard.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl Dashboard {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut next = Instant::now();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = if self.running() {
+                next.saturating_duration_since(Instant::now()).min(FRAME)
+            } else {
+                FRAME
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char(' ') | KeyCode::Char('p') => {
+                            self.paused = !self.paused;
+                            next = Instant::now();
+                        }
+                        KeyCode::Char('s') | KeyCode::Right if self.paused => self.step(),
+                        KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => {
+                            self.speed = (self.speed * 2.0).min(SPEEDS.1)
+                        }
+                        KeyCode::Char('-') | KeyCode::Down => {
+                            self.speed = (self.speed / 2.0).max(SPEEDS.0)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // Fast runs simulate as many epochs as are due before drawing again, but a run that has
+            // fallen behind (because drawing is slow) does not try to catch up.
+            let now = Instant::now();
+            let frame = now + FRAME;
+            while self.running() && next <= now && Instant::now() < frame {
+                self.step();
+                next += Duration::from_secs_f64(1.0 / self.speed);
+            }
+            if next < now {
+                next = now;
+            }
+        }
+    }
+
+    /// Whether epochs are simulated on their own.
+    fn running(&self) -> bool {
+        !self.paused && self.finished.is_none()
+    }
+
+    /// Simulate one epoch, unless the run has finished.
+    fn step(&mut self) {
+        if self.finished.is_some() {
+            return;
+        }
+        if self.sim.epoch() >= self.end {
+            self.finished = Some("finished".to_string());
+            return;
+        }
+        if let Err(err) = self.sim.try_step() {
+            self.finished = Some(err.to_string());
+            return;
+        }
+        if self.sim.epoch() >= self.end {
+            self.finished = Some("finished".to_string());
+        } else if let Some(divergence) = self.sim.aborted() {
+            self.finished = Some(format!("diverged: {}", divergence));
+        } else if let Some(stop) = self.sim.stopped() {
+            self.finished = Some(format!("stopped: {}", stop));
+        } else if self.stop_at_steady_state {
+            if let Some(equilibrium) = &self.sim.history().aggregates().equilibrium {
+                self.finished = Some(format!(
+                    "reached a steady state at epoch {}",
+                    equilibrium.epoch
+                ));
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, tvl, income, pool, footer] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let history = self.sim.history();
+        let first = history.first_epoch();
+        let states = history.states();
+
+        let status = match (&self.finished, self.paused) {
+            (Some(reason), _) => reason.clone(),
+            (None, true) => "paused".to_string(),
+            (None, false) => format!("running at {} epochs/s", self.speed),
+        };
+        let latest = history.latest();
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(format!(
+                    "epoch {} of {}   seed {}   {}",
+                    self.sim.epoch(),
+                    self.end,
+                    self.sim.seed(),
+                    status
+                )),
+                Line::from(format!(
+                    "tvl {}   tvb {}   security {:.2}   mf {:.4}%   bf {:.4}%   r {:.4}%   pool {}",
+                    short(latest.tvl.0),
+                    short(latest.tvb.0),
+                    latest.security_ratio,
+                    latest.mf.0 * 100.0,
+                    latest.bf.0 * 100.0,
+                    latest.r.0 * 100.0,
+                    short(latest.r_pool.0)
+                )),
+            ])
+            .block(Block::bordered().title(" econ-sim ")),
+            header,
+        );
+
+        // Fee income is the increase in the total fees owed to nodes (claimed or not) since the
+        // previous epoch, as in the charts of `--plot`.
+        let owed = |state: &State| (state.f_claimed + state.f_unclaimed).0;
+        let income_points: Vec<(f64, f64)> = states
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| ((first + i + 1) as f64, owed(&w[1]) - owed(&w[0])))
+            .collect();
+        let points = |f: fn(&State) -> f64| -> Vec<(f64, f64)> {
+            states
+                .iter()
+                .enumerate()
+                .map(|(i, state)| ((first + i) as f64, f(state)))
+                .collect()
+        };
+        let x = (
+            first as f64,
+            (first + states.len()).saturating_sub(1).max(first + 1) as f64,
+        );
+        chart(
+            frame,
+            tvl,
+            "TVL vs TVB (USD)",
+            x,
+            &[
+                ("tvl", Color::Red, &points(|state| state.tvl.0)),
+                ("tvb", Color::Blue, &points(|state| state.tvb.0)),
+            ],
+        );
+        chart(
+            frame,
+            income,
+            "Fee income per epoch (USD)",
+            x,
+            &[("income", Color::Green, &income_points)],
+        );
+        chart(
+            frame,
+            pool,
+            "Rebate pool (USD)",
+            x,
+            &[("r_pool", Color::Magenta, &points(|state| state.r_pool.0))],
+        );
+
+        frame.render_widget(
+            Line::from(" space: pause/resume   s/→: step (when paused)   +/-: speed   q: quit ")
+                .reversed(),
+            footer,
+        );
+    }
+}
+
+/// A named series of points (epoch, value), drawn in the given colour.
+type Series<'a> = (&'a str, Color, &'a [(f64, f64)]);
+
+/// Draw a chart of the series over the epochs in `x`.
+fn chart(frame: &mut Frame, area: Rect, title: &str, x: (f64, f64), series: &[Series]) {
+    let values = || {
+        series
+            .iter()
+            .flat_map(|(_, _, points)| points.iter().map(|(_, y)| *y))
+    };
+    let low = values().fold(0.0, f64::min);
+    let high = values().fold(0.0, f64::max).max(low + 1.0);
+    let datasets = series
+        .iter()
+        .map(|(name, colour, points)| {
+            Dataset::default()
+                .name(*name)
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*colour))
+                .data(points)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title(format!(" {} ", title)))
+        .x_axis(
+            Axis::default()
+                .bounds([x.0, x.1])
+                .labels([format!("{}", x.0), format!("{}", x.1)]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([low, high])
+                .labels([short(low), short(high)]),
+        );
+    frame.render_widget(chart, area);
+}
+
+/// A number in a few characters (like `12.3M`), for the labels of the charts.
+fn short(value: f64) -> String {
+    let (scale, suffix) = match value.abs() {
+        v if v >= 1e9 => (1e9, "B"),
+        v if v >= 1e6 => (1e6, "M"),
+        v if v >= 1e3 => (1e3, "k"),
+        _ => (1.0, ""),
+    };
+    format!("{:.1}{}", value / scale, suffix)
+}
@@ -0,0 +1,64 @@
+//! For catching accidental nondeterminism. Two runs of the same configuration with the same seed
+//! must produce the same states, bit for bit; anything that breaks this (iterating over a hash map,
+//! drawing randomness from anywhere but the seeded generator, or depending on how work is spread
+//! between threads) means that runs can no longer be reproduced from their seed. The two runs are
+//! simulated at the same time, on separate threads when there are several, so that they also share
+//! whatever global state there is.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::parallel;
+use crate::simulation::Simulation;
+use crate::state::State;
+use serde_json::Value;
+
+/// Simulate the configuration twice for `steps` epochs with the same seed, and check that every
+/// state (including the initial state) is identical in both, returning the number of states that
+/// were compared. The first difference is described in the error.
+pub fn verify(config: &Config, seed: u64, steps: usize) -> Result<usize> {
+    let mut runs = parallel::map(vec![config.clone(), config.clone()], |config| {
+        let mut sim = Simulation::from_config(&config).with_seed(seed);
+        let mut states = vec![sim.history().latest().clone()];
+        states.extend(sim.run(steps));
+        states
+    });
+    let (second, first) = (runs.pop().expect("two runs"), runs.pop().expect("two runs"));
+    for (epoch, (a, b)) in first.iter().zip(&second).enumerate() {
+        if let Some((field, a, b)) = difference(a, b)? {
+            return Err(Error::Nondeterminism(format!(
+                "runs with seed {} diverged at epoch {}: `{}` was {} and then {}",
+                seed, epoch, field, a, b
+            )));
+        }
+    }
+    Ok(first.len())
+}
+
+/// The first field (by its path) in which the states differ, and its value in each.
+fn difference(a: &State, b: &State) -> Result<Option<(String, Value, Value)>> {
+    Ok(diff(
+        String::new(),
+        serde_json::to_value(a)?,
+        serde_json::to_value(b)?,
+    ))
+}
+
+fn diff(path: String, a: Value, b: Value) -> Option<(String, Value, Value)> {
+    let join = |key: &dyn std::fmt::Display| match path.as_str() {
+        "" => key.to_string(),
+        path => format!("{}.{}", path, key),
+    };
+    match (a, b) {
+        (Value::Object(a), Value::Object(mut b)) => a.into_iter().find_map(|(key, a)| {
+            let b = b.remove(&key).unwrap_or(Value::Null);
+            diff(join(&key), a, b)
+        }),
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => a
+            .into_iter()
+            .zip(b)
+            .enumerate()
+            .find_map(|(i, (a, b))| diff(join(&i), a, b)),
+        (a, b) if a == b => None,
+        (a, b) => Some((path, a, b)),
+    }
+}
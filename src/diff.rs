@@ -0,0 +1,305 @@
+//! For regression workflows: checking whether two result files (written with `--out` or
+//! `--format`, as CSV or JSON Lines) hold the same run, field by field and epoch by epoch. A model
+//! change that should not move anything can be checked against the results from before it, and one
+//! that should can be reviewed by where (and how far) it moved them. Values match when they are
+//! within the tolerances, which are exact by default and can be loosened for every field or for
+//! one. Only the aggregate fields of the state are compared, since per-asset and per-shard columns
+//! are not read back from CSV.
+
+use crate::error::{Error, Result};
+use crate::state::State;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Tolerance is how far apart two values may be and still match: by `abs` plus `rel` of the larger
+/// of the two (by magnitude). Two NaNs match each other.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64,
+}
+
+impl Tolerance {
+    /// Whether the two values match.
+    pub fn allows(&self, a: f64, b: f64) -> bool {
+        a == b
+            || (a.is_nan() && b.is_nan())
+            || (b - a).abs() <= self.abs + self.rel * a.abs().max(b.abs())
+    }
+}
+
+/// Tolerances are the tolerance of every field: one for every field, unless the field has its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tolerances {
+    rel: f64,
+    abs: f64,
+    fields_rel: BTreeMap<&'static str, f64>,
+    fields_abs: BTreeMap<&'static str, f64>,
+}
+
+impl Tolerances {
+    /// Set a relative tolerance from `tol` (for every field) or `field=tol` (for one field).
+    pub fn with_rel(mut self, spec: &str) -> Result<Self> {
+        match parse(spec)? {
+            (Some(field), tol) => self.fields_rel.insert(field, tol),
+            (None, tol) => Some(std::mem::replace(&mut self.rel, tol)),
+        };
+        Ok(self)
+    }
+
+    /// Set an absolute tolerance from `tol` (for every field) or `field=tol` (for one field).
+    pub fn with_abs(mut self, spec: &str) -> Result<Self> {
+        match parse(spec)? {
+            (Some(field), tol) => self.fields_abs.insert(field, tol),
+            (None, tol) => Some(std::mem::replace(&mut self.abs, tol)),
+        };
+        Ok(self)
+    }
+
+    /// The tolerance of the given field.
+    pub fn get(&self, field: &str) -> Tolerance {
+        Tolerance {
+            abs: self.fields_abs.get(field).copied().unwrap_or(self.abs),
+            rel: self.fields_rel.get(field).copied().unwrap_or(self.rel),
+        }
+    }
+}
+
+/// Parse `tol` or `field=tol`, where the field is one of `State::FIELDS` and the tolerance is not
+/// negative.
+fn parse(spec: &str) -> Result<(Option<&'static str>, f64)> {
+    let invalid = || Error::Config(format!("invalid tolerance `{}`", spec));
+    let (field, tol) = match spec.split_once('=') {
+        Some((field, tol)) => {
+            let field = field.trim();
+            let known = State::FIELDS.iter().find(|known| **known == field);
+            let field = known.ok_or_else(|| {
+                Error::Config(format!("no field `{}` to set a tolerance for", field))
+            })?;
+            (Some(*field), tol)
+        }
+        None => (None, spec),
+    };
+    let tol: f64 = tol.trim().parse().map_err(|_| invalid())?;
+    if tol.is_nan() || tol < 0.0 {
+        return Err(invalid());
+    }
+    Ok((field, tol))
+}
+
+/// Difference is one value that does not match between the files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difference {
+    pub epoch: usize,
+    pub field: &'static str,
+    /// The value in the first file.
+    pub a: f64,
+    /// The value in the second file.
+    pub b: f64,
+}
+
+/// FieldDiff summarises how one field differs across every epoch that both files have.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    /// The largest difference (second file less first file), by magnitude.
+    pub max_diff: f64,
+    /// The epoch of the largest difference.
+    pub max_diff_epoch: usize,
+    /// The largest difference relative to the larger of the two values.
+    pub max_rel: f64,
+    /// The number of epochs in which the field does not match.
+    pub epochs: usize,
+    /// The first epoch in which the field does not match, if there is one.
+    pub first: Option<usize>,
+}
+
+/// Diff is how two result files differ.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diff {
+    /// The number of epochs that both files have.
+    pub epochs: usize,
+    /// The epochs that only the first file has.
+    pub only_a: Vec<usize>,
+    /// The epochs that only the second file has.
+    pub only_b: Vec<usize>,
+    /// Every value that does not match, by epoch and then in the order of `State::FIELDS`.
+    pub differences: Vec<Difference>,
+    /// How every field differs, in the order of `State::FIELDS`.
+    pub fields: Vec<FieldDiff>,
+}
+
+impl Diff {
+    /// Compare two runs, given as states with their epochs (as they are read back from result
+    /// files), epoch by epoch.
+    pub fn new(a: &[(usize, State)], b: &[(usize, State)], tolerances: &Tolerances) -> Self {
+        let a: BTreeMap<usize, &State> = a.iter().map(|(epoch, state)| (*epoch, state)).collect();
+        let b: BTreeMap<usize, &State> = b.iter().map(|(epoch, state)| (*epoch, state)).collect();
+        let only = |a: &BTreeMap<usize, &State>, b: &BTreeMap<usize, &State>| {
+            a.keys()
+                .filter(|epoch| !b.contains_key(epoch))
+                .copied()
+                .collect()
+        };
+        let mut fields: Vec<FieldDiff> = State::FIELDS
+            .iter()
+            .map(|&field| FieldDiff {
+                field,
+                max_diff: 0.0,
+                max_diff_epoch: 0,
+                max_rel: 0.0,
+                epochs: 0,
+                first: None,
+            })
+            .collect();
+        let tolerances: Vec<Tolerance> = State::FIELDS
+            .iter()
+            .map(|field| tolerances.get(field))
+            .collect();
+        let mut differences = vec![];
+        let mut epochs = 0;
+        for (&epoch, state_a) in &a {
+            let Some(state_b) = b.get(&epoch) else {
+                continue;
+            };
+            epochs += 1;
+            let (values_a, values_b) = (state_a.values(), state_b.values());
+            for (i, field) in fields.iter_mut().enumerate() {
+                let (a, b) = (values_a[i], values_b[i]);
+                let diff = b - a;
+                if diff.abs() > field.max_diff.abs() {
+                    field.max_diff = diff;
+                    field.max_diff_epoch = epoch;
+                }
+                let larger = a.abs().max(b.abs());
+                if larger > 0.0 {
+                    field.max_rel = field.max_rel.max(diff.abs() / larger);
+                }
+                if !tolerances[i].allows(a, b) {
+                    field.epochs += 1;
+                    field.first.get_or_insert(epoch);
+                    differences.push(Difference {
+                        epoch,
+                        field: field.field,
+                        a,
+                        b,
+                    });
+                }
+            }
+        }
+        Self {
+            epochs,
+            only_a: only(&a, &b),
+            only_b: only(&b, &a),
+            differences,
+            fields,
+        }
+    }
+
+    /// Whether the files match: they have the same epochs, and every value matches.
+    pub fn is_match(&self) -> bool {
+        self.differences.is_empty() && self.only_a.is_empty() && self.only_b.is_empty()
+    }
+
+    /// Write how every field differs as a table (marking the fields that do not match), followed
+    /// by the first `rows` values that do not match, and the epochs that only one file has.
+    pub fn write_text<W: Write>(&self, mut writer: W, rows: usize) -> Result<()> {
+        writeln!(
+            writer,
+            "  {:<18} {:>26} {:>14} {:>8} {:>8}",
+            "field", "largest b - a (epoch)", "largest rel", "epochs", "first"
+        )?;
+        for field in &self.fields {
+            writeln!(
+                writer,
+                "{} {:<18} {:>26} {:>14.3e} {:>8} {:>8}",
+                if field.epochs > 0 { "*" } else { " " },
+                field.field,
+                format!("{:+.6e} ({})", field.max_diff, field.max_diff_epoch),
+                field.max_rel,
+                field.epochs,
+                field
+                    .first
+                    .map_or("-".to_string(), |epoch| epoch.to_string()),
+            )?;
+        }
+        if !self.differences.is_empty() && rows > 0 {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "{:>8} {:<18} {:>24} {:>24} {:>24}",
+                "epoch", "field", "a", "b", "b - a"
+            )?;
+            for d in self.differences.iter().take(rows) {
+                writeln!(
+                    writer,
+                    "{:>8} {:<18} {:>24} {:>24} {:>+24}",
+                    d.epoch,
+                    d.field,
+                    d.a,
+                    d.b,
+                    d.b - d.a
+                )?;
+            }
+            if self.differences.len() > rows {
+                writeln!(writer, "... and {} more", self.differences.len() - rows)?;
+            }
+        }
+        for (name, only) in [("a", &self.only_a), ("b", &self.only_b)] {
+            if let (Some(first), Some(last)) = (only.first(), only.last()) {
+                writeln!(
+                    writer,
+                    "{} epochs only in {} (from {} to {})",
+                    only.len(),
+                    name,
+                    first,
+                    last
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every value that does not match as CSV, with one row per epoch and field.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        writer.write_record(["epoch", "field", "a", "b", "diff"])?;
+        for d in &self.differences {
+            writer.write_record([
+                d.epoch.to_string(),
+                d.field.to_string(),
+                d.a.to_string(),
+                d.b.to_string(),
+                (d.b - d.a).to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Describe in a line whether the files match, and if not, how much of them does not.
+    pub fn summary(&self) -> String {
+        if self.is_match() {
+            return format!(
+                "the files match in all {} epochs ({} fields)",
+                self.epochs,
+                self.fields.len()
+            );
+        }
+        let fields = self.fields.iter().filter(|field| field.epochs > 0).count();
+        let mut parts = vec![];
+        if fields > 0 {
+            parts.push(format!(
+                "{} values of {} fields differ beyond the tolerances in {} common epochs",
+                self.differences.len(),
+                fields,
+                self.epochs
+            ));
+        }
+        let unmatched = self.only_a.len() + self.only_b.len();
+        if unmatched > 0 {
+            parts.push(format!("{} epochs are only in one file", unmatched));
+        }
+        parts.join(", and ")
+    }
+}
@@ -0,0 +1,157 @@
+//! For detecting that a run has clearly failed before it ends, so that it can be abandoned rather
+//! than simulated to the last epoch (which, across a large ensemble, is mostly spent on runs whose
+//! outcome is already settled). A run diverges when, for a whole window of consecutive epochs,
+//! either it is in a death spiral (under-collateralised, with TVB collapsed from its peak while TVL
+//! has held up) or the fees that nodes earn have collapsed to a small share of their peak.
+
+use crate::config::HealthConfig;
+use crate::history::{Aggregates, History};
+use crate::metrics::Security;
+use crate::state::State;
+use crate::Usd;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Detector detects divergence in the history of a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detector {
+    config: HealthConfig,
+}
+
+/// Reason is how a run diverged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reason {
+    /// It stayed under-collateralised with TVB collapsed from its peak while TVL held up.
+    DeathSpiral,
+    /// The fees that nodes earned stayed below a small share of their peak.
+    FeeCollapse,
+}
+
+/// Divergence is the divergence that was detected in a run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Divergence {
+    /// The first epoch of the window over which the run diverged.
+    pub epoch: usize,
+    /// The epoch at which the divergence was detected (the last epoch of the window).
+    pub detected: usize,
+    pub reason: Reason,
+    /// The values that show the divergence, at the epoch at which it was detected.
+    pub values: Vec<(String, f64)>,
+}
+
+impl Detector {
+    /// Create a detector using the given parameters, or `None` if divergence is not detected
+    /// (when its window is zero epochs).
+    pub fn new(config: &HealthConfig) -> Option<Self> {
+        (config.divergence_epochs > 0).then(|| Self {
+            config: config.clone(),
+        })
+    }
+
+    /// The number of consecutive epochs over which a run must diverge.
+    pub fn window(&self) -> usize {
+        self.config.divergence_epochs
+    }
+
+    /// The divergence that the latest epoch ends, if the run has diverged over the window that
+    /// ends with it. The window must not begin before the end of the warm-up.
+    pub fn reached(&self, history: &History) -> Option<Divergence> {
+        self.reached_at(history.states(), history.epoch(), history.aggregates())
+    }
+
+    /// Search the history of a run (from its initial state) for the first divergence, after the
+    /// given number of warm-up epochs.
+    pub fn find(
+        &self,
+        history: &[State],
+        warmup: usize,
+        required_ratio: f64,
+    ) -> Option<Divergence> {
+        let mut aggregates = Aggregates::new(history.first()?, warmup, required_ratio);
+        (1..history.len()).find_map(|epoch| {
+            aggregates.add(epoch, &history[epoch]);
+            self.reached_at(&history[..=epoch], epoch, &aggregates)
+        })
+    }
+
+    /// The divergence that ends with the last of the states (the state at `epoch`), judged
+    /// against the aggregates so far. The latest epoch is checked first, since it is usually
+    /// enough to rule divergence out.
+    fn reached_at(
+        &self,
+        states: &[State],
+        epoch: usize,
+        aggregates: &Aggregates,
+    ) -> Option<Divergence> {
+        let window = self.window();
+        if epoch < aggregates.warmup + window || states.len() <= window {
+            return None;
+        }
+        let recent = &states[states.len() - window - 1..];
+        let spiral = recent[1..]
+            .iter()
+            .rev()
+            .all(|state| is_spiral(state, aggregates, &self.config));
+        let collapse = self.config.fee_collapse > 0.0
+            && aggregates.peak_fees > Usd::ZERO
+            && recent.windows(2).rev().all(|pair| {
+                fees(&pair[0], &pair[1]) < self.config.fee_collapse * aggregates.peak_fees
+            });
+        let latest = states.last().expect("missing latest state");
+        let reason = if spiral {
+            Reason::DeathSpiral
+        } else if collapse {
+            Reason::FeeCollapse
+        } else {
+            return None;
+        };
+        Some(Divergence {
+            epoch: epoch + 1 - window,
+            detected: epoch,
+            reason,
+            values: vec![
+                ("tvl".to_string(), latest.tvl.0),
+                ("tvb".to_string(), latest.tvb.0),
+                ("fees".to_string(), fees(&recent[window - 1], latest).0),
+            ],
+        })
+    }
+}
+
+/// Whether the state is in a death spiral: RenVM is under-collateralised, TVB has fallen below
+/// `spiral_tvb` of its peak, and TVL is still at least `spiral_tvl` of its peak.
+pub fn is_spiral(state: &State, aggregates: &Aggregates, config: &HealthConfig) -> bool {
+    Security::is_breach(state, aggregates.required_ratio)
+        && state.tvb < config.spiral_tvb * aggregates.peak_tvb
+        && state.tvl >= config.spiral_tvl * aggregates.peak_tvl
+}
+
+/// The fees that nodes earned in the epoch that ends with the state, after the previous state
+/// (claiming moves fees from unclaimed to claimed, which leaves the sum as it was).
+pub fn fees(previous: &State, state: &State) -> Usd {
+    state.f_claimed + state.f_unclaimed - previous.f_claimed - previous.f_unclaimed
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::DeathSpiral => "death spiral",
+            Reason::FeeCollapse => "fee collapse",
+        })
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} from epoch {} (detected at epoch {})",
+            self.reason, self.epoch, self.detected
+        )?;
+        for (name, value) in &self.values {
+            write!(f, " {}={:.2}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! The error type shared by everything in this crate that can fail. Stepping the simulation never
+//! fails on its own; errors come from the edges (reading configuration, writing results, etc.), or
+//! from checking the resulting states against invariants.
+
+use crate::invariants::Violation;
+use std::fmt;
+use std::io;
+
+/// Error is returned whenever loading or saving simulation data fails.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A configuration is invalid (for example, a parameter that does not exist was referenced).
+    Config(String),
+    /// Reading or writing a CSV file failed.
+    Csv(csv::Error),
+    /// Fetching the live state of RenVM failed.
+    Fetch(String),
+    /// A state violated an invariant.
+    Invariant(Box<Violation>),
+    /// Reading or writing a JSON document failed.
+    Json(serde_json::Error),
+    /// Two result files differ beyond the tolerances.
+    Mismatch(String),
+    /// Two runs with the same seed produced different states.
+    Nondeterminism(String),
+    /// Reading or writing a Parquet file failed.
+    Parquet(String),
+    /// Drawing a chart failed.
+    Plot(String),
+    /// Reading or writing a SQLite database failed.
+    Sqlite(String),
+    /// A design failed checks of the stress-test suite.
+    Stress(String),
+    /// A TOML document could not be parsed into the expected type.
+    Toml(toml::de::Error),
+    /// A YAML document could not be parsed into the expected type.
+    Yaml(serde_yaml::Error),
+}
+
+/// Result is a shorthand for results that can fail with an `Error`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io: {}", err),
+            Error::Config(err) => write!(f, "config: {}", err),
+            Error::Csv(err) => write!(f, "csv: {}", err),
+            Error::Fetch(err) => write!(f, "fetch: {}", err),
+            Error::Invariant(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "json: {}", err),
+            Error::Mismatch(err) => write!(f, "mismatch: {}", err),
+            Error::Nondeterminism(err) => write!(f, "nondeterminism: {}", err),
+            Error::Parquet(err) => write!(f, "parquet: {}", err),
+            Error::Plot(err) => write!(f, "plot: {}", err),
+            Error::Sqlite(err) => write!(f, "sqlite: {}", err),
+            Error::Stress(err) => write!(f, "stress: {}", err),
+            Error::Toml(err) => write!(f, "toml: {}", err),
+            Error::Yaml(err) => write!(f, "yaml: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Config(_) => None,
+            Error::Csv(err) => Some(err),
+            Error::Fetch(_) => None,
+            Error::Invariant(_) => None,
+            Error::Json(err) => Some(err),
+            Error::Mismatch(_) => None,
+            Error::Nondeterminism(_) => None,
+            Error::Parquet(_) => None,
+            Error::Plot(_) => None,
+            Error::Sqlite(_) => None,
+            Error::Stress(_) => None,
+            Error::Toml(err) => Some(err),
+            Error::Yaml(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(err.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sqlite(err.to_string())
+    }
+}
+
+impl From<Violation> for Error {
+    fn from(err: Violation) -> Self {
+        Error::Invariant(Box::new(err))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::Toml(err)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Self {
+        Error::Yaml(err)
+    }
+}
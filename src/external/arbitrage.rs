@@ -0,0 +1,65 @@
+//! Models for how arbitrageurs respond to the rebate by routing additional burn volume through
+//! RenVM, which is what drains the rebate pool.
+
+use crate::config::ArbitrageConfig;
+use crate::history::History;
+use crate::{Pct, Usd};
+use serde::{Deserialize, Serialize};
+
+/// Arbitrage decides how much additional burn volume arbitrageurs route through RenVM every epoch,
+/// according to an `ArbitrageConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Arbitrage {
+    config: ArbitrageConfig,
+    rebate: Pct,
+    volume: Usd,
+}
+
+impl Arbitrage {
+    /// Create an arbitrage model using the given parameters. The rebate and volume are used by the
+    /// basic model; they are the smallest rebate that incentivises arbitrage, and the volume routed
+    /// for every multiple of that rebate.
+    pub fn new(config: ArbitrageConfig, rebate: Pct, volume: Usd) -> Self {
+        Self {
+            config,
+            rebate,
+            volume,
+        }
+    }
+
+    /// The additional burn volume (in USD) routed through RenVM in the next epoch, given the
+    /// history of states up to (and including) the previous epoch.
+    pub fn volume(&self, history: &History) -> Usd {
+        match self.config {
+            ArbitrageConfig::Basic => {
+                // It is assumed that 0.1% is sufficiently high to incentivise arbitrage of up to
+                // $1M per 0.1% rebate (which also means that at least $1K must be available in the
+                // rebate pool).
+                let state = history.latest();
+                if state.r >= self.rebate {
+                    (state.r_pool / state.r).min(self.volume * (state.r / self.rebate))
+                } else {
+                    Usd::ZERO
+                }
+            }
+            ArbitrageConfig::Agent {
+                capital,
+                cost,
+                threshold,
+                latency,
+            } => {
+                // The arbitrageur only sees the state as it was `latency` epochs ago, and acts as
+                // if it were still true. When the rebate (less the cost of the round trip) is
+                // profitable enough, it burns as much as it can afford, or as much as it expects
+                // the rebate pool to pay for, whichever is less. Acting on stale information means
+                // that it can keep burning after the pool has run dry.
+                let observed = &history.recent(latency + 1)[0];
+                if observed.r > Pct::ZERO && observed.r - cost >= threshold {
+                    capital.min(observed.r_pool / observed.r)
+                } else {
+                    Usd::ZERO
+                }
+            }
+        }
+    }
+}
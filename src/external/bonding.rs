@@ -0,0 +1,398 @@
+//! Models for how node operators respond to fee income by bonding (or unbonding) value.
+
+use crate::config::{BondingConfig, CohortConfig, OperatorsConfig};
+use crate::{Pct, Ren, SimRng, Usd};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Operator is an individual node operator agent, with its own economics and appetite for risk.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Operator {
+    /// The annual return on bonded value that the operator wants to receive.
+    pub target_roi: Pct,
+    /// The annual cost (in USD) of operating one darknode.
+    pub cost: Usd,
+    /// How far expected ROI must exceed the target before the operator registers another
+    /// darknode.
+    pub entry: Pct,
+    /// How far ROI must fall below the target before the operator deregisters a darknode.
+    pub exit: Pct,
+    /// The most darknodes that the operator will run.
+    pub max_nodes: u64,
+    /// The number of darknodes that the operator currently runs.
+    pub nodes: u64,
+    /// The darknodes that the operator registered this epoch, which become active at the start of
+    /// the next one (only with the registration lifecycle).
+    #[serde(default)]
+    pub pending: u64,
+    /// The darknodes that the operator deregistered this epoch, which stay active until the start
+    /// of the next one (only with the registration lifecycle).
+    #[serde(default)]
+    pub leaving: u64,
+    /// The darknodes that the operator deregistered in the previous epoch, whose bonds stay locked
+    /// until the start of the next one (only with the registration lifecycle).
+    #[serde(default)]
+    pub locked: u64,
+}
+
+impl Operator {
+    /// The annual return of one darknode, when the annual fee income is split between `nodes`
+    /// darknodes and each darknode bonds `node` USD.
+    pub fn roi(&self, income: Usd, nodes: u64, node: Usd) -> Pct {
+        Pct((income / nodes.max(1) as f64 - self.cost) / node)
+    }
+
+    /// Begin a new epoch of the registration lifecycle: pending darknodes become active, leaving
+    /// darknodes stop being active (and their bonds are locked), and locked bonds are refunded.
+    fn advance(&mut self) {
+        self.nodes = self.nodes + self.pending - self.leaving;
+        self.locked = self.leaving;
+        self.pending = 0;
+        self.leaving = 0;
+    }
+}
+
+/// Bonds is how much is bonded at each stage of the registration lifecycle, counted in darknodes
+/// (when bonds are made in whole darknodes) or in REN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Bonds {
+    /// Bonds that are active this epoch (including those that are leaving).
+    active: f64,
+    /// Bonds that were registered this epoch, which become active at the start of the next one.
+    pending: f64,
+    /// Bonds that were deregistered this epoch, which stay active until the start of the next one.
+    leaving: f64,
+    /// Bonds that were deregistered in the previous epoch, which are locked until the start of the
+    /// next one, when they are refunded.
+    locked: f64,
+}
+
+impl Bonds {
+    /// Begin a new epoch, and register or deregister bonds so that `desired` is active from the
+    /// next epoch, returning what is active in this one.
+    fn request(&mut self, desired: f64) -> f64 {
+        self.active += self.pending - self.leaving;
+        self.locked = self.leaving;
+        self.pending = (desired - self.active).max(0.0);
+        self.leaving = (self.active - desired).max(0.0);
+        self.active
+    }
+
+    /// What will be active from the next epoch.
+    fn committed(&self) -> f64 {
+        self.active + self.pending - self.leaving
+    }
+
+    /// Request `desired` USD of bonds counted in REN at the given price, returning the value (in
+    /// USD) that is active in this epoch. Without a price, bonds stay as they are.
+    fn request_usd(&mut self, desired: Usd, ren_price: Usd) -> Usd {
+        // Bonds are made in REN, so what is bonded moves with the price of REN until node
+        // operators catch up with it.
+        let desired = if ren_price > Usd::ZERO {
+            desired / ren_price
+        } else {
+            self.committed()
+        };
+        self.request(desired) * ren_price
+    }
+}
+
+/// Bonding turns the annual fee income that node operators expect into the value that is bonded,
+/// according to a `BondingConfig`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bonding {
+    config: BondingConfig,
+    target_roi: Pct,
+    /// The annual cost (in USD) of running one darknode, which every model pays.
+    #[serde(default)]
+    cost: Usd,
+    /// The amount of REN bonded by each darknode when the model does not say.
+    #[serde(default)]
+    bond: Ren,
+    /// The return that node operators want on top of their target, to make up for slashing.
+    #[serde(default)]
+    risk: Pct,
+    /// Whether registrations and deregistrations take effect at the next epoch.
+    #[serde(default)]
+    lifecycle: bool,
+    #[serde(default)]
+    bonds: Bonds,
+    /// The bonds of every cohort, if the model bonds in cohorts, in the same order as the cohorts.
+    #[serde(default)]
+    cohorts: Vec<Bonds>,
+    darknodes: u64,
+    operators: Vec<Operator>,
+}
+
+impl Bonding {
+    /// Create a bonding model using the given parameters. The target ROI is used by the aggregate
+    /// models; operator agents each have their own.
+    pub fn new(config: BondingConfig, target_roi: Pct) -> Self {
+        Self {
+            config,
+            target_roi,
+            cost: Usd::ZERO,
+            bond: Ren::ZERO,
+            risk: Pct::ZERO,
+            lifecycle: false,
+            bonds: Bonds::default(),
+            cohorts: vec![],
+            darknodes: 0,
+            operators: vec![],
+        }
+    }
+
+    /// Make node operators pay the annual cost (in USD) of running every darknode out of their
+    /// fee income, where darknodes bond `bond` REN unless the model bonds its own amount.
+    /// Operator agents pay it on top of their own costs.
+    pub fn with_operating_cost(mut self, cost: Usd, bond: Ren) -> Self {
+        self.cost = cost;
+        self.bond = bond;
+        self
+    }
+
+    /// Make node operators want `risk` more annual return than their targets, to make up for
+    /// what they expect to lose to slashing.
+    pub fn with_risk_premium(mut self, risk: Pct) -> Self {
+        self.risk = risk;
+        self
+    }
+
+    /// Make registrations and deregistrations follow the lifecycle of darknodes: darknodes that
+    /// register become active at the start of the next epoch, and darknodes that deregister stay
+    /// active until then, after which their bonds are locked for one more epoch before they are
+    /// refunded. Operator agents cannot bond again until their locked bonds are refunded.
+    pub fn with_lifecycle(mut self) -> Self {
+        self.lifecycle = true;
+        self
+    }
+
+    /// Begin the registration lifecycle from the value already bonded (and the darknodes already
+    /// registered) in the initial state, at the given price of REN. Cohorts are assumed to have
+    /// bonded it from the lowest target up, and operator agents always begin without any
+    /// darknodes.
+    pub fn start(&mut self, tvb: Usd, darknodes: u64, ren_price: Usd) {
+        self.bonds.active = match self.config {
+            BondingConfig::Usd if ren_price > Usd::ZERO => tvb / ren_price,
+            BondingConfig::Darknodes { .. } => darknodes as f64,
+            _ => 0.0,
+        };
+        if let BondingConfig::Cohorts { capital, cohorts } = &self.config {
+            if ren_price > Usd::ZERO {
+                self.cohorts = fill(*capital, cohorts, |_| tvb)
+                    .into_iter()
+                    .map(|bonded| Bonds {
+                        active: bonded / ren_price,
+                        ..Bonds::default()
+                    })
+                    .collect();
+            }
+        }
+    }
+
+    /// The value that node operators want to bond to earn their target ROI after costs, when each
+    /// darknode bonds `node` USD. A darknode costs as much to run however much it bonds, so
+    /// operators want `income / (target + cost / node)`.
+    fn desired(&self, income: Usd, node: Usd) -> Usd {
+        income / (self.target_roi + self.risk + self.cost_share(node))
+    }
+
+    /// The annual cost of running a darknode that bonds `node` USD, as a share of its bond.
+    fn cost_share(&self, node: Usd) -> Pct {
+        if node > Usd::ZERO && self.cost > Usd::ZERO {
+            Pct(self.cost / node)
+        } else {
+            Pct::ZERO
+        }
+    }
+
+    /// The number of registered darknodes after the most recent call to `bond`, or zero if the
+    /// model does not bond in whole darknodes.
+    pub fn darknodes(&self) -> u64 {
+        self.darknodes
+    }
+
+    /// The operator agents, if the model is agent-based.
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+
+    /// The value (in USD) that is bonded, given the annual fee income that node operators expect,
+    /// the current price of REN, and the share of their bonds that they suddenly withdraw this
+    /// epoch (regardless of income).
+    pub fn bond(&mut self, income: Usd, ren_price: Usd, withdrawn: Pct, rng: &mut SimRng) -> Usd {
+        match &self.config {
+            BondingConfig::Usd => {
+                let desired = self.desired(income, self.bond * ren_price) * (Pct::ALL - withdrawn);
+                if !self.lifecycle {
+                    return desired;
+                }
+                self.bonds.request_usd(desired, ren_price)
+            }
+            BondingConfig::Cohorts { capital, cohorts } => {
+                let cost = self.risk + self.cost_share(self.bond * ren_price);
+                let desired = fill(*capital, cohorts, |cohort| {
+                    income / (cohort.target_roi + cost)
+                });
+                if !self.lifecycle {
+                    return desired.into_iter().sum::<Usd>() * (Pct::ALL - withdrawn);
+                }
+                self.cohorts.resize(cohorts.len(), Bonds::default());
+                self.cohorts
+                    .iter_mut()
+                    .zip(desired)
+                    .map(|(bonds, desired)| {
+                        bonds.request_usd(desired * (Pct::ALL - withdrawn), ren_price)
+                    })
+                    .sum()
+            }
+            BondingConfig::Darknodes { bond } => {
+                // Bonds are made in whole darknodes, so operators register as many darknodes as
+                // they can afford at the current price of REN (and no more).
+                let node = *bond * ren_price;
+                let desired = self.desired(income, node) * (Pct::ALL - withdrawn);
+                self.darknodes = if node > Usd::ZERO {
+                    (desired / node).floor().max(0.0) as u64
+                } else {
+                    0
+                };
+                if self.lifecycle {
+                    self.darknodes = self.bonds.request(self.darknodes as f64) as u64;
+                }
+                self.darknodes as f64 * node
+            }
+            BondingConfig::Operators(config) => {
+                if self.operators.is_empty() {
+                    self.operators = spawn(config, rng);
+                    for op in &mut self.operators {
+                        op.cost += self.cost;
+                        op.target_roi += self.risk;
+                    }
+                }
+                let node = config.bond * ren_price;
+                self.darknodes = if withdrawn > Pct::ZERO {
+                    // Operators that withdraw do not reconsider until the next epoch.
+                    tracing::debug!(withdrawn = withdrawn.0, "operators withdraw their bonds");
+                    for op in &mut self.operators {
+                        let leaving = (op.nodes as f64 * withdrawn.0).round() as u64;
+                        if self.lifecycle {
+                            op.advance();
+                            op.leaving = leaving.min(op.nodes);
+                        } else {
+                            op.nodes -= leaving;
+                        }
+                    }
+                    self.operators.iter().map(|op| op.nodes).sum()
+                } else if self.lifecycle {
+                    step_with_lifecycle(&mut self.operators, income, node, rng)
+                } else {
+                    step(&mut self.operators, income, node, rng)
+                };
+                self.darknodes as f64 * node
+            }
+        }
+    }
+}
+
+/// Share out bonds between cohorts from the lowest target ROI up, where each cohort bonds as much
+/// of its share of the capital as it can without taking the total beyond its `limit` (such as the
+/// most that can be bonded while the cohort still earns its target). Returns what each cohort
+/// bonds, in the same order as the cohorts.
+fn fill(capital: Usd, cohorts: &[CohortConfig], limit: impl Fn(&CohortConfig) -> Usd) -> Vec<Usd> {
+    let mut order: Vec<usize> = (0..cohorts.len()).collect();
+    order.sort_by(|&a, &b| cohorts[a].target_roi.0.total_cmp(&cohorts[b].target_roi.0));
+    let mut taken = vec![Usd::ZERO; cohorts.len()];
+    let mut total = Usd::ZERO;
+    for i in order {
+        let cohort = &cohorts[i];
+        let room = (limit(cohort) - total).max(Usd::ZERO);
+        taken[i] = room.min(capital * cohort.share);
+        total += taken[i];
+    }
+    taken
+}
+
+/// Create a population of operators whose parameters are drawn uniformly from the configured
+/// ranges.
+fn spawn(config: &OperatorsConfig, rng: &mut SimRng) -> Vec<Operator> {
+    let uniform = |rng: &mut SimRng, [lo, hi]: [f64; 2]| {
+        if hi > lo {
+            rng.gen_range(lo..hi)
+        } else {
+            lo
+        }
+    };
+    (0..config.operators)
+        .map(|_| Operator {
+            target_roi: Pct(uniform(rng, config.target_roi.map(f64::from))),
+            cost: Usd(uniform(rng, config.cost.map(f64::from))),
+            entry: config.entry,
+            exit: config.exit,
+            max_nodes: config.max_nodes,
+            nodes: 0,
+            pending: 0,
+            leaving: 0,
+            locked: 0,
+        })
+        .collect()
+}
+
+/// Let every operator (in a random order) register or deregister at most one darknode, returning
+/// the total number of registered darknodes. Fee income is split evenly between darknodes, so every
+/// decision changes the returns seen by the operators that decide after it.
+fn step(operators: &mut [Operator], income: Usd, node: Usd, rng: &mut SimRng) -> u64 {
+    let mut nodes: u64 = operators.iter().map(|op| op.nodes).sum();
+    if node <= Usd::ZERO {
+        return nodes;
+    }
+    let mut order: Vec<usize> = (0..operators.len()).collect();
+    order.shuffle(rng);
+    for i in order {
+        let op = &mut operators[i];
+        if op.nodes < op.max_nodes && op.roi(income, nodes + 1, node) > op.target_roi + op.entry {
+            op.nodes += 1;
+            nodes += 1;
+        } else if op.nodes > 0 && op.roi(income, nodes, node) < op.target_roi - op.exit {
+            op.nodes -= 1;
+            nodes -= 1;
+        }
+    }
+    nodes
+}
+
+/// Let every operator (in a random order) register or deregister at most one darknode like `step`,
+/// but following the registration lifecycle, returning the number of darknodes that are active this
+/// epoch. Fee income is split between the darknodes that will be active in the next epoch, which
+/// is what operators are deciding about.
+fn step_with_lifecycle(
+    operators: &mut [Operator],
+    income: Usd,
+    node: Usd,
+    rng: &mut SimRng,
+) -> u64 {
+    for op in operators.iter_mut() {
+        op.advance();
+    }
+    let mut nodes: u64 = operators.iter().map(|op| op.nodes).sum();
+    if node > Usd::ZERO {
+        let mut order: Vec<usize> = (0..operators.len()).collect();
+        order.shuffle(rng);
+        for i in order {
+            let op = &mut operators[i];
+            // Locked bonds count towards the most that the operator will run until they are
+            // refunded.
+            if op.nodes + op.pending + op.locked < op.max_nodes
+                && op.roi(income, nodes + 1, node) > op.target_roi + op.entry
+            {
+                op.pending += 1;
+                nodes += 1;
+            } else if op.nodes > op.leaving && op.roi(income, nodes, node) < op.target_roi - op.exit
+            {
+                op.leaving += 1;
+                nodes -= 1;
+            }
+        }
+    }
+    operators.iter().map(|op| op.nodes).sum()
+}
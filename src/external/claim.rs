@@ -0,0 +1,54 @@
+//! For modelling when node operators claim the fees that they have earned. Unclaimed fees are not
+//! income yet, so the timing of claims decides the ROI that node operators see: claiming a little
+//! every epoch smoothes it, while claiming everything at once (monthly, or when it is worth the
+//! gas) makes it lumpy.
+
+use crate::config::{ClaimCurve, EpochConfig, ExternalConfig};
+use crate::history::History;
+use crate::Pct;
+use serde::{Deserialize, Serialize};
+
+/// Claim decides how much of the unclaimed fees node operators claim every epoch, according to a
+/// `ClaimCurve`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Claim {
+    curve: ClaimCurve,
+    rate: Pct,
+    /// The number of epochs between claims, with the periodic curve.
+    interval: usize,
+}
+
+impl Default for Claim {
+    fn default() -> Self {
+        Self::new(&ExternalConfig::default(), &EpochConfig::default())
+    }
+}
+
+impl Claim {
+    /// Create the claim curve from the external parameters, with intervals measured in epochs of
+    /// the given length.
+    pub fn new(config: &ExternalConfig, epoch: &EpochConfig) -> Self {
+        let interval = match config.claim_curve {
+            ClaimCurve::Periodic { days } => epoch.epochs(days),
+            _ => 1,
+        };
+        Self {
+            curve: config.claim_curve,
+            rate: config.claim_rate,
+            interval,
+        }
+    }
+
+    /// The share of the unclaimed fees that are claimed in the next epoch of the history.
+    pub fn rate(&self, history: &History) -> Pct {
+        match self.curve {
+            ClaimCurve::Fixed => self.rate,
+            ClaimCurve::Periodic { .. } if history.len().is_multiple_of(self.interval) => Pct::ALL,
+            ClaimCurve::Periodic { .. } => Pct::ZERO,
+            ClaimCurve::Threshold { threshold } if history.latest().f_unclaimed >= threshold => {
+                Pct::ALL
+            }
+            ClaimCurve::Threshold { .. } => Pct::ZERO,
+        }
+    }
+}
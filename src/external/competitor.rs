@@ -0,0 +1,46 @@
+//! Models for a competing bridge, which takes demand away from RenVM when its fees are lower. With
+//! a competitor, the mint and burn volumes are the demand of the whole market, and users choose a
+//! bridge by a logit on the difference between the fees, so that RenVM keeps less and less of the
+//! market the further its fees rise above those of the competitor.
+
+use crate::config::CompetitorConfig;
+use crate::Pct;
+use serde::{Deserialize, Serialize};
+
+/// Competitor shares the market with RenVM according to a `CompetitorConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Competitor {
+    config: CompetitorConfig,
+}
+
+impl Competitor {
+    /// Create a competitor using the given parameters.
+    pub fn new(config: CompetitorConfig) -> Self {
+        Self { config }
+    }
+
+    /// The share of the mint demand that RenVM keeps at the given minting fee, which is its share
+    /// at equal fees when no fee has been charged yet.
+    pub fn mint_share(&self, fee: Option<Pct>) -> f64 {
+        self.share(fee, self.config.mint_fee)
+    }
+
+    /// The share of the burn demand that RenVM keeps at the given burning fee.
+    pub fn burn_share(&self, fee: Option<Pct>) -> f64 {
+        self.share(fee, self.config.burn_fee)
+    }
+
+    fn share(&self, fee: Option<Pct>, competitor: Pct) -> f64 {
+        let parity = self.config.share.0;
+        if parity <= 0.0 || parity >= 1.0 {
+            return parity.clamp(0.0, 1.0);
+        }
+        // The log-odds of choosing RenVM fall by `sensitivity` for every basis point that its fee
+        // is above the fee of the competitor.
+        let differential = fee.map_or(0.0, |fee| (fee.0 - competitor.0) * 10_000.0);
+        let odds = (parity / (1.0 - parity)).ln() - self.config.sensitivity * differential;
+        let share = 1.0 / (1.0 + (-odds).exp());
+        tracing::trace!(share, "competitor");
+        share
+    }
+}
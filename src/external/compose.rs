@@ -0,0 +1,517 @@
+//! Combinators that derive external models from existing ones, so that a variant (noisy demand, or
+//! node operators that respond a few epochs late) does not need a copy of the model that it varies.
+//! A combinator wraps a model and changes some of the values that it returns every epoch (every
+//! output, unless it is restricted with `only`), and forwards everything else to it. Combinators
+//! wrap each other too, for example
+//!
+//! ```
+//! # use eco::external::compose::{Lagged, Output, WithNoise};
+//! # use eco::external::Basic;
+//! let external = Lagged::new(WithNoise::new(Basic::default(), 0.2), 3).only(&[Output::Tvb]);
+//! ```
+//!
+//! has node operators respond three epochs late to what they would have bonded, with noisy volumes
+//! and prices. Other variants can implement `Transform` and wrap a model in a `Composed`.
+
+use crate::error::Result;
+use crate::external::ExternalModel;
+use crate::history::History;
+use crate::{Pct, SimRng, Usd};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Output is one of the values that an external model returns every epoch, which a combinator can
+/// change. The volumes include the volumes (and the transactions) of every asset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Output {
+    Tvb,
+    MintVolume,
+    BurnVolume,
+    RenPrice,
+}
+
+impl Output {
+    /// Every output, which is what combinators change unless they are restricted.
+    pub const ALL: [Output; 4] = [
+        Output::Tvb,
+        Output::MintVolume,
+        Output::BurnVolume,
+        Output::RenPrice,
+    ];
+
+    /// The value of the output in the given state, where assets have none.
+    fn value(&self, history: &History, asset: Option<&str>) -> Usd {
+        let state = history.latest();
+        match (self, asset) {
+            (Output::Tvb, _) => state.tvb,
+            (Output::RenPrice, _) => state.ren_price,
+            (_, Some(_)) => Usd::ZERO,
+            (Output::MintVolume, None) => state.mint_volume,
+            (Output::BurnVolume, None) => state.burn_volume,
+        }
+    }
+}
+
+/// Transform is what a combinator does to an output of the model that it wraps: it is given the
+/// value that the model returned for the output (of an asset, for volumes that are modelled per
+/// asset), and returns the value to use instead. Transforms that keep state between epochs must
+/// implement `checkpoint` and `restore`, like models.
+pub trait Transform {
+    fn apply(
+        &mut self,
+        output: Output,
+        asset: Option<&str>,
+        value: Usd,
+        history: &History,
+        rng: &mut SimRng,
+    ) -> Usd;
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    fn restore(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Composed wraps an external model, changing its outputs with a `Transform`.
+#[derive(Clone, Debug)]
+pub struct Composed<M, T> {
+    model: M,
+    transform: T,
+    outputs: Vec<Output>,
+}
+
+/// WithNoise multiplies the outputs of a model by lognormal noise with a mean of one.
+pub type WithNoise<M> = Composed<M, Noise>;
+
+/// Lagged has the outputs of a model take effect a number of epochs after it returns them.
+pub type Lagged<M> = Composed<M, Lag>;
+
+/// Clamped keeps the outputs of a model between bounds.
+pub type Clamped<M> = Composed<M, Clamp>;
+
+impl<M: ExternalModel, T: Transform> Composed<M, T> {
+    /// Wrap the model, changing every one of its outputs with the transform.
+    pub fn with_transform(model: M, transform: T) -> Self {
+        Self {
+            model,
+            transform,
+            outputs: Output::ALL.to_vec(),
+        }
+    }
+
+    /// Change only the given outputs, and leave the others as the model returns them.
+    pub fn only(mut self, outputs: &[Output]) -> Self {
+        self.outputs = outputs.to_vec();
+        self
+    }
+
+    /// The model that is wrapped.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    fn apply(
+        &mut self,
+        output: Output,
+        asset: Option<&str>,
+        value: Usd,
+        history: &History,
+        rng: &mut SimRng,
+    ) -> Usd {
+        if self.outputs.contains(&output) {
+            self.transform.apply(output, asset, value, history, rng)
+        } else {
+            value
+        }
+    }
+
+    /// Change the total of the transactions like the volume that they make up, scaling every
+    /// transaction by the same factor.
+    fn apply_transactions(
+        &mut self,
+        output: Output,
+        transactions: Vec<Usd>,
+        history: &History,
+        rng: &mut SimRng,
+    ) -> Vec<Usd> {
+        if !self.outputs.contains(&output) {
+            return transactions;
+        }
+        let total = transactions.iter().copied().sum();
+        let changed = self.transform.apply(output, None, total, history, rng);
+        rescale(transactions, total, changed)
+    }
+}
+
+impl<M> WithNoise<M> {
+    /// Wrap the model, multiplying its outputs by noise whose logarithm has a standard deviation of
+    /// `sigma`.
+    pub fn new(model: M, sigma: f64) -> Self {
+        Self {
+            model,
+            transform: Noise { sigma },
+            outputs: Output::ALL.to_vec(),
+        }
+    }
+}
+
+impl<M> Lagged<M> {
+    /// Wrap the model, so that what it returns takes effect `epochs` epochs later. Until then, the
+    /// outputs keep their values from the previous state (and the volumes of assets are zero).
+    pub fn new(model: M, epochs: usize) -> Self {
+        Self {
+            model,
+            transform: Lag {
+                epochs,
+                queues: vec![],
+            },
+            outputs: Output::ALL.to_vec(),
+        }
+    }
+}
+
+impl<M> Clamped<M> {
+    /// Wrap the model, keeping its outputs between `lo` and `hi`.
+    pub fn new(model: M, lo: Usd, hi: Usd) -> Self {
+        Self {
+            model,
+            transform: Clamp { lo, hi },
+            outputs: Output::ALL.to_vec(),
+        }
+    }
+}
+
+/// Noise multiplies values by lognormal noise with a mean of one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    sigma: f64,
+}
+
+impl Transform for Noise {
+    fn apply(
+        &mut self,
+        _output: Output,
+        _asset: Option<&str>,
+        value: Usd,
+        _history: &History,
+        rng: &mut SimRng,
+    ) -> Usd {
+        // Subtracting half of the variance keeps the expected value the same.
+        let z: f64 = Normal::new(0.0, 1.0).expect("standard normal").sample(rng);
+        value * (self.sigma * z - 0.5 * self.sigma * self.sigma).exp()
+    }
+}
+
+/// Lag delays values by a number of epochs, keeping a queue for every output (and asset).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lag {
+    epochs: usize,
+    queues: Vec<(Output, Option<String>, VecDeque<Usd>)>,
+}
+
+impl Transform for Lag {
+    fn apply(
+        &mut self,
+        output: Output,
+        asset: Option<&str>,
+        value: Usd,
+        history: &History,
+        _rng: &mut SimRng,
+    ) -> Usd {
+        if self.epochs == 0 {
+            return value;
+        }
+        let i = match self
+            .queues
+            .iter()
+            .position(|(o, a, _)| *o == output && a.as_deref() == asset)
+        {
+            Some(i) => i,
+            None => {
+                let queue = VecDeque::with_capacity(self.epochs + 1);
+                self.queues.push((output, asset.map(str::to_string), queue));
+                self.queues.len() - 1
+            }
+        };
+        let queue = &mut self.queues[i].2;
+        queue.push_back(value);
+        if queue.len() > self.epochs {
+            queue.pop_front().expect("queue is not empty")
+        } else {
+            output.value(history, asset)
+        }
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(&self.queues).ok()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.queues = serde_json::from_value(state)?;
+        Ok(())
+    }
+}
+
+/// Clamp keeps values between bounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clamp {
+    lo: Usd,
+    hi: Usd,
+}
+
+impl Transform for Clamp {
+    fn apply(
+        &mut self,
+        _output: Output,
+        _asset: Option<&str>,
+        value: Usd,
+        _history: &History,
+        _rng: &mut SimRng,
+    ) -> Usd {
+        value.max(self.lo).min(self.hi)
+    }
+}
+
+/// The state that a composed model keeps between epochs, which is that of the model and of the
+/// transform.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    model: Option<serde_json::Value>,
+    transform: Option<serde_json::Value>,
+}
+
+impl<M: ExternalModel, T: Transform> ExternalModel for Composed<M, T> {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let tvb = self.model.total_value_bonded(history, rng);
+        self.apply(Output::Tvb, None, tvb, history, rng)
+    }
+
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let volume = self.model.mint_volume(history, rng);
+        self.apply(Output::MintVolume, None, volume, history, rng)
+    }
+
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let volume = self.model.burn_volume(history, rng);
+        self.apply(Output::BurnVolume, None, volume, history, rng)
+    }
+
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let transactions = self.model.mint_transactions(history, rng);
+        self.apply_transactions(Output::MintVolume, transactions, history, rng)
+    }
+
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let transactions = self.model.burn_transactions(history, rng);
+        self.apply_transactions(Output::BurnVolume, transactions, history, rng)
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let price = self.model.ren_price(history, rng);
+        self.apply(Output::RenPrice, None, price, history, rng)
+    }
+
+    fn darknodes(&mut self, history: &History, rng: &mut SimRng) -> u64 {
+        self.model.darknodes(history, rng)
+    }
+
+    fn assets(&self) -> Vec<String> {
+        self.model.assets()
+    }
+
+    fn asset_mint_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let volume = self.model.asset_mint_volume(history, asset, rng);
+        self.apply(Output::MintVolume, Some(asset), volume, history, rng)
+    }
+
+    fn asset_burn_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let volume = self.model.asset_burn_volume(history, asset, rng);
+        self.apply(Output::BurnVolume, Some(asset), volume, history, rng)
+    }
+
+    fn asset_price(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.model.asset_price(history, asset, rng)
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, net: Usd, rng: &mut SimRng) -> Usd {
+        self.model.tvl_revaluation(history, net, rng)
+    }
+
+    fn claim_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        self.model.claim_curve(history, rng)
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        let checkpoint = Checkpoint {
+            model: self.model.checkpoint(),
+            transform: self.transform.checkpoint(),
+        };
+        if checkpoint.model.is_none() && checkpoint.transform.is_none() {
+            return None;
+        }
+        serde_json::to_value(checkpoint).ok()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        let checkpoint: Checkpoint = serde_json::from_value(state)?;
+        if let Some(state) = checkpoint.model {
+            self.model.restore(state)?;
+        }
+        if let Some(state) = checkpoint.transform {
+            self.transform.restore(state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Blend mixes the outputs of two models, taking `weight` of what the first returns and the rest
+/// of what the second returns. Both models are called for every output (the first before the
+/// second), so that both keep evolving, but everything else (darknodes, assets and their prices,
+/// revaluations, and claims) comes from the first.
+#[derive(Clone, Debug)]
+pub struct Blend<A, B> {
+    a: A,
+    b: B,
+    weight: f64,
+    outputs: Vec<Output>,
+}
+
+impl<A: ExternalModel, B: ExternalModel> Blend<A, B> {
+    /// Blend the two models, with `weight` of the first.
+    pub fn new(a: A, b: B, weight: f64) -> Self {
+        Self {
+            a,
+            b,
+            weight,
+            outputs: Output::ALL.to_vec(),
+        }
+    }
+
+    /// Blend only the given outputs, and take the others from the first model.
+    pub fn only(mut self, outputs: &[Output]) -> Self {
+        self.outputs = outputs.to_vec();
+        self
+    }
+
+    fn blend(&self, output: Output, a: Usd, b: Usd) -> Usd {
+        if self.outputs.contains(&output) {
+            a * self.weight + b * (1.0 - self.weight)
+        } else {
+            a
+        }
+    }
+}
+
+impl<A: ExternalModel, B: ExternalModel> ExternalModel for Blend<A, B> {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let a = self.a.total_value_bonded(history, rng);
+        let b = self.b.total_value_bonded(history, rng);
+        self.blend(Output::Tvb, a, b)
+    }
+
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let a = self.a.mint_volume(history, rng);
+        let b = self.b.mint_volume(history, rng);
+        self.blend(Output::MintVolume, a, b)
+    }
+
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let a = self.a.burn_volume(history, rng);
+        let b = self.b.burn_volume(history, rng);
+        self.blend(Output::BurnVolume, a, b)
+    }
+
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let a = self.a.mint_transactions(history, rng);
+        let b = self.b.mint_transactions(history, rng);
+        let total = a.iter().copied().sum();
+        let blended = self.blend(Output::MintVolume, total, b.iter().copied().sum());
+        rescale(a, total, blended)
+    }
+
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let a = self.a.burn_transactions(history, rng);
+        let b = self.b.burn_transactions(history, rng);
+        let total = a.iter().copied().sum();
+        let blended = self.blend(Output::BurnVolume, total, b.iter().copied().sum());
+        rescale(a, total, blended)
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        let a = self.a.ren_price(history, rng);
+        let b = self.b.ren_price(history, rng);
+        self.blend(Output::RenPrice, a, b)
+    }
+
+    fn darknodes(&mut self, history: &History, rng: &mut SimRng) -> u64 {
+        self.a.darknodes(history, rng)
+    }
+
+    fn assets(&self) -> Vec<String> {
+        self.a.assets()
+    }
+
+    fn asset_mint_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let a = self.a.asset_mint_volume(history, asset, rng);
+        let b = self.b.asset_mint_volume(history, asset, rng);
+        self.blend(Output::MintVolume, a, b)
+    }
+
+    fn asset_burn_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let a = self.a.asset_burn_volume(history, asset, rng);
+        let b = self.b.asset_burn_volume(history, asset, rng);
+        self.blend(Output::BurnVolume, a, b)
+    }
+
+    fn asset_price(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.a.asset_price(history, asset, rng)
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, net: Usd, rng: &mut SimRng) -> Usd {
+        self.a.tvl_revaluation(history, net, rng)
+    }
+
+    fn claim_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        self.a.claim_curve(history, rng)
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        let (a, b) = (self.a.checkpoint(), self.b.checkpoint());
+        if a.is_none() && b.is_none() {
+            return None;
+        }
+        serde_json::to_value((a, b)).ok()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        let (a, b): (Option<serde_json::Value>, Option<serde_json::Value>) =
+            serde_json::from_value(state)?;
+        if let Some(state) = a {
+            self.a.restore(state)?;
+        }
+        if let Some(state) = b {
+            self.b.restore(state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scale the transactions that add up to `from` so that they add up to `to`, or make a single
+/// transaction of `to` when there were none.
+fn rescale(mut transactions: Vec<Usd>, from: Usd, to: Usd) -> Vec<Usd> {
+    if from > Usd::ZERO {
+        let factor = to / from;
+        for transaction in &mut transactions {
+            *transaction *= factor;
+        }
+        transactions
+    } else if to > Usd::ZERO {
+        vec![to]
+    } else {
+        transactions
+    }
+}
@@ -0,0 +1,59 @@
+//! Models for the gas that users pay to mint and burn, which makes small transactions uneconomical
+//! when it spikes, so that realised volume (and the fees that it pays) falls with it.
+
+use crate::config::GasConfig;
+use crate::external::price::Price;
+use crate::stats;
+use crate::{SimRng, Usd};
+use serde::{Deserialize, Serialize};
+
+/// Gas prices mints and burns according to a `GasConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Gas {
+    config: GasConfig,
+    cost: Price,
+    /// The epoch that the cost was last advanced for.
+    epoch: usize,
+}
+
+impl Gas {
+    /// Create a gas model using the given parameters.
+    pub fn new(config: GasConfig) -> Self {
+        Self {
+            config,
+            cost: Price::new(config.cost),
+            epoch: 0,
+        }
+    }
+
+    /// The gas of one transaction in the given epoch, which moves the first time that the epoch
+    /// asks for it.
+    pub fn cost(&mut self, epoch: usize, rng: &mut SimRng) -> Usd {
+        if self.epoch != epoch {
+            self.epoch = epoch;
+            let cost = self.cost.next(rng);
+            tracing::trace!(gas = cost.0);
+        }
+        self.cost.current()
+    }
+
+    /// Whether a transaction of the given size is worth its gas.
+    pub fn is_economical(&self, size: Usd, cost: Usd) -> bool {
+        cost <= size * self.config.tolerance
+    }
+
+    /// The share of volume that is made up of transactions that are worth their gas, when their
+    /// sizes are lognormally distributed around `size`. The volume in transactions of at least `k`
+    /// is `Φ((ln(size / k) + σ² / 2) / σ)` of the whole.
+    pub fn share(&self, cost: Usd) -> f64 {
+        if cost <= Usd::ZERO {
+            return 1.0;
+        }
+        let ratio = self.config.size / (cost / self.config.tolerance);
+        let volatility = self.config.volatility;
+        if volatility <= 0.0 {
+            return if ratio >= 1.0 { 1.0 } else { 0.0 };
+        }
+        stats::normal_cdf((ratio.ln() + 0.5 * volatility * volatility) / volatility)
+    }
+}
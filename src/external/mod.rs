@@ -0,0 +1,625 @@
+//! For modelling the behaviour of entities that are external to RenVM. This means that the entity
+//! is capable of taking action independently of the precise mechanics of RenVM. For example,
+//! minters, burners, and node operators should all be considered external. Implement
+//! `ExternalModel` when you want to see how the state of RenVM will evolve under different
+//! assumptions about how people will behave. For example, you can implement `total_value_bonded`
+//! to model different node operator (dis)bonding behaviour.
+
+pub mod arbitrage;
+pub mod bonding;
+pub mod claim;
+pub mod competitor;
+pub mod compose;
+pub mod gas;
+pub mod peg;
+pub mod price;
+pub mod regime;
+pub mod shock;
+pub mod transactions;
+pub mod volume;
+
+use crate::config::{EpochConfig, ExternalConfig, RenDemandConfig, RenSupplyConfig, ReplayConfig};
+use crate::error::Result;
+use crate::history::History;
+use crate::state::State;
+use crate::{Pct, Ren, SimRng, Usd};
+use arbitrage::Arbitrage;
+use bonding::Bonding;
+use claim::Claim;
+use competitor::Competitor;
+use gas::Gas;
+use peg::Peg;
+use price::{Correlation, Price};
+use regime::Regimes;
+use serde::{Deserialize, Serialize};
+use shock::Shocks;
+use transactions::Transactions;
+use volume::{Demand, Volume};
+
+/// ExternalModel captures everything that entities outside of RenVM decide each epoch. The
+/// simulation calls each function exactly once per epoch, passing the history of states up to (and
+/// including) the previous epoch. Models are free to keep their own internal state between calls.
+/// Models that need randomness must draw it from the `rng` that is passed to them (and nowhere
+/// else), so that runs remain reproducible from their seed.
+pub trait ExternalModel {
+    /// This function returns the amount of USD that is bonded to RenVM. Changing this function
+    /// allows you to model the behaviour of node operators.
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd;
+
+    /// This function returns the amount of value in USD that will be minted. There are lots of
+    /// factors to consider here: growth of the network, historical minting fees, random deviation,
+    /// etc. so it is important to test different models (both rational and irrational).
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd;
+
+    /// This function is the same as the `mint_volume` function, but for burning volume.
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd;
+
+    /// This function returns the sizes of the individual transactions that mint value this epoch,
+    /// for internal models whose fees depend on the size of a transaction. It is called instead of
+    /// `mint_volume`, whose volume is minted in a single transaction by default.
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        vec![self.mint_volume(history, rng)]
+    }
+
+    /// This function is the same as the `mint_transactions` function, but for burning volume.
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        vec![self.burn_volume(history, rng)]
+    }
+
+    /// This function returns the price of REN (in USD), which is what converts bonds from REN into
+    /// USD. It is called before `total_value_bonded`, so models that bond in REN can use the price
+    /// that they returned. By default, the price does not change.
+    fn ren_price(&mut self, history: &History, _rng: &mut SimRng) -> Usd {
+        history.latest().ren_price
+    }
+
+    /// This function returns the number of registered darknodes. It is called after
+    /// `total_value_bonded`, so models that bond in whole darknodes can return the number that
+    /// they bonded. By default (and for models that do not track darknodes), it is zero.
+    fn darknodes(&mut self, _history: &History, _rng: &mut SimRng) -> u64 {
+        0
+    }
+
+    /// This function returns the names of the assets that are locked in RenVM. It is called once,
+    /// when the simulation is created. When it returns no assets (the default), locked value is
+    /// modelled in aggregate by `mint_volume` and `burn_volume`. Otherwise, those functions are
+    /// never called, and `asset_mint_volume` and `asset_burn_volume` are called once per asset
+    /// per epoch instead.
+    fn assets(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// This function is the same as the `mint_volume` function, but for one asset. Models that
+    /// return any assets must implement it.
+    fn asset_mint_volume(&mut self, _history: &History, _asset: &str, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function is the same as the `burn_volume` function, but for one asset. Models that
+    /// return any assets must implement it.
+    fn asset_burn_volume(&mut self, _history: &History, _asset: &str, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function returns the price of one asset (in USD). It is called once per asset per
+    /// epoch, before the asset is minted or burned, and the value that is already locked is
+    /// revalued by the change in price since the previous epoch. By default, assets are not priced
+    /// (the price is zero) and locked value is never revalued.
+    fn asset_price(&mut self, _history: &History, _asset: &str, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function returns the change in TVL this epoch that is not caused by minting or burning
+    /// (for example, when the price of the locked assets moves), given the net value minted. It is
+    /// called after `total_value_bonded`. By default, there is none.
+    fn tvl_revaluation(&mut self, _history: &History, _net: Usd, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function returns the share of their unclaimed fees that node operators claim (where
+    /// the unclaimed fees are those of the previous epoch). It is called last in every epoch. By
+    /// default, they claim the default `claim_rate` (about half a month of fees every month).
+    fn claim_curve(&mut self, _history: &History, _rng: &mut SimRng) -> Pct {
+        ExternalConfig::default().claim_rate
+    }
+
+    /// This function returns the internal state that the model keeps between epochs, so that it
+    /// can be saved in a checkpoint. Models that keep state must implement both this and
+    /// `restore`, otherwise resumed runs will not continue exactly. By default, there is none.
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// This function replaces the internal state of the model with one that was returned by
+    /// `checkpoint`.
+    fn restore(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The basic external model, which is the one that RenVM was originally designed around.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Basic {
+    roi_window: usize,
+    epochs_per_year: f64,
+    mint: Volume,
+    burn: Volume,
+    #[serde(default)]
+    mint_demand: Demand,
+    #[serde(default)]
+    burn_demand: Demand,
+    ren_price: Price,
+    bonding: Bonding,
+    arbitrage: Arbitrage,
+    assets: Vec<Asset>,
+    #[serde(default)]
+    correlation: Option<Correlation>,
+    /// The correlated shocks to the prices of the assets, and the epoch that they were drawn for.
+    #[serde(default)]
+    correlated: (usize, Vec<f64>),
+    shocks: Shocks,
+    #[serde(default)]
+    regimes: Regimes,
+    #[serde(default)]
+    claim: Claim,
+    #[serde(default)]
+    ren_supply: RenSupplyConfig,
+    /// The REN taken out of circulation (burned, less emitted) that the price of REN has
+    /// responded to.
+    #[serde(default)]
+    ren_removed: Ren,
+    #[serde(default)]
+    replay: ReplayConfig,
+    /// The fees claimed per epoch before the run began.
+    #[serde(default)]
+    prior_income: Usd,
+    /// The transactions that mint and burn value, if they are modelled individually.
+    #[serde(default)]
+    transactions: Option<(Transactions, Transactions)>,
+    /// The gas that users pay to mint and burn, if it is modelled.
+    #[serde(default)]
+    gas: Option<Gas>,
+    /// The bridge that competes with RenVM for demand, if there is one.
+    #[serde(default)]
+    competitor: Option<Competitor>,
+    /// The pool that prices the minted token against its asset, if it is modelled.
+    #[serde(default)]
+    peg: Option<Peg>,
+    #[serde(default)]
+    ren_demand: Option<RenDemandConfig>,
+    /// The annual income of node operators that the price of REN has responded to, once it has.
+    #[serde(default)]
+    income_priced: Option<Usd>,
+}
+
+/// The models for one locked asset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Asset {
+    name: String,
+    mint: Volume,
+    burn: Volume,
+    price: Option<Price>,
+}
+
+impl Basic {
+    /// Create a basic external model using the given parameters, with windows measured in epochs
+    /// of the given length.
+    pub fn new(config: ExternalConfig, epoch: &EpochConfig) -> Self {
+        let correlation = (!config.correlation.is_empty())
+            .then(|| Correlation::new(&config.correlation, config.assets.len()));
+        let claim = Claim::new(&config, epoch);
+        Self {
+            // A window that is shorter than the time between epoch boundaries could miss the
+            // claims of the last one altogether.
+            roi_window: epoch.epochs(config.roi_window_days).max(epoch.boundary()),
+            epochs_per_year: epoch.per_year(),
+            mint: Volume::new(config.mint_volume),
+            burn: Volume::new(config.burn_volume),
+            mint_demand: Demand::new(config.mint_demand),
+            burn_demand: Demand::new(config.burn_demand),
+            ren_price: Price::new(config.ren_price),
+            bonding: {
+                let bonding = Bonding::new(config.bonding, config.target_roi).with_operating_cost(
+                    config.operating_cost.per_year(epoch),
+                    config.operating_cost.bond,
+                );
+                if config.lifecycle {
+                    bonding.with_lifecycle()
+                } else {
+                    bonding
+                }
+            },
+            arbitrage: Arbitrage::new(
+                config.arbitrage,
+                config.arbitrage_rebate,
+                config.arbitrage_volume,
+            ),
+            assets: config
+                .assets
+                .iter()
+                .map(|asset| Asset {
+                    name: asset.name.clone(),
+                    mint: Volume::new(asset.mint_volume),
+                    burn: Volume::new(asset.burn_volume),
+                    price: asset.price.map(Price::new),
+                })
+                .collect(),
+            correlation,
+            correlated: (0, vec![]),
+            shocks: Shocks::new(config.shocks),
+            regimes: Regimes::new(config.regimes),
+            claim,
+            ren_supply: config.ren_supply,
+            ren_removed: Ren::ZERO,
+            replay: config.replay,
+            prior_income: Usd::ZERO,
+            transactions: config.transactions.map(|config| {
+                (
+                    Transactions::new(config.mint),
+                    Transactions::new(config.burn),
+                )
+            }),
+            gas: config.gas.map(Gas::new),
+            competitor: config.competitor.map(Competitor::new),
+            peg: config.peg.map(Peg::new),
+            ren_demand: config.ren_demand,
+            income_priced: None,
+        }
+    }
+
+    /// The multiple of the mint volume that users want from RenVM at the given minting fee, after
+    /// a competitor (if any) takes its share.
+    fn mint_scale(&self, fee: Option<Pct>) -> f64 {
+        let share = self
+            .competitor
+            .map_or(1.0, |competitor| competitor.mint_share(fee));
+        self.mint_demand.scale(fee) * share
+    }
+
+    /// The multiple of the burn volume that users want from RenVM at the given burning fee, after
+    /// a competitor (if any) takes its share.
+    fn burn_scale(&self, fee: Option<Pct>) -> f64 {
+        let share = self
+            .competitor
+            .map_or(1.0, |competitor| competitor.burn_share(fee));
+        self.burn_demand.scale(fee) * share
+    }
+
+    /// The volumes that arbitrageurs mint and burn to hold the peg in the next epoch, in aggregate
+    /// or for an asset (by its index), where less than is locked can be burned. Nothing is minted
+    /// or burned without a pool for it.
+    fn peg_arbitrage(
+        &mut self,
+        history: &History,
+        i: Option<usize>,
+        rng: &mut SimRng,
+    ) -> (Usd, Usd) {
+        let Some(peg) = &mut self.peg else {
+            return (Usd::ZERO, Usd::ZERO);
+        };
+        let state = history.latest();
+        let (fees, locked) = match i {
+            None => ((state.mf, state.bf), state.tvl),
+            Some(i) if self.assets[i].name == peg.asset() => match state.assets.get(i) {
+                Some(asset) => ((asset.mf, asset.bf), asset.tvl),
+                None => ((state.mf, state.bf), Usd::ZERO),
+            },
+            Some(_) => return (Usd::ZERO, Usd::ZERO),
+        };
+        let fees = (history.len() > 1).then_some(fees);
+        let (minted, burned) = peg.advance(history.len(), fees, rng);
+        (minted, burned.min(locked.max(Usd::ZERO)))
+    }
+
+    /// The share of the volume in the given epoch that is worth its gas (all of it, without gas).
+    fn gas_share(&mut self, epoch: usize, rng: &mut SimRng) -> f64 {
+        match &mut self.gas {
+            Some(gas) => {
+                let cost = gas.cost(epoch, rng);
+                gas.share(cost)
+            }
+            None => 1.0,
+        }
+    }
+
+    /// The transactions in the given epoch that are worth their gas.
+    fn economical(
+        &mut self,
+        epoch: usize,
+        mut transactions: Vec<Usd>,
+        rng: &mut SimRng,
+    ) -> Vec<Usd> {
+        if let Some(gas) = &mut self.gas {
+            let cost = gas.cost(epoch, rng);
+            transactions.retain(|&size| gas.is_economical(size, cost));
+        }
+        transactions
+    }
+
+    /// The factor by which the price of REN moves as the REN taken out of circulation changes from
+    /// what the price has responded to so far to `removed`. It stops responding if the whole supply
+    /// is burned.
+    fn scarcity(&self, removed: Ren) -> f64 {
+        let circulating = self.ren_supply.circulating;
+        let (before, after) = (circulating - self.ren_removed, circulating - removed);
+        if removed == self.ren_removed || before <= Ren::ZERO || after <= Ren::ZERO {
+            return 1.0;
+        }
+        (before / after).powf(self.ren_supply.elasticity)
+    }
+
+    /// The annual income of node operators, from the fees that they claimed over the ROI window of
+    /// the history. Epochs of the window from before the run began count as having paid the prior
+    /// income.
+    fn annual_income(&self, history: &History) -> Usd {
+        let window = self.roi_window;
+        let simulated = history.len().saturating_sub(1).min(window);
+        let claimed = Usd(history.increase(window, |state| state.f_claimed.0));
+        (claimed + self.prior_income * (window - simulated) as f64) / window as f64
+            * self.epochs_per_year
+    }
+
+    /// The factor by which the price of REN moves as the income of node operators changes from
+    /// what the price has responded to so far to their income in the history, which is one the
+    /// first time.
+    fn reflexivity(&mut self, history: &History) -> f64 {
+        let Some(config) = self.ren_demand else {
+            return 1.0;
+        };
+        let income = self.annual_income(history);
+        let before = self.income_priced.replace(income).unwrap_or(income);
+        let (before, after) = (
+            before.max(Usd::ZERO) + config.base,
+            income.max(Usd::ZERO) + config.base,
+        );
+        if before <= Usd::ZERO || after <= Usd::ZERO {
+            return 1.0;
+        }
+        (after / before).powf(config.elasticity)
+    }
+
+    /// Make node operators want `risk` more return every year, to make up for what they expect to
+    /// lose to slashing (see `SlashingConfig::risk`).
+    pub fn with_slashing_risk(mut self, risk: Pct) -> Self {
+        self.bonding = self.bonding.with_risk_premium(risk);
+        self
+    }
+
+    /// Make node operators assume that `income` was claimed in every epoch before the run began,
+    /// until the run has filled their ROI window (see `InitialConfig::fee_income`).
+    pub fn with_prior_income(mut self, income: Usd) -> Self {
+        self.prior_income = income;
+        self
+    }
+}
+
+/// The fee that users were charged in the previous epoch, which is what they respond to, or `None`
+/// when nothing has been simulated yet.
+fn previous_fee(history: &History, fee: impl Fn(&State) -> Option<Pct>) -> Option<Pct> {
+    if history.len() > 1 {
+        fee(history.latest())
+    } else {
+        None
+    }
+}
+
+impl Default for Basic {
+    fn default() -> Self {
+        Self::new(ExternalConfig::default(), &EpochConfig::default())
+    }
+}
+
+impl ExternalModel for Basic {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        // The basic model assumes that node operators want to receive some target ROI based on
+        // the average fee over a recent window (one week by default). How they act on it depends
+        // on the bonding model (e.g. whole darknodes at the price of REN, or individual agents).
+        let per_annum = self.annual_income(history);
+        if history.len() == 1 {
+            let initial = history.latest();
+            self.bonding
+                .start(initial.tvb, initial.darknodes, self.ren_price.current());
+        }
+        let withdrawn = self.shocks.unbonding(history.len());
+        self.bonding
+            .bond(per_annum, self.ren_price.current(), withdrawn, rng)
+    }
+
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        // The basic model assumes that there will be ~$4M minted per epoch, which is unaffected by
+        // the minting fee unless demand is elastic. The volume can also be stochastic.
+        self.regimes.advance(history.len(), rng);
+        if self.replay.is_enabled() {
+            let replayed = self.replay.mint_volume.get(history.len() - 1);
+            return replayed.copied().unwrap_or(Usd::ZERO);
+        }
+        let demand = self.mint_scale(previous_fee(history, |state| Some(state.mf)));
+        let volume = self.mint.sample(history.len() - 1, rng)
+            * self.shocks.demand(history.len())
+            * demand
+            * self.regimes.volume()
+            * self.gas_share(history.len(), rng);
+        volume + self.peg_arbitrage(history, None, rng).0
+    }
+
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        // // The basic model assumes that there will be ~$2M burned per epoch (unaffected by the
+        // // burning fee, or the rebate, which is obviously unrealistic).
+        // 2_000_000.0
+
+        // A more complex model considers the available rebate, and adjusts volume accordingly by
+        // adding whatever arbitrageurs decide to route through RenVM to collect it.
+        self.regimes.advance(history.len(), rng);
+        if self.replay.is_enabled() {
+            // Replayed burns already include whatever arbitrage there was.
+            let replayed = self.replay.burn_volume.get(history.len() - 1);
+            return replayed.copied().unwrap_or(Usd::ZERO);
+        }
+        let demand = self.burn_scale(previous_fee(history, |state| Some(state.bf)));
+        // Arbitrageurs only burn when the rebate pays for the gas too, so their burns all go ahead.
+        let volume = self.burn.sample(history.len() - 1, rng)
+            * self.shocks.burn_demand(history.len())
+            * demand
+            * self.regimes.volume()
+            * self.gas_share(history.len(), rng);
+        volume + self.arbitrage.volume(history) + self.peg_arbitrage(history, None, rng).1
+    }
+
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let Some((mint, _)) = self.transactions.filter(|_| !self.replay.is_enabled()) else {
+            return vec![self.mint_volume(history, rng)];
+        };
+        self.regimes.advance(history.len(), rng);
+        let demand = self.mint_scale(previous_fee(history, |state| Some(state.mf)));
+        let scale = self.shocks.demand(history.len()) * demand * self.regimes.volume();
+        let mints = mint.sample(scale, rng);
+        let mut mints = self.economical(history.len(), mints, rng);
+        let (arbitrage, _) = self.peg_arbitrage(history, None, rng);
+        if arbitrage > Usd::ZERO {
+            mints.push(arbitrage);
+        }
+        mints
+    }
+
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        let Some((_, burn)) = self.transactions.filter(|_| !self.replay.is_enabled()) else {
+            return vec![self.burn_volume(history, rng)];
+        };
+        self.regimes.advance(history.len(), rng);
+        let demand = self.burn_scale(previous_fee(history, |state| Some(state.bf)));
+        let scale = self.shocks.burn_demand(history.len()) * demand * self.regimes.volume();
+        let burns = burn.sample(scale, rng);
+        let mut burns = self.economical(history.len(), burns, rng);
+        let (_, peg) = self.peg_arbitrage(history, None, rng);
+        for arbitrage in [self.arbitrage.volume(history), peg] {
+            if arbitrage > Usd::ZERO {
+                burns.push(arbitrage);
+            }
+        }
+        burns
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        self.regimes.advance(history.len(), rng);
+        self.ren_price.next(rng);
+        // The price responds to REN that was burned or emitted in the previous epoch.
+        let latest = history.latest();
+        let removed = latest.ren_burned - latest.ren_emitted;
+        let scarcity = self.scarcity(removed);
+        self.ren_removed = removed;
+        // And to the income of node operators, as far as bonding to earn it is what REN is for.
+        let reflexivity = self.reflexivity(history);
+        self.ren_price.scale(
+            self.shocks.ren_price(history.len()) * self.regimes.price() * scarcity * reflexivity,
+        );
+        self.ren_price.current()
+    }
+
+    fn darknodes(&mut self, _history: &History, _rng: &mut SimRng) -> u64 {
+        self.bonding.darknodes()
+    }
+
+    fn assets(&self) -> Vec<String> {
+        self.assets.iter().map(|asset| asset.name.clone()).collect()
+    }
+
+    fn asset_mint_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let i = match self.assets.iter().position(|a| a.name == asset) {
+            Some(i) => i,
+            None => return Usd::ZERO,
+        };
+        self.regimes.advance(history.len(), rng);
+        let demand = self.mint_scale(previous_fee(history, |state| {
+            state.assets.get(i).map(|asset| asset.mf)
+        }));
+        let volume = self.assets[i].mint.sample(history.len() - 1, rng)
+            * self.shocks.demand(history.len())
+            * demand
+            * self.regimes.volume()
+            * self.gas_share(history.len(), rng);
+        volume + self.peg_arbitrage(history, Some(i), rng).0
+    }
+
+    fn asset_burn_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        let i = match self.assets.iter().position(|a| a.name == asset) {
+            Some(i) => i,
+            None => return Usd::ZERO,
+        };
+        self.regimes.advance(history.len(), rng);
+        let demand = self.burn_scale(previous_fee(history, |state| {
+            state.assets.get(i).map(|asset| asset.bf)
+        }));
+        let volume = self.assets[i].burn.sample(history.len() - 1, rng)
+            * self.shocks.burn_demand(history.len())
+            * demand
+            * self.regimes.volume()
+            * self.gas_share(history.len(), rng);
+
+        // Arbitrageurs can only burn what is locked, so the arbitrage volume is shared between
+        // assets by their share of the locked value (or evenly, when nothing is locked yet).
+        let state = history.latest();
+        let locked: Usd = state
+            .assets
+            .iter()
+            .map(|asset| asset.tvl.max(Usd::ZERO))
+            .sum();
+        let share = match state.assets.get(i) {
+            Some(asset) if locked > Usd::ZERO => asset.tvl.max(Usd::ZERO) / locked,
+            _ => 1.0 / self.assets.len() as f64,
+        };
+        volume
+            + self.arbitrage.volume(history) * share
+            + self.peg_arbitrage(history, Some(i), rng).1
+    }
+
+    fn asset_price(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.regimes.advance(history.len(), rng);
+        let crash = self.shocks.price(history.len(), Some(asset)) * self.regimes.price();
+        // The shocks to every asset are drawn together, the first time that any of them is priced
+        // in the epoch.
+        if let Some(correlation) = &self.correlation {
+            if self.correlated.0 != history.len() {
+                self.correlated = (history.len(), correlation.sample(rng));
+            }
+        }
+        let i = self.assets.iter().position(|a| a.name == asset);
+        let shock = i.and_then(|i| self.correlated.1.get(i).copied());
+        match i.map(|i| &mut self.assets[i]) {
+            Some(Asset {
+                price: Some(price), ..
+            }) => {
+                match shock {
+                    Some(z) => price.next_with(z, rng),
+                    None => price.next(rng),
+                };
+                price.scale(crash);
+                price.current()
+            }
+            _ => Usd::ZERO,
+        }
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, _net: Usd, rng: &mut SimRng) -> Usd {
+        // Crashes (and regimes) in the price of individual assets revalue them directly, so this is
+        // only for locked value that is modelled in aggregate.
+        if !self.assets.is_empty() {
+            return Usd::ZERO;
+        }
+        self.regimes.advance(history.len(), rng);
+        let tvl = history.latest().tvl;
+        (self.shocks.price(history.len(), None) * self.regimes.price() - 1.0) * tvl.max(Usd::ZERO)
+    }
+
+    fn claim_curve(&mut self, history: &History, _rng: &mut SimRng) -> Pct {
+        self.claim.rate(history)
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        *self = serde_json::from_value(state)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Models for the peg of a token that RenVM mints (such as renBTC) to the asset that it locks, as
+//! priced by a Curve-style (StableSwap) pool that pairs the two. Outside order flow moves the pool
+//! off balance, which puts the token at a premium or a discount to the asset. Once the discount is
+//! larger than the burning fee, arbitrageurs buy the token from the pool and burn it for the asset
+//! (until the discount is back to the fee), and once the premium is larger than the minting fee,
+//! they mint the token and sell it into the pool. Their volume goes through RenVM on top of the
+//! organic volume, so much of what is burned can be peg arbitrage rather than exits.
+
+use crate::config::PegConfig;
+use crate::{Pct, SimRng, Usd};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+/// Peg tracks a StableSwap pool according to a `PegConfig`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Peg {
+    config: PegConfig,
+    /// The value (in USD) of the token that is in the pool.
+    token: f64,
+    /// The value (in USD) of the asset that is in the pool.
+    asset: f64,
+    /// The StableSwap invariant, which is the total value of the pool when it is balanced.
+    invariant: f64,
+    /// The epoch that the pool was last advanced for, and the volumes that arbitrageurs minted and
+    /// burned in it.
+    epoch: usize,
+    arbitrage: (Usd, Usd),
+}
+
+impl Peg {
+    /// Create a peg model using the given parameters, with a balanced pool.
+    pub fn new(config: PegConfig) -> Self {
+        let liquidity = config.liquidity.0.max(0.0);
+        Self {
+            token: liquidity / 2.0,
+            asset: liquidity / 2.0,
+            invariant: liquidity,
+            config,
+            epoch: 0,
+            arbitrage: (Usd::ZERO, Usd::ZERO),
+        }
+    }
+
+    /// The name of the asset whose peg this is, when assets are modelled individually.
+    pub fn asset(&self) -> &str {
+        &self.config.asset
+    }
+
+    /// The price of the token in units of the asset, which is one at the peg.
+    pub fn price(&self) -> f64 {
+        self.price_at(self.token, self.asset)
+    }
+
+    /// Advance the pool by an epoch, with the minting and burning fees of the previous epoch (if
+    /// any have been charged yet), and return the volumes that arbitrageurs mint and burn in it.
+    /// The pool only moves the first time that the epoch asks for it.
+    pub fn advance(
+        &mut self,
+        epoch: usize,
+        fees: Option<(Pct, Pct)>,
+        rng: &mut SimRng,
+    ) -> (Usd, Usd) {
+        if self.epoch == epoch || self.invariant <= 0.0 {
+            return self.arbitrage;
+        }
+        self.epoch = epoch;
+        // Outside traders sell the token into the pool (or buy it out of the pool, when negative).
+        let z: f64 = Normal::new(0.0, 1.0).expect("standard normal").sample(rng);
+        let token = (self.token + z * self.config.flow.0).max(self.invariant * 1e-6);
+        self.swap(token);
+        let mut arbitrage = (Usd::ZERO, Usd::ZERO);
+        if let Some((mf, bf)) = fees {
+            let price = self.price();
+            if price < 1.0 - bf.0 {
+                let token = self.balance_at(1.0 - bf.0);
+                arbitrage.1 = Usd(self.token - token);
+                self.swap(token);
+            } else if price > 1.0 + mf.0 {
+                let token = self.balance_at(1.0 + mf.0);
+                arbitrage.0 = Usd(token - self.token);
+                self.swap(token);
+            }
+        }
+        tracing::trace!(
+            price = self.price(),
+            minted = arbitrage.0 .0,
+            burned = arbitrage.1 .0,
+            "peg"
+        );
+        self.arbitrage = arbitrage;
+        arbitrage
+    }
+
+    /// Move the token in the pool to the given balance, taking the asset to where the invariant
+    /// holds.
+    fn swap(&mut self, token: f64) {
+        self.token = token;
+        self.asset = self.balance(token);
+    }
+
+    /// The balance of the asset that holds the invariant with the given balance of the token. With
+    /// `A` the amplification (times four, for two coins), the invariant `A (x + y) + D = A D +
+    /// D³ / 4xy` is a quadratic in `y`.
+    fn balance(&self, token: f64) -> f64 {
+        let a = self.amplification();
+        let d = self.invariant;
+        let b = a * token + d - a * d;
+        let c = d.powi(3) / (4.0 * token);
+        let root = (b * b + 4.0 * a * c).sqrt();
+        // Whichever form of the root does not subtract numbers that are nearly equal.
+        if b >= 0.0 {
+            2.0 * c / (b + root)
+        } else {
+            (root - b) / (2.0 * a)
+        }
+    }
+
+    /// The amplification, times four for two coins (and positive, so that the pool has a price).
+    fn amplification(&self) -> f64 {
+        4.0 * self.config.amplification.max(f64::EPSILON)
+    }
+
+    /// The marginal price of the token in units of the asset, at the given balances.
+    fn price_at(&self, token: f64, asset: f64) -> f64 {
+        let a = self.amplification();
+        let d3 = self.invariant.powi(3) / 4.0;
+        (a + d3 / (token * token * asset)) / (a + d3 / (token * asset * asset))
+    }
+
+    /// The balance of the token at which it has the given price, found by bisection (the price
+    /// falls as the balance of the token grows).
+    fn balance_at(&self, price: f64) -> f64 {
+        let (mut low, mut high) = (self.invariant * 1e-6, self.invariant);
+        for _ in 0..100 {
+            let mid = 0.5 * (low + high);
+            if self.price_at(mid, self.balance(mid)) > price {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        0.5 * (low + high)
+    }
+}
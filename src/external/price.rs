@@ -0,0 +1,167 @@
+//! Models for the price (in USD) of a token over time, such as the price of REN. Geometric Brownian
+//! motion almost never produces a large crash, so jump diffusion adds rare jumps (and fatter tails)
+//! for studying tail risk. The everyday moves of several prices can also be correlated, since the
+//! prices of locked assets tend to crash together.
+
+use crate::config::PriceConfig;
+use crate::{SimRng, Usd};
+use rand_distr::{ChiSquared, Distribution, Normal, Poisson, StudentT};
+use serde::{Deserialize, Serialize};
+
+/// Price evolves the price of a token every epoch according to a `PriceConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Price {
+    config: PriceConfig,
+    price: Usd,
+}
+
+impl Price {
+    /// Create a price model using the given parameters.
+    pub fn new(config: PriceConfig) -> Self {
+        let price = match config {
+            PriceConfig::Constant { price } => price,
+            PriceConfig::Gbm { initial, .. } | PriceConfig::JumpDiffusion { initial, .. } => {
+                initial
+            }
+        };
+        Self { config, price }
+    }
+
+    /// The price in the most recent epoch (or the initial price, if no epoch has been simulated).
+    pub fn current(&self) -> Usd {
+        self.price
+    }
+
+    /// Multiply the price by the given factor (e.g. 0.5 for a 50% crash). Stochastic models
+    /// continue from the new price.
+    pub fn scale(&mut self, factor: f64) {
+        self.price *= factor;
+    }
+
+    /// Advance the price by one epoch and return it.
+    pub fn next(&mut self, rng: &mut SimRng) -> Usd {
+        self.advance(None, rng)
+    }
+
+    /// Advance the price by one epoch like `next`, but with the given standard normal shock driving
+    /// the everyday move (so that it can be correlated with other prices), and return it.
+    pub fn next_with(&mut self, z: f64, rng: &mut SimRng) -> Usd {
+        self.advance(Some(z), rng)
+    }
+
+    fn advance(&mut self, shock: Option<f64>, rng: &mut SimRng) -> Usd {
+        let normal = |rng: &mut SimRng| -> f64 {
+            shock.unwrap_or_else(|| Normal::new(0.0, 1.0).expect("standard normal").sample(rng))
+        };
+        match self.config {
+            PriceConfig::Constant { .. } => {}
+            PriceConfig::Gbm {
+                drift, volatility, ..
+            } => {
+                // Geometric Brownian motion, where the drift and volatility are per epoch.
+                let z = normal(rng);
+                self.price *= ((drift - 0.5 * volatility * volatility) + volatility * z).exp();
+            }
+            PriceConfig::JumpDiffusion {
+                drift,
+                volatility,
+                jump_rate,
+                jump_mean,
+                jump_volatility,
+                degrees_of_freedom: dof,
+                ..
+            } => {
+                // The everyday moves are scaled to unit variance, so that fatter tails do not also
+                // make the price more volatile. A given shock is turned into a t-distributed one by
+                // dividing it by an independent chi-squared draw.
+                let z = match shock {
+                    Some(z) if dof > 2.0 => {
+                        let chi: f64 = ChiSquared::new(dof)
+                            .expect("positive degrees of freedom")
+                            .sample(rng);
+                        z * ((dof - 2.0) / chi).sqrt()
+                    }
+                    None if dof > 2.0 => {
+                        let t: f64 = StudentT::new(dof)
+                            .expect("positive degrees of freedom")
+                            .sample(rng);
+                        t * ((dof - 2.0) / dof).sqrt()
+                    }
+                    _ => normal(rng),
+                };
+                let mut log_return = (drift - 0.5 * volatility * volatility) + volatility * z;
+                // The log returns of the jumps in an epoch add up, and a sum of normals is normal.
+                // The parameters of the jumps are finite in every configuration that is read.
+                if jump_rate > 0.0 {
+                    let jumps: f64 = Poisson::new(jump_rate).expect("positive rate").sample(rng);
+                    if jumps > 0.0 {
+                        tracing::debug!(jumps, "price jumped");
+                        log_return +=
+                            Normal::new(jumps * jump_mean, jumps.sqrt() * jump_volatility.abs())
+                                .expect("finite jump")
+                                .sample(rng);
+                    }
+                }
+                self.price *= log_return.exp();
+            }
+        }
+        self.price
+    }
+}
+
+/// Correlation draws standard normal shocks for several prices at once, with the given
+/// correlation between them, from the Cholesky factor of the correlation matrix.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Correlation {
+    /// The lower triangular factor, by row.
+    factor: Vec<Vec<f64>>,
+}
+
+impl Correlation {
+    /// Create the correlation between `n` prices from a matrix of their correlations, by row.
+    /// Either triangle of the matrix can be given (the lower one is used where both are), the
+    /// diagonal is always one, and missing correlations are zero. A matrix that is not positive
+    /// semi-definite (which no real correlations can be) is made so by dropping what it cannot
+    /// factor, with a warning.
+    pub fn new(matrix: &[Vec<f64>], n: usize) -> Self {
+        let get = |i: usize, j: usize| matrix.get(i).and_then(|row| row.get(j)).copied();
+        let correlation = |i: usize, j: usize| {
+            if i == j {
+                1.0
+            } else {
+                get(i.max(j), i.min(j))
+                    .or_else(|| get(i.min(j), i.max(j)))
+                    .unwrap_or(0.0)
+                    .clamp(-1.0, 1.0)
+            }
+        };
+        let mut factor = vec![vec![0.0; n]; n];
+        let mut dropped = false;
+        for i in 0..n {
+            for j in 0..=i {
+                let sum: f64 = (0..j).map(|k| factor[i][k] * factor[j][k]).sum();
+                if i == j {
+                    let pivot = correlation(i, i) - sum;
+                    dropped |= pivot < -1e-9;
+                    factor[i][i] = pivot.max(0.0).sqrt();
+                } else if factor[j][j] > 0.0 {
+                    factor[i][j] = (correlation(i, j) - sum) / factor[j][j];
+                }
+            }
+        }
+        if dropped {
+            tracing::warn!("the correlation matrix is not positive semi-definite");
+        }
+        Self { factor }
+    }
+
+    /// Draw one correlated standard normal shock for every price, in order.
+    pub fn sample(&self, rng: &mut SimRng) -> Vec<f64> {
+        let normal = Normal::new(0.0, 1.0).expect("standard normal");
+        let z: Vec<f64> = self.factor.iter().map(|_| normal.sample(rng)).collect();
+        self.factor
+            .iter()
+            .map(|row| row.iter().zip(&z).map(|(l, z)| l * z).sum())
+            .collect()
+    }
+}
@@ -0,0 +1,86 @@
+//! For market regimes (bull, bear, and crab markets, for example) that the world outside of RenVM
+//! switches between, so that runs go through cycles that last for months instead of behaving the
+//! same way throughout. The regimes form a Markov chain: every epoch after the first, the next
+//! regime is drawn from the transition probabilities of the current one. Each regime grows mint and
+//! burn volumes, and moves prices, at its own rate.
+
+use crate::config::RegimeConfig;
+use crate::SimRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Regimes follows the Markov chain of regimes according to their `RegimeConfig`s.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Regimes {
+    regimes: Vec<RegimeConfig>,
+    /// The position of the current regime.
+    current: usize,
+    /// The epoch that the current regime was drawn for, or zero before the first epoch.
+    epoch: usize,
+    /// The factor by which volumes have grown across every regime so far.
+    volume: f64,
+}
+
+impl Regimes {
+    /// Create the chain of regimes from their parameters, beginning in the first.
+    pub fn new(regimes: Vec<RegimeConfig>) -> Self {
+        Self {
+            regimes,
+            current: 0,
+            epoch: 0,
+            volume: 1.0,
+        }
+    }
+
+    /// Move on to the regime of the given epoch (where the first simulated epoch is epoch one).
+    /// This can be called any number of times in an epoch, but only the first call in an epoch
+    /// draws the next regime. Nothing is drawn when there are no regimes.
+    pub fn advance(&mut self, epoch: usize, rng: &mut SimRng) {
+        if self.regimes.is_empty() || epoch <= self.epoch {
+            return;
+        }
+        // The first epoch (including the first after a restart) stays in the current regime.
+        if self.epoch > 0 {
+            let transitions = &self.regimes[self.current].transitions;
+            let weights = || {
+                transitions
+                    .iter()
+                    .take(self.regimes.len())
+                    .map(|weight| weight.max(0.0))
+            };
+            let total: f64 = weights().sum();
+            if total > 0.0 && total.is_finite() {
+                let mut draw = rng.gen::<f64>() * total;
+                let next = weights()
+                    .position(|weight| {
+                        draw -= weight;
+                        draw < 0.0
+                    })
+                    .unwrap_or(self.current);
+                if next != self.current {
+                    tracing::debug!(epoch, regime = %self.regimes[next].name, "regime changed");
+                }
+                self.current = next;
+            }
+            self.volume *= 1.0 + self.regimes[self.current].volume_growth;
+        }
+        self.epoch = epoch;
+    }
+
+    /// The factor by which mint and burn volumes are multiplied in the current epoch.
+    pub fn volume(&self) -> f64 {
+        if self.regimes.is_empty() {
+            1.0
+        } else {
+            self.volume
+        }
+    }
+
+    /// The factor by which prices move in the current epoch, on top of their models.
+    pub fn price(&self) -> f64 {
+        match self.regimes.get(self.current) {
+            Some(regime) => regime.price_drift.exp(),
+            None => 1.0,
+        }
+    }
+}
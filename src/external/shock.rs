@@ -0,0 +1,93 @@
+//! For one-off shocks to the world outside of RenVM (price crashes, sudden unbonding, and collapses
+//! in minting or burning demand), so that the resilience of fee and rebate designs can be tested.
+
+use crate::config::ShockConfig;
+use crate::Pct;
+use serde::{Deserialize, Serialize};
+
+/// Shocks answers how hard every kind of shock hits in a given epoch (where the first simulated
+/// epoch is epoch one). Shocks of the same kind in the same epoch compound.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Shocks {
+    shocks: Vec<ShockConfig>,
+}
+
+impl Shocks {
+    /// Create the shocks from their parameters.
+    pub fn new(shocks: Vec<ShockConfig>) -> Self {
+        Self { shocks }
+    }
+
+    /// The factor by which the price of an asset is multiplied in the epoch. Without an asset, this
+    /// is the factor for locked value that is modelled in aggregate.
+    pub fn price(&self, epoch: usize, asset: Option<&str>) -> f64 {
+        self.factor("price crash", epoch, |shock| match shock {
+            ShockConfig::PriceCrash {
+                epoch: at,
+                drop,
+                asset: target,
+            } if *at == epoch && (target.is_none() || target.as_deref() == asset) => Some(*drop),
+            _ => None,
+        })
+    }
+
+    /// The factor by which the price of REN is multiplied in the epoch.
+    pub fn ren_price(&self, epoch: usize) -> f64 {
+        self.factor("REN price crash", epoch, |shock| match shock {
+            ShockConfig::RenPriceCrash { epoch: at, drop } if *at == epoch => Some(*drop),
+            _ => None,
+        })
+    }
+
+    /// The share of bonded value that is withdrawn in the epoch.
+    pub fn unbonding(&self, epoch: usize) -> Pct {
+        Pct(1.0
+            - self.factor("unbonding", epoch, |shock| match shock {
+                ShockConfig::Unbonding { epoch: at, share } if *at == epoch => Some(*share),
+                _ => None,
+            }))
+    }
+
+    /// The factor by which minting volume is multiplied in the epoch.
+    pub fn demand(&self, epoch: usize) -> f64 {
+        self.factor("demand collapse", epoch, |shock| match shock {
+            ShockConfig::DemandCollapse {
+                epoch: from,
+                epochs,
+                drop,
+            } if (*from..from + epochs).contains(&epoch) => Some(*drop),
+            _ => None,
+        })
+    }
+
+    /// The factor by which burning volume (other than arbitrage) is multiplied in the epoch.
+    pub fn burn_demand(&self, epoch: usize) -> f64 {
+        self.factor("burn collapse", epoch, |shock| match shock {
+            ShockConfig::BurnCollapse {
+                epoch: from,
+                epochs,
+                drop,
+            } if (*from..from + epochs).contains(&epoch) => Some(*drop),
+            _ => None,
+        })
+    }
+
+    /// The product of one minus every loss that applies.
+    fn factor<F: Fn(&ShockConfig) -> Option<Pct>>(
+        &self,
+        kind: &'static str,
+        epoch: usize,
+        loss: F,
+    ) -> f64 {
+        let factor = self
+            .shocks
+            .iter()
+            .filter_map(loss)
+            .map(|loss| 1.0 - loss.0)
+            .product();
+        if factor != 1.0 {
+            tracing::debug!(kind, epoch, factor, "shock");
+        }
+        factor
+    }
+}
@@ -0,0 +1,39 @@
+//! Models for the individual transactions that make up the volumes of an epoch, for fees that
+//! depend on the size of a transaction (which the volumes alone cannot tell apart).
+
+use crate::config::TransactionConfig;
+use crate::{SimRng, Usd};
+use rand_distr::{Distribution, Normal, Poisson};
+use serde::{Deserialize, Serialize};
+
+/// Transactions samples the transactions of an epoch according to a `TransactionConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Transactions {
+    config: TransactionConfig,
+}
+
+impl Transactions {
+    /// Create a transaction model using the given parameters.
+    pub fn new(config: TransactionConfig) -> Self {
+        Self { config }
+    }
+
+    /// The sizes of the transactions in the next epoch, where `scale` scales how many of them are
+    /// expected to arrive.
+    pub fn sample(&self, scale: f64, rng: &mut SimRng) -> Vec<Usd> {
+        let rate = self.config.rate * scale;
+        if rate <= 0.0 || !rate.is_finite() {
+            return vec![];
+        }
+        let count = Poisson::new(rate).expect("positive rate").sample(rng) as usize;
+        // Subtracting half of the variance keeps the expected size equal to `size`.
+        let volatility = self.config.volatility;
+        let normal = Normal::new(0.0, 1.0).expect("standard normal");
+        (0..count)
+            .map(|_| {
+                let z: f64 = normal.sample(rng);
+                self.config.size * (volatility * z - 0.5 * volatility * volatility).exp()
+            })
+            .collect()
+    }
+}
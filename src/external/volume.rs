@@ -0,0 +1,69 @@
+//! Models for the amount of value that is minted (or burned) every epoch. Constant volumes make
+//! every run identical, so stochastic volumes are available for studying fee mechanisms under
+//! noisy demand. Volumes can also respond to the fees that are charged on them, which is what
+//! dynamic fee curves exploit.
+
+use crate::config::{DemandConfig, VolumeConfig};
+use crate::{Pct, SimRng, Usd};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+/// Volume samples the volume for an epoch according to a `VolumeConfig`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Volume {
+    config: VolumeConfig,
+}
+
+impl Volume {
+    /// Create a volume model using the given parameters.
+    pub fn new(config: VolumeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Sample the volume for the given epoch (where the first simulated epoch is zero).
+    pub fn sample(&self, epoch: usize, rng: &mut SimRng) -> Usd {
+        match self.config {
+            VolumeConfig::Constant { volume } => volume,
+            VolumeConfig::Lognormal {
+                mean,
+                volatility,
+                drift,
+            } => {
+                // The mean grows (or shrinks) geometrically by `drift` every epoch, and the volume
+                // is lognormally distributed around it. Subtracting half of the variance keeps the
+                // expected volume equal to the drifting mean.
+                let mean = mean * (1.0 + drift).powi(epoch as i32);
+                let z: f64 = Normal::new(0.0, 1.0).expect("standard normal").sample(rng);
+                mean * (volatility * z - 0.5 * volatility * volatility).exp()
+            }
+        }
+    }
+}
+
+/// Demand scales volumes by how they respond to fees according to a `DemandConfig`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Demand {
+    config: DemandConfig,
+}
+
+impl Demand {
+    /// Create a demand model using the given parameters.
+    pub fn new(config: DemandConfig) -> Self {
+        Self { config }
+    }
+
+    /// The multiple of the volume that users want at the given fee, which is one at the reference
+    /// fee (or when no fee has been charged yet).
+    pub fn scale(&self, fee: Option<Pct>) -> f64 {
+        let DemandConfig {
+            elasticity,
+            reference_fee,
+        } = self.config;
+        match fee {
+            Some(fee) if elasticity != 0.0 && reference_fee > Pct::ZERO => {
+                (1.0 - elasticity * (fee.0 - reference_fee.0) / reference_fee.0).max(0.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
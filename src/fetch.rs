@@ -0,0 +1,157 @@
+//! For starting forward simulations from where RenVM actually is, rather than from an all-zero
+//! state. The current TVL, number of registered darknodes, and price of REN, along with the volumes
+//! minted and burned over recent days, are queried from a GraphQL endpoint that indexes RenVM (the
+//! RenVM subgraph by default), and written into a configuration as its initial state and volumes.
+//!
+//! The endpoint is sent this query, with the number of recent days in place of `$days`:
+//!
+//! ```graphql
+//! {
+//!   renVM(id: "1") { tvl darknodes renPrice }
+//!   days(first: $days, orderBy: date, orderDirection: desc) { mintVolume burnVolume }
+//! }
+//! ```
+//!
+//! Values are in USD, and can be numbers or decimal strings (as subgraphs return big decimals).
+//! Any endpoint that answers the same query can be used instead, and a response that was saved to
+//! a file can be read without going to the network at all.
+
+use crate::config::{Config, PriceConfig, VolumeConfig};
+use crate::error::{Error, Result};
+use crate::Usd;
+use serde_json::Value;
+use std::io::Write;
+
+/// The endpoint that is queried by default.
+pub const DEFAULT_URL: &str = "https://api.thegraph.com/subgraphs/name/renproject/renvm";
+
+/// Stats is the live state of RenVM that a simulation begins from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// The total value locked.
+    pub tvl: Usd,
+    /// The number of registered darknodes.
+    pub darknodes: u64,
+    /// The price of REN.
+    pub ren_price: Usd,
+    /// The value minted per day, averaged over the recent days.
+    pub mint_volume: Usd,
+    /// The value burned per day, averaged over the recent days.
+    pub burn_volume: Usd,
+    /// The number of recent days that the volumes are averaged over.
+    pub days: usize,
+}
+
+/// The GraphQL query for the stats, with volumes from the latest `days` days.
+pub fn query(days: usize) -> String {
+    format!(
+        "{{ renVM(id: \"1\") {{ tvl darknodes renPrice }} \
+         days(first: {}, orderBy: date, orderDirection: desc) {{ mintVolume burnVolume }} }}",
+        days
+    )
+}
+
+/// Query the endpoint at the given URL for the stats, with volumes from the latest `days` days.
+pub fn fetch(url: &str, days: usize) -> Result<Stats> {
+    let body = serde_json::json!({ "query": query(days) }).to_string();
+    let response = ureq::post(url)
+        .content_type("application/json")
+        .send(&body)
+        .and_then(|response| response.into_body().read_to_string())
+        .map_err(|err| Error::Fetch(format!("{}: {}", url, err)))?;
+    Stats::from_response(&serde_json::from_str(&response)?)
+}
+
+impl Stats {
+    /// Read the stats from a response to the query (including its `data` object).
+    pub fn from_response(response: &Value) -> Result<Self> {
+        if let Some(errors) = response.get("errors") {
+            return Err(Error::Fetch(format!("the query failed: {}", errors)));
+        }
+        let invalid = |field: &str| Error::Fetch(format!("missing or invalid `{}`", field));
+        let number = |value: Option<&Value>, field: &str| -> Result<f64> {
+            let number = match value {
+                Some(Value::Number(number)) => number.as_f64(),
+                Some(Value::String(string)) => string.trim().parse().ok(),
+                _ => None,
+            };
+            number
+                .filter(|number| number.is_finite() && *number >= 0.0)
+                .ok_or_else(|| invalid(field))
+        };
+        let data = response.get("data").ok_or_else(|| invalid("data"))?;
+        let renvm = data.get("renVM").ok_or_else(|| invalid("renVM"))?;
+        let days = data
+            .get("days")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("days"))?;
+        let mut minted = 0.0;
+        let mut burned = 0.0;
+        for day in days {
+            minted += number(day.get("mintVolume"), "mintVolume")?;
+            burned += number(day.get("burnVolume"), "burnVolume")?;
+        }
+        let average = |total: f64| Usd(total / days.len().max(1) as f64);
+        Ok(Self {
+            tvl: Usd(number(renvm.get("tvl"), "tvl")?),
+            darknodes: number(renvm.get("darknodes"), "darknodes")? as u64,
+            ren_price: Usd(number(renvm.get("renPrice"), "renPrice")?),
+            mint_volume: average(minted),
+            burn_volume: average(burned),
+            days: days.len(),
+        })
+    }
+
+    /// Begin the configuration from the stats: its initial state takes the TVL, darknodes (and
+    /// the value that they bond), and price of REN, and its volume and price models are centred on
+    /// the recent volumes (per epoch) and the current price. The fees that nodes claimed before
+    /// the run are not fetched, so they are taken to be what pays the target ROI on the bonded
+    /// value. Everything else is left as it is.
+    pub fn apply(&self, config: &mut Config) {
+        let external = &mut config.external;
+        let bond = external.bonding.bond(external.operating_cost.bond);
+        config.initial.tvl = self.tvl;
+        config.initial.darknodes = self.darknodes;
+        config.initial.ren_price = self.ren_price;
+        config.initial.tvb = bond * self.ren_price * self.darknodes as f64;
+        config.initial.fee_income =
+            config.initial.tvb * external.target_roi / config.epoch.per_year();
+        if self.days > 0 {
+            let days = config.epoch.days;
+            centre(&mut external.mint_volume, self.mint_volume * days);
+            centre(&mut external.burn_volume, self.burn_volume * days);
+        }
+        match &mut external.ren_price {
+            PriceConfig::Constant { price } => *price = self.ren_price,
+            PriceConfig::Gbm { initial, .. } | PriceConfig::JumpDiffusion { initial, .. } => {
+                *initial = self.ren_price
+            }
+        }
+    }
+
+    /// Write a human readable description of the stats.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "tvl          {:.2}", self.tvl.0)?;
+        writeln!(writer, "darknodes    {}", self.darknodes)?;
+        writeln!(writer, "ren_price    {:.4}", self.ren_price.0)?;
+        writeln!(
+            writer,
+            "mint_volume  {:.2} per day (over {} days)",
+            self.mint_volume.0, self.days
+        )?;
+        writeln!(
+            writer,
+            "burn_volume  {:.2} per day (over {} days)",
+            self.burn_volume.0, self.days
+        )?;
+        Ok(())
+    }
+}
+
+/// Centre a volume model on the given volume per epoch, keeping the rest of its parameters.
+fn centre(model: &mut VolumeConfig, volume: Usd) {
+    match model {
+        VolumeConfig::Constant { volume: v } => *v = volume,
+        VolumeConfig::Lognormal { mean, .. } => *mean = volume,
+    }
+}
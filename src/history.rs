@@ -0,0 +1,521 @@
+//! For holding the states produced by a run. By default every state is kept, but a very long run
+//! (decades at daily resolution, say) can keep only a recent window instead, since that is all the
+//! models ever look back over. Headline numbers that would otherwise need the whole history (peaks,
+//! the lowest security ratio, rebates paid, etc.) are accumulated as every state is added, so they
+//! cover the whole run either way. Models look back over the history through the windowed queries
+//! (moving averages, rolling sums, rates of change, and drawdowns) of any field of the state, which
+//! take whatever is held into account, rather than slicing the states themselves.
+
+use crate::config::SecurityConfig;
+use crate::divergence::{self, Detector, Divergence};
+use crate::metrics::Security;
+use crate::state::State;
+use crate::steady_state::{Equilibrium, SteadyState};
+use crate::{Pct, Usd};
+use serde::{Deserialize, Serialize};
+use std::ops::Index;
+
+/// History holds the states produced by a run, beginning with the initial state, and indexed by
+/// epoch (where epoch zero is the initial state). When it is bounded by a window, states that are
+/// older than the window are dropped, but epochs keep their numbers, so `len` is always the number
+/// of states that have ever been added. While an epoch is simulated with a sequential pipeline, the
+/// epoch so far is held after them as a provisional state (see `push_provisional`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct History {
+    states: Vec<State>,
+    first_epoch: usize,
+    /// Whether the last state is the provisional state of the epoch that is being simulated.
+    provisional: bool,
+    window: Option<usize>,
+    aggregates: Aggregates,
+    steady_state: Option<SteadyState>,
+    divergence: Option<Detector>,
+}
+
+impl History {
+    /// Create a history that holds only the initial state, and keeps every state added to it.
+    pub fn new(initial: State) -> Self {
+        Self::from_states(vec![initial])
+    }
+
+    /// Create a history from every state of a run, beginning with the initial state.
+    pub fn from_states(states: Vec<State>) -> Self {
+        Self::from_parts(0, states, None)
+    }
+
+    /// Create a history from the states that remain of a bounded run, where the first of them is
+    /// the state at `first_epoch`. The aggregates are accumulated from those states unless they are
+    /// given (they have to be given for the aggregates to cover the dropped states).
+    pub fn from_parts(
+        first_epoch: usize,
+        states: Vec<State>,
+        aggregates: Option<Aggregates>,
+    ) -> Self {
+        assert!(!states.is_empty(), "missing initial state");
+        let aggregates = aggregates.unwrap_or_else(|| {
+            let required_ratio = SecurityConfig::default().required_ratio;
+            Aggregates::accumulate(first_epoch, &states, 0, required_ratio)
+        });
+        Self {
+            states,
+            first_epoch,
+            provisional: false,
+            window: None,
+            aggregates,
+            steady_state: None,
+            divergence: None,
+        }
+    }
+
+    /// Keep only the states of the latest `window` epochs (at least two, so that the latest state
+    /// can always be compared with the previous one), or every state if there is no window. The
+    /// window must be at least as long as the longest window that any model looks back over.
+    pub fn with_window(mut self, window: Option<usize>) -> Self {
+        self.window = window.map(|window| window.max(2));
+        self.trim();
+        self
+    }
+
+    /// Accumulate the aggregates after the given number of warm-up epochs, where RenVM is secure
+    /// while TVB is at least `required_ratio` times TVL. They are accumulated afresh from the
+    /// states that are held, so this is best done before anything is dropped.
+    pub fn with_aggregates(mut self, warmup: usize, required_ratio: f64) -> Self {
+        self.aggregates =
+            Aggregates::accumulate(self.first_epoch, &self.states, warmup, required_ratio);
+        self.find_equilibrium();
+        self.find_divergence();
+        self
+    }
+
+    /// Watch for the first steady state that the run reaches, using the given detector.
+    pub fn with_steady_state(mut self, steady_state: Option<SteadyState>) -> Self {
+        self.steady_state = steady_state;
+        self.find_equilibrium();
+        self
+    }
+
+    /// Watch for the first divergence of the run, using the given detector.
+    pub fn with_divergence(mut self, divergence: Option<Detector>) -> Self {
+        self.divergence = divergence;
+        self.find_divergence();
+        self
+    }
+
+    /// The number of states that have been added, including the initial state and any states that
+    /// have since been dropped (but not a provisional state, so that the epoch that is being
+    /// simulated is still `len`).
+    pub fn len(&self) -> usize {
+        self.first_epoch + self.states.len() - usize::from(self.provisional)
+    }
+
+    /// Whether the history is empty, which is never true (it always begins with the initial state).
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The latest epoch (where epoch zero is the initial state).
+    pub fn epoch(&self) -> usize {
+        self.len() - 1
+    }
+
+    /// The epoch of the oldest state that is still held (zero if nothing has been dropped).
+    pub fn first_epoch(&self) -> usize {
+        self.first_epoch
+    }
+
+    /// Whether every state since the initial state is still held.
+    pub fn is_complete(&self) -> bool {
+        self.first_epoch == 0
+    }
+
+    /// The number of epochs that are kept, if the history is bounded.
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    /// The states that are still held, beginning with the state at `first_epoch`. This is every
+    /// state of the run unless the history is bounded.
+    pub fn states(&self) -> &[State] {
+        &self.states
+    }
+
+    /// Iterate over the states that are still held, oldest first.
+    pub fn iter(&self) -> std::slice::Iter<'_, State> {
+        self.states.iter()
+    }
+
+    /// The state at the given epoch, if it is still held.
+    pub fn get(&self, epoch: usize) -> Option<&State> {
+        epoch
+            .checked_sub(self.first_epoch)
+            .and_then(|i| self.states.get(i))
+    }
+
+    /// The latest state.
+    pub fn latest(&self) -> &State {
+        self.states.last().expect("missing initial state")
+    }
+
+    /// The detector that watches for a steady state, if there is one.
+    pub fn steady_state(&self) -> Option<&SteadyState> {
+        self.steady_state.as_ref()
+    }
+
+    /// The detector that watches for divergence, if there is one.
+    pub fn divergence(&self) -> Option<&Detector> {
+        self.divergence.as_ref()
+    }
+
+    /// The headline numbers accumulated over every state that has been added.
+    pub fn aggregates(&self) -> &Aggregates {
+        &self.aggregates
+    }
+
+    /// Add the state at the end of the next epoch, dropping old states if the history is bounded.
+    pub fn push(&mut self, state: State) {
+        self.aggregates.add(self.len(), &state);
+        self.states.push(state);
+        self.trim();
+        if self.aggregates.equilibrium.is_none() {
+            if let Some(steady_state) = &self.steady_state {
+                let equilibrium = steady_state.reached(self, self.aggregates.warmup);
+                if let Some(equilibrium) = &equilibrium {
+                    tracing::debug!(epoch = equilibrium.epoch, "steady state reached");
+                }
+                self.aggregates.equilibrium = equilibrium;
+            }
+        }
+        if self.aggregates.divergence.is_none() {
+            if let Some(divergence) = &self.divergence {
+                let found = divergence.reached(self);
+                if let Some(found) = &found {
+                    tracing::debug!(epoch = found.detected, reason = %found.reason, "diverged");
+                }
+                self.aggregates.divergence = found;
+            }
+        }
+    }
+
+    /// Hold the epoch that is being simulated, as far as it has got, after the latest state for a
+    /// while (until `pop_provisional`), so that models see it as the latest state and in every
+    /// window, along with every epoch before it. Nothing is accumulated, detected, or dropped, and
+    /// `len` and `epoch` stay as they were.
+    pub(crate) fn push_provisional(&mut self, state: State) {
+        assert!(!self.provisional, "already holding a provisional state");
+        self.states.push(state);
+        self.provisional = true;
+    }
+
+    /// Remove the provisional state that was held by `push_provisional`.
+    pub(crate) fn pop_provisional(&mut self) {
+        assert!(self.provisional, "not holding a provisional state");
+        self.states.pop();
+        self.provisional = false;
+    }
+
+    /// Consume the history and return the states that are still held.
+    pub fn into_states(self) -> Vec<State> {
+        self.states
+    }
+
+    /// The states of the latest `window` epochs (or as many of them as are held), oldest first.
+    pub fn recent(&self, window: usize) -> &[State] {
+        &self.states[self.states.len().saturating_sub(window)..]
+    }
+
+    /// The sum of a field over the latest `window` epochs (or as many of them as are held).
+    pub fn sum(&self, window: usize, field: impl Fn(&State) -> f64) -> f64 {
+        self.states.iter().rev().take(window).map(field).sum()
+    }
+
+    /// The simple moving average of a field over the latest `window` epochs (or as many of them as
+    /// are held), which is as far as it looks back.
+    ///
+    /// ```
+    /// # use eco::{History, State, Usd};
+    /// let tvl = |tvl: f64| State { tvl: Usd(tvl), ..State::default() };
+    /// let history = History::from_states(vec![tvl(0.0), tvl(1.0), tvl(2.0), tvl(3.0), tvl(4.0)]);
+    /// assert_eq!(history.sma(2, |state| state.tvl.0), 3.5);
+    /// assert_eq!(history.sma(10, |state| state.tvl.0), 2.0);
+    /// ```
+    pub fn sma(&self, window: usize, field: impl Fn(&State) -> f64) -> f64 {
+        let recent = self.recent(window.max(1));
+        recent.iter().map(field).sum::<f64>() / recent.len() as f64
+    }
+
+    /// The exponential moving average of a field, with the smoothing of a `window` epoch average
+    /// (`2 / (window + 1)`), over the latest `lookback` epochs (or as many of them as are held),
+    /// beginning from the value at the oldest of them. Looking back over a few times the window
+    /// captures nearly all of its weight.
+    ///
+    /// ```
+    /// # use eco::{History, State, Usd};
+    /// let tvl = |tvl: f64| State { tvl: Usd(tvl), ..State::default() };
+    /// let history = History::from_states(vec![tvl(0.0), tvl(1.0), tvl(2.0), tvl(3.0), tvl(4.0)]);
+    /// // 2, then halfway to 3, then halfway to 4.
+    /// assert_eq!(history.ema(3, 3, |state| state.tvl.0), 3.25);
+    /// assert_eq!(history.ema(3, 1, |state| state.tvl.0), 4.0);
+    /// ```
+    pub fn ema(&self, window: usize, lookback: usize, field: impl Fn(&State) -> f64) -> f64 {
+        let alpha = 2.0 / (window as f64 + 1.0);
+        let mut values = self.recent(lookback.max(1)).iter().map(field);
+        let first = values.next().expect("missing initial state");
+        values.fold(first, |ema, value| ema + alpha * (value - ema))
+    }
+
+    /// How much a field increased over the latest `window` epochs (or as many of them as are
+    /// held), as the sum of its increase in every epoch. This is what a cumulative field (such as
+    /// `f_claimed`) added over the window.
+    pub fn increase(&self, window: usize, field: impl Fn(&State) -> f64) -> f64 {
+        self.states
+            .windows(2)
+            .rev()
+            .take(window)
+            .map(|pair| field(&pair[1]) - field(&pair[0]))
+            .sum()
+    }
+
+    /// The rate of change of a field over the last `window` epochs, as a share of its value at the
+    /// beginning of the window. `None` if that epoch is not held, or the field was zero at it.
+    ///
+    /// ```
+    /// # use eco::{History, State, Usd};
+    /// let tvl = |tvl: f64| State { tvl: Usd(tvl), ..State::default() };
+    /// let history = History::from_states(vec![tvl(0.0), tvl(1.0), tvl(2.0), tvl(3.0), tvl(4.0)]);
+    /// assert_eq!(history.rate_of_change(2, |state| state.tvl.0), Some(1.0));
+    /// assert_eq!(history.rate_of_change(4, |state| state.tvl.0), None);
+    /// assert_eq!(history.rate_of_change(5, |state| state.tvl.0), None);
+    /// ```
+    pub fn rate_of_change(&self, window: usize, field: impl Fn(&State) -> f64) -> Option<f64> {
+        // Counted back from the latest state, which is the epoch so far while one is held.
+        let before = field(&self.states[self.states.len().checked_sub(window + 1)?]);
+        (before != 0.0).then(|| (field(self.latest()) - before) / before.abs())
+    }
+
+    /// How far a field is below its peak over the latest `window` epochs (or as many of them as
+    /// are held), as a share of the peak. It is zero at a peak, and while the peak is not positive.
+    ///
+    /// ```
+    /// # use eco::{History, State, Usd};
+    /// let tvl = |tvl: f64| State { tvl: Usd(tvl), ..State::default() };
+    /// let history = History::from_states(vec![tvl(4.0), tvl(8.0), tvl(6.0)]);
+    /// assert_eq!(history.drawdown(3, |state| state.tvl.0), 0.25);
+    /// assert_eq!(history.drawdown(1, |state| state.tvl.0), 0.0);
+    /// ```
+    pub fn drawdown(&self, window: usize, field: impl Fn(&State) -> f64) -> f64 {
+        let recent = self.recent(window.max(1));
+        let peak = recent.iter().map(&field).fold(f64::NEG_INFINITY, f64::max);
+        if peak > 0.0 {
+            ((peak - field(self.latest())) / peak).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Search the states for the first steady state, if they are every state of the run (otherwise
+    /// whatever the aggregates already hold is kept).
+    fn find_equilibrium(&mut self) {
+        if let (Some(steady_state), true) = (&self.steady_state, self.is_complete()) {
+            self.aggregates.equilibrium = steady_state.find(&self.states, self.aggregates.warmup);
+        }
+    }
+
+    /// Search the states for the first divergence, if they are every state of the run.
+    fn find_divergence(&mut self) {
+        if let (Some(divergence), true) = (&self.divergence, self.is_complete()) {
+            let (warmup, required_ratio) = (self.aggregates.warmup, self.aggregates.required_ratio);
+            self.aggregates.divergence = divergence.find(&self.states, warmup, required_ratio);
+        }
+    }
+
+    /// Drop the states that are older than the window. States are dropped in batches (once twice
+    /// the window is held), so that adding a state stays cheap while the states that are held stay
+    /// contiguous.
+    fn trim(&mut self) {
+        if let Some(window) = self.window {
+            if self.states.len() >= 2 * window {
+                let dropped = self.states.len() - window;
+                self.states.drain(..dropped);
+                self.first_epoch += dropped;
+            }
+        }
+    }
+}
+
+impl Index<usize> for History {
+    type Output = State;
+
+    /// The state at the given epoch. Panics if the epoch has not been simulated yet, or has already
+    /// been dropped (which means that the window is too short for the models).
+    fn index(&self, epoch: usize) -> &State {
+        match self.get(epoch) {
+            Some(state) => state,
+            None if epoch < self.first_epoch => panic!(
+                "epoch {} was dropped from the history (it only holds epochs {} to {})",
+                epoch,
+                self.first_epoch,
+                self.epoch()
+            ),
+            None => panic!(
+                "epoch {} is not in the history (it ends at epoch {})",
+                epoch,
+                self.epoch()
+            ),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a History {
+    type Item = &'a State;
+    type IntoIter = std::slice::Iter<'a, State>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Aggregates are headline numbers that are accumulated one state at a time, so that they cover a
+/// whole run even when most of its states have been dropped. Like the summary metrics, they ignore
+/// the warm-up epochs, and the state at the end of the warm-up takes the place of the initial
+/// state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aggregates {
+    /// The number of warm-up epochs that are ignored.
+    pub warmup: usize,
+    /// The multiple of TVL that must be bonded.
+    pub required_ratio: f64,
+    /// The number of epochs after the warm-up.
+    pub epochs: usize,
+    /// The state at the end of the warm-up (or the initial state, if there is none).
+    pub first: State,
+    /// The latest state.
+    pub last: State,
+    /// The highest total value locked.
+    pub peak_tvl: Usd,
+    /// The highest total value bonded.
+    pub peak_tvb: Usd,
+    /// The lowest ratio of TVB to TVL, ignoring epochs in which nothing is locked (or nothing, if
+    /// nothing was ever locked).
+    pub min_security_ratio: Option<f64>,
+    /// The rebates paid to burners.
+    pub rebates_paid: Usd,
+    /// The number of epochs in which the security ratio was below the required ratio.
+    pub breaches: usize,
+    /// The first epoch in which the security ratio was below the required ratio.
+    pub first_breach: Option<usize>,
+    /// The sum of the fees claimed per darknode in every epoch in which any were registered.
+    pub darknode_income: Usd,
+    /// The number of epochs in which any darknodes were registered.
+    pub darknode_epochs: usize,
+    /// The number of epochs in which rebates drained the rebate pool.
+    #[serde(default)]
+    pub exhaustions: usize,
+    /// The first epoch in which rebates drained the rebate pool.
+    #[serde(default)]
+    pub first_exhaustion: Option<usize>,
+    /// The first steady state that the run reached, if it is watched for and has been reached.
+    #[serde(default)]
+    pub equilibrium: Option<Equilibrium>,
+    /// The highest fees that nodes earned in an epoch.
+    #[serde(default)]
+    pub peak_fees: Usd,
+    /// The first divergence of the run, if it is watched for and has been detected.
+    #[serde(default)]
+    pub divergence: Option<Divergence>,
+}
+
+impl Aggregates {
+    /// Begin accumulating from the initial state.
+    pub fn new(initial: &State, warmup: usize, required_ratio: f64) -> Self {
+        let mut aggregates = Self {
+            warmup,
+            required_ratio,
+            epochs: 0,
+            first: State::default(),
+            last: State::default(),
+            peak_tvl: Usd::ZERO,
+            peak_tvb: Usd::ZERO,
+            min_security_ratio: None,
+            rebates_paid: Usd::ZERO,
+            breaches: 0,
+            first_breach: None,
+            darknode_income: Usd::ZERO,
+            darknode_epochs: 0,
+            exhaustions: 0,
+            first_exhaustion: None,
+            equilibrium: None,
+            peak_fees: Usd::ZERO,
+            divergence: None,
+        };
+        aggregates.begin(initial);
+        aggregates
+    }
+
+    /// Accumulate from a run of states, where the first of them is the state at `first_epoch`.
+    pub fn accumulate(
+        first_epoch: usize,
+        states: &[State],
+        warmup: usize,
+        required_ratio: f64,
+    ) -> Self {
+        let initial = states.first().expect("missing initial state");
+        let mut aggregates = Self::new(initial, warmup, required_ratio);
+        for (epoch, state) in (first_epoch..).zip(states).skip(1) {
+            aggregates.add(epoch, state);
+        }
+        aggregates
+    }
+
+    /// Add the state at the end of the given epoch.
+    pub fn add(&mut self, epoch: usize, state: &State) {
+        if epoch <= self.warmup {
+            self.begin(state);
+            return;
+        }
+        let previous = std::mem::replace(&mut self.last, state.clone());
+        self.epochs += 1;
+        self.peak_tvl = self.peak_tvl.max(state.tvl);
+        self.peak_tvb = self.peak_tvb.max(state.tvb);
+        if let Some(ratio) = security_ratio(state) {
+            self.min_security_ratio = Some(self.min_security_ratio.map_or(ratio, |r| r.min(ratio)));
+        }
+        // The rebate pool never goes negative, so what was paid is what left the pool (which can be
+        // less than the rebate on offer).
+        self.rebates_paid += previous.r_pool + (state.tvr - previous.tvr) - state.r_pool;
+        if Security::is_breach(state, self.required_ratio) {
+            self.breaches += 1;
+            self.first_breach.get_or_insert(epoch);
+        }
+        // The pool is drained when it empties while a rebate is still on offer.
+        if previous.r_pool > Usd::ZERO && state.r_pool <= Usd::ZERO && state.r > Pct::ZERO {
+            self.exhaustions += 1;
+            self.first_exhaustion.get_or_insert(epoch);
+        }
+        self.peak_fees = self.peak_fees.max(divergence::fees(&previous, state));
+        if state.darknodes > 0 {
+            self.darknode_income += (state.f_claimed - previous.f_claimed) / state.darknodes as f64;
+            self.darknode_epochs += 1;
+        }
+    }
+
+    /// Start over from a state that takes the place of the initial state.
+    fn begin(&mut self, state: &State) {
+        self.first = state.clone();
+        self.last = state.clone();
+        self.peak_tvl = Usd::ZERO.max(state.tvl);
+        self.peak_tvb = Usd::ZERO.max(state.tvb);
+        self.min_security_ratio = security_ratio(state);
+    }
+}
+
+/// The security ratio of a state (of its weakest shard, if it is sharded), or `None` when nothing
+/// is locked.
+fn security_ratio(state: &State) -> Option<f64> {
+    if state.tvl <= Usd::ZERO {
+        None
+    } else if state.shards.is_empty() {
+        Some(state.tvb / state.tvl)
+    } else {
+        Some(state.security_ratio)
+    }
+}
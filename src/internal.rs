@@ -0,0 +1,388 @@
+//! For modelling the behaviour of components internal to RenVM. These are components that can be
+//! defined and constrained by the actual implementation of RenVM, and so there is no uncertainty in
+//! the way that the component will behave (unlike when thinking about external entities).
+//! Implement `InternalModel` when you want to test different designs for RenVM. For example, you
+//! could implement `rebate_curve` to always return zero if you want to see how the state of RenVM
+//! evolves over time when there are no rebates available.
+
+use crate::audit::Decision;
+use crate::config::{EpochConfig, InternalConfig, RebateCurve};
+use crate::controller::Pid;
+use crate::error::Result;
+use crate::history::History;
+use crate::state::ControllerState;
+use crate::{Pct, SimRng, Usd};
+
+/// InternalModel captures the fee and rebate mechanics of RenVM. The simulation calls each
+/// function exactly once per epoch, passing the history of states up to (and including) the
+/// previous epoch. Models are free to keep their own internal state between calls. Models that need
+/// randomness must draw it from the `rng` that is passed to them (and nowhere else), so that runs
+/// remain reproducible from their seed.
+pub trait InternalModel {
+    /// This function returns the minting fee given the current state (and history) of RenVM. For
+    /// example, you could design a model such that minting fees rise slowly if minting volume is
+    /// rising (and vice versa).
+    fn mint_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct;
+
+    /// This function is the same as the `mint_fee_curve` function, but for burning fees. An
+    /// important difference is that burning fees *must* be zero when the rebate is non-zero.
+    fn burn_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct;
+
+    /// This function models the rebate that will be paid (as a percentage) when burning happens.
+    /// Whenever this value is non-zero, the `burn_fee_curve` function *must* return zero (it makes
+    /// no sense to offer a rebate in the presence of a burning fee; the better thing to do would be
+    /// to remove the burning fee, which has the same initial effect).
+    fn rebate_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct;
+
+    /// This function returns the amount of fees that are going to be made available for rebating.
+    /// Fees that are made available for rebating are *not* paid to the nodes (this is already taken
+    /// into consideration; `State::f_unclaimed` and `State::f_claimed` will not include fees that
+    /// have been made available for rebating).
+    fn rebate_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd;
+
+    /// This function returns the amount of fees that go to the treasury. It is called before
+    /// `rebate_collected`, which is given the fees that are left. Fees that go to the treasury are
+    /// neither rebated nor paid to the nodes. By default, none are.
+    fn treasury_collected(&mut self, _history: &History, _f: Usd, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function returns the amount of fees that buy REN and burn it, which shrinks the supply
+    /// of REN. Like `treasury_collected`, it is called before `rebate_collected`, and the fees are
+    /// neither rebated nor paid to the nodes. By default, none are.
+    fn buyback_collected(&mut self, _history: &History, _f: Usd, _rng: &mut SimRng) -> Usd {
+        Usd::ZERO
+    }
+
+    /// This function returns the fee charged on one mint of the given size (in USD), as a share of
+    /// it, where `fee` is the minting fee of the epoch (or of the asset). It is called once per
+    /// transaction (see `ExternalModel::mint_transactions`), or once per volume when volumes are
+    /// not split into transactions. By default, every mint is charged the fee of the epoch.
+    fn mint_transaction_fee(&mut self, _size: Usd, fee: Pct) -> Pct {
+        fee
+    }
+
+    /// This function is the same as the `mint_transaction_fee` function, but for burns. The same
+    /// restriction as for `burn_fee_curve` applies: burns *must* be free while the rebate is
+    /// non-zero.
+    fn burn_transaction_fee(&mut self, _size: Usd, fee: Pct) -> Pct {
+        fee
+    }
+
+    /// This function returns the minting fee for one asset. When the external model locks
+    /// individual assets, it is called once per asset per epoch instead of `mint_fee_curve`. By
+    /// default, every asset is charged the same fee.
+    fn asset_mint_fee_curve(&mut self, history: &History, _asset: &str, rng: &mut SimRng) -> Pct {
+        self.mint_fee_curve(history, rng)
+    }
+
+    /// This function is the same as the `asset_mint_fee_curve` function, but for burning fees. It
+    /// is called instead of `burn_fee_curve`, and the same restriction applies: burning fees *must*
+    /// be zero when the rebate is non-zero.
+    fn asset_burn_fee_curve(&mut self, history: &History, _asset: &str, rng: &mut SimRng) -> Pct {
+        self.burn_fee_curve(history, rng)
+    }
+
+    /// This function returns the diagnostics of the controller that set the fees of the epoch, if
+    /// the fees are controlled. It is called once per epoch, after every fee has been set.
+    fn controller(&self) -> Option<ControllerState> {
+        None
+    }
+
+    /// This function returns the decisions that the curves made since it was last called, to
+    /// explain them in the audit log. It is called once per epoch, after every curve has been
+    /// called. By default, nothing is explained.
+    fn decisions(&mut self) -> Vec<Decision> {
+        vec![]
+    }
+
+    /// This function returns the internal state that the model keeps between epochs, so that it
+    /// can be saved in a checkpoint (see `ExternalModel::checkpoint`). By default, there is none.
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// This function replaces the internal state of the model with one that was returned by
+    /// `checkpoint`.
+    fn restore(&mut self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The basic internal model, which is how RenVM behaved when it first launched.
+#[derive(Clone, Debug)]
+pub struct Basic {
+    config: InternalConfig,
+    rebate_window: usize,
+    /// The factor by which the decaying rebate curve shrinks every epoch.
+    rebate_decay: f64,
+    controller: Option<Pid>,
+    /// The decisions made since they were last taken.
+    decisions: Vec<Decision>,
+}
+
+impl Basic {
+    /// Create a basic internal model using the given parameters, with windows measured in epochs
+    /// of the given length.
+    pub fn new(config: InternalConfig, epoch: &EpochConfig) -> Self {
+        let rebate_decay = match config.rebate_curve {
+            RebateCurve::Decay { half_life_days, .. } if half_life_days > 0.0 => {
+                0.5f64.powf(epoch.days / half_life_days)
+            }
+            _ => 0.0,
+        };
+        Self {
+            rebate_window: epoch.epochs(config.rebate_window_days),
+            rebate_decay,
+            controller: config.fee_controller.map(Pid::new),
+            config,
+            decisions: vec![],
+        }
+    }
+
+    /// Record the decision of a curve, returning its value.
+    fn decide(
+        &mut self,
+        curve: &'static str,
+        asset: Option<&str>,
+        (branch, reason): (&'static str, &'static str),
+        value: Pct,
+        inputs: Vec<(&'static str, f64)>,
+    ) -> Pct {
+        self.decisions.push(Decision {
+            curve,
+            asset: asset.map(str::to_string),
+            branch,
+            reason,
+            value: value.0,
+            inputs,
+        });
+        value
+    }
+
+    /// The fee for the next epoch, after the controller (if there is one) has adjusted it, where
+    /// `branch` explains the fee of the curve when it is left as it is.
+    fn controlled(
+        &mut self,
+        history: &History,
+        (curve, asset): (&'static str, Option<&str>),
+        fee: Pct,
+        branch: (&'static str, &'static str),
+        mut inputs: Vec<(&'static str, f64)>,
+    ) -> Pct {
+        let Some(controller) = &mut self.controller else {
+            return self.decide(curve, asset, branch, fee, inputs);
+        };
+        let controlled = controller.fee(history, fee);
+        let state = controller.state();
+        inputs.extend([
+            ("curve", fee.0),
+            ("error", state.error),
+            ("integral", state.integral),
+        ]);
+        let branch = (
+            "controlled",
+            "the controller moved the fee of the curve to steer the security ratio to its target",
+        );
+        self.decide(curve, asset, branch, controlled, inputs)
+    }
+
+    /// The burning fee for the next epoch, given the fee of its curve.
+    fn burn_fee(&mut self, history: &History, asset: Option<&str>, fee: Pct) -> Pct {
+        let state = history.latest();
+        let inputs = vec![("tvl", state.tvl.0), ("tvb", state.tvb.0)];
+        if state.tvl < state.tvb {
+            let branch = (
+                "charged",
+                "TVL is below TVB, so burns pay the fee of the curve",
+            );
+            self.controlled(history, ("burn_fee", asset), fee, branch, inputs)
+        } else {
+            let branch = (
+                "waived",
+                "TVL is not below TVB, so burns earn a rebate instead",
+            );
+            self.decide("burn_fee", asset, branch, Pct::ZERO, inputs)
+        }
+    }
+}
+
+impl Default for Basic {
+    fn default() -> Self {
+        Self::new(InternalConfig::default(), &EpochConfig::default())
+    }
+}
+
+impl InternalModel for Basic {
+    fn mint_fee_curve(&mut self, history: &History, _rng: &mut SimRng) -> Pct {
+        // In production, RenVM began with a simple (and static) 0.1% minting fee.
+        let fee = self.config.mint_fee.value(history);
+        let branch = ("curve", "mints pay the fee of the curve");
+        self.controlled(history, ("mint_fee", None), fee, branch, vec![])
+    }
+
+    fn burn_fee_curve(&mut self, history: &History, _rng: &mut SimRng) -> Pct {
+        // In production, RenVM began with a simple (and static) 0.1% minting fee.
+        let fee = self.config.burn_fee.value(history);
+        self.burn_fee(history, None, fee)
+    }
+
+    fn rebate_curve(&mut self, history: &History, _rng: &mut SimRng) -> Pct {
+        let config = &self.config;
+        let window = self.rebate_window;
+        let state = history.latest();
+        let mut inputs = vec![("tvl", state.tvl.0), ("tvb", state.tvb.0)];
+        if state.tvb >= state.tvl {
+            let branch = ("none", "TVB covers TVL, so no rebate is needed");
+            return self.decide("rebate", None, branch, Pct::ZERO, inputs);
+        }
+        let gap = (state.tvl - state.tvb) / state.tvl;
+        let (branch, r) = match &config.rebate_curve {
+            RebateCurve::Adaptive => {
+                // If TVL-TVB has decreased in the last epoch compared to the average over the
+                // window (one week by default), then slowly decrease the rebate. Otherwise, slowly
+                // increase the rebate.
+                let average =
+                    Usd(history.sum(window, |state| (state.tvl - state.tvb).0)) / window as f64;
+                inputs.extend([
+                    ("tvl_tvb", (state.tvl - state.tvb).0),
+                    ("average", average.0),
+                ]);
+                if state.tvl - state.tvb < average {
+                    let branch = (
+                        "decrease",
+                        "TVL-TVB is below its average over the rebate window, so the rebate \
+                         decreased",
+                    );
+                    (branch, (state.r - config.rebate_step).max(Pct::ZERO))
+                } else {
+                    let branch = (
+                        "increase",
+                        "TVL-TVB is not below its average over the rebate window, so the rebate \
+                         increased",
+                    );
+                    (branch, state.r + config.rebate_step)
+                }
+            }
+            RebateCurve::Linear { slope, max } => {
+                inputs.push(("gap", gap));
+                let branch = (
+                    "linear",
+                    "the rebate grows linearly with the gap of TVL over TVB",
+                );
+                (branch, Pct((slope * gap).clamp(0.0, max.0.max(0.0))))
+            }
+            RebateCurve::Sigmoid {
+                max,
+                midpoint,
+                steepness,
+            } => {
+                inputs.push(("gap", gap));
+                let branch = (
+                    "sigmoid",
+                    "the rebate follows a sigmoid of the gap of TVL over TVB",
+                );
+                (branch, *max / (1.0 + (-steepness * (gap - midpoint)).exp()))
+            }
+            RebateCurve::Step { steps } => {
+                inputs.push(("gap", gap));
+                let step = steps
+                    .iter()
+                    .filter(|step| step.gap <= gap)
+                    .max_by(|a, b| a.gap.total_cmp(&b.gap));
+                if let Some(step) = step {
+                    inputs.push(("step", step.gap));
+                }
+                let branch = (
+                    "step",
+                    "the rebate is that of the highest step that the gap reaches",
+                );
+                (branch, step.map_or(Pct::ZERO, |step| step.rebate))
+            }
+            // The rebate is zero while TVB covers TVL, so a rebate of zero means that TVL has just
+            // risen above it.
+            RebateCurve::Decay { initial, .. } if state.r == Pct::ZERO => {
+                let branch = (
+                    "restart",
+                    "TVL has just risen above TVB, so the rebate restarts",
+                );
+                (branch, *initial)
+            }
+            RebateCurve::Decay { .. } => {
+                inputs.push(("previous", state.r.0));
+                let branch = ("decay", "TVL is still above TVB, so the rebate decays");
+                (branch, state.r * self.rebate_decay)
+            }
+        };
+        self.decide("rebate", None, branch, r, inputs)
+    }
+
+    fn rebate_collected(&mut self, history: &History, f: Usd, _rng: &mut SimRng) -> Usd {
+        // 50% of fees are made available as a rebate by default.
+        f * self.config.rebate_share.value(history)
+    }
+
+    fn treasury_collected(&mut self, history: &History, f: Usd, _rng: &mut SimRng) -> Usd {
+        f * self.config.treasury_share.value(history)
+    }
+
+    fn buyback_collected(&mut self, history: &History, f: Usd, _rng: &mut SimRng) -> Usd {
+        f * self.config.buyback_share.value(history)
+    }
+
+    fn asset_mint_fee_curve(&mut self, history: &History, asset: &str, _rng: &mut SimRng) -> Pct {
+        let fee = self
+            .config
+            .assets
+            .get(asset)
+            .and_then(|fees| fees.mint_fee.as_ref())
+            .unwrap_or(&self.config.mint_fee)
+            .value(history);
+        let branch = ("curve", "mints pay the fee of the curve");
+        self.controlled(history, ("mint_fee", Some(asset)), fee, branch, vec![])
+    }
+
+    fn asset_burn_fee_curve(&mut self, history: &History, asset: &str, _rng: &mut SimRng) -> Pct {
+        // Assets can have their own burning fee, but it is charged under the same conditions as
+        // the default one.
+        let fee = self
+            .config
+            .assets
+            .get(asset)
+            .and_then(|fees| fees.burn_fee.as_ref())
+            .unwrap_or(&self.config.burn_fee)
+            .value(history);
+        self.burn_fee(history, Some(asset), fee)
+    }
+
+    fn mint_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        match &self.config.transaction_fee {
+            Some(config) => config.mint_fee(size, fee),
+            None => fee,
+        }
+    }
+
+    fn burn_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        match &self.config.transaction_fee {
+            Some(config) => config.burn_fee(size, fee),
+            None => fee,
+        }
+    }
+
+    fn controller(&self) -> Option<ControllerState> {
+        self.controller.as_ref().map(Pid::state)
+    }
+
+    fn decisions(&mut self) -> Vec<Decision> {
+        std::mem::take(&mut self.decisions)
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.controller.as_ref()?).ok()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.controller = Some(serde_json::from_value(state)?);
+        Ok(())
+    }
+}
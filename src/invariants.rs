@@ -0,0 +1,188 @@
+//! For checking that the state of RenVM stays sensible throughout a simulation. Some rules are
+//! requirements on the models (for example, the burning fee *must* be zero whenever the rebate is
+//! non-zero), and others catch models that have drifted into nonsense (negative locked value, NaNs,
+//! etc.). Runs stop at the first epoch that breaks a rule, reporting everything needed to find out
+//! why.
+
+use crate::config::InvariantsConfig;
+use crate::history::History;
+use crate::state::{AssetState, ControllerState, ShardState, State};
+use crate::{Pct, Usd};
+use std::fmt;
+
+/// Rule is one invariant that every state must satisfy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// The burning fee is zero whenever the rebate is non-zero.
+    NoBurnFeeWithRebate,
+    /// Locked value (in aggregate, and for every asset) is never negative.
+    NonNegativeTvl,
+    /// The rebate pool is never negative.
+    NonNegativeRebatePool,
+    /// Every value in the state is finite (neither NaN nor infinite).
+    Finite,
+}
+
+impl Rule {
+    /// Every rule, in the order that they are checked.
+    pub const ALL: [Rule; 4] = [
+        Rule::NoBurnFeeWithRebate,
+        Rule::NonNegativeTvl,
+        Rule::NonNegativeRebatePool,
+        Rule::Finite,
+    ];
+
+    /// The name of the rule, as it appears in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::NoBurnFeeWithRebate => "no_burn_fee_with_rebate",
+            Rule::NonNegativeTvl => "non_negative_tvl",
+            Rule::NonNegativeRebatePool => "non_negative_r_pool",
+            Rule::Finite => "finite",
+        }
+    }
+
+    /// Check the rule against a state, returning a description of what is wrong if it is broken.
+    pub fn check(&self, state: &State) -> Option<String> {
+        match self {
+            Rule::NoBurnFeeWithRebate if state.r > Pct::ZERO && state.bf != Pct::ZERO => Some(
+                format!("bf is {} while the rebate r is {}", state.bf, state.r),
+            ),
+            Rule::NonNegativeTvl if state.tvl < Usd::ZERO => Some(format!("tvl is {}", state.tvl)),
+            Rule::NonNegativeTvl => state
+                .assets
+                .iter()
+                .position(|asset| asset.tvl < Usd::ZERO)
+                .map(|i| format!("tvl of asset {} is {}", i, state.assets[i].tvl)),
+            Rule::NonNegativeRebatePool if state.r_pool < Usd::ZERO => {
+                Some(format!("r_pool is {}", state.r_pool))
+            }
+            Rule::Finite => {
+                // This is checked every epoch, so the name of a field is only formatted once it
+                // is found not to be finite.
+                let broken = |fields: &[&'static str], values: &[f64]| {
+                    let j = values.iter().position(|value| !value.is_finite())?;
+                    Some((fields[j], values[j]))
+                };
+                if let Some((field, value)) = broken(&State::FIELDS, &state.values()) {
+                    return Some(format!("{} is {}", field, value));
+                }
+                for (i, asset) in state.assets.iter().enumerate() {
+                    if let Some((field, value)) = broken(&AssetState::FIELDS, &asset.values()) {
+                        return Some(format!("assets[{}].{} is {}", i, field, value));
+                    }
+                }
+                for (i, shard) in state.shards.iter().enumerate() {
+                    if let Some((field, value)) = broken(&ShardState::FIELDS, &shard.values()) {
+                        return Some(format!("shards[{}].{} is {}", i, field, value));
+                    }
+                }
+                let controller = state.controller.as_ref()?;
+                let (field, value) = broken(&ControllerState::FIELDS, &controller.values())?;
+                Some(format!("controller.{} is {}", field, value))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Invariants is the set of rules that are checked every epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invariants {
+    rules: Vec<Rule>,
+}
+
+impl Invariants {
+    /// Create the set of rules that are enabled by the configuration.
+    pub fn new(config: &InvariantsConfig) -> Self {
+        let enabled = [
+            (config.no_burn_fee_with_rebate, Rule::NoBurnFeeWithRebate),
+            (config.non_negative_tvl, Rule::NonNegativeTvl),
+            (config.non_negative_r_pool, Rule::NonNegativeRebatePool),
+            (config.finite, Rule::Finite),
+        ];
+        Self {
+            rules: enabled
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, rule)| *rule)
+                .collect(),
+        }
+    }
+
+    /// The rules that are checked.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Check the latest state in the history against every rule, returning the first violation.
+    pub fn check(&self, history: &History) -> Option<Violation> {
+        let state = history.latest();
+        self.rules.iter().find_map(|rule| {
+            rule.check(state).map(|message| {
+                let epoch = history.epoch();
+                tracing::warn!(rule = rule.name(), epoch, %message, "invariant violated");
+                Violation {
+                    rule: *rule,
+                    epoch,
+                    message,
+                    previous: history.len().checked_sub(2).map(|i| history[i].clone()),
+                    state: state.clone(),
+                }
+            })
+        })
+    }
+}
+
+impl Default for Invariants {
+    fn default() -> Self {
+        Self::new(&InvariantsConfig::default())
+    }
+}
+
+/// Violation describes the first epoch at which a rule was broken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// The rule that was broken.
+    pub rule: Rule,
+    /// The epoch at which it was broken (where epoch zero is the initial state).
+    pub epoch: usize,
+    /// What was wrong with the state.
+    pub message: String,
+    /// The state at the end of the previous epoch, if there was one.
+    pub previous: Option<State>,
+    /// The state that broke the rule.
+    pub state: State,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn dump(f: &mut fmt::Formatter<'_>, label: &str, state: &State) -> fmt::Result {
+            write!(f, "\n  {}:", label)?;
+            for (field, value) in State::FIELDS.iter().zip(state.values().iter()) {
+                write!(f, " {}={}", field, value)?;
+            }
+            for (i, asset) in state.assets.iter().enumerate() {
+                write!(f, " assets[{}]={:?}", i, asset)?;
+            }
+            for (i, shard) in state.shards.iter().enumerate() {
+                write!(f, " shards[{}]={:?}", i, shard)?;
+            }
+            if let Some(controller) = &state.controller {
+                write!(f, " controller={:?}", controller)?;
+            }
+            Ok(())
+        }
+        write!(
+            f,
+            "invariant `{}` violated at epoch {}: {}",
+            self.rule.name(),
+            self.epoch,
+            self.message
+        )?;
+        if let Some(previous) = &self.previous {
+            dump(f, "previous state", previous)?;
+        }
+        dump(f, "state", &self.state)
+    }
+}
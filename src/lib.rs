@@ -0,0 +1,74 @@
+pub mod accounting;
+pub mod attack;
+pub mod attacker;
+pub mod audit;
+pub mod backtest;
+pub mod batch;
+pub mod builder;
+pub mod calibrate;
+pub mod checkpoint;
+pub mod compare;
+pub mod config;
+pub mod controller;
+pub mod curve;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod determinism;
+pub mod diff;
+pub mod divergence;
+mod error;
+pub mod external;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod history;
+pub mod internal;
+pub mod invariants;
+pub mod metrics;
+pub mod mint_cap;
+pub mod monte_carlo;
+pub mod observer;
+pub mod optim;
+pub mod optimize;
+pub mod output;
+pub mod parallel;
+pub mod pareto;
+#[cfg(feature = "plot")]
+pub mod plot;
+#[cfg(feature = "python")]
+mod python;
+pub mod registry;
+pub mod scenarios;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod sensitivity;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod shards;
+mod simulation;
+pub mod slashing;
+mod state;
+pub mod stats;
+pub mod steady_state;
+pub mod stop;
+pub mod stress;
+pub mod subsidy;
+pub mod sweep;
+pub mod timeline;
+pub mod units;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use config::Config;
+pub use error::{Error, Result};
+pub use external::ExternalModel;
+pub use history::History;
+pub use internal::InternalModel;
+pub use simulation::{Inputs, Simulation};
+pub use state::{after_warmup, latest_state, AssetState, ControllerState, ShardState, State};
+pub use units::{Pct, Ren, Usd};
+
+/// The random number generator that is threaded through every model call. It is always seeded, so
+/// that any run can be reproduced bit-for-bit from its seed, and it can be serialized, so that a
+/// run can be checkpointed part of the way through. This is the same generator as
+/// `rand::rngs::StdRng`.
+pub type SimRng = rand_chacha::ChaCha12Rng;
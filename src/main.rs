@@ -1,198 +1,1722 @@
-type USD = f64;
-type Percentage = f64;
-
-//
-// STATE
-// For capturing the state of RenVM throughout the simulation.
-//
-
-/// State represents the state of RenVM at the end of an epoch. All values in the state are derived
-/// from the behaviour of the external and internal models; they are never directly simulated. If
-/// you find yourself directly modifying the state, you are probably doing something wrong.
-#[derive(Clone, Copy, Debug, Default)]
-struct State {
-    tvb: USD,
-    tvl: USD,
-    tvr: USD,
-
-    mf: Percentage,
-    bf: Percentage,
-    r: Percentage,
-
-    f_unclaimed: USD,
-    f_claimed: USD,
-    r_pool: USD,
-}
-
-//
-// EXTERNAL
-// For modelling the behaviour of entities that are external to RenVM. This means that the entity
-// is capable of taking action independently of the precise mechanics of RenVM. For example,
-// minters, burners, and node operators should all be considered external. Modify these functions
-// when you want to see how the state of RenVM will evolve under different assumptions about how
-// people will behave. For example, you can modify `total_value_bonded` to model different node
-// operator (dis)bonding behaviour.
-//
-
-/// This function returns the amount of USD that is bonded to RenVM. Changing this function allows
-/// you to model the behaviour of node operators.
-fn total_value_bonded(history: &Vec<State>) -> USD {
-    // The basic model assumes that node operators want to receive some target ROI based on the one
-    // week average fee. 
-    let per_annum = history.windows(2)
-        .rev()
-        .take(7)
-        .map(|w| w[1].f_claimed - w[0].f_claimed)
-        .sum::<f64>() / 7.0 * 365.0;
-    let roi = 0.05;
-    per_annum / roi
-}
-
-/// This function returns the amount of value in USD that will be minted. There are lots of factors
-/// to consider here: growth of the network, historical minting fees, random deviation, etc. so it
-/// is important to test different models (both rational and irrational).
-fn mint_volume(history: &Vec<State>) -> USD {
-    // The basic model assumes that there will be ~$4M minted per epoch (unaffected by the minting
-    // fee, which is obviously unrealistic).
-    4_000_000.0
-}
-
-/// This function is the same as the `mint_volume` function, but for burning volume. 
-fn burn_volume(history: &Vec<State>) -> USD {
-    // // The basic model assumes that there will be ~$2M burned per epoch (unaffected by the burning
-    // // fee, or the rebate, which is obviously unrealistic).
-    // 2_000_000.0
-    
-    // A more complex model considers the available rebate, and adjusts volume accordingly. In this
-    // model, it is assumed that 0.1% is sufficiently high to incentivise arbitrage of up to $1M
-    // per 0.1% rebate (which also means that at least $1K must be available in the rebate pool.
-    let state = latest_state(history);
-    if state.r >= 0.001 {
-        // Consider the rebate fee.
-        2_000_000.0 + (state.r_pool / state.r).min(1_000_000.0 * (state.r / 0.001))
-    } else {
-        // Default to the basic model.
-        2_000_000.0
-    }
-}
-
-//
-// INTERNAL
-// For modelling the behaviour of components internal to RenVM. These are components that can be
-// defined and constrained by the actual implementation of RenVM, and so there is no uncertainty in
-// the way that the component will behave (unlike when thinking about external entities). Modify
-// these function when you want to test different designs for RenVM. For example, you could modify
-// `rebate_curve` to always return zero if you want to see how the state of RenVM evolves over time
-// when there are no rebates available.
-//
-
-/// This function returns the minting fee given the current state (and history) of RenVM. For
-/// example, you could design a model such that minting fees rise slowly if minting volume is
-/// rising (and vice versa).
-fn mint_fee_curve(history: &Vec<State>) -> Percentage {
-    // In production, RenVM began with a simple (and static) 0.1% minting fee.
-    0.003
-}
-
-/// This function is the same as the `mint_fee_curve` function, but for burning fees. An important
-/// difference is that burning fees *must* be zero when the rebate is non-zero.
-fn burn_fee_curve(history: &Vec<State>) -> Percentage {
-    let state = latest_state(history);
-    if state.tvl < state.tvb {
-        // In production, RenVM began with a simple (and static) 0.1% minting fee.
-        0.001
-    } else {
-        0.0
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use eco::backtest;
+use eco::calibrate;
+use eco::checkpoint::Checkpoint;
+use eco::compare::Comparison;
+use eco::config::InitialConfig;
+use eco::curve::Expression;
+use eco::diff::{Diff, Tolerances};
+use eco::registry::{self, Slot};
+use eco::scenarios::{self, Scenario};
+use eco::steady_state::SteadyState;
+use eco::timeline::Timeline;
+use eco::{
+    attack, determinism, metrics, metrics::Health, monte_carlo, optimize, output, parallel, pareto,
+    sensitivity, stress, sweep, AssetState, Config, ControllerState, Pct, ShardState, Simulation,
+    State, Usd,
+};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+
+/// Simulate the evolution of state in RenVM on an epoch-by-epoch basis.
+#[derive(Debug, Parser)]
+#[command(name = "econ-sim", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Only print results: no status messages, per-epoch lines, or progress bars.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print every state field at the end of every epoch, and more detail about what is run.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Print logs to stderr, at levels set per module (e.g. `info,eco::external=trace`), which
+    /// defaults to the value of `RUST_LOG`. Tracing shows which model produced every number.
+    #[cfg(feature = "log")]
+    #[arg(long, global = true, value_name = "FILTER")]
+    log: Option<String>,
+}
+
+/// How much is printed while a command runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// The verbosity chosen on the command line (set once, before the command runs).
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+fn verbosity() -> Verbosity {
+    VERBOSITY.get().copied().unwrap_or(Verbosity::Normal)
+}
+
+/// Print a status message (like `println!`), unless printing is quiet.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if verbosity() > Verbosity::Quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Progress is a progress bar on standard error, for commands that run for a long time without
+/// printing anything. It is not drawn when printing is quiet, or when standard error is not a
+/// terminal.
+struct Progress {
+    #[cfg(feature = "progress")]
+    bar: indicatif::ProgressBar,
+}
+
+impl Progress {
+    /// Begin a progress bar of `len` steps, labelled with what is being done.
+    #[cfg_attr(not(feature = "progress"), allow(unused_variables))]
+    fn new(len: usize, message: &'static str) -> Self {
+        #[cfg(feature = "progress")]
+        {
+            let bar = if verbosity() == Verbosity::Quiet {
+                indicatif::ProgressBar::hidden()
+            } else {
+                indicatif::ProgressBar::new(len as u64)
+            };
+            bar.set_style(
+                indicatif::ProgressStyle::with_template(
+                    "{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} (eta {eta})",
+                )
+                .expect("valid template"),
+            );
+            bar.set_message(message);
+            Self { bar }
+        }
+        #[cfg(not(feature = "progress"))]
+        Self {}
+    }
+
+    /// A progress bar that is never drawn.
+    fn hidden() -> Self {
+        Self {
+            #[cfg(feature = "progress")]
+            bar: indicatif::ProgressBar::hidden(),
+        }
+    }
+
+    /// Advance the progress bar by one step.
+    fn inc(&self) {
+        #[cfg(feature = "progress")]
+        self.bar.inc(1);
+    }
+
+    /// Remove the progress bar.
+    fn finish(&self) {
+        #[cfg(feature = "progress")]
+        self.bar.finish_and_clear();
     }
 }
 
-/// This function models the rebate that will be paid (as a percentage) when burning happens.
-/// Whenever this value is non-zero, the `burn_fee_curve` function *must* return zero (it makes no
-/// sense to offer a rebate in the presence of a burning fee; the better thing to do would be to
-/// remove the burning fee, which has the same initial effect).
-fn rebate_curve(history: &Vec<State>) -> Percentage {
-    let state = latest_state(history);
-    if state.tvb < state.tvl {
-        // If TVL-TVB has decreased in the last epoch compared to the weekly average, then slowly
-        // decrease the rebate. Otherwise, slowly increase the rebate.
-        if state.tvl-state.tvb < history.iter().rev().take(7).map(|state| state.tvl-state.tvb).sum::<f64>() / 7.0 {
-            (state.r - 0.0001).max(0.0)
-        } else {
-            state.r + 0.0001
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a single simulation and print the state at the end of every epoch.
+    Run(RunArgs),
+    /// Run a single simulation in a live dashboard in the terminal, which can be paused and
+    /// stepped.
+    #[cfg(feature = "tui")]
+    Watch(WatchArgs),
+    /// Run a single simulation and stream it to a dashboard in the browser, which anybody who
+    /// can reach the address can watch (and replay).
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+    /// Resume a run from a checkpoint, or restart it from an earlier epoch with different
+    /// parameters.
+    Resume(ResumeArgs),
+    /// Replay historical volumes (and TVL) through the internal models.
+    Backtest(BacktestArgs),
+    /// Fit the external models to historical data and write the calibrated configuration.
+    Calibrate(CalibrateArgs),
+    /// Fetch the live state of RenVM and write a configuration that begins from it.
+    #[cfg(feature = "fetch")]
+    FetchState(FetchStateArgs),
+    /// Run many independent stochastic simulations and aggregate the results per epoch.
+    Mc(McArgs),
+    /// Run the simulation for every combination of parameter values on a grid.
+    Sweep(SweepArgs),
+    /// Perturb every parameter on its own and rank them by how much they move the key outputs.
+    Sensitivity(SensitivityArgs),
+    /// Search the internal-model parameters for the values that best meet an objective.
+    Optimize(OptimizeArgs),
+    /// Sample the internal-model parameters and report the trade-off between node revenue, user
+    /// fees, and the security margin.
+    Pareto(ParetoArgs),
+    /// Compare two runs (of configurations, scenarios, or result files) side by side.
+    Compare(CompareArgs),
+    /// Compare two result files field by field and epoch by epoch, failing if they differ beyond
+    /// the tolerances.
+    Diff(DiffArgs),
+    /// Check the design against a suite of adversarial scenarios (a REN price crash, operators
+    /// exiting, minting stopping, and arbitrage draining the rebate pool), failing if it does not
+    /// meet the security criteria in every one.
+    Stress(StressArgs),
+    /// List the named scenarios that can be selected with `--scenario`.
+    Scenarios,
+    /// List the models that can be selected by name for every function, with `--model` (or under
+    /// `[models]` in the configuration).
+    Models,
+}
+
+/// Arguments for loading the configuration, shared by every subcommand.
+#[derive(Debug, Args)]
+struct ConfigArgs {
+    /// The named scenario to begin from (see `econ-sim scenarios`).
+    #[arg(long, default_value = "baseline", value_parser = parse_scenario)]
+    scenario: &'static Scenario,
+
+    /// A TOML or YAML file containing the model parameters. Parameters that are not in the file
+    /// keep their values from the scenario.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// A YAML file of events by epoch (see `eco::timeline`), which are added to the shocks and
+    /// governance changes of the configuration.
+    #[arg(long)]
+    timeline: Option<PathBuf>,
+
+    /// The number of epochs to simulate (overrides the configuration).
+    #[arg(long)]
+    steps: Option<usize>,
+
+    /// The seed of the random number generator (overrides the configuration). A random seed is
+    /// chosen when there is none, and is printed so that the run can be reproduced.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Select the model of a function by its name, as `function=name` (e.g.
+    /// `mint_volume=lognormal`; see `econ-sim models`). Repeat to select several.
+    #[arg(long = "model", value_name = "FUNCTION=NAME", value_parser = parse_model)]
+    models: Vec<(Slot, String)>,
+}
+
+impl ConfigArgs {
+    /// Load the configuration of the scenario, layer the configuration file named on the command
+    /// line (if any) on top of it, add the events of the timeline (if any), and apply any command
+    /// line overrides.
+    fn load(&self) -> eco::Result<Config> {
+        let base = self.scenario.config();
+        let mut config = match &self.config {
+            Some(path) => Config::load_onto(path, &base)?,
+            None => base,
+        };
+        if let Some(path) = &self.timeline {
+            Timeline::load(path)?.apply(&mut config)?;
         }
-    } else {
-        0.0
+        if let Some(steps) = self.steps {
+            config.steps = steps;
+        }
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        for (slot, name) in &self.models {
+            config.models.set(*slot, name);
+        }
+        config.models.check()?;
+        Ok(config)
+    }
+
+    /// The metadata of a run of the loaded configuration with the given seed, which names the
+    /// scenario that it began from.
+    fn metadata(&self, config: &Config, seed: u64) -> output::Metadata {
+        output::Metadata::new(config, seed).with_scenario(self.scenario.name)
     }
 }
 
-/// This function returns the amount of fees that are going to be made available for rebating. Fees
-/// that are made available for rebating are *not* paid to the nodes (this is already taken into
-/// consideration; `State::f` and `State::f_claimed` will not include fees that have been made
-/// available for rebating).
-fn rebate_collected(history: &Vec<State>, f: USD) -> USD {
-    // 50% of fees are made available as a rebate.
-    f * 0.5
+#[derive(Debug, Parser)]
+struct RunArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    initial: InitialState,
+
+    /// Instead of reporting the run, simulate it twice with the same seed and fail unless every
+    /// epoch is identical in both.
+    #[arg(long)]
+    verify_determinism: bool,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Parser)]
+struct WatchArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// How many epochs are simulated every second (until it is changed with `+` and `-`).
+    #[arg(long, default_value_t = 10.0)]
+    speed: f64,
+
+    #[command(flatten)]
+    initial: InitialState,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Debug, Parser)]
+struct ServeArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// The address to serve the dashboard on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+
+    /// How many epochs are simulated every second.
+    #[arg(long, default_value_t = 10.0)]
+    speed: f64,
+
+    #[command(flatten)]
+    initial: InitialState,
+}
+
+#[derive(Debug, Args)]
+struct ResumeArgs {
+    /// A checkpoint written by `run --checkpoint` (or by an earlier `resume`).
+    #[arg(value_name = "CHECKPOINT")]
+    input: PathBuf,
+
+    /// Restart from this epoch of the checkpoint instead of continuing from its last epoch. The
+    /// history after the epoch is discarded.
+    #[arg(long)]
+    from_epoch: Option<usize>,
+
+    /// A TOML or YAML file of parameters to change, layered on top of the configuration in the
+    /// checkpoint. Changing parameters restarts the models from the last (or `--from-epoch`) epoch.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// The number of epochs to simulate after resuming. By default, the run continues until it has
+    /// simulated the number of steps in its configuration.
+    #[arg(long)]
+    steps: Option<usize>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+struct BacktestArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// A CSV file of historical epochs, with `mint_volume` and `burn_volume` columns, and optional
+    /// `tvl` and `tvb` columns. Every row is replayed as one epoch.
+    #[arg(long)]
+    data: PathBuf,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+struct CalibrateArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// A CSV file of historical epochs, in the same format as for `backtest`. Behavioural
+    /// parameters can only be fitted when it has a `tvb` column.
+    #[arg(long)]
+    data: PathBuf,
+
+    /// A behavioural parameter to fit, by its dotted path in the configuration. Repeat to fit
+    /// several. Defaults to the target ROI of node operators and the claim rate.
+    #[arg(long = "param")]
+    params: Vec<String>,
+
+    /// Write the calibrated configuration to this TOML (or YAML) file.
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[cfg(feature = "fetch")]
+#[derive(Debug, Args)]
+struct FetchStateArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// The GraphQL endpoint to query.
+    #[arg(long, default_value = eco::fetch::DEFAULT_URL)]
+    url: String,
+
+    /// The number of recent days to average the volumes over.
+    #[arg(long, default_value_t = 7)]
+    days: usize,
+
+    /// Read a saved response to the query from this JSON file, instead of querying the endpoint.
+    #[arg(long, conflicts_with = "url")]
+    from: Option<PathBuf>,
+
+    /// Write the configuration to this TOML (or YAML) file.
+    #[arg(long)]
+    out: PathBuf,
+}
+
+/// Arguments for how a single run is reported, shared by `run`, `resume`, and `backtest`.
+#[derive(Debug, Args)]
+struct OutputArgs {
+    /// How the state at the end of every epoch is printed.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// The state fields that are printed for every epoch with `--format text`, in order (every
+    /// field is printed with `--verbose`).
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_field,
+        default_value = "tvl,tvb,darknodes,f_claimed,r_pool"
+    )]
+    fields: Vec<&'static str>,
+
+    /// Print the state every this many epochs with `--format text`, rather than every epoch. Zero
+    /// only prints the epochs in which a `--print-on` condition changes.
+    #[arg(long, default_value_t = 1)]
+    print_every: usize,
+
+    /// Also print the state in every epoch in which this condition (an expression of the state,
+    /// like `security_ratio < 1`) becomes true, or stops being true. Can be repeated.
+    #[arg(long, value_name = "EXPR")]
+    print_on: Vec<Expression>,
+
+    /// Write every state field for every epoch to this file as the run goes. Files ending in
+    /// `.jsonl` are written as JSON Lines, `.parquet` as Parquet (with the `parquet` feature),
+    /// `.sqlite` and `.db` are SQLite stores that the run is appended to (with the `sqlite`
+    /// feature), and everything else is written as CSV.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Flush the per-epoch output (of `--format csv` or `jsonl`, and `--out`) every this many
+    /// epochs, so that a partial run is kept if it is interrupted. Zero only flushes at the end.
+    #[arg(long, default_value_t = output::FLUSH_EVERY)]
+    flush_every: usize,
+
+    /// Render charts of TVL vs TVB, fee income, and the rebate pool to this image once the run is
+    /// complete. Files ending in `.svg` are written as SVG, everything else is written as PNG.
+    #[cfg(feature = "plot")]
+    #[arg(long)]
+    plot: Option<PathBuf>,
+
+    /// Write the end-of-run summary (final and peak TVL and TVB, fees claimed, rebates paid, etc.)
+    /// to this JSON file once the run is complete.
+    #[arg(long)]
+    summary: Option<PathBuf>,
+
+    /// Write the decisions of the internal curves in every epoch (which branch each took, why, and
+    /// on which inputs) to this file as JSON Lines, one decision per line.
+    #[arg(long)]
+    audit: Option<PathBuf>,
+
+    /// Weigh the cost of buying a third of the bond (see `[attack]`) against the value that it
+    /// secures every epoch, marking the epochs in which an attack becomes (or stops being)
+    /// profitable, and summing up at the end. Given a path, also write every epoch to it as CSV.
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    attack: Option<Option<PathBuf>>,
+
+    /// Write a checkpoint of the run to this file once it is complete, from which it can be
+    /// resumed with `econ-sim resume`.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Also write the checkpoint every this many epochs while the run is in progress.
+    #[arg(long, requires = "checkpoint")]
+    checkpoint_every: Option<usize>,
+
+    /// Exit with a code that classifies the outcome of the run, once every output is written: 0 if
+    /// it was healthy, 2 if rebates drained the rebate pool, 3 if it was under-collateralised, and
+    /// 4 if it ended in a death spiral (errors still exit with 1).
+    #[arg(long)]
+    health_exit_code: bool,
+}
+
+#[derive(Debug, Args)]
+struct McArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// The number of independent simulations to run. Run `i` is seeded with the base seed plus `i`.
+    #[arg(long, default_value_t = 1000)]
+    runs: usize,
+
+    /// The percentiles (between 0 and 100) to compute for every series, in addition to the mean
+    /// and the median.
+    #[arg(long, value_delimiter = ',', default_values_t = vec![5.0, 25.0, 75.0, 95.0])]
+    percentiles: Vec<f64>,
+
+    /// Write the per-epoch summary of every series to this CSV file.
+    #[arg(long, default_value = "summary.csv")]
+    out: PathBuf,
+
+    /// Also write every state of every run to this file, for analysing the whole ensemble: a
+    /// Parquet file (`.parquet`, with the `parquet` feature) with the index of the run in a `run`
+    /// column, or a SQLite store (`.sqlite` or `.db`, with the `sqlite` feature) that every run is
+    /// appended to.
+    #[arg(long)]
+    histories: Option<PathBuf>,
+
+    /// Render fan charts of every series (the median, within bands from the 25th to the 75th and
+    /// the 5th to the 95th percentiles) to this image. Files ending in `.svg` are written as SVG,
+    /// everything else is written as PNG.
+    #[cfg(feature = "plot")]
+    #[arg(long)]
+    plot: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct SweepArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// A parameter to sweep, as `path=start:stop:step` (inclusive) or `path=a,b,c`, where the path
+    /// is the dotted path of the parameter in the configuration. Repeat to sweep a grid, e.g.
+    /// `--param internal.mint_fee=0.001:0.005:0.0005 --param internal.rebate_share=0:1:0.1`.
+    #[arg(long = "param", required = true)]
+    axes: Vec<sweep::Axis>,
+
+    /// Write the table of outcomes to this CSV file instead of printing it.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct SensitivityArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// How much every parameter is perturbed down and up, as a percentage of its base value.
+    #[arg(long, default_value_t = 10.0)]
+    perturbation: f64,
+
+    /// Also write the outcome of every perturbed run to this CSV file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct OptimizeArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// A parameter to search, as `path` or `path=low:high`, where the path is the dotted path of
+    /// the parameter in the configuration. Parameters without bounds must be positive. Repeat to
+    /// search several. Defaults to the minting fee and burning fee (between 0 and 1%), the share of
+    /// fees for rebates (between 0 and 1), and the rebate step.
+    #[arg(long = "param")]
+    params: Vec<optimize::Parameter>,
+
+    /// The outcome to optimise (one of tvl, tvb, r_pool, node_income, min_security_ratio, and
+    /// breached_epochs), averaged across runs.
+    #[arg(long, default_value = "node_income")]
+    objective: String,
+
+    /// Minimise the objective instead of maximising it.
+    #[arg(long)]
+    minimize: bool,
+
+    /// Only accept parameters for which the security ratio never drops below this ratio.
+    #[arg(long)]
+    min_security_ratio: Option<f64>,
+
+    /// The number of runs (with consecutive seeds) that every candidate is averaged over.
+    #[arg(long, default_value_t = 1)]
+    runs: usize,
+
+    /// The most candidates to evaluate before giving up.
+    #[arg(long, default_value_t = 500)]
+    max_evaluations: usize,
+
+    /// Write the optimised configuration to this TOML (or YAML) file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct ParetoArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// A parameter to sample, as `path=low:high` or `path` (sampled within a factor of ten of its
+    /// value), where the path is the dotted path of the parameter in the configuration. Repeat to
+    /// sample several. Defaults to the same parameters as `optimize`.
+    #[arg(long = "param")]
+    params: Vec<optimize::Parameter>,
+
+    /// The number of random points to sample.
+    #[arg(long, default_value_t = 200)]
+    samples: usize,
+
+    /// The number of runs (with consecutive seeds) that every point is averaged over.
+    #[arg(long, default_value_t = 1)]
+    runs: usize,
+
+    /// Also write every sampled point to this CSV file, with whether it is on the frontier.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct CompareArgs {
+    /// The first run: a configuration file (`.toml`, `.yaml`, or `.yml`), a result file written by
+    /// `--out` or `--format` (`.csv` or `.jsonl`), or the name of a scenario.
+    a: String,
+
+    /// The second run, in any of the same forms as the first.
+    b: String,
+
+    /// Override the number of epochs to simulate for configurations and scenarios.
+    #[arg(long)]
+    steps: Option<usize>,
+
+    /// The seed for configurations and scenarios. Both are simulated with the same seed, so that
+    /// differences come from the parameters rather than from the noise.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How far apart two values may be (relative to the larger one) before a series is considered
+    /// to have diverged.
+    #[arg(long, default_value_t = 0.01)]
+    tolerance: f64,
+
+    /// Write both runs and their difference for every epoch to this CSV file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Render charts of the key series of both runs, overlaid, to this image.
+    #[cfg(feature = "plot")]
+    #[arg(long)]
+    plot: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    /// The first result file, written by `--out` or `--format` (`.csv`, `.jsonl`, or `.parquet`),
+    /// such as the results from before a change.
+    a: PathBuf,
+
+    /// The second result file, such as the results from after the change.
+    b: PathBuf,
+
+    /// How far apart two values may be, relative to the larger one. `FIELD=TOL` sets the tolerance
+    /// of one field, and can be repeated. Values must be identical by default.
+    #[arg(long = "rel", value_name = "[FIELD=]TOL")]
+    rel: Vec<String>,
+
+    /// How far apart two values may be, in their own units. `FIELD=TOL` sets the tolerance of one
+    /// field, and can be repeated. Values match when they are within either tolerance.
+    #[arg(long = "abs", value_name = "[FIELD=]TOL")]
+    abs: Vec<String>,
+
+    /// The number of values that do not match to list, after the summary of every field.
+    #[arg(long, default_value_t = 20)]
+    rows: usize,
+
+    /// Write every value that does not match, with its epoch and field, to this CSV file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct StressArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// The epoch at which the shocks hit (defaults to a quarter of the way through the run, after
+    /// the warm-up).
+    #[arg(long)]
+    epoch: Option<usize>,
+
+    /// The number of runs of every scenario, seeded as the runs of a Monte Carlo ensemble. A check
+    /// only passes if every run passes it.
+    #[arg(long, default_value_t = 1)]
+    runs: usize,
+
+    /// The share of the security ratio before the shock that a run must be back to by the end.
+    #[arg(long, default_value_t = 0.5)]
+    recovery: f64,
+
+    /// Also write every check to this CSV file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// The number of threads to spread the runs across (defaults to one per core).
+    #[arg(long)]
+    jobs: Option<usize>,
+}
+
+fn parse_field(name: &str) -> Result<&'static str, String> {
+    State::FIELDS
+        .iter()
+        .find(|field| **field == name)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "unknown field `{}` (expected one of: {})",
+                name,
+                State::FIELDS.join(", ")
+            )
+        })
+}
+
+fn parse_scenario(name: &str) -> Result<&'static Scenario, String> {
+    scenarios::find(name).ok_or_else(|| {
+        let names: Vec<&str> = scenarios::SCENARIOS.iter().map(|s| s.name).collect();
+        format!(
+            "unknown scenario `{}` (expected one of: {})",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn parse_model(selection: &str) -> Result<(Slot, String), String> {
+    let (key, name) = selection
+        .split_once('=')
+        .ok_or_else(|| format!("expected `function=name`, got `{}`", selection))?;
+    let slot = Slot::find(key).ok_or_else(|| {
+        let keys: Vec<&str> = Slot::ALL.iter().map(|slot| slot.key()).collect();
+        format!(
+            "unknown function `{}` (expected one of: {})",
+            key,
+            keys.join(", ")
+        )
+    })?;
+    Ok((slot, name.to_string()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// A short human readable line per epoch.
+    Text,
+    /// Every state field, one row per epoch, with a header row.
+    Csv,
+    /// A header record followed by one JSON object per epoch.
+    Jsonl,
+}
+
+/// The state from which the simulation begins, where it differs from `[initial]` in the
+/// configuration.
+#[derive(Debug, Args)]
+struct InitialState {
+    /// Initial total value bonded (USD).
+    #[arg(long = "initial-tvb")]
+    tvb: Option<Usd>,
+    /// Initial price of REN (USD).
+    #[arg(long = "initial-ren-price")]
+    ren_price: Option<Usd>,
+    /// Initial number of registered darknodes.
+    #[arg(long = "initial-darknodes")]
+    darknodes: Option<u64>,
+    /// Initial total value locked (USD).
+    #[arg(long = "initial-tvl")]
+    tvl: Option<Usd>,
+    /// Initial total value collected for rebates (USD).
+    #[arg(long = "initial-tvr")]
+    tvr: Option<Usd>,
+    /// Initial minting fee.
+    #[arg(long = "initial-mf")]
+    mf: Option<Pct>,
+    /// Initial burning fee.
+    #[arg(long = "initial-bf")]
+    bf: Option<Pct>,
+    /// Initial rebate.
+    #[arg(long = "initial-r")]
+    r: Option<Pct>,
+    /// Initial fees that have not yet been claimed by nodes (USD).
+    #[arg(long = "initial-f-unclaimed")]
+    f_unclaimed: Option<Usd>,
+    /// Initial fees that have been claimed by nodes (USD).
+    #[arg(long = "initial-f-claimed")]
+    f_claimed: Option<Usd>,
+    /// Initial balance of the rebate pool (USD).
+    #[arg(long = "initial-r-pool")]
+    r_pool: Option<Usd>,
+    /// Fees claimed by nodes per epoch before the run began (USD).
+    #[arg(long = "initial-fee-income")]
+    fee_income: Option<Usd>,
+}
+
+impl InitialState {
+    /// Replace the initial state of the configuration with every value that was given.
+    fn apply(&self, initial: &mut InitialConfig) {
+        initial.tvb = self.tvb.unwrap_or(initial.tvb);
+        initial.ren_price = self.ren_price.unwrap_or(initial.ren_price);
+        initial.darknodes = self.darknodes.unwrap_or(initial.darknodes);
+        initial.tvl = self.tvl.unwrap_or(initial.tvl);
+        initial.tvr = self.tvr.unwrap_or(initial.tvr);
+        initial.mf = self.mf.unwrap_or(initial.mf);
+        initial.bf = self.bf.unwrap_or(initial.bf);
+        initial.r = self.r.unwrap_or(initial.r);
+        initial.f_unclaimed = self.f_unclaimed.unwrap_or(initial.f_unclaimed);
+        initial.f_claimed = self.f_claimed.unwrap_or(initial.f_claimed);
+        initial.r_pool = self.r_pool.unwrap_or(initial.r_pool);
+        initial.fee_income = self.fee_income.unwrap_or(initial.fee_income);
+    }
 }
 
-//
-// MAIN
-// For running the simulation. You probably do not need to modify this code at all.
-//
+/// Print logs to stderr if a filter is given on the command line or in `RUST_LOG` (nothing is
+/// logged otherwise).
+#[cfg(feature = "log")]
+fn init_log(filter: Option<&str>) {
+    use std::io::IsTerminal;
+    use tracing_subscriber::EnvFilter;
+    let filter = match filter {
+        Some(filter) => EnvFilter::new(filter),
+        None => match EnvFilter::try_from_default_env() {
+            Ok(filter) => filter,
+            Err(_) => return,
+        },
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
 
 fn main() {
-    println!("initialising...");
-
-    let num_steps = 180; 
-
-    drop((0..num_steps).fold(vec!(State::default()), |mut history, step| {
-        let mut state = latest_state(&history);
-
-        // Mint and burn volumes this epoch.
-        let mv = mint_volume(&history);
-        let bv = burn_volume(&history);
-
-        // Fees and rebate collected this epoch.
-        let mf = mint_fee_curve(&history);
-        let bf = burn_fee_curve(&history); 
-        let r = rebate_curve(&history);
-        let r_paid = bv*r;
-        let f_collected = mv*mf + bv*bf;
-        let r_collected = rebate_collected(&history, f_collected);
-        let f_collected = f_collected - r_collected;
-
-        // Update the total values bonded, locked, and available for rebate
-        state.tvb = total_value_bonded(&history);
-        state.tvl += mv - bv;
-        state.tvr += r_collected;
-        
-        // Update the fee and rebate curves
-        state.mf = mf;
-        state.bf = bf;
-        state.r = r;
-        
-        // Update the fees claimed by nodes and the fees collected in total (including all of the
-        // fees claimed up until this point)
-        let claim = state.f_unclaimed*0.024451;
-        state.f_unclaimed += f_collected - claim;
-        state.f_claimed += claim; // Claim ~2% of available fees per epoch (~50% per month)
-        state.r_pool = (state.r_pool + r_collected - r_paid).max(0.0);
+    let cli = Cli::parse();
+    let verbosity = match (cli.quiet, cli.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (_, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    };
+    VERBOSITY
+        .set(verbosity)
+        .expect("verbosity is only set once");
+    #[cfg(feature = "log")]
+    init_log(cli.log.as_deref());
+    let result = match cli.command {
+        Some(Command::Run(args)) => run(&args),
+        #[cfg(feature = "tui")]
+        Some(Command::Watch(args)) => watch(&args),
+        #[cfg(feature = "serve")]
+        Some(Command::Serve(args)) => serve(&args),
+        Some(Command::Resume(args)) => resume(&args),
+        Some(Command::Backtest(args)) => backtest(&args),
+        Some(Command::Calibrate(args)) => calibrate(&args),
+        #[cfg(feature = "fetch")]
+        Some(Command::FetchState(args)) => fetch_state(&args),
+        Some(Command::Mc(args)) => mc(&args),
+        Some(Command::Sweep(args)) => sweep(&args),
+        Some(Command::Sensitivity(args)) => sensitivity(&args),
+        Some(Command::Optimize(args)) => optimize(&args),
+        Some(Command::Pareto(args)) => pareto(&args),
+        Some(Command::Compare(args)) => compare(&args),
+        Some(Command::Diff(args)) => diff(&args),
+        Some(Command::Stress(args)) => stress(&args),
+        Some(Command::Scenarios) => {
+            list_scenarios();
+            Ok(())
+        }
+        Some(Command::Models) => {
+            list_models();
+            Ok(())
+        }
+        // Running without a subcommand is the same as running `econ-sim run` with defaults.
+        None => run(&RunArgs::parse_from(["run"])),
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &RunArgs) -> eco::Result<()> {
+    let mut config = args.config.load()?;
+    args.initial.apply(&mut config.initial);
+    let sim = Simulation::from_config(&config);
+    if args.verify_determinism {
+        status!("verifying determinism... (seed={})", sim.seed());
+        let states = determinism::verify(&config, sim.seed(), config.steps)?;
+        println!("{} states identical across both runs", states);
+        return Ok(());
+    }
+    if args.output.format == Format::Text {
+        status!("initialising... (seed={})", sim.seed());
+    }
+    let scenario = Some(args.config.scenario.name);
+    simulate(sim, &config, config.steps, scenario, &args.output)
+}
+
+#[cfg(feature = "tui")]
+fn watch(args: &WatchArgs) -> eco::Result<()> {
+    let mut config = args.config.load()?;
+    args.initial.apply(&mut config.initial);
+    // The simulation watches for a steady state on its own, but ignores an invalid detector (and
+    // likewise an invalid script or unknown models).
+    SteadyState::new(&config.steady_state, &config.epoch)?;
+    config.script.check(&config.epoch)?;
+    config.models.check()?;
+    let sim = Simulation::from_config(&config);
+    eco::dashboard::watch(sim, &config, config.steps, args.speed)
+}
+
+#[cfg(feature = "serve")]
+fn serve(args: &ServeArgs) -> eco::Result<()> {
+    let mut config = args.config.load()?;
+    args.initial.apply(&mut config.initial);
+    // The simulation watches for a steady state on its own, but ignores an invalid detector (and
+    // likewise an invalid script or unknown models).
+    SteadyState::new(&config.steady_state, &config.epoch)?;
+    config.script.check(&config.epoch)?;
+    config.models.check()?;
+    let sim = Simulation::from_config(&config);
+    status!(
+        "serving the dashboard at http://{} (seed={})",
+        args.address,
+        sim.seed()
+    );
+    eco::serve::serve(sim, &config, config.steps, &args.address, args.speed)
+}
+
+fn resume(args: &ResumeArgs) -> eco::Result<()> {
+    let checkpoint = Checkpoint::load(&args.input)?;
+    let (sim, config) = match (&args.config, args.from_epoch) {
+        (None, None) => (Simulation::resume(&checkpoint)?, checkpoint.config.clone()),
+        (path, epoch) => {
+            let config = match path {
+                Some(path) => Config::load_onto(path, &checkpoint.config)?,
+                None => checkpoint.config.clone(),
+            };
+            let epoch = epoch.unwrap_or_else(|| checkpoint.epoch());
+            (Simulation::restart(&checkpoint, epoch, &config)?, config)
+        }
+    };
+    if args.output.format == Format::Text {
+        status!(
+            "resuming from epoch {}... (seed={})",
+            sim.epoch(),
+            sim.seed()
+        );
+    }
+    // A run that met a stop condition stays stopped.
+    let steps = match sim.stopped() {
+        Some(_) => 0,
+        None => args
+            .steps
+            .unwrap_or_else(|| config.steps.saturating_sub(sim.epoch())),
+    };
+    // The configuration of a checkpoint no longer says which scenario it began from.
+    simulate(sim, &config, steps, None, &args.output)
+}
+
+fn backtest(args: &BacktestArgs) -> eco::Result<()> {
+    if args.output.checkpoint.is_some() {
+        return Err(eco::Error::Config(
+            "backtests cannot be checkpointed".to_string(),
+        ));
+    }
+    let config = args.config.load()?;
+    let records = backtest::load(&args.data)?;
+    let steps = records.len();
+    let sim = backtest::simulation(&config, records);
+    if args.output.format == Format::Text {
+        status!(
+            "replaying {} epochs from {}... (seed={})",
+            steps,
+            args.data.display(),
+            sim.seed()
+        );
+    }
+    simulate(
+        sim,
+        &config,
+        steps,
+        Some(args.config.scenario.name),
+        &args.output,
+    )
+}
+
+/// Advance the simulation by the given number of epochs, reporting it as the arguments ask.
+fn simulate(
+    mut sim: Simulation,
+    config: &Config,
+    steps: usize,
+    scenario: Option<&str>,
+    args: &OutputArgs,
+) -> eco::Result<()> {
+    // The simulation watches for a steady state on its own, but ignores an invalid detector (and
+    // likewise an invalid script or unknown models).
+    SteadyState::new(&config.steady_state, &config.epoch)?;
+    config.script.check(&config.epoch)?;
+    config.models.check()?;
+    #[cfg(feature = "plot")]
+    if args.plot.is_some() && sim.history().window().is_some() {
+        return Err(eco::Error::Config(
+            "charts need every state, which is not kept when the history is bounded (by \
+             history.window_days)"
+                .to_string(),
+        ));
+    }
+
+    // Per-epoch outputs are streamed as the run goes, beginning with the states that already exist
+    // (the initial state, or the run so far if it was resumed). Warm-up epochs are left out.
+    let mut metadata = output::Metadata::new(config, sim.seed());
+    if let Some(scenario) = scenario {
+        metadata = metadata.with_scenario(scenario);
+    }
+    let mut sinks: Vec<Box<dyn output::Sink>> = vec![];
+    match args.format {
+        Format::Text => {}
+        Format::Csv => sinks.push(Box::new(
+            output::csv::Writer::new(io::stdout(), &metadata)?.with_flush_every(args.flush_every),
+        )),
+        Format::Jsonl => sinks.push(Box::new(
+            output::jsonl::Writer::new(io::stdout(), &metadata)?.with_flush_every(args.flush_every),
+        )),
+    }
+    if let Some(path) = &args.out {
+        sinks.push(output::create_file(path, &metadata, args.flush_every)?);
+    }
+    let history = sim.history();
+    for (epoch, state) in (history.first_epoch()..).zip(history.states()) {
+        if epoch >= config.warmup {
+            for sink in &mut sinks {
+                sink.write(epoch, state)?;
+            }
+        }
+    }
+
+    let mut attack_log = match &args.attack {
+        Some(Some(path)) => Some(attack::Log::new(
+            create_csv(path, &metadata)?,
+            &config.epoch,
+        )?),
+        _ => None,
+    };
+    let mut attacks = attack::Summary::default();
+    let mut profitable = false;
+    let mut audit = match &args.audit {
+        Some(path) => Some(eco::audit::Log::new(File::create(path)?, &metadata)?),
+        None => None,
+    };
+
+    let mut conditions: Vec<bool> = args
+        .print_on
+        .iter()
+        .map(|condition| condition.eval(sim.history().latest(), sim.epoch()) != 0.0)
+        .collect();
+
+    if args.format == Format::Text && sim.epoch() < config.warmup {
+        status!("warming up for {} epochs...", config.warmup - sim.epoch());
+    }
+    // Text is printed every epoch, which is progress enough.
+    let progress = match args.format {
+        Format::Text => Progress::hidden(),
+        Format::Csv | Format::Jsonl => Progress::new(steps, "simulating"),
+    };
+    for _ in 0..steps {
+        let step = sim.try_step();
+        // The decisions that led up to a violation are the ones most worth reading.
+        if let Some(audit) = &mut audit {
+            audit.write(sim.epoch(), sim.decisions())?;
+            if step.is_err() {
+                audit.flush()?;
+            }
+        }
+        let state = step?;
+        // The epoch that the step just ended, which governance changes and dates are printed for.
+        let epoch = sim.epoch();
+        if args.format == Format::Text {
+            for change in config
+                .governance
+                .iter()
+                .filter(|change| change.epoch == epoch)
+            {
+                match change.description.as_str() {
+                    "" => status!("governance: parameters changed at epoch {}", epoch),
+                    description => status!("governance: {} at epoch {}", description, epoch),
+                }
+            }
+        }
+        progress.inc();
+        // Conditions are followed through the warm-up, so that only changes after it are printed.
+        let mut changes = String::new();
+        if sim.breach().is_some_and(|breach| breach.epoch == epoch) {
+            changes.push_str(" (attacked)");
+        }
+        if args.attack.is_some() && epoch > config.warmup {
+            let assessment = attack::Assessment::new(&state, &config.attack);
+            if let Some(log) = &mut attack_log {
+                log.write(epoch, &assessment)?;
+            }
+            attacks.add(epoch, assessment);
+            if assessment.is_profitable() != profitable {
+                profitable = assessment.is_profitable();
+                let change = if profitable { "now" } else { "no longer" };
+                changes.push_str(&format!(" (attack {} profitable)", change));
+            }
+        }
+        for (condition, holds) in args.print_on.iter().zip(&mut conditions) {
+            let now = condition.eval(&state, epoch) != 0.0;
+            if now != *holds {
+                let change = if now { "now" } else { "no longer" };
+                changes.push_str(&format!(" ({} {})", change, condition.source()));
+                *holds = now;
+            }
+        }
+        // The first epoch after the warm-up is always printed, and then every `print_every`.
+        let printed = epoch > config.warmup
+            && (!changes.is_empty()
+                || args.print_every > 0
+                    && (epoch - config.warmup - 1).is_multiple_of(args.print_every));
+        if args.format == Format::Text && printed {
+            let date = match config.epoch.date(epoch) {
+                Some(date) => format!(" {}", date),
+                None => String::new(),
+            };
+            match verbosity() {
+                Verbosity::Quiet => {}
+                Verbosity::Normal => {
+                    let values = state.values();
+                    let fields: String = args
+                        .fields
+                        .iter()
+                        .map(|&field| {
+                            let i = State::FIELDS.iter().position(|f| *f == field);
+                            let value = values[i.expect("fields are parsed from State::FIELDS")];
+                            // Amounts are printed to the cent, and prices, ratios, and rates (which
+                            // are mostly below one) to more places.
+                            let mut printed = match field {
+                                "darknodes" => format!(" {}={}", field, value),
+                                "ren_price" | "security_ratio" | "mf" | "bf" | "r"
+                                | "implied_darknodes" | "fee_apy" | "runway" => {
+                                    format!(" {}={:.4}", field, value)
+                                }
+                                _ => format!(" {}={:.2}", field, value),
+                            };
+                            // Per-asset TVL follows the aggregate.
+                            if field == "tvl" {
+                                for (name, asset) in sim.assets().iter().zip(&state.assets) {
+                                    printed.push_str(&format!(" tvl[{}]={:.2}", name, asset.tvl));
+                                }
+                            }
+                            printed
+                        })
+                        .collect();
+                    println!("[{}{}]{}{}", epoch, date, fields, changes);
+                }
+                // Every field at full precision, for debugging.
+                Verbosity::Verbose => {
+                    let fields: String = State::FIELDS
+                        .iter()
+                        .zip(state.values().iter())
+                        .map(|(name, value)| format!(" {}={}", name, value))
+                        .collect();
+                    let assets: String = sim
+                        .assets()
+                        .iter()
+                        .zip(&state.assets)
+                        .flat_map(|(name, asset)| {
+                            AssetState::FIELDS.iter().zip(asset.values()).map(
+                                move |(field, value)| format!(" {}[{}]={}", field, name, value),
+                            )
+                        })
+                        .collect();
+                    let shards: String = state
+                        .shards
+                        .iter()
+                        .enumerate()
+                        .flat_map(|(i, shard)| {
+                            ShardState::FIELDS.iter().zip(shard.values()).map(
+                                move |(field, value)| format!(" {}[shard{}]={}", field, i, value),
+                            )
+                        })
+                        .collect();
+                    let controller: String = state
+                        .controller
+                        .iter()
+                        .flat_map(|controller| {
+                            ControllerState::FIELDS
+                                .iter()
+                                .zip(controller.values())
+                                .map(|(field, value)| format!(" {}[controller]={}", field, value))
+                        })
+                        .collect();
+                    println!(
+                        "[{}{}]{}{}{}{}{}",
+                        epoch, date, fields, assets, shards, controller, changes
+                    );
+                }
+            }
+        }
+        if epoch >= config.warmup {
+            for sink in &mut sinks {
+                sink.write(epoch, &state)?;
+            }
+        }
+        if let (Some(path), Some(every)) = (&args.checkpoint, args.checkpoint_every) {
+            if every > 0 && epoch.is_multiple_of(every) {
+                sim.checkpoint(config).save(path)?;
+            }
+        }
+        if config.steady_state.stop && sim.history().aggregates().equilibrium.is_some() {
+            if args.format == Format::Text {
+                status!("steady state reached at epoch {}, stopping early", epoch);
+            }
+            break;
+        }
+        if let Some(divergence) = sim.aborted() {
+            // Results may be going to standard output, so the diagnostic goes to standard error.
+            match args.format {
+                Format::Text => status!("diverged: {}, aborting", divergence),
+                Format::Csv | Format::Jsonl if verbosity() > Verbosity::Quiet => {
+                    eprintln!("diverged: {}, aborting", divergence)
+                }
+                Format::Csv | Format::Jsonl => {}
+            }
+            break;
+        }
+        if let Some(stop) = sim.stopped() {
+            match args.format {
+                Format::Text => status!("stopped: {}", stop),
+                Format::Csv | Format::Jsonl if verbosity() > Verbosity::Quiet => {
+                    eprintln!("stopped: {}", stop)
+                }
+                Format::Csv | Format::Jsonl => {}
+            }
+            break;
+        }
+    }
+
+    progress.finish();
+    let history = sim.history();
+    // A run that ends during the warm-up is represented by its last state.
+    if history.epoch() < config.warmup {
+        for sink in &mut sinks {
+            sink.write(history.epoch(), history.latest())?;
+        }
+    }
+    for sink in &mut sinks {
+        sink.finish()?;
+    }
+    if let Some(audit) = &mut audit {
+        audit.flush()?;
+    }
+    if let Some(log) = &mut attack_log {
+        log.flush()?;
+    }
+    let report = metrics::Report::from_aggregates(history.aggregates())
+        .with_health(history.aggregates(), &config.health)
+        .with_stop(sim.stopped());
+    if args.format == Format::Text {
         println!(
-            "[{}] tvl={:.2} tvb={:.2} f_claimed={:.2} r_pool={:.2}",
-            step, 
-            state.tvl,
-            state.tvb,
-            state.f_claimed,
-            state.r_pool,
+            "{}",
+            metrics::Security::from_aggregates(history.aggregates())
         );
+        println!("{}", report);
+        if args.attack.is_some() {
+            println!("{}", attacks);
+        }
+        if let Some(breach) = sim.breach() {
+            println!("{}", breach);
+        }
+        if let Some(mint_cap) = sim.mint_cap() {
+            println!("{}", mint_cap);
+        }
+        status!("done");
+    }
+    #[cfg(feature = "plot")]
+    if let Some(path) = &args.plot {
+        eco::plot::render(path, eco::after_warmup(history.states(), config.warmup))?;
+    }
+    if let Some(path) = &args.summary {
+        report.write_file(path, &metadata)?;
+    }
+    if let Some(path) = &args.checkpoint {
+        sim.checkpoint(config).save(path)?;
+    }
+    if args.health_exit_code {
+        let health = report.health.expect("the report is classified");
+        if health != Health::Healthy {
+            process::exit(health.exit_code());
+        }
+    }
+    Ok(())
+}
 
-        history.push(state);
-        history
-    }));
+fn mc(args: &McArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    if let Some(path) = &args.histories {
+        output::check_runs_file(path)?;
+    }
+    status!("running {} simulations... (seed={})", args.runs, seed);
+    if verbosity() == Verbosity::Verbose {
+        println!(
+            "seeds {} to {}, on {}",
+            seed,
+            monte_carlo::run_seed(seed, args.runs.saturating_sub(1)),
+            threads()
+        );
+    }
 
-    println!("done");
+    let progress = Progress::new(args.runs, "simulating");
+    let histories = monte_carlo::run_with_progress(&config, args.runs, seed, &|| progress.inc());
+    progress.finish();
+    let histories = histories?;
+    let metadata = args.config.metadata(&config, seed);
+    let summary = monte_carlo::Summary::new(&histories, &args.percentiles, config.warmup);
+    summary.write_file(&args.out, &metadata)?;
+    let classes: Vec<Health> = histories
+        .iter()
+        .map(|history| {
+            let required_ratio = config.security.required_ratio;
+            Health::new(history, required_ratio, config.warmup, &config.health)
+        })
+        .collect();
+    let counts: Vec<String> = Health::ALL
+        .iter()
+        .map(|health| {
+            let count = classes.iter().filter(|class| *class == health).count();
+            format!("{} {}", count, health)
+        })
+        .collect();
+    println!("health: {}", counts.join(", "));
+    let aborted = histories
+        .iter()
+        .filter(|history| history.len() <= config.steps)
+        .count();
+    if aborted > 0 {
+        let why = if config.stop.is_empty() {
+            "diverged and were aborted early"
+        } else {
+            "diverged or met a stop condition, and ended early"
+        };
+        status!("{} of {} runs {}", aborted, args.runs, why);
+    }
+    if let Some(path) = &args.histories {
+        output::write_runs_file(path, &metadata, &histories)?;
+    }
+    #[cfg(feature = "plot")]
+    if let Some(path) = &args.plot {
+        eco::plot::render_fan(path, &histories, config.warmup)?;
+    }
+
+    status!("done (wrote {})", args.out.display());
+    Ok(())
+}
+
+fn calibrate(args: &CalibrateArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    let records = backtest::load(&args.data)?;
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let params = if args.params.is_empty() {
+        calibrate::DEFAULT_PARAMETERS
+            .iter()
+            .map(|path| path.to_string())
+            .collect()
+    } else {
+        args.params.clone()
+    };
+    status!(
+        "calibrating to {} epochs from {}... (seed={})",
+        records.len(),
+        args.data.display(),
+        seed
+    );
+
+    let calibration = calibrate::run(&config, &records, &params, seed)?;
+    calibration.write_text(io::stdout().lock())?;
+    calibration.config.save(&args.out)?;
+    status!("wrote {}", args.out.display());
+    Ok(())
+}
+
+#[cfg(feature = "fetch")]
+fn fetch_state(args: &FetchStateArgs) -> eco::Result<()> {
+    use eco::fetch::{self, Stats};
+
+    let mut config = args.config.load()?;
+    let stats = match &args.from {
+        Some(path) => {
+            status!("reading the state of RenVM from {}...", path.display());
+            Stats::from_response(&serde_json::from_reader(io::BufReader::new(File::open(
+                path,
+            )?))?)?
+        }
+        None => {
+            status!("fetching the state of RenVM from {}...", args.url);
+            fetch::fetch(&args.url, args.days)?
+        }
+    };
+    stats.write_text(io::stdout().lock())?;
+    stats.apply(&mut config);
+    config.save(&args.out)?;
+    status!("wrote {}", args.out.display());
+    Ok(())
+}
+
+fn sweep(args: &SweepArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let n = sweep::grid(&args.axes).len();
+    if verbosity() == Verbosity::Verbose {
+        for axis in &args.axes {
+            println!("{}: {:?}", axis.path, axis.values);
+        }
+        println!("sweeping {} cells on {} (seed={})", n, threads(), seed);
+    }
+
+    let progress = Progress::new(n, "sweeping");
+    let cells = sweep::run_with_progress(&config, &args.axes, seed, &|| progress.inc());
+    progress.finish();
+    let cells = cells?;
+    match &args.out {
+        Some(path) => {
+            let metadata = args.config.metadata(&config, seed);
+            sweep::write_csv(create_csv(path, &metadata)?, &args.axes, &cells)?;
+            status!(
+                "swept {} cells (seed={}), wrote {}",
+                cells.len(),
+                seed,
+                path.display()
+            );
+        }
+        None => sweep::write_csv(io::stdout().lock(), &args.axes, &cells)?,
+    }
+    Ok(())
+}
+
+fn optimize(args: &OptimizeArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let params = if args.params.is_empty() {
+        optimize::DEFAULT_PARAMETERS
+            .iter()
+            .map(|param| param.parse())
+            .collect::<eco::Result<_>>()?
+    } else {
+        args.params.clone()
+    };
+    let objective = optimize::Objective {
+        metric: args.objective.clone(),
+        minimise: args.minimize,
+        min_security_ratio: args.min_security_ratio,
+    };
+    status!(
+        "{} {} over {} parameters... (seed={})",
+        if args.minimize {
+            "minimising"
+        } else {
+            "maximising"
+        },
+        objective.metric,
+        params.len(),
+        seed
+    );
+
+    let optimum = optimize::run(
+        &config,
+        &params,
+        &objective,
+        args.runs,
+        seed,
+        args.max_evaluations,
+    )?;
+    optimum.write_text(io::stdout().lock())?;
+    if let Some(path) = &args.out {
+        optimum.config.save(path)?;
+        status!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn pareto(args: &ParetoArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let params = if args.params.is_empty() {
+        optimize::DEFAULT_PARAMETERS
+            .iter()
+            .map(|param| param.parse())
+            .collect::<eco::Result<_>>()?
+    } else {
+        args.params.clone()
+    };
+    status!(
+        "sampling {} points of {} parameters... (seed={})",
+        args.samples,
+        params.len(),
+        seed
+    );
+
+    let progress = Progress::new(args.samples + 1, "sampling");
+    let frontier =
+        pareto::run_with_progress(&config, &params, args.samples, args.runs, seed, &|| {
+            progress.inc()
+        });
+    progress.finish();
+    let frontier = frontier?;
+    frontier.write_text(io::stdout().lock())?;
+    if let Some(path) = &args.out {
+        frontier.write_csv(create_csv(path, &args.config.metadata(&config, seed))?)?;
+        status!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn sensitivity(args: &SensitivityArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    status!(
+        "perturbing every parameter by ±{}%... (seed={})",
+        args.perturbation,
+        seed
+    );
+    let parameters = config.parameters();
+    if verbosity() == Verbosity::Verbose {
+        println!(
+            "{} parameters on {}: {}",
+            parameters.len(),
+            threads(),
+            parameters.join(", ")
+        );
+    }
+
+    let progress = Progress::new(parameters.len() + 1, "perturbing");
+    let report = sensitivity::run_with_progress(&config, args.perturbation / 100.0, seed, &|| {
+        progress.inc()
+    });
+    progress.finish();
+    let report = report?;
+    report.write_text(io::stdout().lock())?;
+    if let Some(path) = &args.out {
+        report.write_csv(create_csv(path, &args.config.metadata(&config, seed))?)?;
+    }
+    Ok(())
+}
+
+fn compare(args: &CompareArgs) -> eco::Result<()> {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    // Result files are read as they are, so only simulated runs have metadata of their own.
+    type Run = (Option<output::Metadata>, Vec<(usize, State)>);
+    let load = |input: &str| -> eco::Result<Run> {
+        let path = PathBuf::from(input);
+        let (config, scenario) = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") | Some("yaml") | Some("yml") => (Config::load(&path)?, None),
+            _ if path.exists() => return Ok((None, output::read_file(&path)?)),
+            _ => match scenarios::find(input) {
+                Some(scenario) => (scenario.config(), Some(scenario.name)),
+                None => {
+                    return Err(eco::Error::Config(format!(
+                        "`{}` is not a file or a scenario",
+                        input
+                    )))
+                }
+            },
+        };
+        let steps = args.steps.unwrap_or(config.steps);
+        let mut sim = Simulation::from_config(&config).with_seed(seed);
+        sim.try_run(steps)?;
+        // A bounded history no longer begins at epoch zero, so epochs are counted from its start.
+        let first_epoch = sim.history().first_epoch();
+        let mut metadata = output::Metadata::new(&config, seed);
+        if let Some(scenario) = scenario {
+            metadata = metadata.with_scenario(scenario);
+        }
+        let states = (first_epoch..)
+            .zip(sim.into_history())
+            .filter(|(epoch, _)| *epoch >= config.warmup)
+            .collect();
+        Ok((Some(metadata), states))
+    };
+    status!(
+        "comparing {} (a) with {} (b)... (seed={})",
+        args.a,
+        args.b,
+        seed
+    );
+
+    let (a, b) = (load(&args.a)?, load(&args.b)?);
+    let comparison = Comparison::new(&a.1, &b.1, args.tolerance);
+    if comparison.epochs.is_empty() {
+        return Err(eco::Error::Config(
+            "the runs do not have any epochs in common".to_string(),
+        ));
+    }
+    comparison.write_text(io::stdout().lock())?;
+    if let Some(path) = &args.out {
+        // Both runs are stamped with their metadata, as other CSV files are, each under its input.
+        let mut file = File::create(path)?;
+        for (label, input, metadata) in [("a", &args.a, &a.0), ("b", &args.b, &b.0)] {
+            writeln!(file, "# {}: {}", label, input)?;
+            if let Some(metadata) = metadata {
+                output::csv::write_metadata(&mut file, metadata)?;
+            }
+        }
+        comparison.write_csv(file)?;
+    }
+    #[cfg(feature = "plot")]
+    if let Some(path) = &args.plot {
+        let labels = [args.a.as_str(), args.b.as_str()];
+        eco::plot::render_comparison(path, labels, &comparison.a, &comparison.b)?;
+    }
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> eco::Result<()> {
+    let mut tolerances = Tolerances::default();
+    for spec in &args.rel {
+        tolerances = tolerances.with_rel(spec)?;
+    }
+    for spec in &args.abs {
+        tolerances = tolerances.with_abs(spec)?;
+    }
+    status!(
+        "diffing {} (a) with {} (b)...",
+        args.a.display(),
+        args.b.display()
+    );
+    let a = output::read_file(&args.a)?;
+    let b = output::read_file(&args.b)?;
+    let diff = Diff::new(&a, &b, &tolerances);
+    diff.write_text(io::stdout().lock(), args.rows)?;
+    if let Some(path) = &args.out {
+        diff.write_csv(File::create(path)?)?;
+    }
+    if !diff.is_match() {
+        return Err(eco::Error::Mismatch(diff.summary()));
+    }
+    println!("{}", diff.summary());
+    Ok(())
+}
+
+fn stress(args: &StressArgs) -> eco::Result<()> {
+    let config = args.config.load()?;
+    if let Some(jobs) = args.jobs {
+        parallel::set_threads(jobs)?;
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let epoch = args
+        .epoch
+        .unwrap_or(config.warmup + (config.steps - config.warmup.min(config.steps)) / 4)
+        .max(1);
+    status!(
+        "stress-testing {} scenarios at epoch {}, {} each... (seed={})",
+        stress::STRESSES.len(),
+        epoch,
+        match args.runs {
+            1 => "1 run".to_string(),
+            runs => format!("{} runs", runs),
+        },
+        seed
+    );
+    if verbosity() == Verbosity::Verbose {
+        for stress in &stress::STRESSES {
+            println!("{:<16} {}", stress.name, stress.description);
+        }
+        for (name, description) in &stress::CRITERIA {
+            println!("{:<16} {}", name, description);
+        }
+        println!("on {}", threads());
+    }
+
+    let progress = Progress::new(stress::STRESSES.len() * args.runs, "stress-testing");
+    let report = stress::run_with_progress(&config, epoch, args.runs, seed, args.recovery, &|| {
+        progress.inc()
+    });
+    progress.finish();
+    let report = report?;
+    report.write_text(io::stdout().lock())?;
+    if let Some(path) = &args.out {
+        report.write_csv(create_csv(path, &args.config.metadata(&config, seed))?)?;
+    }
+    match report.failed() {
+        0 => {
+            println!("passed all {} checks", report.checks());
+            Ok(())
+        }
+        failed => Err(eco::Error::Stress(format!(
+            "failed {} of {} checks",
+            failed,
+            report.checks()
+        ))),
+    }
 }
 
-/// Helper function to get a copy of the latest state from a history of states.
-fn latest_state(history: &Vec<State>) -> State {
-    history.iter().last().expect("missing initial state").clone()
+/// Create a CSV file at the given path (replacing it if it already exists) that begins with the
+/// metadata of the run that it comes from.
+fn create_csv(path: &Path, metadata: &output::Metadata) -> eco::Result<File> {
+    let mut file = File::create(path)?;
+    output::csv::write_metadata(&mut file, metadata)?;
+    Ok(file)
+}
+
+/// The number of threads that runs are spread across, for printing.
+fn threads() -> String {
+    match parallel::threads() {
+        1 => "1 thread".to_string(),
+        n => format!("{} threads", n),
+    }
+}
+
+fn list_scenarios() {
+    for scenario in &scenarios::SCENARIOS {
+        let mut name = scenario.name.to_string();
+        if !scenario.aliases.is_empty() {
+            name = format!("{} ({})", name, scenario.aliases.join(", "));
+        }
+        println!("{:<20} {}", name, scenario.description);
+    }
+}
+
+fn list_models() {
+    for slot in Slot::ALL {
+        println!("{}:", slot.key());
+        let models = registry::models(slot);
+        if models.is_empty() {
+            println!("  (none built in)");
+        }
+        for (name, description) in models {
+            println!("  {:<18} {}", name, description);
+        }
+    }
 }
@@ -0,0 +1,375 @@
+//! For computing headline numbers from the history of a completed run, so that runs can be compared
+//! with each other (in sweeps, sensitivity analysis, etc.) without post-processing raw states.
+
+use crate::config::HealthConfig;
+use crate::divergence::{self, Detector, Divergence};
+use crate::error::Result;
+use crate::history::Aggregates;
+use crate::output::Metadata;
+use crate::state::State;
+use crate::steady_state::{Equilibrium, SteadyState};
+use crate::stop::Stop;
+use crate::Usd;
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+/// Outcome summarises a completed run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outcome {
+    /// The total value locked at the end of the run.
+    pub tvl: Usd,
+    /// The total value bonded at the end of the run.
+    pub tvb: Usd,
+    /// The balance of the rebate pool at the end of the run.
+    pub r_pool: Usd,
+    /// The total fees earned by nodes (claimed or not) by the end of the run.
+    pub node_income: Usd,
+    /// The lowest ratio of TVB to TVL seen during the run, ignoring epochs in which nothing is
+    /// locked. This is infinite if nothing was ever locked.
+    pub min_security_ratio: f64,
+    /// The number of epochs in which the security ratio was below the required ratio.
+    pub breached_epochs: usize,
+}
+
+impl Outcome {
+    /// The names of every field in the outcome, in declaration order.
+    pub const FIELDS: [&'static str; 6] = [
+        "tvl",
+        "tvb",
+        "r_pool",
+        "node_income",
+        "min_security_ratio",
+        "breached_epochs",
+    ];
+
+    /// Summarise the history of a run after the given number of warm-up epochs, where RenVM is
+    /// secure while TVB is at least `required_ratio` times TVL.
+    pub fn new(history: &[State], required_ratio: f64, warmup: usize) -> Self {
+        Self::from_aggregates(&Aggregates::accumulate(0, history, warmup, required_ratio))
+    }
+
+    /// Summarise a run from its aggregates (which cover the whole run, even if its history was
+    /// bounded).
+    pub fn from_aggregates(aggregates: &Aggregates) -> Self {
+        let last = &aggregates.last;
+        Self {
+            tvl: last.tvl,
+            tvb: last.tvb,
+            r_pool: last.r_pool,
+            node_income: last.f_claimed + last.f_unclaimed,
+            min_security_ratio: aggregates.min_security_ratio.unwrap_or(f64::INFINITY),
+            breached_epochs: aggregates.breaches,
+        }
+    }
+
+    /// The values of every field in the outcome, in the same order as `Outcome::FIELDS`.
+    pub fn values(&self) -> [f64; 6] {
+        [
+            self.tvl.0,
+            self.tvb.0,
+            self.r_pool.0,
+            self.node_income.0,
+            self.min_security_ratio,
+            self.breached_epochs as f64,
+        ]
+    }
+}
+
+/// Security reports how often (and how early) a run was under-collateralised, which is the main
+/// question that the simulation exists to answer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Security {
+    /// The multiple of TVL that must be bonded.
+    pub required_ratio: f64,
+    /// The number of simulated epochs (not including the initial state or the warm-up).
+    pub epochs: usize,
+    /// The number of simulated epochs in which the ratio was breached.
+    pub breaches: usize,
+    /// The first epoch in which the ratio was breached (where epoch zero is the initial state).
+    pub first_breach: Option<usize>,
+}
+
+impl Security {
+    /// Whether a state is under-collateralised (in aggregate, or in any shard if it is sharded).
+    /// Nothing can be stolen while nothing is locked, so those states are never breaches.
+    pub fn is_breach(state: &State, required_ratio: f64) -> bool {
+        if state.shards.is_empty() {
+            state.tvl > Usd::ZERO && state.tvb < required_ratio * state.tvl
+        } else {
+            state
+                .shards
+                .iter()
+                .any(|shard| shard.tvl > Usd::ZERO && shard.tvb < required_ratio * shard.tvl)
+        }
+    }
+
+    /// Find every breach in the history of a run, after the given number of warm-up epochs.
+    pub fn new(history: &[State], required_ratio: f64, warmup: usize) -> Self {
+        Self::from_aggregates(&Aggregates::accumulate(0, history, warmup, required_ratio))
+    }
+
+    /// Find every breach in a run from its aggregates.
+    pub fn from_aggregates(aggregates: &Aggregates) -> Self {
+        Self {
+            required_ratio: aggregates.required_ratio,
+            epochs: aggregates.epochs,
+            breaches: aggregates.breaches,
+            first_breach: aggregates.first_breach,
+        }
+    }
+}
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.first_breach {
+            Some(epoch) => write!(
+                f,
+                "security: TVB below {}x TVL in {} of {} epochs (first at epoch {})",
+                self.required_ratio, self.breaches, self.epochs, epoch
+            ),
+            None => write!(
+                f,
+                "security: TVB never below {}x TVL in {} epochs",
+                self.required_ratio, self.epochs
+            ),
+        }
+    }
+}
+
+/// Health classifies the outcome of a run by the worst thing that happened in it, so that large
+/// batches of runs can be triaged without reading every summary. From best to worst:
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Health {
+    /// Nothing went wrong.
+    Healthy,
+    /// Rebates drained the rebate pool at least once (while RenVM stayed collateralised).
+    RebatePoolExhausted,
+    /// TVB fell below the required multiple of TVL in at least one epoch.
+    UnderCollateralised,
+    /// The run ended under-collateralised, with TVB collapsed from its peak while TVL had not (see
+    /// `HealthConfig`), so that the bond stopped backing what was locked. A run that diverged (see
+    /// `divergence`) is also in a death spiral, whether or not it carried on to the end.
+    DeathSpiral,
+}
+
+impl Health {
+    /// Every class, from best to worst.
+    pub const ALL: [Health; 4] = [
+        Health::Healthy,
+        Health::RebatePoolExhausted,
+        Health::UnderCollateralised,
+        Health::DeathSpiral,
+    ];
+
+    /// Classify the history of a run after the given number of warm-up epochs.
+    pub fn new(
+        history: &[State],
+        required_ratio: f64,
+        warmup: usize,
+        config: &HealthConfig,
+    ) -> Self {
+        let mut aggregates = Aggregates::accumulate(0, history, warmup, required_ratio);
+        aggregates.divergence = Detector::new(config)
+            .and_then(|detector| detector.find(history, warmup, required_ratio));
+        Self::classify(&aggregates, config)
+    }
+
+    /// Classify a run from its aggregates.
+    pub fn classify(aggregates: &Aggregates, config: &HealthConfig) -> Self {
+        let spiral =
+            aggregates.epochs > 0 && divergence::is_spiral(&aggregates.last, aggregates, config);
+        if spiral || aggregates.divergence.is_some() {
+            Health::DeathSpiral
+        } else if aggregates.breaches > 0 {
+            Health::UnderCollateralised
+        } else if aggregates.exhaustions > 0 {
+            Health::RebatePoolExhausted
+        } else {
+            Health::Healthy
+        }
+    }
+
+    /// The process exit code that reports the class (zero for a healthy run, and one is left for
+    /// errors).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Health::Healthy => 0,
+            Health::RebatePoolExhausted => 2,
+            Health::UnderCollateralised => 3,
+            Health::DeathSpiral => 4,
+        }
+    }
+
+    /// The name of the class, as it is printed and serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::RebatePoolExhausted => "rebate-pool-exhausted",
+            Health::UnderCollateralised => "under-collateralised",
+            Health::DeathSpiral => "death-spiral",
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Report answers the basic questions about a completed run, which would otherwise need the raw
+/// states to be post-processed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    /// The number of simulated epochs (not including the initial state or the warm-up).
+    pub epochs: usize,
+    /// The total value locked at the end of the run.
+    pub final_tvl: Usd,
+    /// The highest total value locked during the run.
+    pub peak_tvl: Usd,
+    /// The total value bonded at the end of the run.
+    pub final_tvb: Usd,
+    /// The highest total value bonded during the run.
+    pub peak_tvb: Usd,
+    /// The fees claimed by nodes during the run.
+    pub fees_claimed: Usd,
+    /// The rebates paid to burners during the run.
+    pub rebates_paid: Usd,
+    /// The lowest ratio of TVB to TVL, ignoring epochs in which nothing is locked (or nothing, if
+    /// nothing was ever locked).
+    pub min_security_ratio: Option<f64>,
+    /// The first steady state that the run reached, if it reached one.
+    pub equilibrium: Option<Equilibrium>,
+    /// The fees claimed by all nodes per epoch, on average.
+    pub node_income_per_epoch: Usd,
+    /// The fees claimed per darknode per epoch, on average over the epochs in which any darknodes
+    /// were registered (or nothing, if darknodes were not tracked).
+    pub darknode_income_per_epoch: Option<Usd>,
+    /// The class of the outcome of the run, once it has been classified (see `with_health`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
+    /// The first divergence of the run, if it is watched for and was detected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub divergence: Option<Divergence>,
+    /// The stop condition that ended the run early, if it met one (see `with_stop`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<Stop>,
+}
+
+impl Report {
+    /// Summarise the history of a run after the given number of warm-up epochs, using the detector
+    /// to find when it reached equilibrium.
+    pub fn new(history: &[State], steady_state: &SteadyState, warmup: usize) -> Self {
+        // The security requirement does not appear in the report.
+        let mut aggregates = Aggregates::accumulate(0, history, warmup, 0.0);
+        aggregates.equilibrium = steady_state.find(history, warmup);
+        Self::from_aggregates(&aggregates)
+    }
+
+    /// Summarise a run from its aggregates (which cover the whole run, even if its history was
+    /// bounded).
+    pub fn from_aggregates(aggregates: &Aggregates) -> Self {
+        let (first, last) = (&aggregates.first, &aggregates.last);
+        let epochs = aggregates.epochs;
+        Self {
+            epochs,
+            final_tvl: last.tvl,
+            peak_tvl: aggregates.peak_tvl,
+            final_tvb: last.tvb,
+            peak_tvb: aggregates.peak_tvb,
+            fees_claimed: last.f_claimed - first.f_claimed,
+            rebates_paid: aggregates.rebates_paid,
+            // Adding zero turns the negative zero of an empty bond into a plain zero.
+            min_security_ratio: aggregates.min_security_ratio.map(|ratio| ratio + 0.0),
+            equilibrium: aggregates.equilibrium.clone(),
+            node_income_per_epoch: if epochs > 0 {
+                (last.f_claimed - first.f_claimed) / epochs as f64
+            } else {
+                Usd::ZERO
+            },
+            darknode_income_per_epoch: if aggregates.darknode_epochs == 0 {
+                None
+            } else {
+                Some(aggregates.darknode_income / aggregates.darknode_epochs as f64)
+            },
+            health: None,
+            divergence: aggregates.divergence.clone(),
+            stopped: None,
+        }
+    }
+
+    /// Classify the outcome of the run, from the same aggregates that it was summarised from.
+    pub fn with_health(mut self, aggregates: &Aggregates, config: &HealthConfig) -> Self {
+        self.health = Some(Health::classify(aggregates, config));
+        self
+    }
+
+    /// Record the stop condition that ended the run early (see `Simulation::stopped`), which its
+    /// aggregates do not know about.
+    pub fn with_stop(mut self, stop: Option<&Stop>) -> Self {
+        self.stopped = stop.cloned();
+        self
+    }
+
+    /// Write the report to a JSON file, with the metadata of the run under a `metadata` field.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, metadata: &Metadata) -> Result<()> {
+        #[derive(Serialize)]
+        struct Stamped<'a> {
+            metadata: &'a Metadata,
+            #[serde(flatten)]
+            report: &'a Report,
+        }
+        let stamped = Stamped {
+            metadata,
+            report: self,
+        };
+        serde_json::to_writer_pretty(File::create(path)?, &stamped)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "tvl: final={:.2} peak={:.2}",
+            self.final_tvl, self.peak_tvl
+        )?;
+        writeln!(
+            f,
+            "tvb: final={:.2} peak={:.2}",
+            self.final_tvb, self.peak_tvb
+        )?;
+        writeln!(f, "fees claimed: {:.2}", self.fees_claimed)?;
+        writeln!(f, "rebates paid: {:.2}", self.rebates_paid)?;
+        match self.min_security_ratio {
+            Some(ratio) => writeln!(f, "min security ratio: {:.4}", ratio)?,
+            None => writeln!(f, "min security ratio: nothing locked")?,
+        }
+        match &self.equilibrium {
+            Some(equilibrium) => writeln!(f, "equilibrium: {}", equilibrium)?,
+            None => writeln!(f, "equilibrium: not reached")?,
+        }
+        write!(
+            f,
+            "node income: {:.2} per epoch",
+            self.node_income_per_epoch
+        )?;
+        if let Some(income) = self.darknode_income_per_epoch {
+            write!(f, " ({:.2} per darknode)", income)?;
+        }
+        if let Some(health) = self.health {
+            write!(f, "\nhealth: {}", health)?;
+        }
+        if let Some(divergence) = &self.divergence {
+            write!(f, "\ndiverged: {}", divergence)?;
+        }
+        if let Some(stop) = &self.stopped {
+            write!(f, "\nstopped: {}", stop)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! For capping what is minted so that locked value cannot outgrow the bond, which controls security
+//! on the supply side (by refusing the value that would be insecure) rather than through the fees
+//! that node operators bond for. Every epoch, there is room to mint up to TVB (of the previous
+//! epoch) divided by the ratio, less what is already locked, plus whatever is burned in the epoch.
+//! Mints beyond that are rejected, and either given up on or tried again in the next epoch (ahead
+//! of new mints). When assets are locked individually, they share the room in the order that they
+//! are listed, each making room with its own burns before it mints.
+
+use crate::config::MintCapConfig;
+use crate::state::State;
+use crate::Usd;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// MintCap caps minting according to a `MintCapConfig`, keeping track of what it rejects.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MintCap {
+    config: MintCapConfig,
+    /// The rejected mints that are waiting to be tried again, for the aggregate or every asset.
+    backlog: Vec<Usd>,
+    /// The number of epochs in which a mint was rejected.
+    epochs: usize,
+    /// The new mints that were rejected (at least once).
+    rejected: Usd,
+    /// Whether a mint has been rejected in the current epoch.
+    #[serde(skip)]
+    rejecting: bool,
+}
+
+impl MintCap {
+    /// Create a mint cap using the given parameters. Returns `None` when nothing is capped (when
+    /// the ratio is not positive).
+    pub fn new(config: MintCapConfig) -> Option<Self> {
+        (config.ratio > 0.0).then(|| Self {
+            config,
+            backlog: vec![],
+            epochs: 0,
+            rejected: Usd::ZERO,
+            rejecting: false,
+        })
+    }
+
+    /// Begin an epoch, returning the room to mint in it after the given state (before anything is
+    /// burned in it).
+    pub fn room(&mut self, previous: &State) -> Usd {
+        self.rejecting = false;
+        (previous.tvb / self.config.ratio - previous.tvl).max(Usd::ZERO)
+    }
+
+    /// Accept as much of the new `demand` (and the backlog) of the aggregate or an asset (by its
+    /// index) as there is room for, after `burned` has made more room. Returns what is minted, and
+    /// takes it out of the room.
+    pub fn accept(&mut self, i: usize, demand: Usd, burned: Usd, room: &mut Usd) -> Usd {
+        if self.backlog.len() <= i {
+            self.backlog.resize(i + 1, Usd::ZERO);
+        }
+        *room += burned.max(Usd::ZERO);
+        let backlog = self.backlog[i];
+        let wanted = backlog + demand;
+        let minted = wanted.min(*room).max(Usd::ZERO);
+        *room -= minted;
+        let rejected = wanted - minted;
+        if rejected > Usd::ZERO {
+            if !self.rejecting {
+                self.epochs += 1;
+                self.rejecting = true;
+            }
+            // The backlog is tried first, so whatever more is rejected is new.
+            self.rejected += (rejected - backlog).max(Usd::ZERO);
+            tracing::trace!(rejected = rejected.0, "mints rejected");
+        }
+        self.backlog[i] = if self.config.carry_over {
+            rejected
+        } else {
+            Usd::ZERO
+        };
+        minted
+    }
+
+    /// The rejected mints that are still waiting to be tried again.
+    pub fn backlog(&self) -> Usd {
+        self.backlog.iter().copied().sum()
+    }
+}
+
+impl fmt::Display for MintCap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epochs == 0 {
+            return write!(f, "mint cap: never reached");
+        }
+        write!(
+            f,
+            "mint cap: reached in {} epochs, rejecting {:.2} of new mints",
+            self.epochs, self.rejected
+        )?;
+        if self.config.carry_over {
+            write!(f, " ({:.2} still waiting)", self.backlog())
+        } else {
+            Ok(())
+        }
+    }
+}
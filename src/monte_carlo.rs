@@ -0,0 +1,172 @@
+//! For running many independent stochastic simulations of the same configuration and aggregating
+//! the results. Every run is seeded differently (run `i` uses the base seed plus `i`), so an
+//! ensemble can be reproduced from its base seed, and any individual run can be reproduced on its
+//! own.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::output::{self, Metadata};
+use crate::parallel;
+use crate::simulation::Simulation;
+use crate::state::State;
+use crate::stats;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// A named series, and how to read its value from a state.
+pub type Series = (&'static str, fn(&State) -> f64);
+
+/// The series that are aggregated across runs.
+pub const SERIES: [Series; 5] = [
+    ("tvl", |state| state.tvl.0),
+    ("tvb", |state| state.tvb.0),
+    ("f_claimed", |state| state.f_claimed.0),
+    ("r_pool", |state| state.r_pool.0),
+    ("security_ratio", |state| state.security_ratio),
+];
+
+/// The seed used by run `i` of an ensemble with the given base seed.
+pub fn run_seed(seed: u64, i: usize) -> u64 {
+    seed.wrapping_add(i as u64)
+}
+
+/// Run `runs` independent simulations of the configuration (in parallel), returning the history of
+/// every run. The seed in the configuration (if any) is ignored in favour of the base seed. If any
+/// run violates an invariant, the error from the first such run is returned. Every epoch of every
+/// run is summarised, so the history cannot be bounded. Runs that are aborted at their first
+/// divergence (with `health.abort`) have shorter histories than the rest.
+pub fn run(config: &Config, runs: usize, seed: u64) -> Result<Vec<Vec<State>>> {
+    run_with_progress(config, runs, seed, &|| {})
+}
+
+/// Run the ensemble like `run`, calling `progress` (from whichever thread ran it) every time a run
+/// completes.
+pub fn run_with_progress(
+    config: &Config,
+    runs: usize,
+    seed: u64,
+    progress: &(dyn Fn() + Sync),
+) -> Result<Vec<Vec<State>>> {
+    if config.history.window_days.is_some() {
+        return Err(Error::Config(
+            "a Monte Carlo ensemble needs every state of every run, so the history cannot be \
+             bounded (by history.window_days)"
+                .to_string(),
+        ));
+    }
+    parallel::map((0..runs).collect(), |i| {
+        let mut sim = Simulation::from_config(config).with_seed(run_seed(seed, i));
+        sim.try_run(config.steps)?;
+        progress();
+        Ok(sim.into_history())
+    })
+    .into_iter()
+    .collect()
+}
+
+/// The aggregate of one series at one epoch across every run of an ensemble.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregate {
+    pub mean: f64,
+    pub median: f64,
+    /// One value for each of the requested percentiles, in the same order.
+    pub percentiles: Vec<f64>,
+}
+
+impl Aggregate {
+    /// Aggregate the values (which do not need to be sorted).
+    pub fn new(mut values: Vec<f64>, percentiles: &[f64]) -> Self {
+        stats::sort(&mut values);
+        Self {
+            mean: stats::mean(&values),
+            median: stats::percentile(&values, 50.0),
+            percentiles: percentiles
+                .iter()
+                .map(|&p| stats::percentile(&values, p))
+                .collect(),
+        }
+    }
+}
+
+/// Summary holds the aggregate of every series in `SERIES` at every epoch across an ensemble.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// The number of runs that were aggregated.
+    pub runs: usize,
+    /// The percentiles (between 0 and 100) that were computed.
+    pub percentiles: Vec<f64>,
+    /// The epoch of the first aggregate (the end of the warm-up, or zero for the initial state).
+    pub first_epoch: usize,
+    /// For every epoch from `first_epoch`, the aggregate of every series in the same order as
+    /// `SERIES`.
+    pub epochs: Vec<Vec<Aggregate>>,
+}
+
+impl Summary {
+    /// Aggregate the histories of an ensemble, after the given number of warm-up epochs. A history
+    /// that is shorter than the longest (because its run was aborted) holds its last state for the
+    /// epochs that it is missing, so that a run that failed early still counts as failed.
+    pub fn new(histories: &[Vec<State>], percentiles: &[f64], warmup: usize) -> Self {
+        let epochs = histories.iter().map(Vec::len).max().unwrap_or(0);
+        let first_epoch = warmup.min(epochs.saturating_sub(1));
+        Self {
+            runs: histories.len(),
+            percentiles: percentiles.to_vec(),
+            first_epoch,
+            epochs: (first_epoch..epochs)
+                .map(|epoch| {
+                    SERIES
+                        .iter()
+                        .map(|(_, value)| {
+                            let values = histories
+                                .iter()
+                                .map(|history| {
+                                    value(history.get(epoch).unwrap_or_else(|| {
+                                        history.last().expect("missing initial state")
+                                    }))
+                                })
+                                .collect();
+                            Aggregate::new(values, percentiles)
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the summary as CSV, with one row per epoch and series.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        let mut header = vec![
+            "epoch".to_string(),
+            "series".to_string(),
+            "mean".to_string(),
+            "median".to_string(),
+        ];
+        header.extend(self.percentiles.iter().map(|p| format!("p{}", p)));
+        writer.write_record(&header)?;
+        for (epoch, aggregates) in (self.first_epoch..).zip(&self.epochs) {
+            for ((name, _), aggregate) in SERIES.iter().zip(aggregates) {
+                let mut record = vec![
+                    epoch.to_string(),
+                    name.to_string(),
+                    aggregate.mean.to_string(),
+                    aggregate.median.to_string(),
+                ];
+                record.extend(aggregate.percentiles.iter().map(|v| v.to_string()));
+                writer.write_record(&record)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the summary as CSV to a file at the given path (replacing it if it already exists),
+    /// beginning with the metadata of the ensemble.
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, metadata: &Metadata) -> Result<()> {
+        let mut file = File::create(path)?;
+        output::csv::write_metadata(&mut file, metadata)?;
+        self.write_csv(file)
+    }
+}
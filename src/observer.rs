@@ -0,0 +1,33 @@
+//! For watching a run from outside of the simulation loop. An observer is told about every epoch as
+//! soon as it has been simulated, so that logging, live plotting, or metrics of its own can follow
+//! the run without driving it one step at a time. Observers are registered with
+//! `Simulation::with_observer`, and any closure of the epoch and state is an observer:
+//!
+//! ```
+//! # use eco::Simulation;
+//! # use std::cell::Cell;
+//! # use std::rc::Rc;
+//! let peak = Rc::new(Cell::new(0.0));
+//! let mut sim = Simulation::default().with_seed(42).with_observer({
+//!     let peak = Rc::clone(&peak);
+//!     move |_epoch: usize, state: &eco::State| peak.set(state.tvl.0.max(peak.get()))
+//! });
+//! sim.run(365);
+//! assert!(peak.get() > 0.0);
+//! ```
+
+use crate::state::State;
+
+/// Observer is called by the simulation after every epoch that it simulates, with the number of
+/// the epoch (so the first step is epoch one) and the state at the end of it. Warm-up epochs, and
+/// epochs whose state breaks an invariant, are observed like any other.
+pub trait Observer {
+    /// Observe the state at the end of the given epoch.
+    fn on_epoch(&mut self, epoch: usize, state: &State);
+}
+
+impl<F: FnMut(usize, &State)> Observer for F {
+    fn on_epoch(&mut self, epoch: usize, state: &State) {
+        self(epoch, state)
+    }
+}
@@ -0,0 +1,98 @@
+//! For minimising functions of a few parameters without knowing their derivatives, which is what
+//! fitting a simulation to data looks like: every evaluation is a whole run, and the error is
+//! neither smooth nor cheap.
+
+/// Minimum is the best point that a minimisation found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minimum {
+    /// The point.
+    pub x: Vec<f64>,
+    /// The value of the function at the point.
+    pub value: f64,
+    /// The number of times that the function was evaluated.
+    pub evaluations: usize,
+}
+
+/// Minimise `f` with the Nelder-Mead simplex method, starting from `x0` with an initial simplex
+/// that extends `step` along every axis. It stops when the values at the vertices of the simplex
+/// are all within `tolerance` of each other, or after `max_evaluations`. Non-finite values are
+/// treated as infinitely bad, so `f` can reject points by returning `f64::INFINITY`.
+pub fn nelder_mead<F>(
+    mut f: F,
+    x0: &[f64],
+    step: f64,
+    tolerance: f64,
+    max_evaluations: usize,
+) -> Minimum
+where
+    F: FnMut(&[f64]) -> f64,
+{
+    let n = x0.len();
+    let mut evaluations = 0;
+    let mut eval = |x: &[f64], evaluations: &mut usize| {
+        *evaluations += 1;
+        let value = f(x);
+        if value.is_finite() {
+            value
+        } else {
+            f64::INFINITY
+        }
+    };
+
+    let mut simplex: Vec<(Vec<f64>, f64)> = (0..=n)
+        .map(|i| {
+            let mut x = x0.to_vec();
+            if i > 0 {
+                x[i - 1] += step;
+            }
+            let value = eval(&x, &mut evaluations);
+            (x, value)
+        })
+        .collect();
+    // The standard coefficients for reflection, expansion, contraction, and shrinking.
+    let (alpha, gamma, rho, sigma) = (1.0, 2.0, 0.5, 0.5);
+    let towards = |from: &[f64], to: &[f64], t: f64| -> Vec<f64> {
+        from.iter().zip(to).map(|(a, b)| a + t * (b - a)).collect()
+    };
+
+    loop {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        if n == 0 || (worst - best).abs() <= tolerance || evaluations >= max_evaluations {
+            break;
+        }
+
+        // Reflect the worst vertex through the centroid of the others.
+        let centroid: Vec<f64> = (0..n)
+            .map(|j| simplex[..n].iter().map(|(x, _)| x[j]).sum::<f64>() / n as f64)
+            .collect();
+        let reflected = towards(&centroid, &simplex[n].0, -alpha);
+        let r = eval(&reflected, &mut evaluations);
+        if r < best {
+            let expanded = towards(&centroid, &simplex[n].0, -gamma);
+            let e = eval(&expanded, &mut evaluations);
+            simplex[n] = if e < r { (expanded, e) } else { (reflected, r) };
+        } else if r < simplex[n - 1].1 {
+            simplex[n] = (reflected, r);
+        } else {
+            let contracted = towards(&centroid, &simplex[n].0, rho);
+            let c = eval(&contracted, &mut evaluations);
+            if c < worst {
+                simplex[n] = (contracted, c);
+            } else {
+                let best = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    vertex.0 = towards(&best, &vertex.0, sigma);
+                    vertex.1 = eval(&vertex.0, &mut evaluations);
+                }
+            }
+        }
+    }
+
+    let (x, value) = simplex.swap_remove(0);
+    Minimum {
+        x,
+        value,
+        evaluations,
+    }
+}
@@ -0,0 +1,325 @@
+//! For searching the internal-model parameters (fee levels, the rebate share, and how fast the
+//! rebate moves) for the values that maximise (or minimise) one of the outcomes of a run, such as
+//! the cumulative node income, optionally subject to the security ratio never dropping below a
+//! threshold. The search is Nelder-Mead, over a transformed space: parameters with bounds are
+//! mapped onto the whole real line with a logistic function (so that the search cannot leave the
+//! bounds), and parameters without bounds are searched in log space (so that they stay positive).
+//!
+//! Every candidate is run with the same seeds, so that the objective only changes with the
+//! parameters. Unless demand is elastic (see `DemandConfig`), mint and burn volumes do not respond
+//! to fees, so an objective like node income grows with the fees until a constraint (or a bound)
+//! stops it: the bounds matter as much as the objective.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::metrics::Outcome;
+use crate::monte_carlo::run_seed;
+use crate::optim;
+use crate::parallel;
+use crate::simulation::Simulation;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The parameters that are searched when no others are given.
+pub const DEFAULT_PARAMETERS: [&str; 4] = [
+    "internal.mint_fee=0:0.01",
+    "internal.burn_fee=0:0.01",
+    "internal.rebate_share=0:1",
+    "internal.rebate_step",
+];
+
+/// How much worse than any feasible point an infeasible point is. Infeasible points get worse the
+/// further they are from the constraint, so a search that begins outside it moves towards it.
+const INFEASIBLE: f64 = 1e100;
+
+/// How close to a bound the search may begin, as a share of the range between the bounds (the
+/// logistic transform cannot reach the bounds themselves).
+const MARGIN: f64 = 1e-3;
+
+/// Parameter is a parameter to search, and the bounds that it is searched between (if any).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    /// The dotted path of the parameter in the configuration.
+    pub path: String,
+    /// The lowest and highest values of the parameter. Without bounds, the parameter can take any
+    /// positive value.
+    pub bounds: Option<(f64, f64)>,
+}
+
+impl FromStr for Parameter {
+    type Err = Error;
+
+    /// Parse a parameter from `path` (unbounded) or `path=low:high`.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("invalid parameter `{}`", s));
+        let (path, bounds) = match s.split_once('=') {
+            None => (s, None),
+            Some((path, bounds)) => {
+                let (low, high) = bounds.split_once(':').ok_or_else(invalid)?;
+                let low: f64 = low.trim().parse().map_err(|_| invalid())?;
+                let high: f64 = high.trim().parse().map_err(|_| invalid())?;
+                if !(low.is_finite() && high.is_finite() && low < high) {
+                    return Err(invalid());
+                }
+                (path, Some((low, high)))
+            }
+        };
+        Ok(Self {
+            path: path.trim().to_string(),
+            bounds,
+        })
+    }
+}
+
+impl Parameter {
+    /// Map a value of the parameter into the space that is searched.
+    fn encode(&self, value: f64) -> f64 {
+        match self.bounds {
+            Some((low, high)) => {
+                let share = ((value - low) / (high - low)).clamp(MARGIN, 1.0 - MARGIN);
+                (share / (1.0 - share)).ln()
+            }
+            None => value.ln(),
+        }
+    }
+
+    /// Map a point in the space that is searched back to a value of the parameter.
+    fn decode(&self, x: f64) -> f64 {
+        match self.bounds {
+            Some((low, high)) => low + (high - low) / (1.0 + (-x).exp()),
+            None => x.exp(),
+        }
+    }
+}
+
+/// Objective is what the search optimises.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Objective {
+    /// The outcome to optimise, by its name in `Outcome::FIELDS`. Outcomes are averaged across
+    /// runs.
+    pub metric: String,
+    /// Whether the outcome is minimised rather than maximised.
+    pub minimise: bool,
+    /// The lowest that the security ratio may drop in any run, if it is constrained.
+    pub min_security_ratio: Option<f64>,
+}
+
+impl Default for Objective {
+    fn default() -> Self {
+        Self {
+            metric: "node_income".to_string(),
+            minimise: false,
+            min_security_ratio: None,
+        }
+    }
+}
+
+/// Evaluation is how one configuration did against the objective.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Evaluation {
+    /// The outcome that is optimised, averaged across runs.
+    pub value: f64,
+    /// The lowest security ratio in any run.
+    pub min_security_ratio: f64,
+    /// Whether every run met the constraint.
+    pub feasible: bool,
+}
+
+/// Tuned is the optimised value of one parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuned {
+    /// The dotted path of the parameter in the configuration.
+    pub path: String,
+    /// The value before optimisation.
+    pub initial: f64,
+    /// The optimised value.
+    pub value: f64,
+}
+
+/// Optimum is the optimised configuration, and how it compares with the configuration that the
+/// search began from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Optimum {
+    /// The configuration with every optimised parameter replaced.
+    pub config: Config,
+    pub objective: Objective,
+    pub parameters: Vec<Tuned>,
+    /// How the initial configuration did, or `None` if a run broke an invariant.
+    pub initial: Option<Evaluation>,
+    /// How the optimised configuration did, or `None` if a run broke an invariant.
+    pub best: Option<Evaluation>,
+    /// The number of configurations that were evaluated.
+    pub evaluations: usize,
+}
+
+impl Optimum {
+    /// Write a human readable description of the optimum.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        for tuned in &self.parameters {
+            writeln!(
+                writer,
+                "{:<24} {} -> {}",
+                tuned.path, tuned.initial, tuned.value
+            )?;
+        }
+        let describe = |evaluation: &Option<Evaluation>| match evaluation {
+            Some(e) => format!(
+                "{} {:.4}, min_security_ratio {:.4}{}",
+                self.objective.metric,
+                e.value,
+                e.min_security_ratio,
+                if e.feasible { "" } else { " (infeasible)" }
+            ),
+            None => "breaks an invariant".to_string(),
+        };
+        writeln!(writer, "initial: {}", describe(&self.initial))?;
+        writeln!(writer, "optimum: {}", describe(&self.best))?;
+        writeln!(writer, "after {} evaluations", self.evaluations)?;
+        Ok(())
+    }
+}
+
+/// Run the configuration with every seed, and evaluate the outcomes against the objective, where
+/// `metric` is the position of the optimised outcome in `Outcome::FIELDS`. Returns `None` if any
+/// run breaks an invariant.
+fn evaluate(
+    config: &Config,
+    objective: &Objective,
+    metric: usize,
+    seeds: &[u64],
+) -> Option<Evaluation> {
+    let outcomes = parallel::map(seeds.to_vec(), |seed| {
+        let mut sim = Simulation::from_config(config).with_seed(seed);
+        sim.try_run(config.steps).ok()?;
+        Some(Outcome::from_aggregates(sim.history().aggregates()))
+    })
+    .into_iter()
+    .collect::<Option<Vec<_>>>()?;
+    let value = outcomes.iter().map(|o| o.values()[metric]).sum::<f64>() / outcomes.len() as f64;
+    let min_security_ratio = outcomes
+        .iter()
+        .map(|o| o.min_security_ratio)
+        .fold(f64::INFINITY, f64::min);
+    let feasible = objective
+        .min_security_ratio
+        .is_none_or(|min| min_security_ratio >= min);
+    Some(Evaluation {
+        value,
+        min_security_ratio,
+        feasible,
+    })
+}
+
+/// Search the parameters for the values that best meet the objective, averaging every candidate
+/// over `runs` runs (run `i` uses the base seed plus `i`, as in an ensemble), and giving up after
+/// `max_evaluations` candidates. Parameters must be continuous, and parameters without bounds must
+/// be positive.
+pub fn run(
+    config: &Config,
+    parameters: &[Parameter],
+    objective: &Objective,
+    runs: usize,
+    seed: u64,
+    max_evaluations: usize,
+) -> Result<Optimum> {
+    let metric = Outcome::FIELDS
+        .iter()
+        .position(|field| *field == objective.metric)
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "unknown objective `{}` (expected one of {})",
+                objective.metric,
+                Outcome::FIELDS.join(", ")
+            ))
+        })?;
+    let initial: Vec<f64> = parameters
+        .iter()
+        .map(|parameter| {
+            let value = config.get(&parameter.path)?;
+            if config.is_integer(&parameter.path)? {
+                return Err(Error::Config(format!(
+                    "parameter `{}` must be continuous to be optimised",
+                    parameter.path
+                )));
+            }
+            if parameter.bounds.is_none() && value <= 0.0 {
+                return Err(Error::Config(format!(
+                    "parameter `{}` must be positive to be optimised without bounds",
+                    parameter.path
+                )));
+            }
+            Ok(value)
+        })
+        .collect::<Result<_>>()?;
+
+    let seeds: Vec<u64> = (0..runs.max(1)).map(|i| run_seed(seed, i)).collect();
+    let start = evaluate(config, objective, metric, &seeds);
+    // Feasible points are scored relative to the initial value, so that the tolerance of the
+    // search does not depend on the scale of the outcome.
+    let scale = start
+        .map(|e| e.value.abs())
+        .filter(|scale| scale.is_finite() && *scale > 0.0)
+        .unwrap_or(1.0);
+    let sign = if objective.minimise { 1.0 } else { -1.0 };
+    let score = |evaluation: Option<Evaluation>| match evaluation {
+        Some(e) if e.feasible => sign * e.value / scale,
+        Some(e) => {
+            let min = objective.min_security_ratio.unwrap_or(0.0);
+            INFEASIBLE * (1.0 + (min - e.min_security_ratio).max(0.0))
+        }
+        None => f64::INFINITY,
+    };
+    let candidate = |x: &[f64]| -> Result<Config> {
+        let mut candidate = config.clone();
+        for (parameter, x) in parameters.iter().zip(x) {
+            candidate.set(&parameter.path, parameter.decode(*x))?;
+        }
+        Ok(candidate)
+    };
+
+    let x0: Vec<f64> = parameters
+        .iter()
+        .zip(&initial)
+        .map(|(parameter, value)| parameter.encode(*value))
+        .collect();
+    let minimum = optim::nelder_mead(
+        |x| match candidate(x) {
+            Ok(config) => score(evaluate(&config, objective, metric, &seeds)),
+            Err(_) => f64::INFINITY,
+        },
+        &x0,
+        0.5,
+        1e-6,
+        max_evaluations,
+    );
+    // Keep the initial configuration if the search found nothing better.
+    let (config, best, values) = if minimum.value < score(start) {
+        let optimised = candidate(&minimum.x)?;
+        let best = evaluate(&optimised, objective, metric, &seeds);
+        let values = parameters
+            .iter()
+            .zip(&minimum.x)
+            .map(|(parameter, x)| parameter.decode(*x))
+            .collect();
+        (optimised, best, values)
+    } else {
+        (config.clone(), start, initial.clone())
+    };
+    Ok(Optimum {
+        config,
+        objective: objective.clone(),
+        parameters: parameters
+            .iter()
+            .zip(initial)
+            .zip(values)
+            .map(|((parameter, initial), value)| Tuned {
+                path: parameter.path.clone(),
+                initial,
+                value,
+            })
+            .collect(),
+        initial: start,
+        best,
+        evaluations: minimum.evaluations + 1,
+    })
+}
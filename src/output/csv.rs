@@ -0,0 +1,164 @@
+//! Writes the history of a simulation as CSV, with one row per epoch and one column per `State`
+//! field. The file begins with the metadata of the run, as a block of `# <field>: <value>` comment
+//! lines (see `write_metadata`). The first row after it is a header, and the first column is the
+//! epoch (where epoch zero is the initial state, or the end of the warm-up). When the epochs have a
+//! start date, the second column is the date on which each epoch ends. Per-asset fields follow the
+//! aggregate ones, as `<asset>_<field>` columns, per-shard fields follow those, as
+//! `shard<i>_<field>` columns, and the diagnostics of the fee controller come last, as
+//! `controller_<field>` columns.
+
+use super::{Metadata, Sink, FLUSH_EVERY};
+use crate::config::EpochConfig;
+use crate::error::{Error, Result};
+use crate::state::{AssetState, ControllerState, ShardState, State};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Writer writes the states of a run as CSV, one row at a time.
+pub struct Writer<W: Write> {
+    writer: ::csv::Writer<W>,
+    epoch: EpochConfig,
+    assets: usize,
+    shards: usize,
+    controlled: bool,
+    flush_every: usize,
+    unflushed: usize,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the metadata and header to the writer, using the metadata to label dates and assets.
+    pub fn new(mut writer: W, metadata: &Metadata) -> Result<Self> {
+        write_metadata(&mut writer, metadata)?;
+        let epoch = metadata.config.epoch;
+        let assets: Vec<&str> = metadata
+            .config
+            .external
+            .assets
+            .iter()
+            .map(|asset| asset.name.as_str())
+            .collect();
+        let shards = metadata.config.internal.shards.count;
+        let controlled = metadata.config.internal.fee_controller.is_some();
+
+        let mut writer = ::csv::Writer::from_writer(writer);
+        let header = std::iter::once("epoch".to_string())
+            .chain(epoch.start.map(|_| "date".to_string()))
+            .chain(State::FIELDS.iter().map(|field| field.to_string()))
+            .chain(assets.iter().flat_map(|asset| {
+                AssetState::FIELDS
+                    .iter()
+                    .map(move |field| format!("{}_{}", asset, field))
+            }))
+            .chain((0..shards).flat_map(|i| {
+                ShardState::FIELDS
+                    .iter()
+                    .map(move |field| format!("shard{}_{}", i, field))
+            }))
+            .chain(
+                ControllerState::FIELDS
+                    .iter()
+                    .filter(|_| controlled)
+                    .map(|field| format!("controller_{}", field)),
+            );
+        writer.write_record(header)?;
+        Ok(Self {
+            writer,
+            epoch,
+            assets: assets.len(),
+            shards,
+            controlled,
+            flush_every: FLUSH_EVERY,
+            unflushed: 0,
+        })
+    }
+
+    /// Flush after every this many rows (or only when asked to, if zero).
+    pub fn with_flush_every(mut self, flush_every: usize) -> Self {
+        self.flush_every = flush_every;
+        self
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    fn write(&mut self, epoch: usize, state: &State) -> Result<()> {
+        // The initial state may not describe any assets or shards, in which case they are all zero.
+        let asset_values = (0..self.assets)
+            .flat_map(|i| state.assets.get(i).copied().unwrap_or_default().values());
+        let shard_values = (0..self.shards)
+            .flat_map(|i| state.shards.get(i).copied().unwrap_or_default().values());
+        let controller_values = Some(state.controller.unwrap_or_default())
+            .filter(|_| self.controlled)
+            .into_iter()
+            .flat_map(|controller| controller.values());
+        self.writer.write_record(
+            std::iter::once(epoch.to_string())
+                .chain(self.epoch.date(epoch).map(|date| date.to_string()))
+                .chain(state.values().iter().map(|value| value.to_string()))
+                .chain(asset_values.map(|value| value.to_string()))
+                .chain(shard_values.map(|value| value.to_string()))
+                .chain(controller_values.map(|value| value.to_string())),
+        )?;
+        self.unflushed += 1;
+        if self.unflushed == self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.unflushed = 0;
+        Ok(())
+    }
+}
+
+/// Write the fields that identify a run (see `Metadata::fields`) to the writer, as one `# <field>:
+/// <value>` comment line each. CSV files of every kind begin with this block, so that a file that
+/// is found on its own says which version, commit, seed, configuration, and scenario produced it,
+/// and when. Readers skip the block by treating lines that begin with `#` as comments.
+pub fn write_metadata<W: Write>(writer: &mut W, metadata: &Metadata) -> Result<()> {
+    for (field, value) in metadata.fields() {
+        writeln!(writer, "# {}: {}", field, value)?;
+    }
+    Ok(())
+}
+
+/// Write the history to the writer as CSV, using the metadata to label dates and assets.
+pub fn write<W: Write>(writer: W, metadata: &Metadata, history: &[State]) -> Result<()> {
+    let mut writer = Writer::new(writer, metadata)?;
+    super::write_history(&mut writer, metadata, history)
+}
+
+/// Read the states back from CSV that was written by `write`, with the epoch of every state.
+/// Per-asset and per-shard columns are not read, so the states only describe locked value in
+/// aggregate.
+pub fn read<R: Read>(reader: R) -> Result<Vec<(usize, State)>> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let epoch = headers
+        .iter()
+        .position(|header| header == "epoch")
+        .ok_or_else(|| Error::Config("missing `epoch` column".to_string()))?;
+    reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            let invalid = || Error::Config(format!("invalid epoch `{}`", &record[epoch]));
+            let epoch = record[epoch].parse().map_err(|_| invalid())?;
+            Ok((epoch, record.deserialize(Some(&headers))?))
+        })
+        .collect()
+}
+
+/// Read the states back from a CSV file at the given path.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<(usize, State)>> {
+    read(File::open(path)?)
+}
+
+/// Write the history to a CSV file at the given path, replacing it if it already exists.
+pub fn write_file<P: AsRef<Path>>(path: P, metadata: &Metadata, history: &[State]) -> Result<()> {
+    write(File::create(path)?, metadata, history)
+}
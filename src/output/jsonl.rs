@@ -0,0 +1,115 @@
+//! Writes a simulation as JSON Lines. The first line is a header record containing the `Metadata`
+//! of the run, and every following line is an epoch record containing the epoch (where epoch zero
+//! is the initial state, and warm-up epochs are left out), the date on which it ends (if the epochs
+//! have a start date), and every `State` field. Every record has a `type` field (either `header` or
+//! `epoch`) so that the two kinds of record can be told apart.
+
+use super::{Metadata, Sink, FLUSH_EVERY};
+use crate::config::EpochConfig;
+use crate::error::Result;
+use crate::state::State;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    Header(&'a Metadata),
+    Epoch {
+        epoch: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        date: Option<NaiveDate>,
+        #[serde(flatten)]
+        state: &'a State,
+    },
+}
+
+/// Writer writes the states of a run as JSON Lines, one epoch record at a time.
+pub struct Writer<W: Write> {
+    writer: BufWriter<W>,
+    epoch: EpochConfig,
+    flush_every: usize,
+    unflushed: usize,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the header record to the writer.
+    pub fn new(writer: W, metadata: &Metadata) -> Result<Self> {
+        let mut writer = BufWriter::new(writer);
+        serde_json::to_writer(&mut writer, &Record::Header(metadata))?;
+        writeln!(writer)?;
+        Ok(Self {
+            writer,
+            epoch: metadata.config.epoch,
+            flush_every: FLUSH_EVERY,
+            unflushed: 0,
+        })
+    }
+
+    /// Flush after every this many records (or only when asked to, if zero).
+    pub fn with_flush_every(mut self, flush_every: usize) -> Self {
+        self.flush_every = flush_every;
+        self
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    fn write(&mut self, epoch: usize, state: &State) -> Result<()> {
+        let date = self.epoch.date(epoch);
+        serde_json::to_writer(&mut self.writer, &Record::Epoch { epoch, date, state })?;
+        writeln!(self.writer)?;
+        self.unflushed += 1;
+        if self.unflushed == self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.unflushed = 0;
+        Ok(())
+    }
+}
+
+/// Write the metadata and history to the writer as JSON Lines.
+pub fn write<W: Write>(writer: W, metadata: &Metadata, history: &[State]) -> Result<()> {
+    let mut writer = Writer::new(writer, metadata)?;
+    super::write_history(&mut writer, metadata, history)
+}
+
+/// An epoch record, as it is read back.
+#[derive(Deserialize)]
+struct EpochRecord {
+    epoch: usize,
+    #[serde(flatten)]
+    state: State,
+}
+
+/// Read the states back from JSON Lines that were written by `write`, with the epoch of every
+/// state. The header is skipped.
+pub fn read<R: Read>(reader: R) -> Result<Vec<(usize, State)>> {
+    let mut states = vec![];
+    for line in BufReader::new(reader).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line?)?;
+        if value.get("type").and_then(|kind| kind.as_str()) == Some("epoch") {
+            let record: EpochRecord = serde_json::from_value(value)?;
+            states.push((record.epoch, record.state));
+        }
+    }
+    Ok(states)
+}
+
+/// Read the states back from a JSON Lines file at the given path.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<(usize, State)>> {
+    read(File::open(path)?)
+}
+
+/// Write the metadata and history to a JSON Lines file at the given path, replacing it if it
+/// already exists.
+pub fn write_file<P: AsRef<Path>>(path: P, metadata: &Metadata, history: &[State]) -> Result<()> {
+    write(File::create(path)?, metadata, history)
+}
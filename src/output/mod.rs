@@ -0,0 +1,279 @@
+//! For writing the results of a simulation somewhere that they can be analysed. Results can be
+//! written once a run is complete, or streamed one epoch at a time as the run goes (so that memory
+//! stays flat, and whatever was written survives if the run is interrupted).
+
+pub mod csv;
+pub mod jsonl;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::state::State;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+/// The number of epochs after which streaming writers flush what they have written, by default.
+pub const FLUSH_EVERY: usize = 100;
+
+/// Sink receives the states of a run one epoch at a time, as they are produced. Sinks flush
+/// periodically on their own, and must be finished once the run is complete.
+pub trait Sink {
+    /// Write the state at the end of the given epoch.
+    fn write(&mut self, epoch: usize, state: &State) -> Result<()>;
+
+    /// Flush everything that has been written so far.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Complete the output once the run is over. For most formats, this is the same as flushing.
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+/// Metadata describes how a run was produced, so that its results can be interpreted (and
+/// reproduced) without having to guess which parameters were used. Every output file is stamped
+/// with it, so that a file that has been passed around can still be traced back to its run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The version of this crate that produced the run.
+    pub version: String,
+    /// The git commit that the crate was built from, if it was built from a git checkout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The seed of the random number generator.
+    pub seed: u64,
+    /// A hash of the configuration (but not of the seed) as 16 hex digits, so that the runs of the
+    /// same configuration can be grouped together whatever their seeds. The hash is FNV-1a of the
+    /// configuration as JSON, which stays the same from one build to the next.
+    #[serde(default)]
+    pub config_hash: String,
+    /// The named scenario that the configuration began from, if it began from one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    /// When the run started (to the second), if the clock could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<DateTime<Utc>>,
+    /// The configuration that the run was produced with.
+    pub config: Config,
+}
+
+impl Metadata {
+    /// Create the metadata for a run of the given configuration and seed that starts now, using
+    /// the current crate version and commit.
+    pub fn new(config: &Config, seed: u64) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: option_env!("ECON_SIM_COMMIT").map(str::to_string),
+            seed,
+            config_hash: config_hash(config),
+            scenario: None,
+            started: now(),
+            config: config.clone(),
+        }
+    }
+
+    /// Name the scenario that the configuration began from.
+    pub fn with_scenario(mut self, scenario: &str) -> Self {
+        self.scenario = Some(scenario.to_string());
+        self
+    }
+
+    /// The fields that identify the run (everything but the configuration itself, which its hash
+    /// stands in for), as names and values, in the order that they are declared.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("version", self.version.clone())];
+        fields.extend(self.commit.clone().map(|commit| ("commit", commit)));
+        fields.push(("seed", self.seed.to_string()));
+        fields.push(("config_hash", self.config_hash.clone()));
+        fields.extend(self.scenario.clone().map(|scenario| ("scenario", scenario)));
+        fields.extend(self.started.map(|started| {
+            (
+                "started",
+                started.to_rfc3339_opts(SecondsFormat::Secs, true),
+            )
+        }));
+        fields
+    }
+}
+
+/// The hash of the configuration with its seed left out (see `Metadata::config_hash`).
+fn config_hash(config: &Config) -> String {
+    let config = Config {
+        seed: None,
+        ..config.clone()
+    };
+    let hash = serde_json::to_string(&config)
+        .expect("configurations serialize")
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The current time to the second. The clock cannot be read in the browser, so runs there have no
+/// start time.
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Option<DateTime<Utc>> {
+    use chrono::SubsecRound;
+    Some(DateTime::<Utc>::from(std::time::SystemTime::now()).trunc_subsecs(0))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> Option<DateTime<Utc>> {
+    None
+}
+
+/// Format is the format of a result file, as chosen by its extension: `.jsonl` and `.ndjson` are
+/// JSON Lines, `.parquet` is Parquet, `.sqlite` and `.db` are a SQLite store, and everything else
+/// is CSV.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Jsonl,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Format {
+    /// The format of the file at the given path. Formats whose feature is not enabled are an error,
+    /// rather than quietly being written as CSV.
+    fn of(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("ndjson") => Ok(Format::Jsonl),
+            #[cfg(feature = "parquet")]
+            Some("parquet") => Ok(Format::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            Some("parquet") => Err(missing("Parquet", "parquet")),
+            #[cfg(feature = "sqlite")]
+            Some("sqlite") | Some("db") => Ok(Format::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            Some("sqlite") | Some("db") => Err(missing("SQLite", "sqlite")),
+            _ => Ok(Format::Csv),
+        }
+    }
+}
+
+/// The error for a file whose format needs a feature that is not enabled.
+#[cfg(not(all(feature = "parquet", feature = "sqlite")))]
+fn missing(format: &str, feature: &str) -> Error {
+    Error::Config(format!("{} files need the `{}` feature", format, feature))
+}
+
+/// Read the states (with their epochs) back from a file that was written by `write_file`, choosing
+/// the format from the file extension in the same way. A SQLite store holds many runs, so it is
+/// queried with SQL instead.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<(usize, State)>> {
+    let path = path.as_ref();
+    match Format::of(path)? {
+        Format::Csv => csv::read_file(path),
+        Format::Jsonl => jsonl::read_file(path),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::read_file(path),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => Err(Error::Config(format!(
+            "{} holds many runs, so read them with SQL",
+            path.display()
+        ))),
+    }
+}
+
+/// Create a streaming writer for a file at the given path (replacing it if it already exists, or
+/// appending a run to it if it is a SQLite store), and write the metadata to it. The format is
+/// chosen from the file extension in the same way as `write_file`.
+pub fn create_file<P: AsRef<Path>>(
+    path: P,
+    metadata: &Metadata,
+    flush_every: usize,
+) -> Result<Box<dyn Sink>> {
+    let path = path.as_ref();
+    Ok(match Format::of(path)? {
+        Format::Csv => {
+            Box::new(csv::Writer::new(File::create(path)?, metadata)?.with_flush_every(flush_every))
+        }
+        Format::Jsonl => Box::new(
+            jsonl::Writer::new(File::create(path)?, metadata)?.with_flush_every(flush_every),
+        ),
+        // Parquet files are unreadable until they are complete, so there is no point flushing
+        // them early.
+        #[cfg(feature = "parquet")]
+        Format::Parquet => Box::new(parquet::Writer::new(File::create(path)?, metadata)?),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => {
+            Box::new(sqlite::Writer::new(path, metadata)?.with_flush_every(flush_every))
+        }
+    })
+}
+
+/// Write a whole history to a sink and flush it. Warm-up epochs are left out, and the state at the
+/// end of the warm-up takes the place of the initial state.
+fn write_history(sink: &mut dyn Sink, metadata: &Metadata, history: &[State]) -> Result<()> {
+    let warmup = metadata.config.warmup.min(history.len().saturating_sub(1));
+    for (epoch, state) in history.iter().enumerate().skip(warmup) {
+        sink.write(epoch, state)?;
+    }
+    sink.finish()
+}
+
+/// Write the metadata and history to a file at the given path, choosing the format from the file
+/// extension (`.jsonl` and `.ndjson` are JSON Lines, `.parquet` is Parquet, `.sqlite` and `.db`
+/// are a SQLite store that the run is appended to, and everything else is CSV).
+pub fn write_file<P: AsRef<Path>>(path: P, metadata: &Metadata, history: &[State]) -> Result<()> {
+    let path = path.as_ref();
+    match Format::of(path)? {
+        Format::Csv => csv::write_file(path, metadata, history),
+        Format::Jsonl => jsonl::write_file(path, metadata, history),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::write_file(path, metadata, history),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => sqlite::write_file(path, metadata, history),
+    }
+}
+
+/// Write the histories of every run in an ensemble (where run `i` was seeded with the seed of the
+/// metadata plus `i`) to a file at the given path. Only the formats that can hold many runs can be
+/// written: Parquet, with the index of every run in a `run` column, and a SQLite store, with every
+/// run appended as a run of its own.
+#[cfg_attr(
+    not(any(feature = "parquet", feature = "sqlite")),
+    allow(unused_variables)
+)]
+pub fn write_runs_file<P: AsRef<Path>>(
+    path: P,
+    metadata: &Metadata,
+    histories: &[Vec<State>],
+) -> Result<()> {
+    let path = path.as_ref();
+    match runs_format(path)? {
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::write_runs_file(path, metadata, histories),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => sqlite::write_runs_file(path, metadata, histories),
+        _ => unreachable!("only formats that hold many runs are checked"),
+    }
+}
+
+/// Check that the runs of an ensemble can be written to a file at the given path (by
+/// `write_runs_file`), before spending the time to simulate them.
+pub fn check_runs_file<P: AsRef<Path>>(path: P) -> Result<()> {
+    runs_format(path.as_ref()).map(|_| ())
+}
+
+fn runs_format(path: &Path) -> Result<Format> {
+    match Format::of(path)? {
+        Format::Csv | Format::Jsonl => Err(Error::Config(format!(
+            "the runs of an ensemble can only be written to Parquet (`.parquet`) or SQLite \
+             (`.sqlite` or `.db`) files, not {}",
+            path.display()
+        ))),
+        #[cfg(any(feature = "parquet", feature = "sqlite"))]
+        format => Ok(format),
+    }
+}
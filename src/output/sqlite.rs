@@ -0,0 +1,295 @@
+//! Appends runs to a SQLite database, so that the runs of many experiments (every configuration of
+//! a study, every seed of an ensemble) can be queried together with SQL rather than by stitching
+//! files together. The database is created on first use, and has four tables:
+//!
+//! - `runs`: one row per run, with its `id`, the `config_hash` of its configuration (see
+//!   `Metadata::config_hash`), the crate `version`, the `seed` (as text, since seeds do not fit
+//!   SQLite's signed integers), the whole `config` as JSON, the time it was `created` (in seconds
+//!   since the Unix epoch), and the `git_commit` and `scenario` that it was produced from (null
+//!   when they are not known). Stores that were created before the last two columns existed gain
+//!   them when they are next opened.
+//! - `epochs`: one row per run and epoch, keyed by `run_id` and `epoch`, with the `config_hash` of
+//!   the run, the `date` (if the epochs have a start date), every `State` field, and the
+//!   diagnostics of the fee controller (null when there is none) as `controller_<field>` columns.
+//! - `assets`: one row per run, epoch, and locked `asset`, with every per-asset field.
+//! - `shards`: one row per run, epoch, and `shard`, with every per-shard field.
+//!
+//! Rows are written in a transaction that is committed whenever the writer flushes, so that a run
+//! that is interrupted keeps what had been flushed.
+
+use super::{Metadata, Sink, FLUSH_EVERY};
+use crate::config::EpochConfig;
+use crate::error::Result;
+use crate::monte_carlo;
+use crate::state::{AssetState, ControllerState, ShardState, State};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writer appends the states of runs to a SQLite database, one epoch at a time.
+pub struct Writer {
+    connection: Connection,
+    metadata: String,
+    version: String,
+    commit: Option<String>,
+    scenario: Option<String>,
+    config_hash: String,
+    epoch: EpochConfig,
+    assets: Vec<String>,
+    run: i64,
+    flush_every: usize,
+    unflushed: usize,
+}
+
+impl Writer {
+    /// Open the database at the given path (creating it and its tables if they do not exist yet),
+    /// and append a run with the given metadata to it.
+    pub fn new<P: AsRef<Path>>(path: P, metadata: &Metadata) -> Result<Self> {
+        let mut writer = Self::open(path, metadata)?;
+        writer.begin_run(metadata.seed)?;
+        Ok(writer)
+    }
+
+    /// Open the database without appending a run yet (see `begin_run`).
+    fn open<P: AsRef<Path>>(path: P, metadata: &Metadata) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(&schema())?;
+        migrate(&connection)?;
+        connection.execute_batch("BEGIN")?;
+        Ok(Self {
+            connection,
+            metadata: serde_json::to_string(&metadata.config)?,
+            version: metadata.version.clone(),
+            commit: metadata.commit.clone(),
+            scenario: metadata.scenario.clone(),
+            config_hash: metadata.config_hash.clone(),
+            epoch: metadata.config.epoch,
+            assets: metadata
+                .config
+                .external
+                .assets
+                .iter()
+                .map(|asset| asset.name.clone())
+                .collect(),
+            run: 0,
+            flush_every: FLUSH_EVERY,
+            unflushed: 0,
+        })
+    }
+
+    /// Flush after every this many epochs (or only when asked to, if zero).
+    pub fn with_flush_every(mut self, flush_every: usize) -> Self {
+        self.flush_every = flush_every;
+        self
+    }
+
+    /// The id of the run that states are being written to, in the `runs` table.
+    pub fn run(&self) -> i64 {
+        self.run
+    }
+
+    /// Append a new run with the given seed (and the configuration of the metadata), which the
+    /// states that are written from now on belong to.
+    pub fn begin_run(&mut self, seed: u64) -> Result<()> {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        self.connection.execute(
+            "INSERT INTO runs (config_hash, version, seed, config, created, git_commit, scenario) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                self.config_hash,
+                self.version,
+                seed.to_string(),
+                self.metadata,
+                created,
+                self.commit,
+                self.scenario
+            ],
+        )?;
+        self.run = self.connection.last_insert_rowid();
+        Ok(())
+    }
+}
+
+impl Sink for Writer {
+    fn write(&mut self, epoch: usize, state: &State) -> Result<()> {
+        let controller = state.controller.map(|controller| controller.values());
+        let mut row = vec![
+            Value::Integer(self.run),
+            Value::Text(self.config_hash.clone()),
+            Value::Integer(epoch as i64),
+            self.epoch
+                .date(epoch)
+                .map_or(Value::Null, |date| Value::Text(date.to_string())),
+        ];
+        row.extend(
+            State::FIELDS
+                .iter()
+                .zip(state.values())
+                .map(|(field, value)| match *field {
+                    "darknodes" => Value::Integer(value as i64),
+                    _ => Value::Real(value),
+                }),
+        );
+        row.extend(
+            (0..ControllerState::FIELDS.len())
+                .map(|i| controller.map_or(Value::Null, |values| Value::Real(values[i]))),
+        );
+        self.connection
+            .prepare_cached(&insert("epochs", row.len()))?
+            .execute(params_from_iter(row))?;
+        for (name, asset) in self.assets.iter().zip(&state.assets) {
+            let mut row = vec![
+                Value::Integer(self.run),
+                Value::Integer(epoch as i64),
+                Value::Text(name.clone()),
+            ];
+            row.extend(asset.values().map(Value::Real));
+            self.connection
+                .prepare_cached(&insert("assets", row.len()))?
+                .execute(params_from_iter(row))?;
+        }
+        for (i, shard) in state.shards.iter().enumerate() {
+            let mut row = vec![
+                Value::Integer(self.run),
+                Value::Integer(epoch as i64),
+                Value::Integer(i as i64),
+            ];
+            row.extend(shard.values().map(Value::Real));
+            self.connection
+                .prepare_cached(&insert("shards", row.len()))?
+                .execute(params_from_iter(row))?;
+        }
+
+        self.unflushed += 1;
+        if self.unflushed == self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Commit everything that has been written so far, and carry on in a new transaction.
+    fn flush(&mut self) -> Result<()> {
+        self.connection.execute_batch("COMMIT; BEGIN")?;
+        self.unflushed = 0;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        self.unflushed = 0;
+        Ok(())
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // A run that stops early keeps what it wrote, as it would in a CSV file. Errors cannot be
+        // reported from here; `finish` reports them when it is called explicitly.
+        let _ = self.finish();
+    }
+}
+
+/// The statements that create the tables (and indices) that do not exist yet.
+fn schema() -> String {
+    let columns = |fields: &[&str]| -> String {
+        fields
+            .iter()
+            .map(|field| match *field {
+                "darknodes" => format!(",\n    {} INTEGER NOT NULL", field),
+                field => format!(",\n    {} REAL NOT NULL", field),
+            })
+            .collect()
+    };
+    let controller: String = ControllerState::FIELDS
+        .iter()
+        .map(|field| format!(",\n    controller_{} REAL", field))
+        .collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    config_hash TEXT NOT NULL,
+    version TEXT NOT NULL,
+    seed TEXT NOT NULL,
+    config TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    git_commit TEXT,
+    scenario TEXT
+);
+CREATE INDEX IF NOT EXISTS runs_config_hash ON runs (config_hash);
+CREATE TABLE IF NOT EXISTS epochs (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    config_hash TEXT NOT NULL,
+    epoch INTEGER NOT NULL,
+    date TEXT{}{},
+    PRIMARY KEY (run_id, epoch)
+);
+CREATE INDEX IF NOT EXISTS epochs_config_hash ON epochs (config_hash, epoch);
+CREATE TABLE IF NOT EXISTS assets (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    epoch INTEGER NOT NULL,
+    asset TEXT NOT NULL{},
+    PRIMARY KEY (run_id, epoch, asset)
+);
+CREATE TABLE IF NOT EXISTS shards (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    epoch INTEGER NOT NULL,
+    shard INTEGER NOT NULL{},
+    PRIMARY KEY (run_id, epoch, shard)
+);",
+        columns(&State::FIELDS),
+        controller,
+        columns(&AssetState::FIELDS),
+        columns(&ShardState::FIELDS),
+    )
+}
+
+/// Add the columns of the `runs` table that a store created by an older version does not have yet.
+fn migrate(connection: &Connection) -> Result<()> {
+    let columns = connection
+        .prepare("SELECT name FROM pragma_table_info('runs')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for column in ["git_commit", "scenario"] {
+        if !columns.iter().any(|name| name == column) {
+            connection.execute_batch(&format!("ALTER TABLE runs ADD COLUMN {} TEXT", column))?;
+        }
+    }
+    Ok(())
+}
+
+/// The statement that inserts a row of the given number of values into a table, in the order that
+/// its columns were created in.
+fn insert(table: &str, values: usize) -> String {
+    let placeholders = vec!["?"; values].join(", ");
+    format!("INSERT INTO {} VALUES ({})", table, placeholders)
+}
+
+/// Append the history to the database at the given path as a run of its own.
+pub fn write_file<P: AsRef<Path>>(path: P, metadata: &Metadata, history: &[State]) -> Result<()> {
+    let mut writer = Writer::new(path, metadata)?;
+    super::write_history(&mut writer, metadata, history)
+}
+
+/// Append the histories of every run in an ensemble to the database at the given path, each as a
+/// run of its own with the seed that it was simulated with. Warm-up epochs are left out of every
+/// run.
+pub fn write_runs_file<P: AsRef<Path>>(
+    path: P,
+    metadata: &Metadata,
+    histories: &[Vec<State>],
+) -> Result<()> {
+    let mut writer = Writer::open(path, metadata)?;
+    for (run, history) in histories.iter().enumerate() {
+        writer.begin_run(monte_carlo::run_seed(metadata.seed, run))?;
+        let warmup = metadata.config.warmup.min(history.len().saturating_sub(1));
+        for (epoch, state) in history.iter().enumerate().skip(warmup) {
+            writer.write(epoch, state)?;
+        }
+    }
+    writer.finish()
+}
@@ -0,0 +1,59 @@
+//! For spreading independent runs (of an ensemble, a sweep, or a sensitivity analysis) across every
+//! core. Every run builds its own simulation with its own seed, so runs share nothing and their
+//! results do not depend on how they are scheduled. Without the `parallel` feature, runs happen one
+//! after another.
+
+use crate::error::Result;
+
+/// Apply `f` to every item, returning the results in the same order as the items.
+#[cfg(feature = "parallel")]
+pub fn map<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    use rayon::prelude::*;
+    items.into_par_iter().map(f).collect()
+}
+
+/// Apply `f` to every item, returning the results in the same order as the items.
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    items.into_iter().map(f).collect()
+}
+
+/// The number of threads that runs are spread across.
+#[cfg(feature = "parallel")]
+pub fn threads() -> usize {
+    rayon::current_num_threads()
+}
+
+/// The number of threads that runs are spread across, which is always one without the `parallel`
+/// feature.
+#[cfg(not(feature = "parallel"))]
+pub fn threads() -> usize {
+    1
+}
+
+/// Limit the number of threads that runs are spread across (by default, one per core). This can
+/// only be done once, before anything has run in parallel.
+#[cfg(feature = "parallel")]
+pub fn set_threads(threads: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|err| crate::error::Error::Config(err.to_string()))
+}
+
+/// Limit the number of threads that runs are spread across. Without the `parallel` feature there is
+/// only ever one, so this does nothing.
+#[cfg(not(feature = "parallel"))]
+pub fn set_threads(_threads: usize) -> Result<()> {
+    Ok(())
+}
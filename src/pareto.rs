@@ -0,0 +1,248 @@
+//! For mapping the trade-off between objectives that conflict, rather than optimising one of them:
+//! the fee income of nodes, the fees that users pay, and the security margin. Any choice of
+//! parameters is a compromise between them, so instead of a single best point this finds the
+//! Pareto frontier, the points that no other point beats on every objective at once.
+//!
+//! Points are sampled at random between the bounds of every parameter (parameters without bounds
+//! are sampled log-uniformly within a factor of ten of their initial value), along with the initial
+//! configuration, and every point is run with the same seeds so that the objectives only change
+//! with the parameters.
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::metrics::Outcome;
+use crate::monte_carlo::run_seed;
+use crate::optimize::Parameter;
+use crate::parallel;
+use crate::simulation::Simulation;
+use crate::SimRng;
+use rand::{Rng, SeedableRng};
+use std::io::Write;
+
+/// The names of the objectives, in the order of `Point::objectives`.
+pub const OBJECTIVES: [&str; 3] = ["node_revenue", "user_fees", "min_security_ratio"];
+
+/// How far parameters without bounds are sampled from their initial value, as a factor.
+const RANGE: f64 = 10.0;
+
+/// Point is one sampled choice of parameters, and how it did on every objective.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    /// The value of every parameter, in the same order as the parameters.
+    pub params: Vec<f64>,
+    /// The fees earned by nodes after the warm-up, averaged across runs (higher is better).
+    pub node_revenue: f64,
+    /// The fees paid by users after the warm-up, less the rebates paid back to them, averaged
+    /// across runs (lower is better).
+    pub user_fees: f64,
+    /// The lowest security ratio in any run (higher is better).
+    pub min_security_ratio: f64,
+}
+
+impl Point {
+    /// The objectives, in the order of `OBJECTIVES`, all turned so that higher is better.
+    fn objectives(&self) -> [f64; 3] {
+        [self.node_revenue, -self.user_fees, self.min_security_ratio]
+    }
+
+    /// Whether this point is at least as good as the other on every objective, and better on one.
+    pub fn dominates(&self, other: &Point) -> bool {
+        let (a, b) = (self.objectives(), other.objectives());
+        a.iter().zip(&b).all(|(a, b)| a >= b) && a.iter().zip(&b).any(|(a, b)| a > b)
+    }
+}
+
+/// Frontier is every point that was sampled, and which of them are on the Pareto frontier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frontier {
+    /// The dotted paths of the parameters.
+    pub paths: Vec<String>,
+    /// Every point whose runs kept the invariants, beginning with the initial configuration (if
+    /// its runs did).
+    pub points: Vec<Point>,
+    /// Whether each point is on the frontier, in the same order as the points.
+    pub optimal: Vec<bool>,
+    /// The number of points that were left out because a run broke an invariant.
+    pub broken: usize,
+}
+
+impl Frontier {
+    /// The points on the frontier, by descending node revenue.
+    pub fn frontier(&self) -> Vec<&Point> {
+        let mut frontier: Vec<&Point> = self
+            .points
+            .iter()
+            .zip(&self.optimal)
+            .filter(|(_, optimal)| **optimal)
+            .map(|(point, _)| point)
+            .collect();
+        frontier.sort_by(|a, b| b.node_revenue.total_cmp(&a.node_revenue));
+        frontier
+    }
+
+    /// Write the points on the frontier as a table.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        for header in self.paths.iter().map(String::as_str).chain(OBJECTIVES) {
+            write!(writer, "{:>24}", header)?;
+        }
+        writeln!(writer)?;
+        for point in self.frontier() {
+            for value in point.params.iter().chain(&[
+                point.node_revenue,
+                point.user_fees,
+                point.min_security_ratio,
+            ]) {
+                write!(writer, "{:>24.6}", value)?;
+            }
+            writeln!(writer)?;
+        }
+        writeln!(
+            writer,
+            "{} of {} points on the frontier ({} broke an invariant)",
+            self.frontier().len(),
+            self.points.len(),
+            self.broken
+        )?;
+        Ok(())
+    }
+
+    /// Write every point as CSV, with a column for whether it is on the frontier.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        writer.write_record(
+            self.paths
+                .iter()
+                .map(String::as_str)
+                .chain(OBJECTIVES)
+                .chain(["pareto"]),
+        )?;
+        for (point, optimal) in self.points.iter().zip(&self.optimal) {
+            writer.write_record(
+                point
+                    .params
+                    .iter()
+                    .chain(&[
+                        point.node_revenue,
+                        point.user_fees,
+                        point.min_security_ratio,
+                    ])
+                    .map(|v| v.to_string())
+                    .chain([optimal.to_string()]),
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Run the configuration with every seed, or return `None` if any run breaks an invariant.
+fn evaluate(config: &Config, params: Vec<f64>, seeds: &[u64]) -> Option<Point> {
+    let mut revenue = 0.0;
+    let mut fees = 0.0;
+    let mut min_security_ratio = f64::INFINITY;
+    for &seed in seeds {
+        let mut sim = Simulation::from_config(config).with_seed(seed);
+        sim.try_run(config.steps).ok()?;
+        let aggregates = sim.history().aggregates();
+        let (first, last) = (&aggregates.first, &aggregates.last);
+        let earned = (last.f_claimed + last.f_unclaimed - first.f_claimed - first.f_unclaimed).0;
+        // Users pay what nodes earn, what goes to the treasury or buys REN, and what goes into the
+        // rebate pool, and get back what is paid out of it, so what they pay in the end is what
+        // nodes earn, what goes elsewhere, and what the pool has gained.
+        let elsewhere = last.treasury + last.buyback - first.treasury - first.buyback;
+        revenue += earned;
+        fees += earned + (elsewhere + last.r_pool - first.r_pool).0;
+        min_security_ratio =
+            min_security_ratio.min(Outcome::from_aggregates(aggregates).min_security_ratio);
+    }
+    let n = seeds.len() as f64;
+    Some(Point {
+        params,
+        node_revenue: revenue / n,
+        user_fees: fees / n,
+        min_security_ratio,
+    })
+}
+
+/// Find the Pareto frontier of the parameters from `samples` random points (and the initial
+/// configuration), averaging every point over `runs` runs (run `i` uses the base seed plus `i`, as
+/// in an ensemble). The points are drawn with the base seed too, so the whole analysis can be
+/// reproduced from it.
+pub fn run(
+    config: &Config,
+    parameters: &[Parameter],
+    samples: usize,
+    runs: usize,
+    seed: u64,
+) -> Result<Frontier> {
+    run_with_progress(config, parameters, samples, runs, seed, &|| {})
+}
+
+/// Find the frontier like `run`, calling `progress` (from whichever thread ran it) once for every
+/// point, as it is simulated.
+pub fn run_with_progress(
+    config: &Config,
+    parameters: &[Parameter],
+    samples: usize,
+    runs: usize,
+    seed: u64,
+    progress: &(dyn Fn() + Sync),
+) -> Result<Frontier> {
+    let initial: Vec<f64> = parameters
+        .iter()
+        .map(|parameter| {
+            let value = config.get(&parameter.path)?;
+            if config.is_integer(&parameter.path)? {
+                return Err(Error::Config(format!(
+                    "parameter `{}` must be continuous to be sampled",
+                    parameter.path
+                )));
+            }
+            if parameter.bounds.is_none() && value <= 0.0 {
+                return Err(Error::Config(format!(
+                    "parameter `{}` needs bounds, or a positive value to sample around",
+                    parameter.path
+                )));
+            }
+            Ok(value)
+        })
+        .collect::<Result<_>>()?;
+    let mut rng = SimRng::seed_from_u64(seed);
+    let points: Vec<Vec<f64>> = std::iter::once(initial.clone())
+        .chain((0..samples).map(|_| {
+            parameters
+                .iter()
+                .zip(&initial)
+                .map(|(parameter, value)| match parameter.bounds {
+                    Some((low, high)) => rng.gen_range(low..=high),
+                    None => value * RANGE.powf(rng.gen_range(-1.0..=1.0)),
+                })
+                .collect()
+        }))
+        .collect();
+
+    let seeds: Vec<u64> = (0..runs.max(1)).map(|i| run_seed(seed, i)).collect();
+    let evaluated = parallel::map(points, |params| -> Result<Option<Point>> {
+        let mut config = config.clone();
+        for (parameter, value) in parameters.iter().zip(&params) {
+            config.set(&parameter.path, *value)?;
+        }
+        let point = evaluate(&config, params, &seeds);
+        progress();
+        Ok(point)
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>>>()?;
+    let broken = evaluated.iter().filter(|point| point.is_none()).count();
+    let points: Vec<Point> = evaluated.into_iter().flatten().collect();
+    let optimal = points
+        .iter()
+        .map(|point| !points.iter().any(|other| other.dominates(point)))
+        .collect();
+    Ok(Frontier {
+        paths: parameters.iter().map(|p| p.path.clone()).collect(),
+        points,
+        optimal,
+        broken,
+    })
+}
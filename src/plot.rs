@@ -0,0 +1,305 @@
+//! For rendering charts of a simulation, so that runs can be inspected without round-tripping
+//! through a spreadsheet. One image contains three charts stacked on top of each other: TVL vs TVB,
+//! fee income earned by nodes per epoch, and the balance of the rebate pool. Monte Carlo ensembles
+//! are drawn as fan charts instead, with one chart for every aggregated series.
+
+use crate::error::{Error, Result};
+use crate::monte_carlo::{Aggregate, Summary, SERIES};
+use crate::state::State;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::Path;
+
+/// Render charts of the history to an image at the given path. Files ending in `.svg` are written
+/// as SVG, everything else is written as PNG.
+pub fn render<P: AsRef<Path>>(path: P, history: &[State]) -> Result<()> {
+    let path = path.as_ref();
+    let size = (1024, 1200);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("svg") => draw(SVGBackend::new(path, size).into_drawing_area(), history)
+            .map_err(|err| Error::Plot(err.to_string())),
+        _ => draw(BitMapBackend::new(path, size).into_drawing_area(), history)
+            .map_err(|err| Error::Plot(err.to_string())),
+    }
+}
+
+/// Render charts of two runs overlaid on each other (the first in red, the second in blue) to an
+/// image at the given path, in the same formats as `render`. The runs should already be aligned, so
+/// that the same index is the same epoch in both.
+pub fn render_comparison<P: AsRef<Path>>(
+    path: P,
+    labels: [&str; 2],
+    a: &[State],
+    b: &[State],
+) -> Result<()> {
+    let path = path.as_ref();
+    let size = (1024, 1600);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("svg") => draw_comparison(
+            SVGBackend::new(path, size).into_drawing_area(),
+            labels,
+            a,
+            b,
+        )
+        .map_err(|err| Error::Plot(err.to_string())),
+        _ => draw_comparison(
+            BitMapBackend::new(path, size).into_drawing_area(),
+            labels,
+            a,
+            b,
+        )
+        .map_err(|err| Error::Plot(err.to_string())),
+    }
+}
+
+/// The percentiles that bound the bands of a fan chart, from the outermost band in. The median is
+/// drawn as a line in the middle.
+const FAN: [(f64, f64); 2] = [(5.0, 95.0), (25.0, 75.0)];
+
+/// Render fan charts of an ensemble to an image at the given path, in the same formats as
+/// `render`: for every series in `SERIES`, the median across runs at every epoch, within bands from
+/// the 25th to 75th and the 5th to 95th percentiles. Warm-up epochs are left out, and a history
+/// that is shorter than the rest holds its last state (as in `Summary::new`).
+pub fn render_fan<P: AsRef<Path>>(path: P, histories: &[Vec<State>], warmup: usize) -> Result<()> {
+    let percentiles: Vec<f64> = FAN.iter().flat_map(|&(low, high)| [low, high]).collect();
+    let summary = Summary::new(histories, &percentiles, warmup);
+    let path = path.as_ref();
+    let size = (1024, 400 * SERIES.len() as u32);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("svg") => draw_fan(SVGBackend::new(path, size).into_drawing_area(), &summary)
+            .map_err(|err| Error::Plot(err.to_string())),
+        _ => draw_fan(BitMapBackend::new(path, size).into_drawing_area(), &summary)
+            .map_err(|err| Error::Plot(err.to_string())),
+    }
+}
+
+/// A named series of values, one per epoch, drawn in the given colour.
+struct Series {
+    name: String,
+    colour: RGBColor,
+    values: Vec<f64>,
+}
+
+impl Series {
+    fn new(name: &str, colour: RGBColor, history: &[State], f: fn(&State) -> f64) -> Self {
+        Self {
+            name: name.to_string(),
+            colour,
+            values: history.iter().map(f).collect(),
+        }
+    }
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    history: &[State],
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((3, 1));
+
+    // Fee income is the increase in the total fees owed to nodes (claimed or not) since the
+    // previous epoch. The initial state has no income.
+    let income = Series {
+        name: "fee income".to_string(),
+        colour: GREEN,
+        values: std::iter::once(0.0)
+            .chain(history.windows(2).map(|w| {
+                ((w[1].f_claimed + w[1].f_unclaimed) - (w[0].f_claimed + w[0].f_unclaimed)).0
+            }))
+            .collect(),
+    };
+
+    chart(
+        &areas[0],
+        "TVL vs TVB (USD)",
+        &[
+            Series::new("tvl", RED, history, |state| state.tvl.0),
+            Series::new("tvb", BLUE, history, |state| state.tvb.0),
+        ],
+    )?;
+    chart(&areas[1], "Fee income per epoch (USD)", &[income])?;
+    chart(
+        &areas[2],
+        "Rebate pool (USD)",
+        &[Series::new("r_pool", MAGENTA, history, |state| {
+            state.r_pool.0
+        })],
+    )?;
+
+    root.present()
+}
+
+fn draw_comparison<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    labels: [&str; 2],
+    a: &[State],
+    b: &[State],
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((4, 1));
+    let charts: [crate::compare::Series; 4] = [
+        ("TVL (USD)", |state| state.tvl.0),
+        ("TVB (USD)", |state| state.tvb.0),
+        ("Fees claimed (USD)", |state| state.f_claimed.0),
+        ("Rebate pool (USD)", |state| state.r_pool.0),
+    ];
+    for (area, (caption, value)) in areas.iter().zip(charts) {
+        chart(
+            area,
+            caption,
+            &[
+                Series::new(labels[0], RED, a, value),
+                Series::new(labels[1], BLUE, b, value),
+            ],
+        )?;
+    }
+    root.present()
+}
+
+fn draw_fan<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    summary: &Summary,
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((SERIES.len(), 1));
+    for (i, (area, (name, _))) in areas.iter().zip(SERIES).enumerate() {
+        let values = |f: &dyn Fn(&Aggregate) -> f64| -> Vec<f64> {
+            summary.epochs.iter().map(|series| f(&series[i])).collect()
+        };
+        // The percentiles of the summary are the bounds of every band, in the order of `FAN`.
+        let bands: Vec<(Vec<f64>, Vec<f64>)> = (0..FAN.len())
+            .map(|band| {
+                (
+                    values(&|aggregate| aggregate.percentiles[2 * band]),
+                    values(&|aggregate| aggregate.percentiles[2 * band + 1]),
+                )
+            })
+            .collect();
+        let median = values(&|aggregate| aggregate.median);
+        fan(
+            area,
+            &format!("{} across {} runs", name, summary.runs),
+            summary.first_epoch,
+            &bands,
+            &median,
+        )?;
+    }
+    root.present()
+}
+
+/// Draw a fan chart: every band is filled between its lower and upper values, with inner bands
+/// drawn darker on top of outer ones, and the median is drawn as a line over them.
+fn fan<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    caption: &str,
+    first_epoch: usize,
+    bands: &[(Vec<f64>, Vec<f64>)],
+    median: &[f64],
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let epochs = first_epoch..first_epoch + median.len().max(1);
+    let (lo, hi) = bands
+        .iter()
+        .flat_map(|(low, high)| low.iter().chain(high))
+        .chain(median)
+        .copied()
+        .filter(|value| value.is_finite())
+        .fold((0.0f64, 0.0f64), |(lo, hi), value| {
+            (lo.min(value), hi.max(value))
+        });
+    let hi = if hi > lo { hi } else { lo + 1.0 };
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(90)
+        .build_cartesian_2d(epochs, lo..hi * 1.05)?;
+    chart
+        .configure_mesh()
+        .x_desc("epoch")
+        .y_label_formatter(&|value| {
+            // Ratios are small, so they keep their decimals.
+            if hi > 10.0 {
+                format!("{:.0}", value)
+            } else {
+                format!("{:.2}", value)
+            }
+        })
+        .draw()?;
+
+    for (n, (low, high)) in bands.iter().enumerate() {
+        let points: Vec<(usize, f64)> = (first_epoch..)
+            .zip(high.iter().copied())
+            .chain(
+                (first_epoch..first_epoch + low.len())
+                    .zip(low.iter().copied())
+                    .rev(),
+            )
+            .collect();
+        let colour = BLUE.mix(0.15 * (n + 1) as f64);
+        let (lower, upper) = FAN[n];
+        chart
+            .draw_series(std::iter::once(Polygon::new(points, colour.filled())))?
+            .label(format!("p{}-p{}", lower, upper))
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], colour.filled()));
+    }
+    chart
+        .draw_series(LineSeries::new(
+            (first_epoch..).zip(median.iter().copied()),
+            BLUE.stroke_width(2),
+        ))?
+        .label("median")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE.stroke_width(2)));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+}
+
+fn chart<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    caption: &str,
+    series: &[Series],
+) -> std::result::Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let epochs = series.iter().map(|s| s.values.len()).max().unwrap_or(0);
+    let (lo, hi) = series
+        .iter()
+        .flat_map(|s| s.values.iter().copied())
+        .filter(|value| value.is_finite())
+        .fold((0.0f64, 0.0f64), |(lo, hi), value| {
+            (lo.min(value), hi.max(value))
+        });
+    // Avoid an empty range when every value is the same.
+    let hi = if hi > lo { hi } else { lo + 1.0 };
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(90)
+        .build_cartesian_2d(0..epochs.max(1), lo..hi * 1.05)?;
+    chart
+        .configure_mesh()
+        .x_desc("epoch")
+        .y_label_formatter(&|value| format!("{:.0}", value))
+        .draw()?;
+
+    for s in series {
+        let colour = s.colour;
+        chart
+            .draw_series(LineSeries::new(
+                s.values.iter().copied().enumerate(),
+                colour.stroke_width(2),
+            ))?
+            .label(s.name.as_str())
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], colour.stroke_width(2))
+            });
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+}
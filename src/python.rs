@@ -0,0 +1,342 @@
+//! For driving the simulation from Python, which is where most analysis happens (in notebooks, with
+//! pandas and matplotlib). With the `python` feature, the library is also a Python extension module
+//! named `eco`, built with maturin (`maturin develop --release`, see `pyproject.toml`). The module
+//! wraps the configuration and the simulation, and hands results back as plain dictionaries and
+//! lists, so that nothing on the Python side needs to know about the types in here:
+//!
+//! ```python
+//! import eco, pandas
+//! config = eco.Config(scenario="bear-market")
+//! config["internal.mint_fee"] = 0.002
+//! sim = eco.Simulation(config, seed=42)
+//! sim.run()
+//! frame = pandas.DataFrame(sim.columns())
+//! ```
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::metrics::{Report, Security};
+use crate::scenarios as registry;
+use crate::simulation::Simulation;
+use crate::state::{AssetState, ControllerState, ShardState, State};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pythonize::{depythonize, pythonize};
+use std::path::Path;
+
+create_exception!(
+    eco,
+    InvariantError,
+    PyException,
+    "Raised when a state of the simulation violates an invariant."
+);
+
+/// Raise an error as the closest Python exception.
+fn raise(err: Error) -> PyErr {
+    match err {
+        Error::Io(err) => PyOSError::new_err(err.to_string()),
+        Error::Invariant(violation) => InvariantError::new_err(violation.to_string()),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// The configuration of a simulation. Parameters are read and written by their dotted paths (the
+/// same paths as `econ-sim sweep` and `sensitivity` use), and the whole configuration converts to
+/// and from a dictionary in the same shape as a configuration file.
+#[pyclass(name = "Config", module = "eco")]
+#[derive(Clone)]
+struct PyConfig {
+    config: Config,
+}
+
+#[pymethods]
+impl PyConfig {
+    /// The configuration of the named scenario, or the default configuration.
+    #[new]
+    #[pyo3(signature = (scenario = None))]
+    fn new(scenario: Option<&str>) -> PyResult<Self> {
+        let config = match scenario {
+            Some(name) => registry::find(name)
+                .ok_or_else(|| PyValueError::new_err(format!("unknown scenario `{}`", name)))?
+                .config(),
+            None => Config::default(),
+        };
+        Ok(Self { config })
+    }
+
+    /// Load a TOML or YAML configuration file, layered on top of the named scenario (or the
+    /// defaults).
+    #[staticmethod]
+    #[pyo3(signature = (path, scenario = None))]
+    fn load(path: &str, scenario: Option<&str>) -> PyResult<Self> {
+        let base = Self::new(scenario)?;
+        let config = Config::load_onto(path, &base.config).map_err(raise)?;
+        config.script.check(&config.epoch).map_err(raise)?;
+        config.models.check().map_err(raise)?;
+        Ok(Self { config })
+    }
+
+    /// Create a configuration from a dictionary in the same shape as a configuration file.
+    /// Parameters that are left out keep their defaults, and a script is read relative to the
+    /// working directory.
+    #[staticmethod]
+    fn from_dict(dict: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let mut config: Config =
+            depythonize(dict).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        config.read_script(Path::new("")).map_err(raise)?;
+        config.read_replay(Path::new("")).map_err(raise)?;
+        config.script.check(&config.epoch).map_err(raise)?;
+        config.models.check().map_err(raise)?;
+        Ok(Self { config })
+    }
+
+    /// The whole configuration as a dictionary.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        Ok(pythonize(py, &self.config)?)
+    }
+
+    /// The whole configuration as TOML.
+    fn to_toml(&self) -> PyResult<String> {
+        self.config.to_toml().map_err(raise)
+    }
+
+    /// The dotted paths of every model parameter.
+    fn parameters(&self) -> Vec<String> {
+        self.config.parameters()
+    }
+
+    /// The number of epochs to simulate.
+    #[getter]
+    fn steps(&self) -> usize {
+        self.config.steps
+    }
+
+    #[setter]
+    fn set_steps(&mut self, steps: usize) {
+        self.config.steps = steps;
+    }
+
+    /// The seed of the random number generator, if there is one.
+    #[getter]
+    fn seed(&self) -> Option<u64> {
+        self.config.seed
+    }
+
+    #[setter]
+    fn set_seed(&mut self, seed: Option<u64>) {
+        self.config.seed = seed;
+    }
+
+    fn __getitem__(&self, path: &str) -> PyResult<f64> {
+        self.config
+            .get(path)
+            .map_err(|err| PyKeyError::new_err(err.to_string()))
+    }
+
+    fn __setitem__(&mut self, path: &str, value: f64) -> PyResult<()> {
+        self.config
+            .set(path, value)
+            .map_err(|err| PyKeyError::new_err(err.to_string()))
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("Config({:?})", self.to_toml()?))
+    }
+}
+
+/// A simulation of the configuration, driven by the basic models. Its states can be read back as it
+/// goes (all of them, unless the history is bounded by `history.window_days`).
+#[pyclass(name = "Simulation", module = "eco", unsendable)]
+struct PySimulation {
+    sim: Simulation,
+    config: Config,
+}
+
+#[pymethods]
+impl PySimulation {
+    /// Create a simulation of the configuration, seeded with the seed (or the seed of the
+    /// configuration, or a random one).
+    #[new]
+    #[pyo3(signature = (config = None, seed = None))]
+    fn new(config: Option<PyConfig>, seed: Option<u64>) -> Self {
+        let config = config.map_or_else(Config::default, |config| config.config);
+        let sim = Simulation::from_config(&config);
+        let sim = match seed {
+            Some(seed) => sim.with_seed(seed),
+            None => sim,
+        };
+        Self { sim, config }
+    }
+
+    /// The seed that the simulation was seeded with.
+    #[getter]
+    fn seed(&self) -> u64 {
+        self.sim.seed()
+    }
+
+    /// The latest epoch (where epoch zero is the initial state).
+    #[getter]
+    fn epoch(&self) -> usize {
+        self.sim.epoch()
+    }
+
+    /// The names of the assets that are locked, if they are modelled individually.
+    #[getter]
+    fn assets(&self) -> Vec<String> {
+        self.sim.assets().to_vec()
+    }
+
+    /// Advance the simulation by one epoch, and return the new state as a dictionary. Raises
+    /// `InvariantError` if the state violates an invariant.
+    fn step<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let state = self.sim.try_step().map_err(raise)?;
+        Ok(pythonize(py, &state)?)
+    }
+
+    /// Advance the simulation by the given number of epochs (or the number of steps in the
+    /// configuration), stopping early at a steady state, at the first divergence, or at a stop
+    /// condition if the configuration says so.
+    #[pyo3(signature = (steps = None))]
+    fn run(&mut self, steps: Option<usize>) -> PyResult<()> {
+        for _ in 0..steps.unwrap_or(self.config.steps) {
+            self.sim.try_step().map_err(raise)?;
+            if self.config.steady_state.stop
+                && self.sim.history().aggregates().equilibrium.is_some()
+            {
+                break;
+            }
+            if self.sim.aborted().is_some() || self.sim.stopped().is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// The state at the end of every epoch so far (leaving out the warm-up), as dictionaries that
+    /// also hold their epoch.
+    fn states<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        self.epochs()
+            .map(|(epoch, state)| {
+                let dict = pythonize(py, state)?.downcast_into::<PyDict>()?;
+                dict.set_item("epoch", epoch)?;
+                Ok(dict.into_any())
+            })
+            .collect()
+    }
+
+    /// Every state field of every epoch so far (leaving out the warm-up), as a dictionary of
+    /// columns with the same names (and in the same order) as the CSV output, which
+    /// `pandas.DataFrame` takes as it is.
+    fn columns<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let controlled = self.config.internal.fee_controller.is_some();
+        let names: Vec<String> = State::FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .chain(self.sim.assets().iter().flat_map(|asset| {
+                AssetState::FIELDS
+                    .iter()
+                    .map(move |field| format!("{}_{}", asset, field))
+            }))
+            .chain((0..self.sim.shards()).flat_map(|i| {
+                ShardState::FIELDS
+                    .iter()
+                    .map(move |field| format!("shard{}_{}", i, field))
+            }))
+            .chain(
+                ControllerState::FIELDS
+                    .iter()
+                    .filter(|_| controlled)
+                    .map(|field| format!("controller_{}", field)),
+            )
+            .collect();
+        let mut epochs = vec![];
+        let mut values = vec![vec![]; names.len()];
+        for (epoch, state) in self.epochs() {
+            epochs.push(epoch);
+            // The initial state may not describe any assets or shards, in which case they are all
+            // zero.
+            let asset_values = (0..self.sim.assets().len())
+                .flat_map(|i| state.assets.get(i).copied().unwrap_or_default().values());
+            let shard_values = (0..self.sim.shards())
+                .flat_map(|i| state.shards.get(i).copied().unwrap_or_default().values());
+            let controller_values = Some(state.controller.unwrap_or_default())
+                .filter(|_| controlled)
+                .into_iter()
+                .flat_map(|controller| controller.values());
+            let row = state.values();
+            let row = row
+                .iter()
+                .copied()
+                .chain(asset_values)
+                .chain(shard_values)
+                .chain(controller_values);
+            for (column, value) in values.iter_mut().zip(row) {
+                column.push(value);
+            }
+        }
+        let columns = PyDict::new(py);
+        columns.set_item("epoch", epochs)?;
+        for (name, column) in names.into_iter().zip(values) {
+            columns.set_item(name, column)?;
+        }
+        Ok(columns)
+    }
+
+    /// The summary of the run so far (final and peak TVL and TVB, fees claimed, rebates paid, the
+    /// first steady state, the class of its outcome, etc.), along with how often TVB fell below the
+    /// required multiple of TVL.
+    fn summary<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let aggregates = self.sim.history().aggregates();
+        let report = Report::from_aggregates(aggregates)
+            .with_health(aggregates, &self.config.health)
+            .with_stop(self.sim.stopped());
+        let summary = pythonize(py, &report)?.downcast_into::<PyDict>()?;
+        let security = Security::from_aggregates(aggregates);
+        summary.set_item("breaches", security.breaches)?;
+        summary.set_item("first_breach", security.first_breach)?;
+        Ok(summary.into_any())
+    }
+}
+
+impl PySimulation {
+    /// The states that are held with their epochs, leaving out the warm-up (unless the run has not
+    /// got past it, in which case the latest state stands for it).
+    fn epochs(&self) -> impl Iterator<Item = (usize, &State)> {
+        let history = self.sim.history();
+        let warmup = self.config.warmup.min(history.epoch());
+        (history.first_epoch()..)
+            .zip(history.states())
+            .filter(move |(epoch, _)| *epoch >= warmup)
+    }
+}
+
+/// Run a configuration (or the default configuration) for its number of steps, and return the
+/// simulation that holds the results.
+#[pyfunction]
+#[pyo3(signature = (config = None, seed = None))]
+fn run(config: Option<PyConfig>, seed: Option<u64>) -> PyResult<PySimulation> {
+    let mut sim = PySimulation::new(config, seed);
+    sim.run(None)?;
+    Ok(sim)
+}
+
+/// The names of every registered scenario.
+#[pyfunction]
+fn scenarios() -> Vec<&'static str> {
+    registry::SCENARIOS
+        .iter()
+        .map(|scenario| scenario.name)
+        .collect()
+}
+
+#[pymodule]
+fn eco(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyConfig>()?;
+    m.add_class::<PySimulation>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_function(wrap_pyfunction!(scenarios, m)?)?;
+    m.add("InvariantError", m.py().get_type::<InvariantError>())?;
+    Ok(())
+}
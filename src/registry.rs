@@ -0,0 +1,562 @@
+//! A registry of named models, so that a configuration (or `--model` on the command line) can
+//! select the model of a function by its name instead of by its table, and so that crates built on
+//! this one can add models of their own under new names. The `[models]` table maps every function
+//! (a `Slot`) to the name of a model:
+//!
+//! ```toml
+//! [models]
+//! mint_volume = "lognormal"
+//! rebate = "sigmoid"
+//! ```
+//!
+//! A built-in name selects the configured model of that kind, keeping its parameters when it is
+//! already the configured one, and otherwise switching to it with the parameters of the examples in
+//! `sim.toml` (at the level of the model that it replaces, for volumes and prices). Models whose
+//! parameters have no sensible default (like the operators bonding model) are only selected with
+//! their tables. Fees are curves, so their built-in names select what moves them: `pid` adds the
+//! controller of the fees (and, for the rebate, steers by it in place of a rebate), and `tiered`
+//! charges transactions by their size.
+//!
+//! A registered model is a function that is constructed from the configuration when a simulation
+//! is, and that replaces the function of its slot like a closure given to `SimulationBuilder`:
+//!
+//! ```
+//! # use eco::registry::{self, Slot};
+//! # use eco::Usd;
+//! registry::register_usd(Slot::MintVolume, "weekly", |config| {
+//!     let volume = Usd(4_000_000.0) * config.epoch.days;
+//!     Box::new(move |history, _rng| if history.len() % 7 == 0 { volume * 7.0 } else { Usd::ZERO })
+//! })
+//! .unwrap();
+//! ```
+//!
+//! Registered models draw from the random number generator of the simulation, but they are not
+//! checkpointed, so any state that they keep begins again when a run resumes. A selected name that
+//! is not registered is reported when the configuration is checked, and ignored by the simulation.
+
+use crate::config::{
+    BondingConfig, ClaimCurve, Config, FeeTier, ModelsConfig, PidConfig, PriceConfig, RebateCurve,
+    RebateStep, TransactionFeeConfig, VolumeConfig,
+};
+use crate::error::{Error, Result};
+use crate::history::History;
+use crate::{Pct, Ren, SimRng, Usd};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Slot is a function of the models that a model can be selected for by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Slot {
+    /// The value that is bonded (`ExternalModel::total_value_bonded`).
+    Tvb,
+    /// The minting volume (`ExternalModel::mint_volume`).
+    MintVolume,
+    /// The burning volume (`ExternalModel::burn_volume`).
+    BurnVolume,
+    /// The price of REN (`ExternalModel::ren_price`).
+    RenPrice,
+    /// The share of unclaimed fees that is claimed (`ExternalModel::claim_curve`).
+    Claim,
+    /// The minting fee (`InternalModel::mint_fee_curve`).
+    MintFee,
+    /// The burning fee (`InternalModel::burn_fee_curve`).
+    BurnFee,
+    /// The rebate (`InternalModel::rebate_curve`).
+    Rebate,
+}
+
+impl Slot {
+    /// Every slot, in the order of the `[models]` table.
+    pub const ALL: [Slot; 8] = [
+        Slot::Tvb,
+        Slot::MintVolume,
+        Slot::BurnVolume,
+        Slot::RenPrice,
+        Slot::Claim,
+        Slot::MintFee,
+        Slot::BurnFee,
+        Slot::Rebate,
+    ];
+
+    /// The key of the slot in the `[models]` table.
+    pub fn key(self) -> &'static str {
+        match self {
+            Slot::Tvb => "tvb",
+            Slot::MintVolume => "mint_volume",
+            Slot::BurnVolume => "burn_volume",
+            Slot::RenPrice => "ren_price",
+            Slot::Claim => "claim",
+            Slot::MintFee => "mint_fee",
+            Slot::BurnFee => "burn_fee",
+            Slot::Rebate => "rebate",
+        }
+    }
+
+    /// Find a slot by its key.
+    pub fn find(key: &str) -> Option<Slot> {
+        Self::ALL.iter().copied().find(|slot| slot.key() == key)
+    }
+
+    /// Whether the function of the slot returns a share (rather than an amount of USD).
+    fn is_share(self) -> bool {
+        matches!(
+            self,
+            Slot::Claim | Slot::MintFee | Slot::BurnFee | Slot::Rebate
+        )
+    }
+}
+
+/// A model function, which is given the history and the random number generator of the
+/// simulation.
+pub type Model<T> = Box<dyn FnMut(&History, &mut SimRng) -> T>;
+
+/// A function that constructs a model function from the configuration.
+type Construct<T> = Arc<dyn Fn(&Config) -> Model<T> + Send + Sync>;
+
+/// A registered model, which constructs its function from the configuration.
+#[derive(Clone)]
+enum Constructor {
+    Usd(Construct<Usd>),
+    Pct(Construct<Pct>),
+}
+
+/// A registered model function that has been constructed for a simulation.
+pub(crate) enum Constructed {
+    Usd(Model<Usd>),
+    Pct(Model<Pct>),
+}
+
+/// Every registered model, by its slot and name.
+static REGISTERED: RwLock<BTreeMap<(Slot, String), Constructor>> = RwLock::new(BTreeMap::new());
+
+/// Builtin is a model of the configuration that can be selected by name.
+struct Builtin {
+    slot: Slot,
+    name: &'static str,
+    description: &'static str,
+    select: fn(&mut Config),
+}
+
+/// Every built-in model that can be selected by name.
+const BUILTINS: [Builtin; 22] = [
+    Builtin {
+        slot: Slot::Tvb,
+        name: "usd",
+        description: "Node operators bond exactly as much USD as they want to.",
+        select: |config| config.external.bonding = BondingConfig::Usd,
+    },
+    Builtin {
+        slot: Slot::Tvb,
+        name: "darknodes",
+        description: "Node operators bond REN in whole darknodes.",
+        select: |config| {
+            if !matches!(config.external.bonding, BondingConfig::Darknodes { .. }) {
+                config.external.bonding = BondingConfig::Darknodes {
+                    bond: Ren(100_000.0),
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::MintVolume,
+        name: "constant",
+        description: "The same minting volume every epoch.",
+        select: |config| constant_volume(&mut config.external.mint_volume),
+    },
+    Builtin {
+        slot: Slot::MintVolume,
+        name: "lognormal",
+        description: "A lognormal minting volume around a drifting mean.",
+        select: |config| lognormal_volume(&mut config.external.mint_volume),
+    },
+    Builtin {
+        slot: Slot::BurnVolume,
+        name: "constant",
+        description: "The same burning volume every epoch.",
+        select: |config| constant_volume(&mut config.external.burn_volume),
+    },
+    Builtin {
+        slot: Slot::BurnVolume,
+        name: "lognormal",
+        description: "A lognormal burning volume around a drifting mean.",
+        select: |config| lognormal_volume(&mut config.external.burn_volume),
+    },
+    Builtin {
+        slot: Slot::RenPrice,
+        name: "constant",
+        description: "The same price of REN every epoch.",
+        select: |config| {
+            let price = &mut config.external.ren_price;
+            if !matches!(price, PriceConfig::Constant { .. }) {
+                *price = PriceConfig::Constant {
+                    price: level(price),
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::RenPrice,
+        name: "gbm",
+        description: "The price of REN follows geometric Brownian motion.",
+        select: |config| {
+            let price = &mut config.external.ren_price;
+            if !matches!(price, PriceConfig::Gbm { .. }) {
+                *price = PriceConfig::Gbm {
+                    initial: level(price),
+                    drift: 0.0,
+                    volatility: 0.05,
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::RenPrice,
+        name: "jump-diffusion",
+        description: "The price of REN follows geometric Brownian motion with rare jumps.",
+        select: |config| {
+            let price = &mut config.external.ren_price;
+            if !matches!(price, PriceConfig::JumpDiffusion { .. }) {
+                *price = PriceConfig::JumpDiffusion {
+                    initial: level(price),
+                    drift: 0.0,
+                    volatility: 0.05,
+                    jump_rate: 0.01,
+                    jump_mean: -0.4,
+                    jump_volatility: 0.1,
+                    degrees_of_freedom: 0.0,
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Claim,
+        name: "fixed",
+        description: "Node operators claim `claim_rate` of their unclaimed fees every epoch.",
+        select: |config| config.external.claim_curve = ClaimCurve::Fixed,
+    },
+    Builtin {
+        slot: Slot::Claim,
+        name: "periodic",
+        description: "Node operators claim all of their unclaimed fees every so many days.",
+        select: |config| {
+            if !matches!(config.external.claim_curve, ClaimCurve::Periodic { .. }) {
+                config.external.claim_curve = ClaimCurve::Periodic { days: 30.0 };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Claim,
+        name: "threshold",
+        description: "Node operators claim all of their unclaimed fees once they are worth it.",
+        select: |config| {
+            if !matches!(config.external.claim_curve, ClaimCurve::Threshold { .. }) {
+                config.external.claim_curve = ClaimCurve::Threshold {
+                    threshold: Usd(100_000.0),
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::MintFee,
+        name: "pid",
+        description: "A PID controller moves the fees to keep the security ratio at a target.",
+        select: pid,
+    },
+    Builtin {
+        slot: Slot::MintFee,
+        name: "tiered",
+        description: "Mints are charged by their size, from a table of tiers.",
+        select: |config| {
+            let tiers = &mut transaction_fee(config).mint_tiers;
+            if tiers.is_empty() {
+                *tiers = vec![tier(100_000.0, 0.002), tier(1_000_000.0, 0.001)];
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::BurnFee,
+        name: "pid",
+        description: "A PID controller moves the fees to keep the security ratio at a target.",
+        select: pid,
+    },
+    Builtin {
+        slot: Slot::BurnFee,
+        name: "tiered",
+        description: "Burns are charged by their size, from a table of tiers.",
+        select: |config| {
+            let tiers = &mut transaction_fee(config).burn_tiers;
+            if tiers.is_empty() {
+                *tiers = vec![FeeTier {
+                    above: Usd(1_000_000.0),
+                    fee: None,
+                    scale: Some(0.5),
+                }];
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "adaptive",
+        description: "The rebate steps towards keeping TVL-TVB at its average.",
+        select: |config| config.internal.rebate_curve = RebateCurve::Adaptive,
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "linear",
+        description: "The rebate grows in proportion to the gap, up to a maximum.",
+        select: |config| {
+            if !matches!(config.internal.rebate_curve, RebateCurve::Linear { .. }) {
+                config.internal.rebate_curve = RebateCurve::Linear {
+                    slope: 0.01,
+                    max: Pct(0.005),
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "sigmoid",
+        description: "The rebate follows a logistic curve of the gap.",
+        select: |config| {
+            if !matches!(config.internal.rebate_curve, RebateCurve::Sigmoid { .. }) {
+                config.internal.rebate_curve = RebateCurve::Sigmoid {
+                    max: Pct(0.005),
+                    midpoint: 0.5,
+                    steepness: 10.0,
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "step",
+        description: "The rebate jumps at every step of the gap that has been reached.",
+        select: |config| {
+            if !matches!(config.internal.rebate_curve, RebateCurve::Step { .. }) {
+                let step = |gap, rebate| RebateStep {
+                    gap,
+                    rebate: Pct(rebate),
+                };
+                config.internal.rebate_curve = RebateCurve::Step {
+                    steps: vec![step(0.2, 0.001), step(0.6, 0.004)],
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "decay",
+        description: "The rebate begins high when TVL rises above TVB, and halves over time.",
+        select: |config| {
+            if !matches!(config.internal.rebate_curve, RebateCurve::Decay { .. }) {
+                config.internal.rebate_curve = RebateCurve::Decay {
+                    initial: Pct(0.005),
+                    half_life_days: 10.0,
+                };
+            }
+        },
+    },
+    Builtin {
+        slot: Slot::Rebate,
+        name: "pid",
+        description: "No rebate, while a PID controller of the fees steers the security ratio.",
+        select: |config| {
+            config.internal.rebate_curve = RebateCurve::Linear {
+                slope: 0.0,
+                max: Pct::ZERO,
+            };
+            pid(config);
+        },
+    },
+];
+
+/// Control the fees with the configured PID controller, or with that of the example.
+fn pid(config: &mut Config) {
+    config
+        .internal
+        .fee_controller
+        .get_or_insert_with(PidConfig::default);
+}
+
+/// The configured transaction fees, or none (without a minimum) to add tiers to.
+fn transaction_fee(config: &mut Config) -> &mut TransactionFeeConfig {
+    config
+        .internal
+        .transaction_fee
+        .get_or_insert_with(TransactionFeeConfig::default)
+}
+
+fn tier(above: f64, fee: f64) -> FeeTier {
+    FeeTier {
+        above: Usd(above),
+        fee: Some(Pct(fee)),
+        scale: None,
+    }
+}
+
+/// The expected volume of a volume model (in the first epoch).
+fn volume(volume: &VolumeConfig) -> Usd {
+    match *volume {
+        VolumeConfig::Constant { volume } => volume,
+        VolumeConfig::Lognormal { mean, .. } => mean,
+    }
+}
+
+fn constant_volume(config: &mut VolumeConfig) {
+    if !matches!(config, VolumeConfig::Constant { .. }) {
+        *config = VolumeConfig::Constant {
+            volume: volume(config),
+        };
+    }
+}
+
+fn lognormal_volume(config: &mut VolumeConfig) {
+    if !matches!(config, VolumeConfig::Lognormal { .. }) {
+        *config = VolumeConfig::Lognormal {
+            mean: volume(config),
+            volatility: 0.3,
+            drift: 0.0,
+        };
+    }
+}
+
+/// The price of a price model before the first epoch.
+fn level(price: &PriceConfig) -> Usd {
+    match *price {
+        PriceConfig::Constant { price } => price,
+        PriceConfig::Gbm { initial, .. } | PriceConfig::JumpDiffusion { initial, .. } => initial,
+    }
+}
+
+/// Register a model of the given slot (which must return an amount of USD) under a new name. The
+/// constructor is called with the configuration of every simulation that selects the model.
+pub fn register_usd<F>(slot: Slot, name: &str, constructor: F) -> Result<()>
+where
+    F: Fn(&Config) -> Model<Usd> + Send + Sync + 'static,
+{
+    if slot.is_share() {
+        return Err(Error::Config(format!(
+            "models of `{}` return shares, not USD",
+            slot.key()
+        )));
+    }
+    register(slot, name, Constructor::Usd(Arc::new(constructor)))
+}
+
+/// Register a model of the given slot (which must return a share) under a new name. The
+/// constructor is called with the configuration of every simulation that selects the model.
+pub fn register_pct<F>(slot: Slot, name: &str, constructor: F) -> Result<()>
+where
+    F: Fn(&Config) -> Model<Pct> + Send + Sync + 'static,
+{
+    if !slot.is_share() {
+        return Err(Error::Config(format!(
+            "models of `{}` return USD, not shares",
+            slot.key()
+        )));
+    }
+    register(slot, name, Constructor::Pct(Arc::new(constructor)))
+}
+
+fn register(slot: Slot, name: &str, constructor: Constructor) -> Result<()> {
+    let mut registered = REGISTERED.write().expect("registry is never poisoned");
+    let key = (slot, name.to_string());
+    if builtin(slot, name).is_some() || registered.contains_key(&key) {
+        return Err(Error::Config(format!(
+            "a model of `{}` is already named `{}`",
+            slot.key(),
+            name
+        )));
+    }
+    registered.insert(key, constructor);
+    Ok(())
+}
+
+fn builtin(slot: Slot, name: &str) -> Option<&'static Builtin> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.slot == slot && builtin.name == name)
+}
+
+fn registered(slot: Slot, name: &str) -> Option<Constructor> {
+    let registered = REGISTERED.read().expect("registry is never poisoned");
+    registered.get(&(slot, name.to_string())).cloned()
+}
+
+/// The names of every model of the given slot (built in first, then registered), each with a one
+/// line description.
+pub fn models(slot: Slot) -> Vec<(String, String)> {
+    let mut models: Vec<_> = BUILTINS
+        .iter()
+        .filter(|builtin| builtin.slot == slot)
+        .map(|builtin| (builtin.name.to_string(), builtin.description.to_string()))
+        .collect();
+    let registered = REGISTERED.read().expect("registry is never poisoned");
+    models.extend(
+        registered
+            .keys()
+            .filter(|(s, _)| *s == slot)
+            .map(|(_, name)| (name.clone(), "A registered model.".to_string())),
+    );
+    models
+}
+
+/// Check that every model that the configuration selects is built in or registered.
+pub(crate) fn check(models: &ModelsConfig) -> Result<()> {
+    for slot in Slot::ALL {
+        if let Some(name) = models.get(slot) {
+            if builtin(slot, name).is_none() && registered(slot, name).is_none() {
+                let names: Vec<_> = self::models(slot).into_iter().map(|(n, _)| n).collect();
+                return Err(Error::Config(format!(
+                    "unknown model `{}` of `{}` (expected one of: {})",
+                    name,
+                    slot.key(),
+                    names.join(", ")
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The configuration with the built-in models that it selects in place of those that it
+/// configures.
+pub fn resolve(config: &Config) -> Cow<'_, Config> {
+    let builtins: Vec<_> = Slot::ALL
+        .iter()
+        .filter_map(|&slot| builtin(slot, config.models.get(slot)?))
+        .collect();
+    if builtins.is_empty() {
+        return Cow::Borrowed(config);
+    }
+    let mut config = config.clone();
+    for builtin in builtins {
+        (builtin.select)(&mut config);
+    }
+    Cow::Owned(config)
+}
+
+/// Whether the configuration selects any registered model.
+pub(crate) fn selects_registered(config: &Config) -> bool {
+    Slot::ALL.iter().any(|&slot| {
+        config
+            .models
+            .get(slot)
+            .is_some_and(|name| registered(slot, name).is_some())
+    })
+}
+
+/// Construct the functions of the registered models that the configuration selects.
+pub(crate) fn construct(config: &Config) -> Vec<(Slot, Constructed)> {
+    Slot::ALL
+        .iter()
+        .filter_map(|&slot| {
+            let constructor = registered(slot, config.models.get(slot)?)?;
+            Some(match constructor {
+                Constructor::Usd(f) => (slot, Constructed::Usd(f(config))),
+                Constructor::Pct(f) => (slot, Constructed::Pct(f(config))),
+            })
+        })
+        .collect()
+}
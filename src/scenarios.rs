@@ -0,0 +1,97 @@
+//! A registry of named scenarios, so that commonly discussed cases can be run by name (with
+//! `--scenario`) instead of by editing code, and so that everybody runs the same definitions. A
+//! scenario is a starting configuration; any configuration file is layered on top of it.
+
+use crate::config::{
+    BondingConfig, Config, ExternalConfig, InternalConfig, PriceConfig, VolumeConfig,
+};
+use crate::curve::Curve;
+use crate::{Pct, Ren, Usd};
+
+/// Scenario is a named combination of external and internal models (and their parameters).
+#[derive(Clone, Copy, Debug)]
+pub struct Scenario {
+    /// The name that selects the scenario.
+    pub name: &'static str,
+    /// Other names that also select the scenario.
+    pub aliases: &'static [&'static str],
+    /// A one line description of what the scenario represents.
+    pub description: &'static str,
+    config: fn() -> Config,
+}
+
+impl Scenario {
+    /// The configuration of the scenario.
+    pub fn config(&self) -> Config {
+        (self.config)()
+    }
+}
+
+/// Every registered scenario. The first one is the default.
+pub const SCENARIOS: [Scenario; 3] = [
+    Scenario {
+        name: "baseline",
+        aliases: &["basic"],
+        description: "The basic external and internal models with their default parameters.",
+        config: Config::default,
+    },
+    Scenario {
+        name: "bear-market",
+        aliases: &[],
+        description: "Minting dries up faster than burning, and the price of REN falls, while \
+                      node operators bond REN in whole darknodes.",
+        config: bear_market,
+    },
+    Scenario {
+        name: "aggressive-rebate",
+        aliases: &[],
+        description: "The rebate reacts quickly over a short window, funded by most of the fees.",
+        config: aggressive_rebate,
+    },
+];
+
+/// Find a scenario by its name (or one of its aliases).
+pub fn find(name: &str) -> Option<&'static Scenario> {
+    SCENARIOS
+        .iter()
+        .find(|scenario| scenario.name == name || scenario.aliases.contains(&name))
+}
+
+fn bear_market() -> Config {
+    Config {
+        external: ExternalConfig {
+            mint_volume: VolumeConfig::Lognormal {
+                mean: Usd(4_000_000.0),
+                volatility: 0.3,
+                drift: -0.01,
+            },
+            burn_volume: VolumeConfig::Lognormal {
+                mean: Usd(2_000_000.0),
+                volatility: 0.3,
+                drift: -0.005,
+            },
+            ren_price: PriceConfig::Gbm {
+                initial: Usd(0.3),
+                drift: -0.005,
+                volatility: 0.05,
+            },
+            bonding: BondingConfig::Darknodes {
+                bond: Ren(100_000.0),
+            },
+            ..ExternalConfig::default()
+        },
+        ..Config::default()
+    }
+}
+
+fn aggressive_rebate() -> Config {
+    Config {
+        internal: InternalConfig {
+            rebate_step: Pct(0.0005),
+            rebate_window_days: 3.0,
+            rebate_share: Curve::Constant(Pct(0.8)),
+            ..InternalConfig::default()
+        },
+        ..Config::default()
+    }
+}
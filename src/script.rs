@@ -0,0 +1,401 @@
+//! For prototyping models without recompiling. With the `script` feature, a configuration can name
+//! a [Rhai](https://rhai.rs) script whose functions replace the matching model functions, so that
+//! anybody can try out a fee curve or a volume model by editing a text file. Every function is
+//! given the latest state and the recent history (as arrays of maps with the same fields as the CSV
+//! output, oldest first, each with its `epoch`), and returns a number:
+//!
+//! - external: `tvb(state, history)`, `mint_volume(state, history)`, `burn_volume(state, history)`,
+//!   `asset_mint_volume(asset, state, history)`, `asset_burn_volume(asset, state, history)`, and
+//!   `claim_rate(state, history)`,
+//! - internal: `mint_fee(state, history)`, `burn_fee(state, history)`, `rebate(state, history)`,
+//!   `rebate_collected(fees, state, history)`, `treasury_collected(fees, state, history)`,
+//!   `buyback_collected(fees, state, history)`, `asset_mint_fee(asset, state, history)`, and
+//!   `asset_burn_fee(asset, state, history)` (which fall back to `mint_fee` and `burn_fee`).
+//!
+//! Functions that the script does not define are left to the configured models. Scripts draw
+//! randomness with `rand()` (uniform in `[0, 1)`) and `normal()` (standard normal), both from the
+//! random number generator of the simulation, so scripted runs are as reproducible as any other. A
+//! script that fails while the simulation runs returns NaN, which the `finite` invariant catches,
+//! and the error is logged.
+
+use crate::audit::Decision;
+use crate::config::{Config, EpochConfig, ScriptConfig};
+use crate::error::{Error, Result};
+use crate::external::ExternalModel;
+use crate::history::History;
+use crate::internal::InternalModel;
+use crate::state::{ControllerState, State};
+use crate::{Pct, SimRng, Usd};
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rhai::{Array, Dynamic, Engine, FuncArgs, AST};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// The most operations that one call of a script function can take, so that a script that never
+/// returns stops the run instead of hanging it.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The latest state and recent history as script values, along with the length of the history
+/// and the latest state that they were converted from.
+type Converted = (usize, State, Dynamic, Dynamic);
+
+/// Script is a compiled script, which can replace the functions of any model. Clones share the
+/// same compiled script (and random number generator).
+#[derive(Clone)]
+pub struct Script {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+    functions: Rc<HashSet<String>>,
+    window: usize,
+    /// The random number generator of the simulation, which is lent to the script while one of its
+    /// functions is called.
+    rng: Rc<RefCell<Option<SimRng>>>,
+    /// The latest state and recent history as script values, which are converted once per epoch
+    /// (or once per stage, while a sequential pipeline changes the latest state).
+    cache: Rc<RefCell<Option<Converted>>>,
+}
+
+impl Script {
+    /// Compile the script in the configuration, if there is one, giving its functions the history
+    /// of the latest `window_days`.
+    pub fn new(config: &ScriptConfig, epoch: &EpochConfig) -> Result<Option<Self>> {
+        let source = match &config.source {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+        let rng: Rc<RefCell<Option<SimRng>>> = Rc::new(RefCell::new(None));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let uniform = rng.clone();
+        engine.register_fn("rand", move || -> f64 {
+            uniform
+                .borrow_mut()
+                .as_mut()
+                .map_or(f64::NAN, |rng| rng.gen())
+        });
+        let normal = rng.clone();
+        engine.register_fn("normal", move || -> f64 {
+            normal
+                .borrow_mut()
+                .as_mut()
+                .map_or(f64::NAN, |rng| rng.sample(StandardNormal))
+        });
+        let ast = engine
+            .compile(source)
+            .map_err(|err| Error::Config(format!("script: {}", err)))?;
+        let functions = ast.iter_functions().map(|f| f.name.to_string()).collect();
+        Ok(Some(Self {
+            engine: Rc::new(engine),
+            ast: Rc::new(ast),
+            functions: Rc::new(functions),
+            window: epoch.epochs(config.window_days),
+            rng,
+            cache: Rc::new(RefCell::new(None)),
+        }))
+    }
+
+    /// Compile the script in the configuration, if there is one. An invalid script is reported when
+    /// the configuration is loaded, so here it is ignored.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Self::new(&config.script, &config.epoch).ok().flatten()
+    }
+
+    /// Replace the functions of an external model that the script defines.
+    pub fn external<E: ExternalModel>(&self, inner: E) -> External<E> {
+        External {
+            script: self.clone(),
+            inner,
+        }
+    }
+
+    /// Replace the functions of an internal model that the script defines.
+    pub fn internal<I: InternalModel>(&self, inner: I) -> Internal<I> {
+        Internal {
+            script: self.clone(),
+            inner,
+        }
+    }
+
+    /// Whether the script defines a function.
+    fn defines(&self, name: &str) -> bool {
+        self.functions.contains(name)
+    }
+
+    /// Call a function of the script with the given arguments, followed by the latest state and the
+    /// recent history.
+    fn call(&self, name: &str, args: Array, history: &History, rng: &mut SimRng) -> f64 {
+        let (state, recent) = self.history(history);
+        let mut args = args;
+        args.extend([state, recent]);
+        // The generator is lent to the script (and put back whatever happens).
+        let lent = std::mem::replace(rng, SimRng::seed_from_u64(0));
+        *self.rng.borrow_mut() = Some(lent);
+        let result =
+            self.engine
+                .call_fn::<Dynamic>(&mut rhai::Scope::new(), &self.ast, name, Args(args));
+        *rng = self.rng.borrow_mut().take().expect("lent generator");
+        let value = result.map_err(|err| err.to_string()).and_then(|value| {
+            value
+                .as_float()
+                .or_else(|_| value.as_int().map(|value| value as f64))
+                .map_err(|kind| format!("returned {} instead of a number", kind))
+        });
+        match value {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::error!(function = name, epoch = history.len(), %err, "script failed");
+                f64::NAN
+            }
+        }
+    }
+
+    /// The latest state and the recent history, as script values.
+    fn history(&self, history: &History) -> (Dynamic, Dynamic) {
+        let mut cache = self.cache.borrow_mut();
+        if let Some((epoch, latest, state, recent)) = &*cache {
+            if *epoch == history.len() && latest == history.latest() {
+                return (state.clone(), recent.clone());
+            }
+        }
+        // The recent states include the epoch so far, while a sequential pipeline holds it.
+        let held = history.recent(self.window);
+        let first = history.first_epoch() + history.states().len() - held.len();
+        let recent: Array = (first..)
+            .zip(held)
+            .map(|(epoch, state)| {
+                let mut state = rhai::serde::to_dynamic(state).unwrap_or_default();
+                if let Some(mut map) = state.write_lock::<rhai::Map>() {
+                    map.insert("epoch".into(), Dynamic::from_int(epoch as rhai::INT));
+                }
+                state
+            })
+            .collect();
+        let state = recent.last().cloned().unwrap_or_default();
+        let recent = Dynamic::from_array(recent);
+        *cache = Some((
+            history.len(),
+            history.latest().clone(),
+            state.clone(),
+            recent.clone(),
+        ));
+        (state, recent)
+    }
+}
+
+/// The arguments of a script function.
+struct Args(Array);
+
+impl FuncArgs for Args {
+    fn parse<C: Extend<Dynamic>>(self, args: &mut C) {
+        args.extend(self.0);
+    }
+}
+
+/// External is an external model whose functions are replaced by the functions of a script, where
+/// it defines them.
+pub struct External<E> {
+    script: Script,
+    inner: E,
+}
+
+impl<E: ExternalModel> ExternalModel for External<E> {
+    fn total_value_bonded(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        // The model is always consulted, so that whatever state it keeps stays up to date.
+        let bonded = self.inner.total_value_bonded(history, rng);
+        if self.script.defines("tvb") {
+            return Usd(self.script.call("tvb", vec![], history, rng));
+        }
+        bonded
+    }
+
+    fn mint_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        if self.script.defines("mint_volume") {
+            return Usd(self.script.call("mint_volume", vec![], history, rng));
+        }
+        self.inner.mint_volume(history, rng)
+    }
+
+    fn burn_volume(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        if self.script.defines("burn_volume") {
+            return Usd(self.script.call("burn_volume", vec![], history, rng));
+        }
+        self.inner.burn_volume(history, rng)
+    }
+
+    fn mint_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        if self.script.defines("mint_volume") {
+            return vec![self.mint_volume(history, rng)];
+        }
+        self.inner.mint_transactions(history, rng)
+    }
+
+    fn burn_transactions(&mut self, history: &History, rng: &mut SimRng) -> Vec<Usd> {
+        if self.script.defines("burn_volume") {
+            return vec![self.burn_volume(history, rng)];
+        }
+        self.inner.burn_transactions(history, rng)
+    }
+
+    fn ren_price(&mut self, history: &History, rng: &mut SimRng) -> Usd {
+        self.inner.ren_price(history, rng)
+    }
+
+    fn darknodes(&mut self, history: &History, rng: &mut SimRng) -> u64 {
+        self.inner.darknodes(history, rng)
+    }
+
+    fn assets(&self) -> Vec<String> {
+        self.inner.assets()
+    }
+
+    fn asset_mint_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        if self.script.defines("asset_mint_volume") {
+            let args = vec![asset.into()];
+            return Usd(self.script.call("asset_mint_volume", args, history, rng));
+        }
+        self.inner.asset_mint_volume(history, asset, rng)
+    }
+
+    fn asset_burn_volume(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        if self.script.defines("asset_burn_volume") {
+            let args = vec![asset.into()];
+            return Usd(self.script.call("asset_burn_volume", args, history, rng));
+        }
+        self.inner.asset_burn_volume(history, asset, rng)
+    }
+
+    fn asset_price(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Usd {
+        self.inner.asset_price(history, asset, rng)
+    }
+
+    fn tvl_revaluation(&mut self, history: &History, net: Usd, rng: &mut SimRng) -> Usd {
+        self.inner.tvl_revaluation(history, net, rng)
+    }
+
+    fn claim_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        if self.script.defines("claim_rate") {
+            return Pct(self.script.call("claim_rate", vec![], history, rng));
+        }
+        self.inner.claim_curve(history, rng)
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        self.inner.checkpoint()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore(state)
+    }
+}
+
+/// Internal is an internal model whose functions are replaced by the functions of a script, where
+/// it defines them.
+pub struct Internal<I> {
+    script: Script,
+    inner: I,
+}
+
+impl<I: InternalModel> Internal<I> {
+    /// Call the first of the functions that the script defines, where the per-asset function is
+    /// given the asset.
+    fn asset_fee(
+        &self,
+        names: [&str; 2],
+        history: &History,
+        asset: &str,
+        rng: &mut SimRng,
+    ) -> Option<Pct> {
+        let [per_asset, aggregate] = names;
+        if self.script.defines(per_asset) {
+            let args = vec![asset.into()];
+            Some(Pct(self.script.call(per_asset, args, history, rng)))
+        } else if self.script.defines(aggregate) {
+            Some(Pct(self.script.call(aggregate, vec![], history, rng)))
+        } else {
+            None
+        }
+    }
+}
+
+impl<I: InternalModel> InternalModel for Internal<I> {
+    fn mint_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        if self.script.defines("mint_fee") {
+            return Pct(self.script.call("mint_fee", vec![], history, rng));
+        }
+        self.inner.mint_fee_curve(history, rng)
+    }
+
+    fn burn_fee_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        if self.script.defines("burn_fee") {
+            return Pct(self.script.call("burn_fee", vec![], history, rng));
+        }
+        self.inner.burn_fee_curve(history, rng)
+    }
+
+    fn rebate_curve(&mut self, history: &History, rng: &mut SimRng) -> Pct {
+        if self.script.defines("rebate") {
+            return Pct(self.script.call("rebate", vec![], history, rng));
+        }
+        self.inner.rebate_curve(history, rng)
+    }
+
+    fn rebate_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        if self.script.defines("rebate_collected") {
+            let args = vec![f.0.into()];
+            return Usd(self.script.call("rebate_collected", args, history, rng));
+        }
+        self.inner.rebate_collected(history, f, rng)
+    }
+
+    fn treasury_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        if self.script.defines("treasury_collected") {
+            let args = vec![f.0.into()];
+            return Usd(self.script.call("treasury_collected", args, history, rng));
+        }
+        self.inner.treasury_collected(history, f, rng)
+    }
+
+    fn buyback_collected(&mut self, history: &History, f: Usd, rng: &mut SimRng) -> Usd {
+        if self.script.defines("buyback_collected") {
+            let args = vec![f.0.into()];
+            return Usd(self.script.call("buyback_collected", args, history, rng));
+        }
+        self.inner.buyback_collected(history, f, rng)
+    }
+
+    fn asset_mint_fee_curve(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Pct {
+        self.asset_fee(["asset_mint_fee", "mint_fee"], history, asset, rng)
+            .unwrap_or_else(|| self.inner.asset_mint_fee_curve(history, asset, rng))
+    }
+
+    fn asset_burn_fee_curve(&mut self, history: &History, asset: &str, rng: &mut SimRng) -> Pct {
+        self.asset_fee(["asset_burn_fee", "burn_fee"], history, asset, rng)
+            .unwrap_or_else(|| self.inner.asset_burn_fee_curve(history, asset, rng))
+    }
+
+    fn mint_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        self.inner.mint_transaction_fee(size, fee)
+    }
+
+    fn burn_transaction_fee(&mut self, size: Usd, fee: Pct) -> Pct {
+        self.inner.burn_transaction_fee(size, fee)
+    }
+
+    fn controller(&self) -> Option<ControllerState> {
+        self.inner.controller()
+    }
+
+    fn decisions(&mut self) -> Vec<Decision> {
+        self.inner.decisions()
+    }
+
+    fn checkpoint(&self) -> Option<serde_json::Value> {
+        self.inner.checkpoint()
+    }
+
+    fn restore(&mut self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore(state)
+    }
+}
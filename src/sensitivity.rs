@@ -0,0 +1,200 @@
+//! For one-at-a-time sensitivity analysis: every model parameter is perturbed on its own, down and
+//! up by the same relative amount, while every other parameter keeps its base value. Ranking the
+//! parameters by how far each perturbation moves the key outputs (a tornado chart) shows which
+//! assumptions matter most. Every run uses the same seed, so that differences come from the
+//! parameters rather than from the noise.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::metrics::Outcome;
+use crate::parallel;
+use crate::simulation::Simulation;
+use std::io::Write;
+
+/// The key outputs that parameters are ranked by, and how to read each of them from an outcome.
+pub type Metric = (&'static str, fn(&Outcome) -> f64);
+
+/// The key outputs that parameters are ranked by.
+pub const METRICS: [Metric; 3] = [
+    ("final TVL", |outcome| outcome.tvl.0),
+    ("cumulative node income", |outcome| outcome.node_income.0),
+    ("minimum TVB/TVL ratio", |outcome| {
+        outcome.min_security_ratio
+    }),
+];
+
+/// Perturbation is the result of perturbing one parameter down and up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Perturbation {
+    /// The dotted path of the parameter in the configuration.
+    pub path: String,
+    /// The base value of the parameter.
+    pub base: f64,
+    /// The perturbed values of the parameter (down, then up).
+    pub values: [f64; 2],
+    /// The outcomes of the perturbed runs (down, then up).
+    pub outcomes: [Outcome; 2],
+}
+
+impl Perturbation {
+    /// How far the perturbation moved the metric, from the lowest to the highest outcome.
+    pub fn swing(&self, metric: &Metric) -> f64 {
+        let (_, value) = metric;
+        (value(&self.outcomes[1]) - value(&self.outcomes[0])).abs()
+    }
+}
+
+/// Report holds the base outcome, and the outcome of perturbing every parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// The relative perturbation that was applied (e.g. 0.1 is ±10%).
+    pub perturbation: f64,
+    pub base: Outcome,
+    pub perturbations: Vec<Perturbation>,
+}
+
+/// Perturb a parameter value by a relative amount. Integer parameters (like window lengths) are
+/// rounded, but always move by at least one so that they are actually perturbed.
+fn perturb(value: f64, integer: bool, by: f64) -> f64 {
+    let perturbed = value * (1.0 + by);
+    if !integer {
+        return perturbed;
+    }
+    let rounded = perturbed.round();
+    if rounded != value || value == 0.0 {
+        rounded.max(1.0)
+    } else {
+        (value + by.signum()).max(1.0)
+    }
+}
+
+/// Perturb every model parameter of the configuration down and up by a relative amount (e.g. 0.1
+/// is ±10%), and run the simulation for each (in parallel).
+pub fn run(config: &Config, perturbation: f64, seed: u64) -> Result<Report> {
+    run_with_progress(config, perturbation, seed, &|| {})
+}
+
+/// Run the analysis like `run`, calling `progress` (from whichever thread ran it) once for the base
+/// run and once for every parameter, as each is simulated.
+pub fn run_with_progress(
+    config: &Config,
+    perturbation: f64,
+    seed: u64,
+    progress: &(dyn Fn() + Sync),
+) -> Result<Report> {
+    let simulate = |config: &Config| -> Result<Outcome> {
+        let mut sim = Simulation::from_config(config).with_seed(seed);
+        sim.try_run(config.steps)?;
+        Ok(Outcome::from_aggregates(sim.history().aggregates()))
+    };
+    let perturbations = parallel::map(config.parameters(), |path| {
+        let base = config.get(&path)?;
+        let integer = config.is_integer(&path)?;
+        let values = [
+            perturb(base, integer, -perturbation),
+            perturb(base, integer, perturbation),
+        ];
+        let mut outcomes = [None, None];
+        for (outcome, &value) in outcomes.iter_mut().zip(&values) {
+            let mut config = config.clone();
+            config.set(&path, value)?;
+            *outcome = Some(simulate(&config)?);
+        }
+        let [down, up] = outcomes;
+        progress();
+        Ok(Perturbation {
+            path,
+            base,
+            values,
+            outcomes: [down.expect("simulated"), up.expect("simulated")],
+        })
+    })
+    .into_iter()
+    .collect::<Result<_>>()?;
+    let base = simulate(config)?;
+    progress();
+    Ok(Report {
+        perturbation,
+        base,
+        perturbations,
+    })
+}
+
+impl Report {
+    /// The perturbations ranked by how far they swing the metric, largest first.
+    pub fn ranked(&self, metric: &Metric) -> Vec<&Perturbation> {
+        let mut ranked: Vec<_> = self.perturbations.iter().collect();
+        ranked.sort_by(|a, b| {
+            let (a, b) = (a.swing(metric), b.swing(metric));
+            b.partial_cmp(&a)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+        });
+        ranked
+    }
+
+    /// Write a tornado-style ranking of the parameters for every metric, as text.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        const BAR: f64 = 30.0;
+        let pct = self.perturbation * 100.0;
+        for metric in &METRICS {
+            let (name, value) = metric;
+            let base = value(&self.base);
+            writeln!(writer, "{} (base {:.4})", name, base)?;
+            writeln!(
+                writer,
+                "  {:>4}  {:<36} {:>16} {:>16}",
+                "rank",
+                "parameter",
+                format!("-{}%", pct),
+                format!("+{}%", pct),
+            )?;
+            let ranked = self.ranked(metric);
+            let widest = ranked
+                .first()
+                .map(|p| p.swing(metric))
+                .filter(|swing| swing.is_finite() && *swing > 0.0)
+                .unwrap_or(1.0);
+            for (rank, p) in ranked.iter().enumerate() {
+                let bar = if p.swing(metric).is_finite() {
+                    (p.swing(metric) / widest * BAR).round() as usize
+                } else {
+                    0
+                };
+                writeln!(
+                    writer,
+                    "  {:>4}  {:<36} {:>+16.4} {:>+16.4} {}",
+                    rank + 1,
+                    p.path,
+                    value(&p.outcomes[0]) - base,
+                    value(&p.outcomes[1]) - base,
+                    "#".repeat(bar),
+                )?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write every perturbation as CSV, with one row per parameter and direction.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        writer.write_record(
+            ["parameter", "base", "value"]
+                .iter()
+                .copied()
+                .chain(Outcome::FIELDS.iter().copied()),
+        )?;
+        for p in &self.perturbations {
+            for (value, outcome) in p.values.iter().zip(&p.outcomes) {
+                writer.write_record(
+                    [p.path.clone(), p.base.to_string(), value.to_string()]
+                        .iter()
+                        .cloned()
+                        .chain(outcome.values().iter().map(|v| v.to_string())),
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! For watching runs in a browser, so that several people can follow (and replay) the same run
+//! without installing anything. With the `serve` feature, `econ-sim serve` runs the simulation at a
+//! steady pace and streams every epoch over a websocket (at `/ws`) to a dashboard that is bundled
+//! into the binary (and served at `/`). Epochs are sent as the same records as the JSON Lines
+//! output, beginning with the header, and a run ends with a `summary` record (or an `error` record,
+//! if it breaks an invariant). Everybody who connects is first sent every record so far, so late
+//! arrivals see the whole run, and the server keeps serving after the run is complete.
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::metrics::{Report, Security};
+use crate::output::{jsonl, Metadata, Sink};
+use crate::simulation::Simulation;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// The dashboard, which connects back to the server that it was loaded from.
+const DASHBOARD: &str = include_str!("../web/dashboard.html");
+
+/// How long a connection may take to send a request, or to accept a record, before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most header lines that are read from a request.
+const MAX_HEADERS: usize = 100;
+
+/// The slowest and fastest that a run can go, in epochs per second.
+const SPEEDS: (f64, f64) = (0.01, 1e6);
+
+/// The records that end a run.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Summary {
+        #[serde(flatten)]
+        summary: Box<Report>,
+        breaches: usize,
+        first_breach: Option<usize>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Channel holds every record of the run so far, and the websockets that they are sent to.
+#[derive(Default)]
+struct Channel {
+    records: Vec<String>,
+    clients: Vec<WebSocket<TcpStream>>,
+}
+
+impl Channel {
+    /// Send a record to every client (dropping the ones that cannot take it), and keep it for
+    /// clients that connect later.
+    fn send(&mut self, record: String) {
+        self.clients
+            .retain_mut(|client| client.send(Message::text(record.as_str())).is_ok());
+        self.records.push(record);
+    }
+
+    /// Send every record so far to a new client, and then keep it for the records that follow.
+    fn join(&mut self, mut client: WebSocket<TcpStream>) {
+        for record in &self.records {
+            if client.send(Message::text(record.as_str())).is_err() {
+                return;
+            }
+        }
+        self.clients.push(client);
+    }
+}
+
+/// Broadcast sends every line that is written to it as a record.
+struct Broadcast {
+    channel: Arc<Mutex<Channel>>,
+    line: Vec<u8>,
+}
+
+impl Write for Broadcast {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                let record = String::from_utf8_lossy(&self.line).into_owned();
+                self.channel.lock().expect("channel lock").send(record);
+                self.line.clear();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Serve the dashboard on the address (like `127.0.0.1:8080`), and run the simulation for the given
+/// number of epochs at the given speed (in epochs per second), streaming every epoch (leaving out
+/// the warm-up) to everybody who is watching. The run stops early at a steady state or at its first
+/// divergence if the configuration says so. The server keeps serving until the process is stopped,
+/// so this only returns if the address cannot be bound.
+pub fn serve(
+    mut sim: Simulation,
+    config: &Config,
+    steps: usize,
+    address: &str,
+    speed: f64,
+) -> Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let channel = Arc::new(Mutex::new(Channel::default()));
+    let accepting = {
+        let channel = channel.clone();
+        thread::spawn(move || accept(listener, channel))
+    };
+
+    let metadata = Metadata::new(config, sim.seed());
+    let broadcast = Broadcast {
+        channel: channel.clone(),
+        line: vec![],
+    };
+    let mut writer = jsonl::Writer::new(broadcast, &metadata)?.with_flush_every(1);
+    if config.warmup == 0 {
+        writer.write(0, sim.history().latest())?;
+    }
+    let interval = Duration::from_secs_f64(1.0 / speed.clamp(SPEEDS.0, SPEEDS.1));
+    let mut next = Instant::now();
+    let mut failure = None;
+    for _ in 0..steps {
+        next += interval;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+        match sim.try_step() {
+            Ok(state) if sim.epoch() >= config.warmup => writer.write(sim.epoch(), &state)?,
+            Ok(_) => {}
+            Err(err) => {
+                failure = Some(err.to_string());
+                break;
+            }
+        }
+        if config.steady_state.stop && sim.history().aggregates().equilibrium.is_some() {
+            break;
+        }
+        if sim.aborted().is_some() || sim.stopped().is_some() {
+            break;
+        }
+    }
+    // A run that ends during the warm-up is represented by its last state.
+    if sim.epoch() < config.warmup {
+        writer.write(sim.epoch(), sim.history().latest())?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    let record = match failure {
+        Some(message) => Record::Error { message },
+        None => {
+            let aggregates = sim.history().aggregates();
+            let security = Security::from_aggregates(aggregates);
+            Record::Summary {
+                summary: Box::new(
+                    Report::from_aggregates(aggregates)
+                        .with_health(aggregates, &config.health)
+                        .with_stop(sim.stopped()),
+                ),
+                breaches: security.breaches,
+                first_breach: security.first_breach,
+            }
+        }
+    };
+    let record = serde_json::to_string(&record)?;
+    channel.lock().expect("channel lock").send(record);
+    accepting.join().expect("accepting connections");
+    Ok(())
+}
+
+/// Accept connections until the listener fails, handling each on its own thread.
+fn accept(listener: TcpListener, channel: Arc<Mutex<Channel>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                tracing::warn!(%err, "cannot accept connection");
+                continue;
+            }
+        };
+        let channel = channel.clone();
+        thread::spawn(move || {
+            if let Err(err) = handle(stream, &channel) {
+                tracing::debug!(%err, "connection failed");
+            }
+        });
+    }
+}
+
+/// Answer one request: the dashboard, or a websocket that the records are sent to.
+fn handle(mut stream: TcpStream, channel: &Mutex<Channel>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut key = None;
+    for _ in 0..MAX_HEADERS {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    match (path, key) {
+        ("/ws", Some(key)) => {
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: {}\r\n\r\n",
+                derive_accept_key(key.as_bytes())
+            )?;
+            let client = WebSocket::from_raw_socket(stream, Role::Server, None);
+            channel.lock().expect("channel lock").join(client);
+            Ok(())
+        }
+        ("/", _) | ("/index.html", _) => respond(stream, "200 OK", "text/html", DASHBOARD),
+        _ => respond(stream, "404 Not Found", "text/plain", "not found\n"),
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, kind: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n{}",
+        status,
+        kind,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,146 @@
+//! For splitting locked value and bonds between shards. Every shard is only secured by the bonds of
+//! its own darknodes, so a shard that locks more than its share of value can be insecure while TVB
+//! still covers TVL in aggregate. The models only decide the aggregate values; this decides how
+//! they are spread. Minted value goes to shards by weight, and burns and revaluations change every
+//! shard in proportion to what it locks. Bonds that join (counted in REN, so that changes in the
+//! price of REN move every shard alike) are spread evenly, and bonds that leave are taken from
+//! every shard in proportion to what it bonds. At the end of every epoch, the rebalancing rule then
+//! moves bonds or locked value between shards.
+
+use crate::config::{RebalanceRule, ShardsConfig};
+use crate::state::{ShardState, State};
+use crate::Usd;
+
+/// Shards splits the aggregate state between shards according to a `ShardsConfig`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shards {
+    /// The share of minted value that goes to each shard, which sum to one.
+    weights: Vec<f64>,
+    rebalance: RebalanceRule,
+    rate: f64,
+}
+
+impl Shards {
+    /// Create the shards using the given parameters. Returns `None` when there are no shards.
+    pub fn new(config: &ShardsConfig) -> Option<Self> {
+        if config.count == 0 {
+            return None;
+        }
+        // Shards without a weight get none of the minted value, unless no shard has a weight.
+        let mut weights: Vec<f64> = (0..config.count)
+            .map(|i| config.weights.get(i).copied().unwrap_or(0.0).max(0.0))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total > 0.0 && total.is_finite() {
+            weights.iter_mut().for_each(|weight| *weight /= total);
+        } else {
+            weights = vec![1.0 / config.count as f64; config.count];
+        }
+        Some(Self {
+            weights,
+            rebalance: config.rebalance,
+            rate: config.rebalance_rate.0.clamp(0.0, 1.0),
+        })
+    }
+
+    /// The number of shards.
+    pub fn count(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Split the aggregate TVL and TVB of the state between shards, following on from the shards of
+    /// the previous state (or from an even split of it, if it was not sharded), where `minted` is
+    /// the value minted this epoch. The security ratio of the state becomes the lowest ratio of any
+    /// shard.
+    pub fn split(&self, previous: &State, state: &mut State, minted: Usd) {
+        let n = self.count();
+        let shards = if previous.shards.len() == n {
+            previous.shards.clone()
+        } else {
+            self.weights
+                .iter()
+                .map(|weight| ShardState {
+                    tvl: previous.tvl * *weight,
+                    tvb: previous.tvb / n as f64,
+                    security_ratio: 0.0,
+                })
+                .collect()
+        };
+        let locked: Usd = shards.iter().map(|shard| shard.tvl).sum();
+        let bonded: Usd = shards.iter().map(|shard| shard.tvb).sum();
+
+        // Locked value, before rebalancing.
+        let rest = state.tvl - locked - minted;
+        let mut tvl: Vec<f64> = shards
+            .iter()
+            .zip(&self.weights)
+            .map(|(shard, weight)| {
+                let share = if locked > Usd::ZERO {
+                    shard.tvl / locked
+                } else {
+                    *weight
+                };
+                (shard.tvl + minted * *weight + rest * share).0
+            })
+            .collect();
+
+        // Shares of the bonds, before rebalancing.
+        let mut bonds: Vec<f64> = shards
+            .iter()
+            .map(|shard| {
+                if bonded > Usd::ZERO {
+                    shard.tvb / bonded
+                } else {
+                    1.0 / n as f64
+                }
+            })
+            .collect();
+        let (before, after) = if state.tvb_ren.0 > 0.0 {
+            (previous.tvb_ren.0, state.tvb_ren.0)
+        } else {
+            (previous.tvb.0, state.tvb.0)
+        };
+        if after > before {
+            let joined = (after - before) / after;
+            bonds
+                .iter_mut()
+                .for_each(|share| *share = *share * (1.0 - joined) + joined / n as f64);
+        }
+
+        let total = state.tvl.0;
+        match self.rebalance {
+            RebalanceRule::None => {}
+            RebalanceRule::Bonds if total > 0.0 => {
+                for (share, tvl) in bonds.iter_mut().zip(&tvl) {
+                    *share += self.rate * (tvl / total - *share);
+                }
+            }
+            RebalanceRule::Bonds => {}
+            RebalanceRule::Value => {
+                for (tvl, share) in tvl.iter_mut().zip(&bonds) {
+                    *tvl += self.rate * (share * total - *tvl);
+                }
+            }
+        }
+
+        state.shards = tvl
+            .into_iter()
+            .zip(bonds)
+            .map(|(tvl, share)| {
+                let (tvl, tvb) = (Usd(tvl), state.tvb * share);
+                ShardState {
+                    tvl,
+                    tvb,
+                    security_ratio: if tvl > Usd::ZERO { tvb / tvl } else { 0.0 },
+                }
+            })
+            .collect();
+        state.security_ratio = state
+            .shards
+            .iter()
+            .filter(|shard| shard.tvl > Usd::ZERO)
+            .map(|shard| shard.security_ratio)
+            .reduce(f64::min)
+            .unwrap_or(0.0);
+    }
+}
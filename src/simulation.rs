@@ -0,0 +1,1081 @@
+//! For running the simulation. You probably do not need to modify this code at all; implement the
+//! `ExternalModel` and `InternalModel` traits instead.
+
+use crate::accounting::Ledger;
+use crate::attacker::{Attacker, Breach};
+use crate::audit::Decision;
+use crate::builder::SimulationBuilder;
+use crate::checkpoint::Checkpoint;
+use crate::config::{
+    AccountingConfig, Config, EpochConfig, OperatingCostConfig, Overflow, PipelineConfig,
+    RebatePoolConfig, SlashDestination, Stage,
+};
+use crate::divergence::{Detector, Divergence};
+use crate::error::{Error, Result};
+use crate::external::{self, ExternalModel};
+use crate::history::History;
+use crate::internal::{self, InternalModel};
+use crate::invariants::Invariants;
+use crate::mint_cap::MintCap;
+use crate::observer::Observer;
+use crate::registry;
+#[cfg(feature = "script")]
+use crate::script::Script;
+use crate::shards::Shards;
+use crate::slashing::Slashing;
+use crate::state::{AssetState, State};
+use crate::steady_state::SteadyState;
+use crate::stop::{self, Stop};
+use crate::subsidy::Subsidy;
+use crate::{Pct, Ren, SimRng, Usd};
+use rand::SeedableRng;
+use tracing::trace;
+
+/// The log targets of model calls, so that their levels can be set by module like everything else
+/// (the calls are made from here, but the numbers come from the models).
+const EXTERNAL: &str = "eco::external";
+const INTERNAL: &str = "eco::internal";
+
+/// Simulation owns the history of states produced so far, and advances it one epoch at a time by
+/// calling into the external and internal models. The history always begins with the initial
+/// state, so after `n` steps it contains `n + 1` states (although older states are dropped if it is
+/// bounded).
+///
+/// A simulation is also an iterator over the states that it produces, one per `step`, so that it
+/// can be advanced epoch by epoch while the caller inspects the history or sets its `inputs` in
+/// between:
+///
+/// ```
+/// # use eco::{Simulation, Usd};
+/// let mut sim = Simulation::default().with_seed(42);
+/// for state in sim.by_ref().take(30) {
+///     assert!(state.tvl >= Usd::ZERO);
+/// }
+/// // A crash in the price of REN from epoch 31 on.
+/// sim.inputs_mut().ren_price = Some(Usd(0.05));
+/// let state = sim.next().expect("the run is not aborted");
+/// assert_eq!(state.ren_price, Usd(0.05));
+/// ```
+pub struct Simulation {
+    history: History,
+    external: Box<dyn ExternalModel>,
+    internal: Box<dyn InternalModel>,
+    schedule: Vec<(usize, Box<dyn InternalModel>)>,
+    assets: Vec<String>,
+    invariants: Invariants,
+    ledger: Ledger,
+    slashing: Option<Slashing>,
+    attacker: Option<Attacker>,
+    mint_cap: Option<MintCap>,
+    shards: Option<Shards>,
+    subsidy: Option<Subsidy>,
+    rebate_pool: RebatePoolConfig,
+    /// Whether the run stops at its first divergence.
+    abort: bool,
+    /// The conditions that stop the run early.
+    stop: stop::Conditions,
+    /// The number of epochs from one epoch boundary to the next.
+    boundary: usize,
+    /// The order of the stages of every epoch.
+    pipeline: PipelineConfig,
+    /// The decisions of the internal model in the latest epoch.
+    decisions: Vec<Decision>,
+    /// The inputs that are set in place of what the external model returns.
+    inputs: Inputs,
+    /// What is told about every epoch once it has been simulated.
+    observers: Vec<Box<dyn Observer>>,
+    /// The number of epochs in a year, and the REN bonded by a darknode, for deriving metrics.
+    epochs_per_year: f64,
+    bond: Ren,
+    seed: u64,
+    rng: SimRng,
+}
+
+impl Simulation {
+    /// Create a new simulation that begins from the default (all zero) state and is driven by the
+    /// given models. The random number generator is seeded with a random seed; use `with_seed` to
+    /// reproduce an earlier run.
+    pub fn new<E, I>(external: E, internal: I) -> Self
+    where
+        E: ExternalModel + 'static,
+        I: InternalModel + 'static,
+    {
+        Self {
+            history: History::new(State::default()),
+            assets: external.assets(),
+            external: Box::new(external),
+            internal: Box::new(internal),
+            schedule: vec![],
+            invariants: Invariants::default(),
+            ledger: Ledger::new(&AccountingConfig::default()),
+            slashing: None,
+            attacker: None,
+            mint_cap: None,
+            shards: None,
+            subsidy: None,
+            rebate_pool: RebatePoolConfig::default(),
+            abort: false,
+            stop: stop::Conditions::default(),
+            boundary: 1,
+            pipeline: PipelineConfig::default(),
+            decisions: vec![],
+            inputs: Inputs::default(),
+            observers: vec![],
+            epochs_per_year: EpochConfig::default().per_year(),
+            bond: OperatingCostConfig::default().bond,
+            seed: 0,
+            rng: SimRng::seed_from_u64(0),
+        }
+        .with_seed(rand::random())
+    }
+
+    /// Build a simulation from the default configuration, replacing any of the functions of its
+    /// models with closures (see `SimulationBuilder`).
+    pub fn builder() -> SimulationBuilder {
+        SimulationBuilder::new()
+    }
+
+    /// Create a new simulation driven by the basic external and internal models, using the
+    /// parameters from the given configuration (and the functions of its script, and the models
+    /// that it selects by name, if it has them).
+    pub fn from_config(config: &Config) -> Self {
+        if registry::selects_registered(config) {
+            return Self::builder().config(config).build();
+        }
+        let config = &*registry::resolve(config);
+        let (external, internal) = basic_models(config);
+        #[cfg(feature = "script")]
+        if let Some(script) = Script::from_config(config) {
+            return Self::from_models(config, script.external(external), script.internal(internal))
+                .with_governance(config);
+        }
+        Self::from_models(config, external, internal).with_governance(config)
+    }
+
+    /// Create a new simulation driven by the given models, using the initial state, invariants,
+    /// accounting, slashing, attacker, mint cap, shards, subsidy, rebate pool cap, seed, history
+    /// window, steady-state detector, divergence detector, stop conditions, and pipeline from the
+    /// given configuration.
+    pub fn from_models<E, I>(config: &Config, external: E, internal: I) -> Self
+    where
+        E: ExternalModel + 'static,
+        I: InternalModel + 'static,
+    {
+        let mut sim = Self::new(external, internal)
+            .with_initial_state(config.initial.state())
+            .with_invariants(Invariants::new(&config.invariants))
+            .with_ledger(Ledger::new(&config.accounting))
+            .with_rebate_pool(config.internal.rebate_pool)
+            .with_abort(config.health.abort)
+            .with_stop_conditions(stop::Conditions::new(&config.stop))
+            .with_pipeline(config.pipeline)
+            .with_history_window(config.history.window(&config.epoch));
+        if let Some(slashing) = Slashing::new(
+            config.internal.slashing,
+            config.external.operating_cost.bond,
+        ) {
+            sim = sim.with_slashing(slashing);
+        }
+        if let Some(attacker) = config.external.attacker {
+            sim = sim.with_attacker(Attacker::new(attacker, config.attack));
+        }
+        if let Some(mint_cap) = config.internal.mint_cap.and_then(MintCap::new) {
+            sim = sim.with_mint_cap(mint_cap);
+        }
+        if let Some(shards) = Shards::new(&config.internal.shards) {
+            sim = sim.with_shards(shards);
+        }
+        if let Some(subsidy) = Subsidy::new(config.internal.subsidy, &config.epoch) {
+            sim = sim.with_subsidy(subsidy);
+        }
+        sim.boundary = config.epoch.boundary();
+        sim.epochs_per_year = config.epoch.per_year();
+        sim.bond = config
+            .external
+            .bonding
+            .bond(config.external.operating_cost.bond);
+        // An invalid detector is reported by `SteadyState::new` wherever the steady state is used,
+        // so here it only means that the run is not watched.
+        let steady_state = SteadyState::new(&config.steady_state, &config.epoch).ok();
+        sim.history = sim
+            .history
+            .with_aggregates(config.warmup, config.security.required_ratio)
+            .with_steady_state(steady_state)
+            .with_divergence(Detector::new(&config.health));
+        match config.seed {
+            Some(seed) => sim.with_seed(seed),
+            None => sim,
+        }
+    }
+
+    /// Resume a simulation from a checkpoint, using the models and parameters from the
+    /// configuration in the checkpoint. It continues exactly as the original run would have.
+    pub fn resume(checkpoint: &Checkpoint) -> Result<Self> {
+        let mut sim = Self::from_config(&checkpoint.config).with_seed(checkpoint.seed);
+        sim.replace_history(
+            checkpoint.first_epoch,
+            checkpoint.history.clone(),
+            checkpoint.aggregates.clone(),
+        );
+        sim.rng = checkpoint.rng.clone();
+        // The internal state in the checkpoint belongs to whichever model was in effect last.
+        sim.apply_schedule(sim.epoch());
+        if let Some(state) = &checkpoint.external {
+            sim.external.restore(state.clone())?;
+        }
+        if let Some(state) = &checkpoint.internal {
+            sim.internal.restore(state.clone())?;
+        }
+        if let Some(balances) = &checkpoint.ledger {
+            sim.ledger.restore(balances.clone())?;
+        }
+        if let Some(attacker) = &checkpoint.attacker {
+            sim.attacker = Some(attacker.clone());
+        }
+        if let Some(mint_cap) = &checkpoint.mint_cap {
+            sim.mint_cap = Some(mint_cap.clone());
+        }
+        if let Some(stop) = &checkpoint.stop {
+            sim.stop = stop.clone();
+        }
+        Ok(sim)
+    }
+
+    /// Restart a simulation from the state at the given epoch of a checkpoint, using the models and
+    /// parameters from a (possibly modified) configuration. The history after that epoch is
+    /// discarded. The models begin afresh from the recorded history (so anything that they keep
+    /// internally, like the current price of a price model, starts over), and the random number
+    /// generator is reseeded from the seed of the checkpoint and the epoch. The checkpoint must
+    /// hold the whole history (it cannot be bounded).
+    pub fn restart(checkpoint: &Checkpoint, epoch: usize, config: &Config) -> Result<Self> {
+        if checkpoint.first_epoch > 0 {
+            return Err(Error::Config(format!(
+                "cannot restart from a checkpoint whose history begins at epoch {}",
+                checkpoint.first_epoch
+            )));
+        }
+        if epoch > checkpoint.epoch() {
+            return Err(Error::Config(format!(
+                "cannot restart from epoch {}, the checkpoint ends at epoch {}",
+                epoch,
+                checkpoint.epoch()
+            )));
+        }
+        let mut sim =
+            Self::from_config(config).with_seed(checkpoint.seed.wrapping_add(epoch as u64));
+        sim.replace_history(0, checkpoint.history[..=epoch].to_vec(), None);
+        Ok(sim)
+    }
+
+    /// Save the simulation so far, including the exact state of the random number generator and
+    /// the models, so that it can be resumed later. The configuration should be the one that the
+    /// simulation was created from.
+    pub fn checkpoint(&self, config: &Config) -> Checkpoint {
+        Checkpoint {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config: config.clone(),
+            seed: self.seed,
+            rng: self.rng.clone(),
+            external: self.external.checkpoint(),
+            internal: self.internal.checkpoint(),
+            ledger: self.ledger.checkpoint(),
+            attacker: self.attacker.clone(),
+            mint_cap: self.mint_cap.clone(),
+            stop: (!self.stop.is_empty()).then(|| self.stop.clone()),
+            history: self.history.states().to_vec(),
+            first_epoch: self.history.first_epoch(),
+            aggregates: if self.history.is_complete() {
+                None
+            } else {
+                Some(self.history.aggregates().clone())
+            },
+        }
+    }
+
+    /// Replace the initial state of the simulation. This discards any history that has already
+    /// been simulated.
+    pub fn with_initial_state(mut self, state: State) -> Self {
+        self.replace_history(0, vec![state], None);
+        self
+    }
+
+    /// Keep only the states of the latest `window` epochs while the simulation runs, or every state
+    /// if there is no window (the default). The window must be at least as long as the longest
+    /// window that the models look back over.
+    pub fn with_history_window(mut self, window: Option<usize>) -> Self {
+        self.history = self.history.with_window(window);
+        self
+    }
+
+    /// Slash darknodes that misbehave (nothing is slashed by default).
+    pub fn with_slashing(mut self, slashing: Slashing) -> Self {
+        self.slashing = Some(slashing);
+        self
+    }
+
+    /// Add an adversary that bonds REN over time and attacks when it pays (there is none by
+    /// default).
+    pub fn with_attacker(mut self, attacker: Attacker) -> Self {
+        self.attacker = Some(attacker);
+        self
+    }
+
+    /// Cap minting by TVB (nothing is capped by default).
+    pub fn with_mint_cap(mut self, mint_cap: MintCap) -> Self {
+        self.mint_cap = Some(mint_cap);
+        self
+    }
+
+    /// Split locked value and bonds between shards, each with its own security ratio (they are
+    /// compared in aggregate by default).
+    pub fn with_shards(mut self, shards: Shards) -> Self {
+        self.shards = Some(shards);
+        self
+    }
+
+    /// Subsidise node operators with emitted REN on top of their fees (there is no subsidy by
+    /// default).
+    pub fn with_subsidy(mut self, subsidy: Subsidy) -> Self {
+        self.subsidy = Some(subsidy);
+        self
+    }
+
+    /// Cap the rebate pool, sending the fees that would take it beyond the cap elsewhere (the pool
+    /// is not capped by default).
+    pub fn with_rebate_pool(mut self, rebate_pool: RebatePoolConfig) -> Self {
+        self.rebate_pool = rebate_pool;
+        self
+    }
+
+    /// Replace the invariants that are checked by `try_step` (every invariant is checked by
+    /// default).
+    pub fn with_invariants(mut self, invariants: Invariants) -> Self {
+        self.invariants = invariants;
+        self
+    }
+
+    /// Stop the run at its first divergence (if divergence is watched for), rather than running it
+    /// to the end.
+    pub fn with_abort(mut self, abort: bool) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    /// Stop the run early once one of the conditions is met (see `stop`).
+    pub fn with_stop_conditions(mut self, conditions: stop::Conditions) -> Self {
+        self.stop = conditions;
+        self
+    }
+
+    /// Simulate the stages of every epoch in the order of the given pipeline (see `step`).
+    pub fn with_pipeline(mut self, pipeline: PipelineConfig) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Replace the ledger that accounts for fees, rebates, and the balances that RenVM keeps (which
+    /// is floating point by default).
+    pub fn with_ledger(mut self, ledger: Ledger) -> Self {
+        self.ledger = ledger;
+        self
+    }
+
+    /// Tell the observer about every epoch from now on, once it has been simulated (see
+    /// `Observer`). Observers are told in the order that they were added, and are not kept in
+    /// checkpoints.
+    pub fn with_observer<O>(mut self, observer: O) -> Self
+    where
+        O: Observer + 'static,
+    {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Replace the internal model with another one from the given epoch onwards (where the first
+    /// simulated epoch is epoch one), for example to model governance changing the fees mid-run.
+    /// Models are replaced in the order of their epochs, and anything that the replaced model kept
+    /// internally is lost.
+    pub fn with_internal_from<I>(mut self, epoch: usize, internal: I) -> Self
+    where
+        I: InternalModel + 'static,
+    {
+        self.schedule.push((epoch, Box::new(internal)));
+        self.schedule.sort_by_key(|(epoch, _)| *epoch);
+        self
+    }
+
+    /// Schedule the governance changes in the configuration, each of which replaces the internal
+    /// model with a basic one that uses the changed parameters (and every earlier change), and the
+    /// functions of the script in the configuration, if it has one. The built-in models that the
+    /// configuration selects by name are selected for them too, but registered models are not (a
+    /// `SimulationBuilder` keeps them in effect).
+    pub fn with_governance(self, config: &Config) -> Self {
+        let config = &*registry::resolve(config);
+        #[cfg(feature = "script")]
+        let script = Script::from_config(config);
+        governance(config)
+            .into_iter()
+            .fold(self, |sim, (epoch, model)| {
+                #[cfg(feature = "script")]
+                if let Some(script) = &script {
+                    return sim.with_internal_from(epoch, script.internal(model));
+                }
+                sim.with_internal_from(epoch, model)
+            })
+    }
+
+    /// Reseed the random number generator. Two simulations with the same models, initial state, and
+    /// seed will produce exactly the same history.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = SimRng::seed_from_u64(seed);
+        self
+    }
+
+    /// The seed that the random number generator was seeded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The names of the assets that are locked in RenVM, in the same order as `State::assets`.
+    /// This is empty when locked value is only modelled in aggregate.
+    pub fn assets(&self) -> &[String] {
+        &self.assets
+    }
+
+    /// The number of shards that locked value and bonds are split between, in the same order as
+    /// `State::shards`. This is zero when TVB is only compared with TVL in aggregate.
+    pub fn shards(&self) -> usize {
+        self.shards.as_ref().map_or(0, Shards::count)
+    }
+
+    /// The history of states produced so far, beginning with the initial state.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// The number of epochs that have been simulated so far.
+    pub fn epoch(&self) -> usize {
+        self.history.epoch()
+    }
+
+    /// The decisions that the internal model made in the latest epoch (and why), for the audit log.
+    /// They are empty before the first epoch, and in epochs between epoch boundaries (in which no
+    /// curve is called).
+    pub fn decisions(&self) -> &[Decision] {
+        &self.decisions
+    }
+
+    /// The inputs that are set in place of what the external model returns, from the next epoch on.
+    pub fn inputs(&self) -> &Inputs {
+        &self.inputs
+    }
+
+    /// Set (or clear) inputs in place of what the external model returns, from the next epoch on.
+    /// They stay set until they are changed, and are not kept in checkpoints.
+    pub fn inputs_mut(&mut self) -> &mut Inputs {
+        &mut self.inputs
+    }
+
+    /// Consume the simulation and return the states in its history (which is every state, unless
+    /// the history is bounded).
+    pub fn into_history(self) -> Vec<State> {
+        self.history.into_states()
+    }
+
+    /// Replace the history with the given states (where the first of them is the state at
+    /// `first_epoch`), keeping the same window and the same settings for the aggregates. The
+    /// aggregates are accumulated from the states unless they are given.
+    fn replace_history(
+        &mut self,
+        first_epoch: usize,
+        states: Vec<State>,
+        aggregates: Option<crate::history::Aggregates>,
+    ) {
+        let settings = self.history.aggregates();
+        let (warmup, required_ratio) = (settings.warmup, settings.required_ratio);
+        let history = match aggregates {
+            Some(aggregates) => History::from_parts(first_epoch, states, Some(aggregates)),
+            None => History::from_parts(first_epoch, states, None)
+                .with_aggregates(warmup, required_ratio),
+        };
+        self.history = history
+            .with_window(self.history.window())
+            .with_steady_state(self.history.steady_state().cloned())
+            .with_divergence(self.history.divergence().cloned());
+    }
+
+    /// Switch to the last internal model that is scheduled for the given epoch (or earlier).
+    fn apply_schedule(&mut self, epoch: usize) {
+        let due = self
+            .schedule
+            .iter()
+            .take_while(|(from, _)| *from <= epoch)
+            .count();
+        if let Some((_, internal)) = self.schedule.drain(..due).next_back() {
+            tracing::debug!(epoch, "internal model replaced");
+            self.internal = internal;
+        }
+    }
+
+    /// Advance the simulation by one epoch, returning the new state.
+    ///
+    /// An epoch is simulated as a pipeline of stages (see `Stage`), in the order that the
+    /// configuration gives them, which is by default:
+    ///
+    /// 1. Fees: the volumes that are minted and burned (within the mint cap, and what is left after
+    ///    a theft), the prices of locked assets, the fee levels and the rebate, and the fees that
+    ///    are charged.
+    /// 2. Rebate: the shares of the fees that the treasury, the buy-and-burn, and the rebate pool
+    ///    take, and the rebate that the pool pays on what was burned.
+    /// 3. Bonds: the price of REN, and then the value that is bonded, the darknodes, slashing, the
+    ///    subsidy, and the bond of the attacker.
+    /// 4. Locked: the value that is locked, after what was minted and burned and its revaluation.
+    /// 5. Claims: the unclaimed fees that nodes claim.
+    ///
+    /// The epoch is then closed in the same way whatever the order: the REN that was bought is
+    /// burned, slashed bonds and emitted REN are collected, the attacker attacks, the security
+    /// ratio and shards follow from what is locked and bonded, the rebate pool is kept under its
+    /// cap, and the ledger is settled into the balances. Unless the pipeline is sequential, every
+    /// model sees the history up to the previous epoch, whichever stage it is called in (so, for
+    /// example, `total_value_bonded` never sees the value locked in the same epoch). When it is,
+    /// the epoch so far is held after the previous epoch as the latest state while every stage is
+    /// simulated, and the rebate pool and claims are settled in their own stages.
+    pub fn step(&mut self) -> State {
+        // The epoch that is being simulated.
+        let span = tracing::debug_span!("epoch", epoch = self.history.len());
+        let _entered = span.enter();
+        self.apply_schedule(self.history.len());
+        let mut state = self.history.latest().clone();
+        let mut flows = Flows {
+            // Epoch-based mechanics only happen in the first epoch after a boundary. In between,
+            // fee levels and the rebate stay where they were, nothing is claimed, and no darknode
+            // registers or deregisters.
+            boundary: (self.history.len() - 1).is_multiple_of(self.boundary),
+            room: self.mint_cap.as_mut().map(|cap| cap.room(&state)),
+            ..Flows::default()
+        };
+        let pipeline = self.pipeline;
+        for &stage in pipeline.order.stages() {
+            // A sequential stage sees the epoch so far as the latest state, after every epoch
+            // before it.
+            if pipeline.sequential {
+                self.history.push_provisional(state.clone());
+            }
+            match stage {
+                Stage::Fees => self.fees(&mut state, &mut flows),
+                Stage::Rebate => self.rebate(&mut state, &mut flows),
+                Stage::Bonds => self.bonds(&mut state, &mut flows),
+                Stage::Locked => self.locked(&mut state),
+                Stage::Claims => self.claims(&mut state, &mut flows),
+            }
+            if pipeline.sequential {
+                self.history.pop_provisional();
+            }
+        }
+        self.close(&mut state, flows);
+
+        tracing::debug!(
+            tvl = state.tvl.0,
+            tvb = state.tvb.0,
+            f_claimed = state.f_claimed.0,
+            r_pool = state.r_pool.0,
+            "epoch simulated"
+        );
+        self.history.push(state.clone());
+        let epoch = self.history.epoch();
+        if epoch > self.history.aggregates().warmup {
+            self.stop.observe(epoch, &state);
+        }
+        for observer in &mut self.observers {
+            observer.on_epoch(epoch, &state);
+        }
+        state
+    }
+
+    /// The fees stage of an epoch (see `step`), which sets the volumes, fee levels, and rebate of
+    /// the state and charges the fees to the ledger.
+    fn fees(&mut self, state: &mut State, flows: &mut Flows) {
+        let history = &self.history;
+        let external = &mut self.external;
+        let internal = &mut self.internal;
+        let rng = &mut self.rng;
+        let ledger = &mut self.ledger;
+        let mint_cap = &mut self.mint_cap;
+        let inputs = &self.inputs;
+        let boundary = flows.boundary;
+        let (mv, bv, mf, bf) = if self.assets.is_empty() {
+            // Mint and burn volumes this epoch, as the transactions that they are made of.
+            let mut mints = external.mint_transactions(history, rng);
+            if let Some(volume) = inputs.mint_volume {
+                mints = vec![volume];
+            }
+            let mut mv: Usd = mints.iter().copied().sum();
+            trace!(target: EXTERNAL, mint_volume = mv.0);
+            let mut burns = external.burn_transactions(history, rng);
+            if let Some(volume) = inputs.burn_volume {
+                burns = vec![volume];
+            }
+            let mut bv: Usd = burns.iter().copied().sum();
+            trace!(target: EXTERNAL, burn_volume = bv.0);
+            // No more can be burned than is locked, including what is minted in the epoch (and once
+            // what was locked has been stolen, only what has been locked since).
+            let capped = bv.min((state.tvl + mv).max(Usd::ZERO));
+            if capped < bv {
+                rescale(&mut burns, bv, capped);
+                bv = capped;
+            }
+            if let (Some(cap), Some(room)) = (mint_cap.as_mut(), flows.room.as_mut()) {
+                let minted = cap.accept(0, mv, bv, room);
+                trace!(target: INTERNAL, minted = minted.0);
+                rescale(&mut mints, mv, minted);
+                mv = minted;
+            }
+            let (mf, bf) = if boundary {
+                let mf = internal.mint_fee_curve(history, rng);
+                trace!(target: INTERNAL, mint_fee = mf.0);
+                let bf = internal.burn_fee_curve(history, rng);
+                trace!(target: INTERNAL, burn_fee = bf.0);
+                (mf, bf)
+            } else {
+                (state.mf, state.bf)
+            };
+            // Every transaction is charged on its own, so that its fee can depend on its size.
+            for &size in &mints {
+                ledger.charge_mint(size, internal.mint_transaction_fee(size, mf));
+            }
+            for &size in &burns {
+                ledger.charge_burn(size, internal.burn_transaction_fee(size, bf));
+            }
+            (mv, bv, mf, bf)
+        } else {
+            // Every asset has its own volumes and fees. The aggregate fees are the averages
+            // weighted by volume. Priced assets are locked in their own units, which are minted and
+            // burned at the price of the epoch and valued at it, so a change in price revalues what
+            // is locked without changing how much is. An asset that is priced for the first time is
+            // converted into its own units at that price. Unpriced assets are only locked in USD.
+            state
+                .assets
+                .resize(self.assets.len(), AssetState::default());
+            let (mut mv, mut bv) = (Usd::ZERO, Usd::ZERO);
+            for (i, (name, asset)) in self.assets.iter().zip(state.assets.iter_mut()).enumerate() {
+                let price = external.asset_price(history, name, rng);
+                trace!(target: EXTERNAL, asset = %name, price = price.0);
+                let before = asset.tvl;
+                if price > Usd::ZERO && asset.price <= Usd::ZERO {
+                    asset.amount = asset.tvl / price;
+                }
+                asset.price = price;
+                let mut asset_mv = external.asset_mint_volume(history, name, rng);
+                trace!(target: EXTERNAL, asset = %name, mint_volume = asset_mv.0);
+                let mut asset_bv = external.asset_burn_volume(history, name, rng);
+                trace!(target: EXTERNAL, asset = %name, burn_volume = asset_bv.0);
+                let locked = if price > Usd::ZERO {
+                    price * asset.amount
+                } else {
+                    asset.tvl
+                };
+                asset_bv = asset_bv.min((locked + asset_mv).max(Usd::ZERO));
+                if let (Some(cap), Some(room)) = (mint_cap.as_mut(), flows.room.as_mut()) {
+                    asset_mv = cap.accept(i, asset_mv, asset_bv, room);
+                    trace!(target: INTERNAL, asset = %name, minted = asset_mv.0);
+                }
+                if boundary {
+                    asset.mf = internal.asset_mint_fee_curve(history, name, rng);
+                    trace!(target: INTERNAL, asset = %name, mint_fee = asset.mf.0);
+                    asset.bf = internal.asset_burn_fee_curve(history, name, rng);
+                    trace!(target: INTERNAL, asset = %name, burn_fee = asset.bf.0);
+                }
+                if price > Usd::ZERO {
+                    asset.amount = (asset.amount + (asset_mv - asset_bv) / price).max(0.0);
+                    asset.tvl = price * asset.amount;
+                    // The aggregate only takes the revaluation here, since what is minted and
+                    // burned is added to it below.
+                    state.tvl += asset.tvl - before - (asset_mv - asset_bv);
+                } else {
+                    asset.amount = 0.0;
+                    asset.tvl = (asset.tvl + (asset_mv - asset_bv)).max(Usd::ZERO);
+                }
+                mv += asset_mv;
+                bv += asset_bv;
+                ledger.charge_mint(asset_mv, internal.mint_transaction_fee(asset_mv, asset.mf));
+                ledger.charge_burn(asset_bv, internal.burn_transaction_fee(asset_bv, asset.bf));
+            }
+            let average = |f: Usd, v: Usd| if v > Usd::ZERO { Pct(f / v) } else { Pct::ZERO };
+            let mf = average(ledger.minted(), mv);
+            let bf = average(ledger.burned(), bv);
+            (mv, bv, mf, bf)
+        };
+        state.controller = internal.controller();
+        if let Some(controller) = &state.controller {
+            trace!(target: INTERNAL, error = controller.error, integral = controller.integral);
+        }
+        // The rebate is decided along with the fees, since burns only pay a fee while they earn no
+        // rebate.
+        if boundary {
+            state.r = internal.rebate_curve(history, rng);
+            trace!(target: INTERNAL, rebate = state.r.0);
+        }
+        state.mint_volume = mv;
+        state.burn_volume = bv;
+        state.mf = mf;
+        state.bf = bf;
+    }
+
+    /// The rebate stage of an epoch (see `step`), which splits the fees that have been charged
+    /// between the treasury, the buy-and-burn, the rebate pool, and node operators, and pays the
+    /// rebate on what was burned out of the rebate pool.
+    fn rebate(&mut self, state: &mut State, flows: &mut Flows) {
+        let history = &self.history;
+        let internal = &mut self.internal;
+        let rng = &mut self.rng;
+        let ledger = &mut self.ledger;
+        let f_collected = ledger.minted() + ledger.burned();
+        // The treasury and the buy-and-burn take their shares first, and settle outside of the
+        // ledger. REN is bought at its price this epoch, once the epoch is closed.
+        let t_collected = internal.treasury_collected(history, f_collected, rng);
+        if t_collected != Usd::ZERO {
+            trace!(target: INTERNAL, treasury_collected = t_collected.0);
+            ledger.withdraw(t_collected);
+            state.treasury += t_collected;
+        }
+        flows.b_collected = internal.buyback_collected(history, f_collected, rng);
+        if flows.b_collected != Usd::ZERO {
+            trace!(target: INTERNAL, buyback_collected = flows.b_collected.0);
+            ledger.withdraw(flows.b_collected);
+            state.buyback += flows.b_collected;
+        }
+        flows.r_collected =
+            internal.rebate_collected(history, f_collected - t_collected - flows.b_collected, rng);
+        trace!(target: INTERNAL, rebate_collected = flows.r_collected.0);
+        self.decisions = internal.decisions();
+        flows.rebated = state.burn_volume;
+        // Otherwise, no stage sees the pool before the epoch is closed, so it is settled then.
+        if self.pipeline.sequential {
+            self.cap(state, flows);
+            self.ledger
+                .pool(state, flows.r_collected, flows.rebated, state.r);
+            flows.r_collected = Usd::ZERO;
+            flows.rebated = Usd::ZERO;
+        }
+    }
+
+    /// Keep the rebate pool under its cap, once the rebate collected (and paid) so far is settled.
+    /// What would overflow it goes to node operators along with the rest of the fees, to the
+    /// treasury, or is never charged.
+    fn cap(&mut self, state: &mut State, flows: &mut Flows) {
+        if let Some(max) = self.rebate_pool.max {
+            let r_paid = flows.rebated * state.r;
+            let overflow = (state.r_pool + flows.r_collected - r_paid - max)
+                .max(Usd::ZERO)
+                .min(flows.r_collected.max(Usd::ZERO));
+            trace!(target: INTERNAL, rebate_overflow = overflow.0);
+            flows.r_collected -= overflow;
+            match self.rebate_pool.overflow {
+                Overflow::Nodes => {}
+                Overflow::Treasury => {
+                    self.ledger.withdraw(overflow);
+                    state.treasury += overflow;
+                }
+                Overflow::Stop => self.ledger.withdraw(overflow),
+            }
+        }
+    }
+
+    /// The bonds stage of an epoch (see `step`), which sets the price of REN and the values bonded
+    /// in the state. The price of REN is set first, so that bonds made in REN can be valued in USD.
+    fn bonds(&mut self, state: &mut State, flows: &mut Flows) {
+        let history = &self.history;
+        let external = &mut self.external;
+        let rng = &mut self.rng;
+        let inputs = &self.inputs;
+        state.ren_price = external.ren_price(history, rng);
+        if let Some(price) = inputs.ren_price {
+            state.ren_price = price;
+        }
+        trace!(target: EXTERNAL, ren_price = state.ren_price.0);
+        if flows.boundary {
+            state.tvb = external.total_value_bonded(history, rng);
+            trace!(target: EXTERNAL, total_value_bonded = state.tvb.0);
+            state.darknodes = external.darknodes(history, rng);
+            trace!(target: EXTERNAL, darknodes = state.darknodes);
+        } else if state.tvb_ren > Ren::ZERO {
+            // The same REN stays bonded, at its new price.
+            state.tvb = state.tvb_ren * state.ren_price;
+        }
+        if let Some(tvb) = inputs.tvb {
+            state.tvb = tvb;
+        }
+        if let Some(slashing) = &self.slashing {
+            flows.slashed = slashing.slash(state.tvb, state.darknodes, state.ren_price, rng);
+            trace!(target: INTERNAL, slashed = flows.slashed.0);
+            state.tvb -= flows.slashed;
+        }
+        if let Some(subsidy) = &self.subsidy {
+            let emitted = subsidy.emission(history.len());
+            trace!(target: INTERNAL, ren_emitted = emitted.0);
+            state.ren_emitted += emitted;
+            flows.emitted = emitted * state.ren_price;
+        }
+        if let Some(attacker) = &mut self.attacker {
+            state.tvb = attacker.bond(history.len(), flows.boundary, state.tvb, state.ren_price);
+            trace!(target: EXTERNAL, attacker_ren = attacker.ren().0);
+        }
+        state.tvb_ren = if state.ren_price > Usd::ZERO {
+            Ren(state.tvb / state.ren_price)
+        } else {
+            Ren::ZERO
+        };
+    }
+
+    /// The locked stage of an epoch (see `step`), which adds what was minted and burned to the
+    /// value locked in the state, along with its revaluation.
+    fn locked(&mut self, state: &mut State) {
+        let net = state.mint_volume - state.burn_volume;
+        let revaluation = self
+            .external
+            .tvl_revaluation(&self.history, net, &mut self.rng);
+        trace!(target: EXTERNAL, tvl_revaluation = revaluation.0);
+        // Burns are capped at what is locked (see `fees`), so TVL only falls below zero by
+        // rounding when everything is burned.
+        state.tvl = (state.tvl + (net + revaluation)).max(Usd::ZERO);
+    }
+
+    /// The claims stage of an epoch (see `step`), which decides how much of the fees that nodes
+    /// have not claimed yet they claim.
+    fn claims(&mut self, state: &mut State, flows: &mut Flows) {
+        flows.claim_rate = if flows.boundary {
+            let mut claim_rate = self.external.claim_curve(&self.history, &mut self.rng);
+            if let Some(rate) = self.inputs.claim_rate {
+                claim_rate = rate;
+            }
+            if self.boundary > 1 {
+                // What would have been claimed one epoch at a time is claimed all at once.
+                claim_rate = Pct(1.0 - (1.0 - claim_rate.0).powi(self.boundary as i32));
+            }
+            trace!(target: EXTERNAL, claim_rate = claim_rate.0);
+            claim_rate
+        } else {
+            Pct::ZERO
+        };
+        // Otherwise, no stage sees the claim before the epoch is closed, so it is settled then.
+        if self.pipeline.sequential {
+            self.ledger.claim(state, flows.claim_rate);
+            flows.claim_rate = Pct::ZERO;
+        }
+    }
+
+    /// Close an epoch once every stage of it has been simulated (see `step`), settling what the
+    /// stages collected into the state.
+    fn close(&mut self, state: &mut State, mut flows: Flows) {
+        if flows.b_collected != Usd::ZERO && state.ren_price > Usd::ZERO {
+            state.ren_burned += Ren(flows.b_collected / state.ren_price);
+        }
+        match self.slashing.as_ref().map(Slashing::destination) {
+            None | Some(SlashDestination::Burn) => {}
+            Some(SlashDestination::Rebates) => {
+                self.ledger.collect(flows.slashed);
+                flows.r_collected += flows.slashed;
+            }
+            Some(SlashDestination::Nodes) => self.ledger.collect(flows.slashed),
+        }
+        if self.subsidy.is_some() {
+            self.ledger.collect(flows.emitted);
+        }
+        // The attacker sees the epoch through, and steals what is locked at the end of it.
+        if let Some(attacker) = &mut self.attacker {
+            attacker.attack(self.history.len(), flows.boundary, state);
+        }
+        state.security_ratio = if state.tvl > Usd::ZERO {
+            state.tvb / state.tvl
+        } else {
+            0.0
+        };
+        let mv = state.mint_volume;
+        if let Some(shards) = &self.shards {
+            shards.split(self.history.latest(), state, mv);
+        }
+
+        self.cap(state, &mut flows);
+        // Update the fees claimed by nodes and the fees collected in total (including all of the
+        // fees claimed up until this point), the rebates collected, and the rebate pool
+        self.ledger.settle(
+            state,
+            flows.r_collected,
+            flows.rebated,
+            state.r,
+            flows.claim_rate,
+        );
+        state.derive(self.history.latest(), self.epochs_per_year, self.bond);
+    }
+
+    /// Advance the simulation by `n` epochs, returning the `n` new states in order (or fewer, if it
+    /// is aborted or stopped, and none if it already was).
+    pub fn run(&mut self, n: usize) -> Vec<State> {
+        let mut states = vec![];
+        for _ in 0..n {
+            if self.ended() {
+                break;
+            }
+            states.push(self.step());
+        }
+        states
+    }
+
+    /// Advance the simulation by one epoch and check the new state against the invariants,
+    /// returning an error that describes the first violation. The violating state is still added
+    /// to the history, so that it can be inspected.
+    pub fn try_step(&mut self) -> Result<State> {
+        let state = self.step();
+        match self.invariants.check(&self.history) {
+            Some(violation) => Err(violation.into()),
+            None => Ok(state),
+        }
+    }
+
+    /// Advance the simulation by `n` epochs, checking every new state against the invariants and
+    /// stopping at the first violation (or earlier, if it is aborted or stopped).
+    pub fn try_run(&mut self, n: usize) -> Result<Vec<State>> {
+        let mut states = vec![];
+        for _ in 0..n {
+            if self.ended() {
+                break;
+            }
+            states.push(self.try_step()?);
+        }
+        Ok(states)
+    }
+
+    /// The mint cap, with what it has rejected so far, if minting is capped.
+    pub fn mint_cap(&self) -> Option<&MintCap> {
+        self.mint_cap.as_ref()
+    }
+
+    /// The attack that the attacker made, if there is an attacker and it has attacked.
+    pub fn breach(&self) -> Option<&Breach> {
+        self.attacker.as_ref().and_then(Attacker::breach)
+    }
+
+    /// The divergence that the run stops at, if it stops at its first divergence and has diverged.
+    pub fn aborted(&self) -> Option<&Divergence> {
+        self.history
+            .aggregates()
+            .divergence
+            .as_ref()
+            .filter(|_| self.abort)
+    }
+
+    /// The stop condition that the run stopped at, if it has met one.
+    pub fn stopped(&self) -> Option<&Stop> {
+        self.stop.stop()
+    }
+
+    /// Whether the run is over before its last epoch, because it was aborted or stopped.
+    fn ended(&self) -> bool {
+        self.aborted().is_some() || self.stopped().is_some()
+    }
+}
+
+impl Iterator for Simulation {
+    type Item = State;
+
+    /// Advance the simulation by one epoch (see `step`), or end once the run is aborted or
+    /// stopped. The simulation never ends on its own otherwise, so take as many epochs as are
+    /// needed.
+    fn next(&mut self) -> Option<State> {
+        if self.ended() {
+            None
+        } else {
+            Some(self.step())
+        }
+    }
+}
+
+/// Inputs are values that a caller sets in place of what the external model returns, such as a
+/// shock to the price of REN partway through a run, or the volumes that a user drags a slider to.
+/// The external model is still called every epoch (and so still draws its random numbers), and its
+/// values are only replaced where an input is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inputs {
+    /// The value bonded in every epoch, in place of `total_value_bonded` (before slashing).
+    pub tvb: Option<Usd>,
+    /// The value minted in every epoch, in a single transaction. It is only set in place of the
+    /// aggregate volume, so it does not apply to models that lock assets of their own.
+    pub mint_volume: Option<Usd>,
+    /// The value burned in every epoch, in the same way as the value minted.
+    pub burn_volume: Option<Usd>,
+    /// The price of REN in every epoch.
+    pub ren_price: Option<Usd>,
+    /// The share of unclaimed fees that nodes claim per epoch, in place of `claim_curve` (which is
+    /// only called at epoch boundaries).
+    pub claim_rate: Option<Pct>,
+}
+
+/// Flows are what the stages of an epoch work out along the way, for the stages after them and for
+/// closing the epoch.
+#[derive(Clone, Copy, Debug, Default)]
+struct Flows {
+    /// Whether the epoch is the first after an epoch boundary.
+    boundary: bool,
+    /// How much more can be locked before the mint cap is reached, if minting is capped.
+    room: Option<Usd>,
+    /// The fees that go to the buy-and-burn, and to the rebate pool (until they are pooled).
+    b_collected: Usd,
+    r_collected: Usd,
+    /// The value burned that the rebate pool pays the rebate on, until it is paid.
+    rebated: Usd,
+    /// The bonds that were slashed, and the value of the REN that was emitted, which are collected
+    /// when the epoch is closed (so that the treasury never takes a share of them).
+    slashed: Usd,
+    emitted: Usd,
+    /// The share of unclaimed fees that nodes claim, until they claim it.
+    claim_rate: Pct,
+}
+
+/// The basic external and internal models, using the parameters from the given configuration.
+pub(crate) fn basic_models(config: &Config) -> (external::Basic, internal::Basic) {
+    let external = external::Basic::new(config.external.clone(), &config.epoch)
+        .with_slashing_risk(config.internal.slashing.risk(&config.epoch))
+        .with_prior_income(config.initial.fee_income);
+    let internal = internal::Basic::new(config.internal.clone(), &config.epoch);
+    (external, internal)
+}
+
+/// The internal models that the governance changes in the configuration put in effect, with the
+/// epochs from which they do, in order. Each is a basic model that uses the changed parameters (and
+/// every earlier change).
+pub(crate) fn governance(config: &Config) -> Vec<(usize, internal::Basic)> {
+    let mut changes: Vec<_> = config.governance.iter().collect();
+    changes.sort_by_key(|change| change.epoch);
+    let mut internal = config.internal.clone();
+    changes
+        .into_iter()
+        .map(|change| {
+            change.apply(&mut internal);
+            let model = internal::Basic::new(internal.clone(), &config.epoch);
+            (change.epoch, model)
+        })
+        .collect()
+}
+
+/// Scale transactions that add up to `from` so that they add up to `to` instead (without disturbing
+/// a transaction that makes up the whole volume on its own).
+fn rescale(transactions: &mut [Usd], from: Usd, to: Usd) {
+    match transactions {
+        [] => {}
+        [transaction] => *transaction = to,
+        transactions if from > Usd::ZERO => {
+            for transaction in transactions {
+                *transaction *= to / from;
+            }
+        }
+        transactions => transactions.fill(Usd::ZERO),
+    }
+}
+
+impl Default for Simulation {
+    /// A simulation driven by the basic external and internal models.
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
@@ -0,0 +1,57 @@
+//! For slashing darknodes that misbehave. Every epoch, each darknode is slashed with some
+//! probability and loses part of its bond, which is taken out of TVB for that epoch (node operators
+//! replace it as they would any other shortfall) and is destroyed, added to the rebate pool, or
+//! shared between node operators like fees. Node operators also want a higher return to make up for
+//! what they expect to lose (see `SlashingConfig::risk`), which is where most of the effect of
+//! slashing on bonding comes from.
+
+use crate::config::{SlashDestination, SlashingConfig};
+use crate::{Pct, Ren, SimRng, Usd};
+use rand_distr::{Binomial, Distribution};
+
+/// Slashing slashes darknodes according to a `SlashingConfig`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slashing {
+    config: SlashingConfig,
+    bond: Ren,
+}
+
+impl Slashing {
+    /// Create a slashing mechanism using the given parameters, where darknodes are counted as
+    /// bonding `bond` REN each when the bonding model does not count them. Returns `None` when
+    /// nothing is ever slashed.
+    pub fn new(config: SlashingConfig, bond: Ren) -> Option<Self> {
+        (config.probability > Pct::ZERO && config.fraction > Pct::ZERO)
+            .then_some(Self { config, bond })
+    }
+
+    /// Where the value that is slashed goes.
+    pub fn destination(&self) -> SlashDestination {
+        self.config.destination
+    }
+
+    /// Slash the darknodes that misbehave this epoch, returning the value (in USD) that they lose,
+    /// where `tvb` is bonded between the given number of darknodes (or, when that is zero,
+    /// darknodes of the configured bond at the given price of REN).
+    pub fn slash(&self, tvb: Usd, darknodes: u64, ren_price: Usd, rng: &mut SimRng) -> Usd {
+        let node = self.bond * ren_price;
+        let nodes = if darknodes > 0 {
+            darknodes
+        } else if node > Usd::ZERO {
+            (tvb / node).round().max(0.0) as u64
+        } else {
+            0
+        };
+        if nodes == 0 || tvb <= Usd::ZERO {
+            return Usd::ZERO;
+        }
+        // A probability that is not a number (which a configuration that is read never has) slashes
+        // nothing.
+        let probability = self.config.probability.0.clamp(0.0, 1.0);
+        let slashed = Binomial::new(nodes, probability).map_or(0, |binomial| binomial.sample(rng));
+        if slashed > 0 {
+            tracing::debug!(slashed, "darknodes slashed");
+        }
+        tvb / nodes as f64 * slashed as f64 * self.config.fraction.0.clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,238 @@
+//! For capturing the state of RenVM throughout the simulation.
+
+use crate::{Pct, Ren, Usd};
+use serde::{Deserialize, Serialize};
+
+/// State represents the state of RenVM at the end of an epoch. All values in the state are derived
+/// from the behaviour of the external and internal models; they are never directly simulated. If
+/// you find yourself directly modifying the state, you are probably doing something wrong.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub tvb: Usd,
+    pub tvb_ren: Ren,
+    pub ren_price: Usd,
+    pub darknodes: u64,
+    pub tvl: Usd,
+    pub tvr: Usd,
+    /// The ratio of TVB to TVL, or zero when nothing is locked. When value is split between
+    /// shards, this is the lowest ratio of any shard that locks anything.
+    pub security_ratio: f64,
+
+    pub mf: Pct,
+    pub bf: Pct,
+    pub r: Pct,
+
+    pub f_unclaimed: Usd,
+    pub f_claimed: Usd,
+    pub r_pool: Usd,
+    /// The fees that have gone to the treasury, as its share of fees or when the rebate pool
+    /// overflows into it.
+    #[serde(default)]
+    pub treasury: Usd,
+    /// The fees that have bought REN to burn.
+    #[serde(default)]
+    pub buyback: Usd,
+    /// The REN that fees have bought and burned.
+    #[serde(default)]
+    pub ren_burned: Ren,
+    /// The REN that has been emitted to subsidise node operators.
+    #[serde(default)]
+    pub ren_emitted: Ren,
+    /// The value minted in the epoch.
+    #[serde(default)]
+    pub mint_volume: Usd,
+    /// The value burned in the epoch.
+    #[serde(default)]
+    pub burn_volume: Usd,
+
+    // Metrics that `State::derive` derives from the rest of the state, so that they do not have to
+    // be recomputed from the output.
+    /// The number of darknodes that TVB would bond, at the bond of one darknode.
+    #[serde(default)]
+    pub implied_darknodes: f64,
+    /// The annual return of a darknode if it earned what it earned in the epoch for a year, or zero
+    /// when nothing is bonded.
+    #[serde(default)]
+    pub fee_apy: f64,
+    /// The value minted in the epoch, less the value burned.
+    #[serde(default)]
+    pub net_flow: Usd,
+    /// The number of epochs that the rebate pool would last, if rebates were paid out of it at the
+    /// rate of the epoch and nothing more was collected, or zero when no rebates are paid.
+    #[serde(default)]
+    pub runway: f64,
+
+    /// The state of every asset that is locked in RenVM, in the order that the external model
+    /// lists them. This is empty when locked value is only modelled in aggregate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assets: Vec<AssetState>,
+
+    /// The state of every shard, in order. This is empty when TVB is only compared with TVL in
+    /// aggregate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shards: Vec<ShardState>,
+
+    /// The state of the controller that sets the fees, if they are controlled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller: Option<ControllerState>,
+}
+
+/// AssetState represents the state of one locked asset at the end of an epoch. The aggregate `tvl`
+/// of the `State` includes the `tvl` of every asset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetState {
+    /// The value of the asset that is locked, which is `amount` at `price` for a priced asset.
+    pub tvl: Usd,
+    /// The amount of the asset that is locked, in its own units (e.g. BTC), or zero when the asset
+    /// is not priced (in which case only its value is known).
+    #[serde(default)]
+    pub amount: f64,
+    pub price: Usd,
+    pub mf: Pct,
+    pub bf: Pct,
+}
+
+impl AssetState {
+    /// The names of every field in the asset state, in declaration order.
+    pub const FIELDS: [&'static str; 5] = ["tvl", "amount", "price", "mf", "bf"];
+
+    /// The values of every field in the asset state, in the same order as `AssetState::FIELDS`.
+    pub fn values(&self) -> [f64; 5] {
+        [self.tvl.0, self.amount, self.price.0, self.mf.0, self.bf.0]
+    }
+}
+
+/// ShardState represents the locked value and bonds of one shard at the end of an epoch. The
+/// aggregate `tvl` and `tvb` of the `State` are the sums over every shard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShardState {
+    pub tvl: Usd,
+    pub tvb: Usd,
+    /// The ratio of the TVB of the shard to its TVL, or zero when it locks nothing.
+    pub security_ratio: f64,
+}
+
+impl ShardState {
+    /// The names of every field in the shard state, in declaration order.
+    pub const FIELDS: [&'static str; 3] = ["tvl", "tvb", "security_ratio"];
+
+    /// The values of every field in the shard state, in the same order as `ShardState::FIELDS`.
+    pub fn values(&self) -> [f64; 3] {
+        [self.tvl.0, self.tvb.0, self.security_ratio]
+    }
+}
+
+/// ControllerState represents the diagnostics of the controller that set the fees of an epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControllerState {
+    /// The target security ratio less the security ratio of the previous epoch.
+    pub error: f64,
+    /// The sum of the errors of every epoch so far.
+    pub integral: f64,
+}
+
+impl ControllerState {
+    /// The names of every field in the controller state, in declaration order.
+    pub const FIELDS: [&'static str; 2] = ["error", "integral"];
+
+    /// The values of every field in the controller state, in the same order as
+    /// `ControllerState::FIELDS`.
+    pub fn values(&self) -> [f64; 2] {
+        [self.error, self.integral]
+    }
+}
+
+impl State {
+    /// The names of every aggregate field in the state, in declaration order. Per-asset and
+    /// per-shard fields are described by `AssetState::FIELDS` and `ShardState::FIELDS`.
+    pub const FIELDS: [&'static str; 23] = [
+        "tvb",
+        "tvb_ren",
+        "ren_price",
+        "darknodes",
+        "tvl",
+        "tvr",
+        "security_ratio",
+        "mf",
+        "bf",
+        "r",
+        "f_unclaimed",
+        "f_claimed",
+        "r_pool",
+        "treasury",
+        "buyback",
+        "ren_burned",
+        "ren_emitted",
+        "mint_volume",
+        "burn_volume",
+        "implied_darknodes",
+        "fee_apy",
+        "net_flow",
+        "runway",
+    ];
+
+    /// The values of every aggregate field in the state, in the same order as `State::FIELDS`.
+    pub fn values(&self) -> [f64; 23] {
+        [
+            self.tvb.0,
+            self.tvb_ren.0,
+            self.ren_price.0,
+            self.darknodes as f64,
+            self.tvl.0,
+            self.tvr.0,
+            self.security_ratio,
+            self.mf.0,
+            self.bf.0,
+            self.r.0,
+            self.f_unclaimed.0,
+            self.f_claimed.0,
+            self.r_pool.0,
+            self.treasury.0,
+            self.buyback.0,
+            self.ren_burned.0,
+            self.ren_emitted.0,
+            self.mint_volume.0,
+            self.burn_volume.0,
+            self.implied_darknodes,
+            self.fee_apy,
+            self.net_flow.0,
+            self.runway,
+        ]
+    }
+
+    /// Derive the metrics of the state from its other fields and those of the previous state, where
+    /// there are `epochs_per_year` epochs in a year and every darknode bonds `bond` REN. What nodes
+    /// earn is split evenly between darknodes, so every darknode earns the same return.
+    pub fn derive(&mut self, previous: &State, epochs_per_year: f64, bond: Ren) {
+        self.implied_darknodes = if bond > Ren::ZERO {
+            self.tvb_ren / bond
+        } else {
+            0.0
+        };
+        let earned = self.f_claimed + self.f_unclaimed - previous.f_claimed - previous.f_unclaimed;
+        self.fee_apy = if self.tvb > Usd::ZERO {
+            earned / self.tvb * epochs_per_year
+        } else {
+            0.0
+        };
+        self.net_flow = self.mint_volume - self.burn_volume;
+        let paid = self.burn_volume * self.r;
+        self.runway = if paid > Usd::ZERO {
+            self.r_pool / paid
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Helper function to get the part of a history that follows the given number of warm-up epochs.
+/// The state at the end of the warm-up takes the place of the initial state, so the result is never
+/// empty (even if the warm-up is longer than the history).
+pub fn after_warmup(history: &[State], warmup: usize) -> &[State] {
+    &history[warmup.min(history.len().saturating_sub(1))..]
+}
+
+/// Helper function to get a copy of the latest state from a history of states.
+pub fn latest_state(history: &[State]) -> State {
+    history.last().expect("missing initial state").clone()
+}
@@ -0,0 +1,44 @@
+//! Small statistical helpers shared by the modes that aggregate many values (ensembles, sweeps,
+//! reports, etc.).
+
+/// The arithmetic mean of the values, or NaN if there are none.
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The `p`th percentile (where `p` is between 0 and 100) of values that have already been sorted in
+/// ascending order, linearly interpolating between the two closest ranks. Returns NaN if there are
+/// no values.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// The cumulative distribution function of the standard normal distribution, to within about 1e-7
+/// (from the approximation of the error function by Abramowitz and Stegun, 7.1.26).
+pub fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+/// Sort the values in ascending order, placing NaNs last.
+pub fn sort(values: &mut [f64]) {
+    values.sort_by(|a, b| {
+        a.partial_cmp(b)
+            .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+    });
+}
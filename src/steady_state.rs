@@ -0,0 +1,135 @@
+//! For detecting that a run has converged, which is when every tracked field of the state has
+//! stayed within a tolerance of its latest value for a whole window of epochs. Cumulative fields
+//! (like the fees claimed) keep growing for as long as anything happens, so they are not tracked
+//! by default.
+
+use crate::config::{EpochConfig, SteadyStateConfig};
+use crate::error::{Error, Result};
+use crate::history::History;
+use crate::state::State;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// SteadyState detects steady states in the history of a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SteadyState {
+    tolerance: f64,
+    window: usize,
+    fields: Vec<usize>,
+}
+
+/// Equilibrium is a steady state that was reached by a run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equilibrium {
+    /// The first epoch of the window over which the state was steady.
+    pub epoch: usize,
+    /// The epoch at which the steady state was detected (the last epoch of the window).
+    pub detected: usize,
+    /// The tracked fields and their values at the epoch at which the steady state was detected.
+    pub values: Vec<(String, f64)>,
+}
+
+impl SteadyState {
+    /// Create a detector using the given parameters, with the window measured in epochs of the
+    /// given length.
+    pub fn new(config: &SteadyStateConfig, epoch: &EpochConfig) -> Result<Self> {
+        let fields = config
+            .fields
+            .iter()
+            .map(|field| {
+                State::FIELDS
+                    .iter()
+                    .position(|name| name == field)
+                    .ok_or_else(|| {
+                        Error::Config(format!(
+                            "unknown steady state field `{}` (expected one of: {})",
+                            field,
+                            State::FIELDS.join(", ")
+                        ))
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            tolerance: config.tolerance,
+            window: epoch.epochs(config.window_days),
+            fields,
+        })
+    }
+
+    /// The number of epochs over which the state must be steady.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Whether the state has been steady over the window that ends with the latest epoch, where the
+    /// window must not begin before the end of the given number of warm-up epochs.
+    pub fn is_steady(&self, history: &History, warmup: usize) -> bool {
+        let states = history.states();
+        history.len() > warmup + self.window
+            && states.len() > self.window
+            && self.is_steady_at(states, states.len() - 1)
+    }
+
+    /// The steady state that the latest epoch ends, if the state has been steady over the window
+    /// that ends with it (see `is_steady`). Checking this every epoch finds the same equilibrium as
+    /// `find`, without needing the whole history.
+    pub fn reached(&self, history: &History, warmup: usize) -> Option<Equilibrium> {
+        if self.is_steady(history, warmup) {
+            Some(self.equilibrium(history.epoch(), history.latest()))
+        } else {
+            None
+        }
+    }
+
+    /// Find the first window over which the state was steady, not counting windows that begin
+    /// before the end of the given number of warm-up epochs.
+    pub fn find(&self, history: &[State], warmup: usize) -> Option<Equilibrium> {
+        let detected =
+            (warmup + self.window..history.len()).find(|&end| self.is_steady_at(history, end))?;
+        Some(self.equilibrium(detected, &history[detected]))
+    }
+
+    /// The equilibrium that was detected at the given epoch, in the given state.
+    fn equilibrium(&self, detected: usize, state: &State) -> Equilibrium {
+        let values = state.values();
+        Equilibrium {
+            epoch: detected - self.window,
+            detected,
+            values: self
+                .fields
+                .iter()
+                .map(|&i| (State::FIELDS[i].to_string(), values[i]))
+                .collect(),
+        }
+    }
+
+    /// Whether the state was steady over the window that ends with the given epoch.
+    fn is_steady_at(&self, history: &[State], end: usize) -> bool {
+        let latest = history[end].values();
+        history[end - self.window..end].iter().all(|state| {
+            let values = state.values();
+            self.fields
+                .iter()
+                .all(|&i| (values[i] - latest[i]).abs() <= self.tolerance * latest[i].abs())
+        })
+    }
+}
+
+impl fmt::Display for Equilibrium {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "steady from epoch {} (detected at epoch {})",
+            self.epoch, self.detected
+        )?;
+        for (name, value) in &self.values {
+            // Fees and rebates are small fractions, so they need more precision than values in USD.
+            if value.abs() < 1.0 {
+                write!(f, " {}={:.6}", name, value)?;
+            } else {
+                write!(f, " {}={:.2}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
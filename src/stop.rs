@@ -0,0 +1,95 @@
+//! For ending a run early once a condition of its own has held for long enough, like the rebate
+//! pool staying empty for ten epochs, or TVB falling below TVL. Conditions are declared as
+//! `[[stop]]` in the configuration, as expressions of the state (see `curve`) that hold whenever
+//! they are not zero, and are only counted after the warm-up. The first condition to be met stops
+//! the run, and is recorded in its summary.
+
+use crate::config::StopConfig;
+use crate::state::State;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Conditions counts how long each stop condition has held, and remembers the first that was met.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Conditions {
+    conditions: Vec<StopConfig>,
+    /// The number of consecutive epochs (up to the latest) that each condition has held for.
+    held: Vec<usize>,
+    stop: Option<Stop>,
+}
+
+/// Stop is a stop condition that was met by a run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    /// The condition, as it was written in the configuration.
+    pub condition: String,
+    /// What the condition means, if the configuration says.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// The first epoch of the consecutive epochs in which the condition held.
+    pub epoch: usize,
+    /// The epoch at which the run stopped (the last epoch in which the condition held).
+    pub stopped: usize,
+}
+
+impl Conditions {
+    /// Count the given stop conditions from the next epoch on.
+    pub fn new(conditions: &[StopConfig]) -> Self {
+        Self {
+            conditions: conditions.to_vec(),
+            held: vec![0; conditions.len()],
+            stop: None,
+        }
+    }
+
+    /// Whether there are no conditions to count.
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// The condition that was met, if one has been.
+    pub fn stop(&self) -> Option<&Stop> {
+        self.stop.as_ref()
+    }
+
+    /// Count the conditions that hold for the state at the end of the given epoch, and stop at the
+    /// first (in the order that they were declared) that has now held for long enough. Nothing is
+    /// counted once a condition has been met.
+    pub fn observe(&mut self, epoch: usize, state: &State) -> Option<&Stop> {
+        if self.stop.is_some() {
+            return self.stop.as_ref();
+        }
+        for (condition, held) in self.conditions.iter().zip(&mut self.held) {
+            *held = if condition.when.eval(state, epoch) != 0.0 {
+                *held + 1
+            } else {
+                0
+            };
+        }
+        let (condition, held) = self
+            .conditions
+            .iter()
+            .zip(&self.held)
+            .find(|(condition, held)| **held >= condition.epochs.max(1))?;
+        self.stop = Some(Stop {
+            condition: condition.when.source().to_string(),
+            description: condition.description.clone(),
+            epoch: epoch + 1 - held,
+            stopped: epoch,
+        });
+        self.stop.as_ref()
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.description.is_empty() {
+            write!(f, "{}: ", self.description)?;
+        }
+        write!(
+            f,
+            "`{}` from epoch {} (stopped at epoch {})",
+            self.condition, self.epoch, self.stopped
+        )
+    }
+}
@@ -0,0 +1,270 @@
+//! For stress-testing an internal design: a curated suite of adversarial scenarios is layered onto
+//! the configuration (so the external world and the internal models are otherwise the ones being
+//! studied), and every scenario is checked against the same security criteria. The result is a
+//! pass/fail matrix, with a control row in which nothing goes wrong, so that a failure can be told
+//! apart from one that the design has regardless.
+
+use crate::config::{ArbitrageConfig, Config, ShockConfig};
+use crate::error::{Error, Result};
+use crate::metrics::Health;
+use crate::monte_carlo;
+use crate::parallel;
+use crate::simulation::Simulation;
+use crate::Pct;
+use std::io::Write;
+
+/// Stress is one adversarial scenario, applied to a configuration at the epoch of the shock.
+#[derive(Clone, Copy, Debug)]
+pub struct Stress {
+    /// The name of the scenario.
+    pub name: &'static str,
+    /// A one line description of what goes wrong.
+    pub description: &'static str,
+    apply: fn(&mut Config, usize),
+}
+
+impl Stress {
+    /// The configuration with the scenario applied to it, where the shock hits at the given epoch.
+    pub fn config(&self, config: &Config, epoch: usize) -> Config {
+        let mut config = config.clone();
+        (self.apply)(&mut config, epoch);
+        config
+    }
+}
+
+/// Every scenario in the suite, beginning with the control.
+pub const STRESSES: [Stress; 5] = [
+    Stress {
+        name: "control",
+        description: "Nothing goes wrong.",
+        apply: |_, _| {},
+    },
+    Stress {
+        name: "price-crash",
+        description: "The price of REN falls by 80% in one epoch.",
+        apply: |config, epoch| {
+            config.external.shocks.push(ShockConfig::RenPriceCrash {
+                epoch,
+                drop: Pct(0.8),
+            })
+        },
+    },
+    Stress {
+        name: "operator-exit",
+        description: "Node operators withdraw half of their bonds at once.",
+        apply: |config, epoch| {
+            config.external.shocks.push(ShockConfig::Unbonding {
+                epoch,
+                share: Pct(0.5),
+            })
+        },
+    },
+    Stress {
+        name: "demand-shutdown",
+        description: "Minting stops entirely, for the rest of the run.",
+        apply: |config, epoch| {
+            config.external.shocks.push(ShockConfig::DemandCollapse {
+                epoch,
+                epochs: config.steps,
+                drop: Pct(1.0),
+            })
+        },
+    },
+    Stress {
+        name: "rebate-drain",
+        description: "From the first epoch, an arbitrageur with no costs burns whatever the \
+                      rebate pool pays for (up to a tenth of the initial locked value every \
+                      epoch), a step behind.",
+        apply: |config, _| {
+            config.external.arbitrage = ArbitrageConfig::Agent {
+                capital: config.initial.tvl * 0.1,
+                cost: Pct::ZERO,
+                threshold: Pct::ZERO,
+                latency: 1,
+            }
+        },
+    },
+];
+
+/// The security criteria that every scenario is checked against, with what each of them requires.
+pub const CRITERIA: [(&str, &str); 4] = [
+    (
+        "collateralised",
+        "TVB never falls below the required multiple of TVL",
+    ),
+    (
+        "no-death-spiral",
+        "the run is not classified as a death spiral (see `[health]`)",
+    ),
+    (
+        "pool-solvent",
+        "rebates never drain the rebate pool while they are owed",
+    ),
+    (
+        "recovers",
+        "by the end, the security ratio is back to the given share of what it was before the shock",
+    ),
+];
+
+/// Row is the results of one scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub stress: &'static str,
+    /// The number of runs that failed each criterion, in the order of `CRITERIA`.
+    pub failures: [usize; 4],
+    /// The first invariant that a run broke, if any did. A run that breaks an invariant stops
+    /// there, and fails every criterion.
+    pub violation: Option<String>,
+}
+
+/// Report is the pass/fail matrix of every scenario against every criterion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// The epoch at which the shocks hit.
+    pub epoch: usize,
+    /// The number of runs (with different seeds) of every scenario.
+    pub runs: usize,
+    pub rows: Vec<Row>,
+}
+
+/// Check the configuration against every scenario in the suite (in parallel), with the shocks at
+/// the given epoch, `runs` runs of every scenario (seeded as the runs of an ensemble), and the
+/// share of the security ratio before the shock that a run must recover to.
+pub fn run(config: &Config, epoch: usize, runs: usize, seed: u64, recovery: f64) -> Result<Report> {
+    run_with_progress(config, epoch, runs, seed, recovery, &|| {})
+}
+
+/// Run the suite like `run`, calling `progress` (from whichever thread ran it) every time a run
+/// completes.
+pub fn run_with_progress(
+    config: &Config,
+    epoch: usize,
+    runs: usize,
+    seed: u64,
+    recovery: f64,
+    progress: &(dyn Fn() + Sync),
+) -> Result<Report> {
+    if epoch == 0 || epoch > config.steps {
+        return Err(Error::Config(format!(
+            "the shocks must hit between epoch 1 and the last epoch ({}), not epoch {}",
+            config.steps, epoch
+        )));
+    }
+    let trials: Vec<(usize, usize)> = (0..STRESSES.len())
+        .flat_map(|stress| (0..runs).map(move |run| (stress, run)))
+        .collect();
+    let results = parallel::map(trials, |(stress, run)| {
+        let config = STRESSES[stress].config(config, epoch);
+        let mut sim = Simulation::from_config(&config).with_seed(monte_carlo::run_seed(seed, run));
+        let mut before = 0.0;
+        let simulated = sim.try_run(epoch - 1).and_then(|_| {
+            before = sim.history().latest().security_ratio;
+            match (sim.aborted(), sim.stopped()) {
+                (None, None) => sim.try_run(config.steps + 1 - epoch),
+                _ => Ok(vec![]),
+            }
+        });
+        progress();
+        match simulated {
+            Ok(_) => {}
+            Err(Error::Invariant(violation)) => {
+                return Ok((stress, [true; 4], Some(violation.to_string())));
+            }
+            Err(err) => return Err(err),
+        }
+        let aggregates = sim.history().aggregates();
+        let health = Health::classify(aggregates, &config.health);
+        let failed = [
+            aggregates.breaches > 0,
+            health == Health::DeathSpiral,
+            aggregates.exhaustions > 0,
+            aggregates.last.security_ratio < recovery * before,
+        ];
+        Ok((stress, failed, None))
+    });
+    let mut rows: Vec<Row> = STRESSES
+        .iter()
+        .map(|stress| Row {
+            stress: stress.name,
+            failures: [0; 4],
+            violation: None,
+        })
+        .collect();
+    for result in results {
+        let (stress, failed, violation) = result?;
+        let row = &mut rows[stress];
+        for (failures, failed) in row.failures.iter_mut().zip(failed) {
+            *failures += failed as usize;
+        }
+        if row.violation.is_none() {
+            row.violation = violation;
+        }
+    }
+    Ok(Report { epoch, runs, rows })
+}
+
+impl Report {
+    /// The number of checks (of a scenario against a criterion) that failed in any run.
+    pub fn failed(&self) -> usize {
+        self.rows
+            .iter()
+            .flat_map(|row| row.failures)
+            .filter(|&failures| failures > 0)
+            .count()
+    }
+
+    /// The number of checks, one per scenario and criterion.
+    pub fn checks(&self) -> usize {
+        self.rows.len() * CRITERIA.len()
+    }
+
+    /// Write the matrix as text, with a row per scenario and a column per criterion. A check
+    /// passes only if every run passed it; otherwise it says how many runs failed.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> Result<()> {
+        let cell = |failures: usize| match (failures, self.runs) {
+            (0, _) => "pass".to_string(),
+            (_, 1) => "FAIL".to_string(),
+            (failures, runs) => format!("FAIL {}/{}", failures, runs),
+        };
+        write!(writer, "{:<16}", "stress")?;
+        for (name, _) in &CRITERIA {
+            write!(writer, " {:>16}", name)?;
+        }
+        writeln!(writer)?;
+        for row in &self.rows {
+            write!(writer, "{:<16}", row.stress)?;
+            for &failures in &row.failures {
+                write!(writer, " {:>16}", cell(failures))?;
+            }
+            writeln!(writer)?;
+        }
+        for row in &self.rows {
+            if let Some(violation) = &row.violation {
+                // Only the first line, since the states that follow it are too long for a table.
+                let violation = violation.lines().next().unwrap_or_default();
+                writeln!(writer, "{}: {}", row.stress, violation)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every check as CSV, with one row per scenario and criterion.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = ::csv::Writer::from_writer(writer);
+        writer.write_record(["stress", "criterion", "epoch", "runs", "failures", "pass"])?;
+        for row in &self.rows {
+            for ((criterion, _), failures) in CRITERIA.iter().zip(row.failures) {
+                writer.write_record([
+                    row.stress.to_string(),
+                    criterion.to_string(),
+                    self.epoch.to_string(),
+                    self.runs.to_string(),
+                    failures.to_string(),
+                    (failures == 0).to_string(),
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+//! For subsidising node operators with REN inflation while fees alone cannot pay for security,
+//! like a block reward during the bootstrapping phase of a network. Every epoch, REN is emitted
+//! according to a schedule and paid to node operators along with their fees, at the price of REN in
+//! that epoch, so that it counts towards the return that they bond for. Emitted REN adds to the
+//! circulating supply, which weighs on the price of REN (see `RenSupplyConfig`).
+
+use crate::config::{EmissionSchedule, EpochConfig, SubsidyConfig};
+use crate::Ren;
+
+/// Subsidy emits REN according to a `SubsidyConfig`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Subsidy {
+    schedule: EmissionSchedule,
+    /// The REN emitted in the first epoch.
+    initial: Ren,
+    /// The length of an epoch, in days.
+    days: f64,
+}
+
+impl Subsidy {
+    /// Create a subsidy using the given parameters, with epochs of the given length. Returns `None`
+    /// when nothing is ever emitted.
+    pub fn new(config: SubsidyConfig, epoch: &EpochConfig) -> Option<Self> {
+        (config.per_year > Ren::ZERO).then(|| Self {
+            schedule: config.schedule,
+            initial: config.per_year / epoch.per_year(),
+            days: epoch.days,
+        })
+    }
+
+    /// The REN emitted in the given epoch (where the first simulated epoch is epoch one).
+    pub fn emission(&self, epoch: usize) -> Ren {
+        let elapsed = epoch.saturating_sub(1) as f64 * self.days;
+        let factor = match self.schedule {
+            EmissionSchedule::Constant => 1.0,
+            EmissionSchedule::Decay { half_life_days } if half_life_days > 0.0 => {
+                0.5f64.powf(elapsed / half_life_days)
+            }
+            EmissionSchedule::Halving { interval_days } if interval_days > 0.0 => {
+                0.5f64.powf((elapsed / interval_days).floor())
+            }
+            EmissionSchedule::Linear { days } if days > 0.0 => (1.0 - elapsed / days).max(0.0),
+            // A schedule without a positive duration has already run out.
+            _ => 0.0,
+        };
+        self.initial * factor
+    }
+}
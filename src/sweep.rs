@@ -0,0 +1,165 @@
+//! For running the simulation for every combination of values on a grid of parameters, which is the
+//! main workflow for tuning fee curves. Parameters are addressed by their dotted path in the
+//! configuration (e.g. `internal.mint_fee`), so any numeric parameter can be swept. Every cell is
+//! run with the same seed, so that differences between cells come from the parameters rather than
+//! from the noise.
+//!
+//! When every cell can be simulated by a batch (see `batch::supports`), the cells are simulated in
+//! batches that advance many of them in lockstep, which gives exactly the same outcomes as running
+//! them one by one, only several times faster. Otherwise every cell is run on its own.
+
+use crate::batch::{self, Batch};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::metrics::Outcome;
+use crate::parallel;
+use crate::simulation::Simulation;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Axis is one dimension of the grid: a parameter and the values that it takes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Axis {
+    /// The dotted path of the parameter in the configuration.
+    pub path: String,
+    /// The values that the parameter takes.
+    pub values: Vec<f64>,
+}
+
+impl FromStr for Axis {
+    type Err = Error;
+
+    /// Parse an axis from `path=start:stop:step` (an inclusive range) or `path=a,b,c` (a list).
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("invalid sweep axis `{}`", s));
+        let (path, values) = s.split_once('=').ok_or_else(invalid)?;
+        let numbers = |sep: char| -> Result<Vec<f64>> {
+            values
+                .split(sep)
+                .map(|v| v.trim().parse::<f64>().map_err(|_| invalid()))
+                .collect()
+        };
+        let values = if values.contains(':') {
+            match numbers(':')?[..] {
+                [start, stop, step] if step > 0.0 && stop >= start => {
+                    // Count the steps up front (allowing for rounding error) rather than adding
+                    // the step repeatedly, so that the values do not accumulate drift.
+                    let n = ((stop - start) / step + 1e-9).floor() as usize;
+                    (0..=n).map(|i| start + step * i as f64).collect()
+                }
+                _ => return Err(invalid()),
+            }
+        } else {
+            numbers(',')?
+        };
+        Ok(Self {
+            path: path.trim().to_string(),
+            values,
+        })
+    }
+}
+
+/// Cell is one point on the grid and the outcome of simulating it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    /// The value of every parameter, in the same order as the axes.
+    pub params: Vec<f64>,
+    pub outcome: Outcome,
+}
+
+/// The most cells that are simulated in one batch, so that the columns of a batch stay small.
+const LANES: usize = 256;
+
+/// Every point on the grid, varying the last axis fastest.
+pub fn grid(axes: &[Axis]) -> Vec<Vec<f64>> {
+    axes.iter().fold(vec![vec![]], |points, axis| {
+        points
+            .iter()
+            .flat_map(|point| {
+                axis.values.iter().map(move |&value| {
+                    let mut point = point.clone();
+                    point.push(value);
+                    point
+                })
+            })
+            .collect()
+    })
+}
+
+/// Run the simulation for every point on the grid (in parallel), using the base configuration for
+/// every parameter that is not on an axis.
+pub fn run(config: &Config, axes: &[Axis], seed: u64) -> Result<Vec<Cell>> {
+    run_with_progress(config, axes, seed, &|| {})
+}
+
+/// Run the sweep like `run`, calling `progress` (from whichever thread ran it) every time a cell
+/// is simulated.
+pub fn run_with_progress(
+    config: &Config,
+    axes: &[Axis],
+    seed: u64,
+    progress: &(dyn Fn() + Sync),
+) -> Result<Vec<Cell>> {
+    let grid = grid(axes);
+    let configs = parallel::map(grid.clone(), |params| {
+        let mut config = config.clone();
+        for (axis, &value) in axes.iter().zip(&params) {
+            config.set(&axis.path, value)?;
+        }
+        Ok(config)
+    })
+    .into_iter()
+    .collect::<Result<Vec<Config>>>()?;
+    let batched = configs.iter().all(batch::supports)
+        && configs.iter().all(|cell| cell.steps == config.steps);
+    let outcomes = if batched {
+        // Enough batches to keep every thread busy, but no larger than `LANES` cells.
+        let lanes = configs.len().div_ceil(parallel::threads()).clamp(1, LANES);
+        let chunks: Vec<&[Config]> = configs.chunks(lanes).collect();
+        parallel::map(chunks, |configs| {
+            let mut batch = Batch::new(configs, seed).expect("every cell is supported");
+            batch.run(config.steps);
+            configs.iter().for_each(|_| progress());
+            batch.outcomes()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    } else {
+        parallel::map(configs, |config| {
+            let mut sim = Simulation::from_config(&config).with_seed(seed);
+            sim.try_run(config.steps)?;
+            progress();
+            Ok(Outcome::from_aggregates(sim.history().aggregates()))
+        })
+    };
+    grid.into_iter()
+        .zip(outcomes)
+        .map(|(params, outcome)| {
+            Ok(Cell {
+                params,
+                outcome: outcome?,
+            })
+        })
+        .collect()
+}
+
+/// Write the cells as CSV, with one column per axis followed by one column per outcome field.
+pub fn write_csv<W: Write>(writer: W, axes: &[Axis], cells: &[Cell]) -> Result<()> {
+    let mut writer = ::csv::Writer::from_writer(writer);
+    writer.write_record(
+        axes.iter()
+            .map(|axis| axis.path.as_str())
+            .chain(Outcome::FIELDS.iter().copied()),
+    )?;
+    for cell in cells {
+        writer.write_record(
+            cell.params
+                .iter()
+                .chain(cell.outcome.values().iter())
+                .map(|value| value.to_string()),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! For telling a story with a run ("a bull run, then a hack scare, then a recovery") in a file of
+//! its own, rather than as shocks and governance changes scattered through a configuration. A
+//! timeline is a YAML map from epochs (where the first simulated epoch is epoch one) to the events
+//! that happen at the start of them:
+//!
+//! ```yaml
+//! 30:
+//!   - kind: ren-price
+//!     factor: 2.0
+//!   - kind: mint-volume
+//!     factor: 1.5
+//!     epochs: 60
+//! 90:
+//!   - kind: operator-exit
+//!     share: 0.3
+//!   - kind: parameters
+//!     description: raise the burn fee
+//!     burn_fee: 0.003
+//! ```
+//!
+//! Applying a timeline to a configuration (of any scenario) adds every event to it as the shock or
+//! governance change that it stands for, so a timeline can be shared and layered onto whichever
+//! design is being discussed.
+
+use crate::config::{Config, GovernanceConfig, ShockConfig};
+use crate::error::{Error, Result};
+use crate::Pct;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Timeline is the events of a run, by the epoch that they happen at.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timeline {
+    events: BTreeMap<usize, Vec<Event>>,
+}
+
+/// Event is something that happens to a run at the start of an epoch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Event {
+    /// The price of REN is multiplied by `factor`, and its price model continues from there.
+    RenPrice { factor: f64 },
+    /// The price of a locked asset is multiplied by `factor`, revaluing what is locked. Without an
+    /// asset, every priced asset is (or, when locked value is modelled in aggregate, all of it).
+    Price {
+        factor: f64,
+        #[serde(default)]
+        asset: Option<String>,
+    },
+    /// Minting volume is multiplied by `factor` for `epochs` epochs.
+    MintVolume {
+        factor: f64,
+        #[serde(default = "Event::one")]
+        epochs: usize,
+    },
+    /// Burning volume (other than arbitrage) is multiplied by `factor` for `epochs` epochs.
+    BurnVolume {
+        factor: f64,
+        #[serde(default = "Event::one")]
+        epochs: usize,
+    },
+    /// Node operators withdraw `share` of their bonds.
+    OperatorExit { share: Pct },
+    /// The internal parameters change, as they would by governance.
+    Parameters(Box<GovernanceConfig>),
+}
+
+impl Event {
+    /// Volumes change for a single epoch, unless the event says otherwise.
+    fn one() -> usize {
+        1
+    }
+}
+
+impl Timeline {
+    /// Load a timeline from a YAML file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_yaml(&fs::read_to_string(path)?)
+    }
+
+    /// Parse a timeline from YAML.
+    pub fn from_yaml(contents: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+
+    /// The events at every epoch, in order.
+    pub fn events(&self) -> impl Iterator<Item = (usize, &Event)> {
+        self.events
+            .iter()
+            .flat_map(|(epoch, events)| events.iter().map(move |event| (*epoch, event)))
+    }
+
+    /// Add every event to the configuration, as the shock or governance change that it stands for.
+    /// Events are checked first, so that a configuration is never left half changed.
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        for (epoch, event) in self.events() {
+            check(epoch, event)?;
+        }
+        // A factor is a shock that drops by the rest of it, which is negative for a rise.
+        let drop = |factor: f64| Pct(1.0 - factor);
+        for (epoch, event) in self.events() {
+            let shock = match event {
+                Event::RenPrice { factor } => ShockConfig::RenPriceCrash {
+                    epoch,
+                    drop: drop(*factor),
+                },
+                Event::Price { factor, asset } => ShockConfig::PriceCrash {
+                    epoch,
+                    drop: drop(*factor),
+                    asset: asset.clone(),
+                },
+                Event::MintVolume { factor, epochs } => ShockConfig::DemandCollapse {
+                    epoch,
+                    epochs: *epochs,
+                    drop: drop(*factor),
+                },
+                Event::BurnVolume { factor, epochs } => ShockConfig::BurnCollapse {
+                    epoch,
+                    epochs: *epochs,
+                    drop: drop(*factor),
+                },
+                Event::OperatorExit { share } => ShockConfig::Unbonding {
+                    epoch,
+                    share: *share,
+                },
+                Event::Parameters(change) => {
+                    config.governance.push(GovernanceConfig {
+                        epoch,
+                        ..(**change).clone()
+                    });
+                    continue;
+                }
+            };
+            config.external.shocks.push(shock);
+        }
+        Ok(())
+    }
+}
+
+/// Check that an event can happen at the given epoch.
+fn check(epoch: usize, event: &Event) -> Result<()> {
+    let invalid = |what: String| Err(Error::Config(format!("timeline epoch {}: {}", epoch, what)));
+    if epoch == 0 {
+        return invalid("the first simulated epoch is epoch one".to_string());
+    }
+    match event {
+        Event::RenPrice { factor }
+        | Event::Price { factor, .. }
+        | Event::MintVolume { factor, .. }
+        | Event::BurnVolume { factor, .. }
+            if !(*factor >= 0.0 && factor.is_finite()) =>
+        {
+            invalid(format!("factor must be at least zero (not {})", factor))
+        }
+        Event::OperatorExit { share } if !(0.0..=1.0).contains(&share.0) => invalid(format!(
+            "share must be between zero and one (not {})",
+            share.0
+        )),
+        Event::Parameters(change) if change.epoch != 0 => invalid(
+            "parameter changes happen at the epoch that they are listed under, so they do not \
+             take an `epoch`"
+                .to_string(),
+        ),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,216 @@
+//! For the units of the values that flow through the simulation. Values in USD, amounts of REN,
+//! and percentages are all floating point numbers underneath, but they are not interchangeable:
+//! adding a fee to a volume is a bug, while multiplying a volume by a fee is how fees are charged.
+//! Wrapping each of them in its own type turns mistakes like that into compile errors. Only the
+//! arithmetic that makes sense between units is implemented, and everything else has to unwrap
+//! the number explicitly (with `.0`). Every unit is serialized as a plain number.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::num::ParseFloatError;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Implements a unit: a number that can be added to (and subtracted from) itself, scaled by plain
+/// numbers, and divided by itself to get a plain ratio.
+macro_rules! unit {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f64);
+
+        impl $name {
+            /// Nothing at all.
+            pub const ZERO: Self = Self(0.0);
+
+            /// The larger of two values (ignoring NaN, like `f64::max`).
+            pub fn max(self, other: Self) -> Self {
+                Self(self.0.max(other.0))
+            }
+
+            /// The smaller of two values (ignoring NaN, like `f64::min`).
+            pub fn min(self, other: Self) -> Self {
+                Self(self.0.min(other.0))
+            }
+
+            /// The absolute value.
+            pub fn abs(self) -> Self {
+                Self(self.0.abs())
+            }
+
+            /// Whether the value is neither NaN nor infinite.
+            pub fn is_finite(self) -> bool {
+                self.0.is_finite()
+            }
+        }
+
+        impl fmt::Display for $name {
+            /// Formats the number (respecting precision, width, etc.) without a unit, so that
+            /// outputs are the same as for a plain number.
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        /// Parses a plain number.
+        impl FromStr for $name {
+            type Err = ParseFloatError;
+            fn from_str(s: &str) -> Result<Self, ParseFloatError> {
+                s.parse().map(Self)
+            }
+        }
+
+        impl From<$name> for f64 {
+            fn from(value: $name) -> f64 {
+                value.0
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, other: Self) -> Self {
+                Self(self.0 + other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: Self) {
+                self.0 += other.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, other: Self) -> Self {
+                Self(self.0 - other.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: Self) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self {
+                Self(-self.0)
+            }
+        }
+
+        impl Mul<f64> for $name {
+            type Output = Self;
+            fn mul(self, factor: f64) -> Self {
+                Self(self.0 * factor)
+            }
+        }
+
+        impl Mul<$name> for f64 {
+            type Output = $name;
+            fn mul(self, value: $name) -> $name {
+                $name(self * value.0)
+            }
+        }
+
+        impl MulAssign<f64> for $name {
+            fn mul_assign(&mut self, factor: f64) {
+                self.0 *= factor;
+            }
+        }
+
+        impl Div<f64> for $name {
+            type Output = Self;
+            fn div(self, divisor: f64) -> Self {
+                Self(self.0 / divisor)
+            }
+        }
+
+        impl DivAssign<f64> for $name {
+            fn div_assign(&mut self, divisor: f64) {
+                self.0 /= divisor;
+            }
+        }
+
+        /// The ratio of two values of the same unit is a plain number.
+        impl Div for $name {
+            type Output = f64;
+            fn div(self, other: Self) -> f64 {
+                self.0 / other.0
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                Self(iter.map(|value| value.0).sum())
+            }
+        }
+
+        impl<'a> Sum<&'a $name> for $name {
+            fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+                iter.copied().sum()
+            }
+        }
+    };
+}
+
+unit!(
+    /// A value in USD, such as a volume, a fee, or a price (the value of one token).
+    Usd
+);
+
+unit!(
+    /// An amount of REN.
+    Ren
+);
+
+unit!(
+    /// A percentage, as a fraction (e.g. 0.001 is 0.1%), such as a fee, a rebate, or a share.
+    Pct
+);
+
+impl Pct {
+    /// All of something (100%).
+    pub const ALL: Self = Self(1.0);
+}
+
+/// Charging a percentage of a value in USD (e.g. a fee on a volume).
+impl Mul<Pct> for Usd {
+    type Output = Usd;
+    fn mul(self, pct: Pct) -> Usd {
+        Usd(self.0 * pct.0)
+    }
+}
+
+impl Mul<Usd> for Pct {
+    type Output = Usd;
+    fn mul(self, usd: Usd) -> Usd {
+        Usd(self.0 * usd.0)
+    }
+}
+
+/// The value in USD on which a percentage comes to the given amount (e.g. the volume that a rebate
+/// pool can pay the rebate on).
+impl Div<Pct> for Usd {
+    type Output = Usd;
+    fn div(self, pct: Pct) -> Usd {
+        Usd(self.0 / pct.0)
+    }
+}
+
+/// Valuing an amount of REN at a price in USD (per REN).
+impl Mul<Usd> for Ren {
+    type Output = Usd;
+    fn mul(self, price: Usd) -> Usd {
+        Usd(self.0 * price.0)
+    }
+}
+
+impl Mul<Ren> for Usd {
+    type Output = Usd;
+    fn mul(self, ren: Ren) -> Usd {
+        Usd(self.0 * ren.0)
+    }
+}
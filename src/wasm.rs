@@ -0,0 +1,123 @@
+//! For running the simulation in a browser. With the `wasm` feature, the library exposes a small
+//! JavaScript API through wasm-bindgen, where everything goes in and comes out as JSON, so that a
+//! web page can let people play with the parameters without installing Rust. Build it with
+//! `wasm-pack build --target web --no-default-features --features wasm` (threads, files, and
+//! charts are not available in the browser, so the other features are left out).
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::metrics::{Report, Security};
+use crate::output::Metadata;
+use crate::scenarios;
+use crate::simulation::Simulation;
+use crate::state::State;
+use crate::steady_state::SteadyState;
+use chrono::NaiveDate;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Results holds everything that a run produced.
+#[derive(Serialize)]
+struct Results {
+    /// The configuration and seed that the run was produced with.
+    metadata: Metadata,
+    /// The state at the end of every epoch, leaving out the warm-up.
+    states: Vec<Epoch>,
+    /// The number of epochs in which TVB fell below the required multiple of TVL.
+    breaches: usize,
+    /// The first epoch in which TVB fell below the required multiple of TVL.
+    first_breach: Option<usize>,
+    /// The summary of the run.
+    summary: Report,
+}
+
+/// Epoch is the state at the end of one epoch, like the epoch records of the JSON Lines output.
+#[derive(Serialize)]
+struct Epoch {
+    epoch: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<NaiveDate>,
+    #[serde(flatten)]
+    state: State,
+}
+
+/// A scenario, with its whole configuration, so that a page can start from it.
+#[derive(Serialize)]
+struct Scenario {
+    name: &'static str,
+    description: &'static str,
+    config: Config,
+}
+
+/// Run the simulation for the configuration (as JSON, in the same shape as a configuration file)
+/// and return the results as JSON. Parameters that are left out keep their defaults, and a random
+/// seed is chosen unless one is given (small enough for a JavaScript number to hold it exactly, so
+/// that the run can be reproduced from the seed in the results). The error describes an invalid
+/// configuration, or the first invariant that the run broke.
+#[wasm_bindgen]
+pub fn run(config: &str) -> std::result::Result<String, JsError> {
+    simulate(config).map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// Every registered scenario (as JSON), with its name, description, and whole configuration.
+#[wasm_bindgen]
+pub fn scenarios() -> String {
+    let scenarios: Vec<_> = scenarios::SCENARIOS
+        .iter()
+        .map(|scenario| Scenario {
+            name: scenario.name,
+            description: scenario.description,
+            config: scenario.config(),
+        })
+        .collect();
+    serde_json::to_string(&scenarios).expect("scenarios serialize")
+}
+
+fn simulate(config: &str) -> Result<String> {
+    let config: Config = serde_json::from_str(config)?;
+    // The simulation watches for a steady state on its own, but ignores an invalid detector (and
+    // likewise an invalid script or unknown models).
+    SteadyState::new(&config.steady_state, &config.epoch)?;
+    config.script.check(&config.epoch)?;
+    config.models.check()?;
+    // Integers above 2^53 lose precision as JavaScript numbers.
+    let seed = config.seed.unwrap_or_else(|| rand::random::<u64>() >> 11);
+    let mut sim = Simulation::from_config(&config).with_seed(seed);
+    let epoch = |epoch, state| Epoch {
+        epoch,
+        date: config.epoch.date(epoch),
+        state,
+    };
+    let mut states = vec![];
+    if config.warmup == 0 {
+        states.push(epoch(0, sim.history().latest().clone()));
+    }
+    for _ in 0..config.steps {
+        let state = sim.try_step()?;
+        if sim.epoch() >= config.warmup {
+            states.push(epoch(sim.epoch(), state));
+        }
+        if config.steady_state.stop && sim.history().aggregates().equilibrium.is_some() {
+            break;
+        }
+        if sim.aborted().is_some() || sim.stopped().is_some() {
+            break;
+        }
+    }
+    // A run that ends during the warm-up is represented by its last state.
+    if states.is_empty() {
+        states.push(epoch(sim.epoch(), sim.history().latest().clone()));
+    }
+    let aggregates = sim.history().aggregates();
+    let security = Security::from_aggregates(aggregates);
+    let results = Results {
+        metadata: Metadata::new(&config, seed),
+        states,
+        breaches: security.breaches,
+        first_breach: security.first_breach,
+        summary: Report::from_aggregates(aggregates)
+            .with_health(aggregates, &config.health)
+            .with_stop(sim.stopped()),
+    };
+    serde_json::to_string(&results).map_err(Error::from)
+}
@@ -0,0 +1,106 @@
+//! Tests that a batch follows exactly the path of independent simulations: every cell of a sweep
+//! that is simulated in lockstep by a `Batch` has the same outcome (bit for bit) as a
+//! `Simulation` of the same configuration and seed, with deterministic and stochastic models
+//! alike.
+
+use eco::batch::{self, Batch};
+use eco::config::{PriceConfig, VolumeConfig};
+use eco::metrics::Outcome;
+use eco::sweep::{self, Axis};
+use eco::{scenarios, Config, Simulation, Usd};
+
+/// The seed that every run is made with.
+const SEED: u64 = 7;
+
+/// The outcome of running the configuration on its own.
+fn independent(config: &Config) -> Outcome {
+    let mut sim = Simulation::from_config(config).with_seed(SEED);
+    sim.try_run(config.steps)
+        .expect("configuration keeps the invariants");
+    Outcome::from_aggregates(sim.history().aggregates())
+}
+
+/// Outcomes compare as text, so that a NaN matches a NaN.
+fn text(outcome: &Outcome) -> String {
+    format!("{:?}", outcome)
+}
+
+/// The baseline, with lognormal volumes and a price of REN that follows geometric Brownian motion.
+fn stochastic() -> Config {
+    let mut config = scenarios::find("baseline")
+        .expect("baseline scenario")
+        .config();
+    config.external.mint_volume = VolumeConfig::Lognormal {
+        mean: Usd(4_000_000.0),
+        volatility: 0.3,
+        drift: 0.001,
+    };
+    config.external.burn_volume = VolumeConfig::Lognormal {
+        mean: Usd(2_000_000.0),
+        volatility: 0.5,
+        drift: 0.0,
+    };
+    config.external.ren_price = PriceConfig::Gbm {
+        initial: Usd(0.3),
+        drift: 0.0,
+        volatility: 0.05,
+    };
+    config
+}
+
+#[test]
+fn batch_matches_independent_simulations() {
+    let baseline = scenarios::find("baseline")
+        .expect("baseline scenario")
+        .config();
+    // Burns that outgrow what is locked, which are capped at it.
+    let mut draining = stochastic();
+    draining.initial.tvl = Usd(1_000_000.0);
+    draining.external.burn_volume = VolumeConfig::Lognormal {
+        mean: Usd(5_000_000.0),
+        volatility: 0.5,
+        drift: 0.0,
+    };
+    let configs = vec![baseline, stochastic(), draining];
+    assert!(configs.iter().all(batch::supports));
+
+    let steps = configs[0].steps;
+    assert!(configs.iter().all(|config| config.steps == steps));
+    let mut batch = Batch::new(&configs, SEED).expect("every configuration is supported");
+    batch.run(steps);
+    let batched: Vec<_> = batch
+        .outcomes()
+        .into_iter()
+        .map(|outcome| text(&outcome.expect("lane keeps the invariants")))
+        .collect();
+    let independent: Vec<_> = configs.iter().map(|c| text(&independent(c))).collect();
+    assert_eq!(batched, independent);
+}
+
+#[test]
+fn sweep_matches_independent_simulations() {
+    let config = stochastic();
+    let axes: Vec<Axis> = [
+        "internal.mint_fee=0.001,0.003",
+        "internal.rebate_share=0:1:0.5",
+        "external.mint_volume.volatility=0.1,0.6",
+    ]
+    .iter()
+    .map(|axis| axis.parse().expect("valid axis"))
+    .collect();
+    let cells = sweep::run(&config, &axes, SEED).expect("sweep keeps the invariants");
+    assert_eq!(cells.len(), 12);
+    for cell in &cells {
+        let mut cell_config = config.clone();
+        for (axis, &value) in axes.iter().zip(&cell.params) {
+            cell_config.set(&axis.path, value).expect("valid path");
+        }
+        assert!(batch::supports(&cell_config));
+        assert_eq!(
+            text(&cell.outcome),
+            text(&independent(&cell_config)),
+            "cell {:?}",
+            cell.params
+        );
+    }
+}
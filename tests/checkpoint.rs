@@ -0,0 +1,58 @@
+//! Tests of resuming runs from checkpoints: a run that is checkpointed (and written out as JSON)
+//! part of the way through goes on exactly as it would have without stopping, including how long
+//! its stop conditions have held, and a run that met a stop condition stays stopped.
+
+use eco::checkpoint::Checkpoint;
+use eco::{Config, Simulation};
+
+/// The baseline, stopped once TVL (which is always positive) has been so for twenty epochs.
+fn config() -> Config {
+    Config::from_toml(
+        r#"
+        steps = 100
+
+        [[stop]]
+        when = "tvl > 0"
+        epochs = 20
+        description = "TVL stayed positive"
+        "#,
+    )
+    .expect("valid configuration")
+}
+
+/// Checkpoint the simulation, and resume it from the checkpoint as it would be read from disk.
+fn resume(sim: &Simulation, config: &Config) -> Simulation {
+    let json = serde_json::to_string(&sim.checkpoint(config)).expect("serialize checkpoint");
+    let checkpoint: Checkpoint = serde_json::from_str(&json).expect("deserialize checkpoint");
+    Simulation::resume(&checkpoint).expect("resume checkpoint")
+}
+
+#[test]
+fn resumed_run_keeps_counting_stop_conditions() {
+    let config = config();
+    let mut whole = Simulation::from_config(&config).with_seed(42);
+    whole.run(config.steps);
+    let stop = whole.stopped().expect("whole run stops").clone();
+    assert_eq!((stop.epoch, stop.stopped), (1, 20));
+
+    let mut sim = Simulation::from_config(&config).with_seed(42);
+    sim.run(12);
+    assert!(sim.stopped().is_none());
+    let mut sim = resume(&sim, &config);
+    sim.run(config.steps);
+    assert_eq!(sim.stopped(), Some(&stop));
+    assert_eq!(sim.history().states(), whole.history().states());
+}
+
+#[test]
+fn resumed_stopped_run_stays_stopped() {
+    let config = config();
+    let mut sim = Simulation::from_config(&config).with_seed(42);
+    sim.run(config.steps);
+    let stop = sim.stopped().expect("run stops").clone();
+
+    let mut resumed = resume(&sim, &config);
+    assert_eq!(resumed.stopped(), Some(&stop));
+    assert!(resumed.run(config.steps).is_empty());
+    assert_eq!(resumed.epoch(), stop.stopped);
+}
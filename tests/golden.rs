@@ -0,0 +1,93 @@
+//! Golden-file regression tests: every registered scenario is run with a fixed seed, and its
+//! per-epoch output is compared with the CSV that is checked in under `tests/golden`, so that a
+//! refactor of the simulation loop or of the models cannot change results without anybody noticing.
+//! When results are meant to change, regenerate the files with `UPDATE_GOLDEN=1 cargo test --test
+//! golden` and review the diff like any other change.
+
+use eco::output::{self, Metadata};
+use eco::{scenarios, Simulation};
+use std::fs;
+use std::path::PathBuf;
+
+/// The seed that every scenario is run with.
+const SEED: u64 = 42;
+
+/// The golden file of a scenario.
+fn path(scenario: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.csv", scenario))
+}
+
+/// Run a scenario for its configured number of steps, and write every epoch as CSV (exactly as
+/// `econ-sim run --scenario <name> --seed 42 --format csv` prints it, less the metadata block at
+/// the top, which says when and from which commit the run was produced).
+fn run(config: &eco::Config) -> String {
+    let mut sim = Simulation::from_config(config).with_seed(SEED);
+    sim.try_run(config.steps)
+        .expect("scenario keeps the invariants");
+    let mut csv = vec![];
+    output::csv::write(&mut csv, &Metadata::new(config, SEED), &sim.into_history())
+        .expect("write CSV");
+    String::from_utf8(csv)
+        .expect("CSV is UTF-8")
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn scenarios_match_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = vec![];
+    for scenario in &scenarios::SCENARIOS {
+        let path = path(scenario.name);
+        let actual = run(&scenario.config());
+        if update {
+            fs::create_dir_all(path.parent().expect("golden directory")).expect("create directory");
+            fs::write(&path, &actual).expect("write golden file");
+            continue;
+        }
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(err) => {
+                failures.push(format!(
+                    "{}: cannot read {}: {}",
+                    scenario.name,
+                    path.display(),
+                    err
+                ));
+                continue;
+            }
+        };
+        let mismatch = expected
+            .lines()
+            .zip(actual.lines())
+            .enumerate()
+            .find(|(_, (expected, actual))| expected != actual);
+        if let Some((line, (expected, actual))) = mismatch {
+            failures.push(format!(
+                "{}: line {} differs\n  expected: {}\n    actual: {}",
+                scenario.name,
+                line + 1,
+                expected,
+                actual
+            ));
+        } else if expected.lines().count() != actual.lines().count() {
+            failures.push(format!(
+                "{}: expected {} lines, but the run wrote {}",
+                scenario.name,
+                expected.lines().count(),
+                actual.lines().count()
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "results no longer match the golden files (regenerate them with `UPDATE_GOLDEN=1 cargo \
+         test --test golden` if the change is intended):\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,285 @@
+//! Property-based tests of the invariants that the models must keep: fees and rebates are never
+//! negative, the burning fee is zero whenever the rebate is positive, the rebate pool never goes
+//! negative, and nothing in the state is ever NaN. The internal model is tested directly against
+//! random histories, and whole simulations are run from every scenario with random parameters.
+
+use eco::config::{
+    AssetConfig, AssetFeeConfig, EpochConfig, InternalConfig, Order, PipelineConfig, PriceConfig,
+    Stage, VolumeConfig,
+};
+use eco::curve::Curve;
+use eco::internal::{self, InternalModel};
+use eco::invariants::Rule;
+use eco::{scenarios, Config, History, Pct, SimRng, Simulation, State, Usd};
+use proptest::prelude::*;
+use rand::SeedableRng;
+use std::convert::TryFrom;
+
+/// A configuration with locked assets, stochastic volumes and prices, agent-based arbitrageurs and
+/// operators, shocks, and governance, so that every model is exercised (not just the defaults).
+const RICH: &str = r#"
+[external.mint_volume]
+model = "lognormal"
+mean = 4000000.0
+volatility = 0.3
+drift = 0.001
+
+[external.arbitrage]
+model = "agent"
+capital = 3000000.0
+cost = 0.0005
+threshold = 0.0005
+latency = 3
+
+[external.ren_price]
+model = "gbm"
+initial = 0.3
+drift = 0.0
+volatility = 0.05
+
+[external.bonding]
+model = "operators"
+bond = 100000.0
+operators = 20
+max_nodes = 10
+target_roi = [0.03, 0.15]
+cost = [500.0, 5000.0]
+entry = 0.01
+exit = 0.01
+
+[[external.assets]]
+name = "BTC"
+mint_volume = { model = "constant", volume = 3000000.0 }
+burn_volume = { model = "constant", volume = 1500000.0 }
+price = { model = "gbm", initial = 30000.0, drift = 0.0, volatility = 0.04 }
+
+[[external.assets]]
+name = "ZEC"
+mint_volume = { model = "lognormal", mean = 1000000.0, volatility = 0.3, drift = 0.0 }
+burn_volume = { model = "constant", volume = 500000.0 }
+
+[[external.shocks]]
+kind = "price-crash"
+epoch = 40
+drop = 0.5
+
+[[external.shocks]]
+kind = "unbonding"
+epoch = 50
+share = 0.3
+
+[[external.shocks]]
+kind = "demand-collapse"
+epoch = 60
+epochs = 14
+drop = 0.8
+
+[[external.shocks]]
+kind = "burn-collapse"
+epoch = 70
+epochs = 7
+drop = 0.9
+
+[internal.assets.ZEC]
+mint_fee = 0.005
+burn_fee = 0.002
+
+[[governance]]
+epoch = 30
+mint_fee = 0.005
+"#;
+
+/// Check the invariants that every state must keep, whatever the parameters. Locked value is left
+/// out, since burning more than is minted can legitimately drive it negative.
+fn check(state: &State) -> std::result::Result<(), TestCaseError> {
+    for rule in &Rule::ALL {
+        if *rule == Rule::NonNegativeTvl {
+            continue;
+        }
+        if let Some(message) = rule.check(state) {
+            return Err(TestCaseError::fail(format!("{}: {}", rule.name(), message)));
+        }
+    }
+    prop_assert!(state.mf >= Pct::ZERO, "mf is {}", state.mf);
+    prop_assert!(state.bf >= Pct::ZERO, "bf is {}", state.bf);
+    prop_assert!(state.r >= Pct::ZERO, "r is {}", state.r);
+    for asset in &state.assets {
+        prop_assert!(asset.mf >= Pct::ZERO, "asset mf is {}", asset.mf);
+        prop_assert!(asset.bf >= Pct::ZERO, "asset bf is {}", asset.bf);
+        prop_assert!(
+            state.r == Pct::ZERO || asset.bf == Pct::ZERO,
+            "asset bf is {} while the rebate r is {}",
+            asset.bf,
+            state.r
+        );
+    }
+    Ok(())
+}
+
+/// The configuration of a scenario (or of the rich configuration), with every model parameter
+/// scaled by its own factor between zero and two. Shares stay at most one.
+fn config(base: usize, factors: &[f64]) -> Config {
+    let mut config = match scenarios::SCENARIOS.get(base) {
+        Some(scenario) => scenario.config(),
+        None => Config::from_toml(RICH).expect("valid configuration"),
+    };
+    for (path, factor) in config.parameters().iter().zip(factors.iter().cycle()) {
+        let mut value = config.get(path).expect("parameter") * factor;
+        if path.ends_with("share") || path.ends_with("claim_rate") {
+            value = value.min(1.0);
+        }
+        if config.is_integer(path).expect("parameter") {
+            value = value.round();
+        }
+        config.set(path, value).expect("parameter");
+    }
+    config
+}
+
+/// A valid order of the stages of an epoch, which may or may not be sequential.
+fn pipeline() -> impl Strategy<Value = PipelineConfig> {
+    let order = Just(Stage::ALL.to_vec())
+        .prop_shuffle()
+        .prop_filter_map("fees come first", |stages| Order::try_from(stages).ok());
+    (order, any::<bool>()).prop_map(|(order, sequential)| PipelineConfig { order, sequential })
+}
+
+/// A state with random locked and bonded value, rebate, and rebate pool.
+fn state() -> impl Strategy<Value = State> {
+    (0.0..1e9, 0.0..1e9, 0.0..0.1, 0.0..1e7).prop_map(|(tvl, tvb, r, r_pool)| State {
+        tvl: Usd(tvl),
+        tvb: Usd(tvb),
+        r: Pct(r),
+        r_pool: Usd(r_pool),
+        ..State::default()
+    })
+}
+
+/// Parameters for the internal model, including fees for one asset.
+fn internal_config() -> impl Strategy<Value = InternalConfig> {
+    (
+        (0.0..0.05, 0.0..0.05, 0.0..0.01, 1.0..60.0, 0.0..=1.0),
+        (prop::option::of(0.0..0.05), prop::option::of(0.0..0.05)),
+    )
+        .prop_map(
+            |((mint_fee, burn_fee, rebate_step, rebate_window_days, rebate_share), asset)| {
+                let mut config = InternalConfig {
+                    mint_fee: Curve::Constant(Pct(mint_fee)),
+                    burn_fee: Curve::Constant(Pct(burn_fee)),
+                    rebate_step: Pct(rebate_step),
+                    rebate_window_days,
+                    rebate_share: Curve::Constant(Pct(rebate_share)),
+                    ..InternalConfig::default()
+                };
+                let (mint_fee, burn_fee) = asset;
+                config.assets.insert(
+                    "ZEC".to_string(),
+                    AssetFeeConfig {
+                        mint_fee: mint_fee.map(|fee| Curve::Constant(Pct(fee))),
+                        burn_fee: burn_fee.map(|fee| Curve::Constant(Pct(fee))),
+                    },
+                );
+                config
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn internal_model_keeps_invariants(
+        config in internal_config(),
+        states in prop::collection::vec(state(), 1..60),
+        f in 0.0..1e7,
+        seed in any::<u64>(),
+    ) {
+        let mut model = internal::Basic::new(config, &EpochConfig::default());
+        let history = History::from_states(states);
+        let mut rng = SimRng::seed_from_u64(seed);
+        let mf = model.mint_fee_curve(&history, &mut rng);
+        let bf = model.burn_fee_curve(&history, &mut rng);
+        let r = model.rebate_curve(&history, &mut rng);
+        prop_assert!(mf >= Pct::ZERO && mf.is_finite(), "mf is {}", mf);
+        prop_assert!(bf >= Pct::ZERO && bf.is_finite(), "bf is {}", bf);
+        prop_assert!(r >= Pct::ZERO && r.is_finite(), "r is {}", r);
+        prop_assert!(r == Pct::ZERO || bf == Pct::ZERO, "bf is {} while the rebate r is {}", bf, r);
+        for asset in &["BTC", "ZEC"] {
+            let mf = model.asset_mint_fee_curve(&history, asset, &mut rng);
+            let bf = model.asset_burn_fee_curve(&history, asset, &mut rng);
+            prop_assert!(mf >= Pct::ZERO && mf.is_finite(), "{} mf is {}", asset, mf);
+            prop_assert!(bf >= Pct::ZERO && bf.is_finite(), "{} bf is {}", asset, bf);
+            prop_assert!(
+                r == Pct::ZERO || bf == Pct::ZERO,
+                "{} bf is {} while the rebate r is {}",
+                asset,
+                bf,
+                r
+            );
+        }
+        let r_collected = model.rebate_collected(&history, Usd(f), &mut rng);
+        prop_assert!(r_collected >= Usd::ZERO, "rebate collected is {}", r_collected);
+        prop_assert!(
+            r_collected <= Usd(f),
+            "rebate collected {} is more than the fees {}",
+            r_collected,
+            f
+        );
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn simulation_keeps_invariants(
+        base in 0..=scenarios::SCENARIOS.len(),
+        factors in prop::collection::vec(0.0..2.0, 1..40),
+        steps in 1..120usize,
+        seed in any::<u64>(),
+        pipeline in pipeline(),
+    ) {
+        let mut config = config(base, &factors);
+        config.pipeline = pipeline;
+        let mut sim = Simulation::from_config(&config).with_seed(seed);
+        check(sim.history().latest())?;
+        for state in sim.run(steps) {
+            check(&state)?;
+        }
+    }
+}
+
+/// The bear market of the README (`run --steps 365 --scenario bear-market --initial-tvl 1000000`),
+/// where burns outgrow what little is locked, runs to the end without TVL going below zero,
+/// whether locked value is modelled in aggregate or by asset.
+#[test]
+fn bear_market_from_little_locked_runs_to_completion() {
+    let mut config = scenarios::find("bear-market")
+        .expect("bear-market scenario")
+        .config();
+    config.initial.tvl = Usd(1_000_000.0);
+    let mut by_asset = config.clone();
+    by_asset.initial.tvl = Usd::ZERO;
+    let asset = |name: &str, mint, burn, price| AssetConfig {
+        name: name.to_string(),
+        mint_volume: VolumeConfig::Constant { volume: Usd(mint) },
+        burn_volume: VolumeConfig::Constant { volume: Usd(burn) },
+        price,
+    };
+    let price = PriceConfig::Gbm {
+        initial: Usd(30_000.0),
+        drift: -0.01,
+        volatility: 0.04,
+    };
+    by_asset.external.assets = vec![
+        asset("BTC", 1_000_000.0, 1_500_000.0, Some(price)),
+        asset("ZEC", 500_000.0, 800_000.0, None),
+    ];
+    for config in [config, by_asset] {
+        for seed in 0..8 {
+            let mut sim = Simulation::from_config(&config).with_seed(seed);
+            let states = sim
+                .try_run(365)
+                .unwrap_or_else(|err| panic!("seed {}: {}", seed, err));
+            assert_eq!(states.len(), 365, "seed {}", seed);
+        }
+    }
+}
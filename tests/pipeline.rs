@@ -0,0 +1,76 @@
+//! Tests of the order of the stages of an epoch: without a sequential pipeline, the stages of a
+//! deterministic run happen at once, so their order changes nothing, while with one, every stage
+//! sees what the stages before it did (as the latest state, which windows of the history count
+//! back from), so the order changes the results.
+
+use eco::config::{Order, PipelineConfig, Stage};
+use eco::{scenarios, Config, Simulation, State, Usd};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// The states of a run of the baseline (which draws no random numbers) with the given pipeline.
+fn run(stages: &[Stage], sequential: bool) -> Vec<State> {
+    let mut config: Config = scenarios::find("baseline")
+        .expect("baseline scenario")
+        .config();
+    config.pipeline = PipelineConfig {
+        order: Order::try_from(stages.to_vec()).expect("valid order"),
+        sequential,
+    };
+    let mut sim = Simulation::from_config(&config).with_seed(42);
+    sim.try_run(config.steps)
+        .expect("baseline keeps the invariants");
+    sim.into_history()
+}
+
+/// Claims and locked value before bonds, so that bonding can respond to both in the same epoch.
+const BONDS_LAST: [Stage; 5] = [
+    Stage::Fees,
+    Stage::Rebate,
+    Stage::Claims,
+    Stage::Locked,
+    Stage::Bonds,
+];
+
+#[test]
+fn order_only_matters_when_sequential() {
+    assert_eq!(run(&Stage::ALL, false), run(&BONDS_LAST, false));
+}
+
+#[test]
+fn sequential_orders_differ() {
+    let default = run(&Stage::ALL, true);
+    let bonds_last = run(&BONDS_LAST, true);
+    assert_eq!(default.len(), bonds_last.len());
+    // Bonding responds to the fees claimed in the same epoch, rather than in the one before.
+    assert!(
+        default.iter().zip(&bonds_last).any(|(a, b)| a.tvb != b.tvb),
+        "TVB is the same in both orders"
+    );
+}
+
+#[test]
+fn rates_of_change_look_back_from_the_epoch_so_far() {
+    let mut config = Config::default();
+    config.pipeline.sequential = true;
+    let rates = Rc::new(RefCell::new(vec![]));
+    let recorded = Rc::clone(&rates);
+    let mut sim = Simulation::builder()
+        .config(&config)
+        .seed(42)
+        // Epoch n mints n million, which is 1/(n-1) more than the epoch before it.
+        .mint_volume(|history| Usd(1_000_000.0 * history.len() as f64))
+        .total_value_bonded(move |history| {
+            let rate = history.rate_of_change(1, |state| state.mint_volume.0);
+            recorded.borrow_mut().push((history.len(), rate));
+            history.latest().tvb
+        })
+        .build();
+    sim.try_run(10).expect("run keeps the invariants");
+    let rates = rates.borrow();
+    assert_eq!(rates.len(), 10);
+    for &(epoch, rate) in rates.iter().skip(1) {
+        assert_eq!(rate, Some(1.0 / (epoch - 1) as f64), "epoch {}", epoch);
+    }
+}
@@ -0,0 +1,45 @@
+//! Tests of the built-in models of the registry: every name that `econ-sim models` lists can be
+//! selected through the `[models]` table of a configuration, resolves to a configuration that
+//! selects it for good, and runs without breaking the invariants.
+
+use eco::registry::{self, Slot};
+use eco::{scenarios, Simulation};
+
+/// The number of epochs that every model is run for.
+const STEPS: usize = 90;
+
+#[test]
+fn every_builtin_resolves_through_config() {
+    let base = scenarios::find("baseline")
+        .expect("baseline scenario")
+        .config();
+    for slot in Slot::ALL {
+        let models = registry::models(slot);
+        assert!(
+            !models.is_empty(),
+            "`{}` has no built-in models",
+            slot.key()
+        );
+        for (name, _) in models {
+            let mut config = base.clone();
+            config.models.set(slot, &name);
+            config
+                .models
+                .check()
+                .unwrap_or_else(|err| panic!("{} = {:?}: {}", slot.key(), name, err));
+
+            let resolved = registry::resolve(&config).into_owned();
+            assert_eq!(
+                *registry::resolve(&resolved),
+                resolved,
+                "{} = {:?} does not resolve to a configuration of its own",
+                slot.key(),
+                name
+            );
+
+            let mut sim = Simulation::from_config(&config).with_seed(42);
+            sim.try_run(STEPS)
+                .unwrap_or_else(|err| panic!("{} = {:?}: {}", slot.key(), name, err));
+        }
+    }
+}